@@ -0,0 +1,107 @@
+//! Firmware skeleton for a handheld HP-41C replica
+//!
+//! Build with `cargo run --example firmware_skeleton --features embedded`.
+//! On real hardware, replace the `board` module with your HAL's GPIO pins
+//! and delay (anything implementing the embedded-hal 1.0 traits); the main
+//! loop stays the same. The stand-in board below presses a fixed sequence
+//! of keys and prints what would go to the LCD, so the wiring can be tried
+//! on a desktop first.
+
+use hp41c::embedded::{Hd44780Sink, KeyMatrix, HP41_KEYMAP};
+use hp41c::keyboard::InputSource;
+use hp41c::HP41CCalculator;
+
+/// Stand-in for a board support crate
+mod board {
+    use std::cell::RefCell;
+    use std::convert::Infallible;
+    use std::rc::Rc;
+    use embedded_hal::delay::DelayNs;
+    use embedded_hal::digital::{ErrorType, InputPin, OutputPin};
+
+    /// Simulated keypad: which (row, col) is held and which column is driven
+    #[derive(Default)]
+    pub struct Keypad {
+        pub driven_col: Option<usize>,
+        pub held: Option<(usize, usize)>,
+        pub script: Vec<(usize, usize)>,
+        pub scans: usize,
+    }
+
+    pub struct Row(pub usize, pub Rc<RefCell<Keypad>>);
+    pub struct Col(pub usize, pub Rc<RefCell<Keypad>>);
+    pub struct LcdPin;
+    pub struct Delay;
+
+    impl ErrorType for Row { type Error = Infallible; }
+    impl ErrorType for Col { type Error = Infallible; }
+    impl ErrorType for LcdPin { type Error = Infallible; }
+
+    impl InputPin for Row {
+        fn is_high(&mut self) -> Result<bool, Infallible> {
+            self.is_low().map(|low| !low)
+        }
+        fn is_low(&mut self) -> Result<bool, Infallible> {
+            let pad = self.1.borrow();
+            Ok(matches!((pad.held, pad.driven_col), (Some((r, c)), Some(d)) if r == self.0 && c == d))
+        }
+    }
+
+    impl OutputPin for Col {
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            let mut pad = self.1.borrow_mut();
+            pad.driven_col = Some(self.0);
+            if self.0 == 0 {
+                // Start of a scan: hold each scripted key for a few scans
+                pad.scans += 1;
+                let step = pad.scans / 4;
+                pad.held = if pad.scans.is_multiple_of(4) { None } else { pad.script.get(step).copied() };
+            }
+            Ok(())
+        }
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            self.1.borrow_mut().driven_col = None;
+            Ok(())
+        }
+    }
+
+    impl OutputPin for LcdPin {
+        fn set_low(&mut self) -> Result<(), Infallible> { Ok(()) }
+        fn set_high(&mut self) -> Result<(), Infallible> { Ok(()) }
+    }
+
+    impl DelayNs for Delay {
+        fn delay_ns(&mut self, _ns: u32) {}
+    }
+}
+
+fn main() -> std::io::Result<()> {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use board::{Col, Delay, Keypad, LcdPin, Row};
+
+    // 6 ENTER 7 * on the HP-41 key layout
+    let keypad = Rc::new(RefCell::new(Keypad {
+        script: vec![(5, 3), (3, 0), (4, 1), (6, 0)],
+        ..Keypad::default()
+    }));
+    let rows: [Row; 8] = std::array::from_fn(|r| Row(r, keypad.clone()));
+    let cols: [Col; 5] = std::array::from_fn(|c| Col(c, keypad.clone()));
+
+    let mut keys = KeyMatrix::new(rows, cols, Delay, HP41_KEYMAP)?;
+    let mut lcd = Hd44780Sink::new(LcdPin, LcdPin, [LcdPin, LcdPin, LcdPin, LcdPin], Delay, 16)?;
+    let mut calc = HP41CCalculator::new();
+
+    for _ in 0..4 {
+        let Some(key) = keys.next_key()? else { break };
+        if let Some(input) = key.to_input() {
+            if let Err(e) = calc.process_input(&input) {
+                println!("error: {}", e);
+            }
+        }
+        calc.refresh_display(&mut lcd)?;
+        println!("LCD: {}", calc.lcd_frame());
+    }
+
+    Ok(())
+}
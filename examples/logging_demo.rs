@@ -1,5 +1,5 @@
-/// Quick demonstration of the HP-41C logging system
-/// Add this as examples/logging_demo.rs to see it in action
+//! Quick demonstration of the HP-41C logging system
+//! Add this as examples/logging_demo.rs to see it in action
 
 use hp41c::{HP41CCalculator, Logger};
 
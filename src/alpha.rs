@@ -0,0 +1,228 @@
+//! The ALPHA register and alpha data in numeric registers
+//!
+//! ALPHA holds up to 24 characters of text. `ASTO nn` copies its first six
+//! characters into a data register and `ARCL nn` appends a register to it:
+//! text as stored, numbers as the display would show them.
+//!
+//! As on the HP-41, where a register of alpha data is marked by its first
+//! nibble, a register holding text is still one 64-bit value: the text is
+//! packed into the payload of a quiet NaN with a marker bit, one byte per
+//! character. Arithmetic on it gives NaN like any invalid operand, and
+//! saved states write it as a JSON string (see `serde_values`).
+
+use crate::display::DisplayFormatter;
+
+/// Characters ALPHA can hold; older characters scroll off the left
+pub const ALPHA_LENGTH: usize = 24;
+
+/// Characters of alpha data one register holds
+pub const CHARS_PER_REGISTER: usize = 6;
+
+/// What the keys do in ALPHA mode, shown in place of the command reference
+pub const KEY_MAP: [&str; 2] = [
+    "A-Z a-z 0-9 space  . , : ; = ? ! ' \" + - * / < > ( ) % $ # &",
+    "Tab append  ⌫ delete (clears ALPHA)  Enter end text  ^N exit",
+];
+
+/// Quiet NaN exponent and mantissa bit, plus the alpha marker bit
+const ALPHA_TAG: u64 = 0x7FFC_0000_0000_0000;
+const TAG_MASK: u64 = 0xFFFF_0000_0000_0000;
+
+/// Pack up to six characters into a register value
+///
+/// Characters beyond the sixth are dropped, and characters outside
+/// Latin-1 become `?`, since each character takes one byte.
+pub fn pack(text: &str) -> f64 {
+    let payload = text.chars().take(CHARS_PER_REGISTER)
+        .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+        .fold(0u64, |bits, byte| (bits << 8) | u64::from(byte));
+    let length = text.chars().count().min(CHARS_PER_REGISTER);
+    // Left-align so "A" and "\0A" stay distinct
+    f64::from_bits(ALPHA_TAG | (payload << (8 * (CHARS_PER_REGISTER - length))))
+}
+
+/// The text in a register, if it holds alpha data
+pub fn unpack(value: f64) -> Option<String> {
+    let bits = value.to_bits();
+    if bits & TAG_MASK != ALPHA_TAG {
+        return None;
+    }
+    let text = (0..CHARS_PER_REGISTER).rev()
+        .map(|i| ((bits >> (8 * i)) & 0xFF) as u8)
+        .take_while(|&byte| byte != 0)
+        .map(char::from)
+        .collect();
+    Some(text)
+}
+
+/// Whether a register holds alpha data
+pub fn is_alpha(value: f64) -> bool {
+    value.to_bits() & TAG_MASK == ALPHA_TAG
+}
+
+/// A register as text for listings and summaries: numbers as Rust prints
+/// them, alpha data in quotes
+pub fn describe(value: f64) -> String {
+    match unpack(value) {
+        Some(text) => format!("\"{}\"", text),
+        None => value.to_string(),
+    }
+}
+
+/// The ALPHA register
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AlphaRegister {
+    text: String,
+}
+
+impl AlphaRegister {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Replace the contents, keeping the last 24 characters
+    pub fn set(&mut self, text: &str) {
+        self.text.clear();
+        self.append(text);
+    }
+
+    /// Append text, scrolling the oldest characters off when full
+    pub fn append(&mut self, text: &str) {
+        self.text.push_str(text);
+        let excess = self.text.chars().count().saturating_sub(ALPHA_LENGTH);
+        if excess > 0 {
+            self.text = self.text.chars().skip(excess).collect();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+    }
+
+    /// Take the last character off
+    pub fn pop(&mut self) {
+        self.text.pop();
+    }
+
+    /// ASTO: the first six characters as a register value
+    pub fn to_register(&self) -> f64 {
+        pack(&self.text)
+    }
+
+    /// ARCL: append a register, text as is and numbers as displayed
+    pub fn recall(&mut self, value: f64, display: &DisplayFormatter) {
+        let text = unpack(value)
+            .unwrap_or_else(|| display.format_number(value, ALPHA_LENGTH).trim().to_string());
+        self.append(&text);
+    }
+}
+
+/// Text being typed in ALPHA mode, shown with a cursor until it ends
+///
+/// Outside PRGM mode each key goes straight into the ALPHA register; in
+/// PRGM mode the text becomes a text line when the entry ends.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AlphaEntry {
+    pub text: String,
+    /// Begun with APPEND, so it adds to ALPHA instead of replacing it
+    pub append: bool,
+}
+
+/// Serde helpers writing alpha data as strings, since JSON has no NaN
+///
+/// ```text
+/// "registers": [1.5, "ABC", 0.0]
+/// ```
+pub mod serde_values {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        Number(f64),
+        Alpha(String),
+    }
+
+    impl From<f64> for Value {
+        fn from(value: f64) -> Self {
+            match super::unpack(value) {
+                Some(text) => Value::Alpha(text),
+                None => Value::Number(value),
+            }
+        }
+    }
+
+    impl From<Value> for f64 {
+        fn from(value: Value) -> Self {
+            match value {
+                Value::Number(n) => n,
+                Value::Alpha(text) => super::pack(&text),
+            }
+        }
+    }
+
+    /// For a single value such as LASTX
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        Value::from(*value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        Value::deserialize(deserializer).map(f64::from)
+    }
+
+    /// For lists of values: registers, stack levels
+    pub mod list {
+        use super::*;
+
+        pub fn serialize<S, L>(values: &L, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+            L: AsRef<[f64]>,
+        {
+            serializer.collect_seq(values.as_ref().iter().map(|&value| Value::from(value)))
+        }
+
+        pub fn deserialize<'de, D, L>(deserializer: D) -> Result<L, D::Error>
+        where
+            D: Deserializer<'de>,
+            L: TryFrom<Vec<f64>>,
+        {
+            let values: Vec<f64> = Vec::<Value>::deserialize(deserializer)?.into_iter().map(f64::from).collect();
+            let count = values.len();
+            L::try_from(values).map_err(|_| serde::de::Error::invalid_length(count, &"the stack's four levels"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_round_trip() {
+        for text in ["", "A", "HELLO", "ABCDEF", "é 1"] {
+            assert_eq!(unpack(pack(text)).as_deref(), Some(text));
+        }
+        assert_eq!(unpack(pack("ABCDEFGH")).as_deref(), Some("ABCDEF"));
+        assert_ne!(pack("A").to_bits(), pack("").to_bits());
+        assert!(unpack(1.5).is_none() && unpack(f64::NAN).is_none());
+        assert!(pack("X").is_nan());
+    }
+
+    #[test]
+    fn test_alpha_register() {
+        let mut alpha = AlphaRegister::new();
+        alpha.set("RESULT=");
+        alpha.recall(2.5, &DisplayFormatter::new());
+        assert_eq!(alpha.text(), "RESULT=2.5000");
+        alpha.recall(pack("ABC"), &DisplayFormatter::new());
+        assert_eq!(alpha.text(), "RESULT=2.5000ABC");
+        alpha.append("0123456789");
+        assert_eq!(alpha.text(), "SULT=2.5000ABC0123456789");
+        assert_eq!(unpack(alpha.to_register()).as_deref(), Some("SULT=2"));
+    }
+}
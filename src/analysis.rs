@@ -0,0 +1,428 @@
+//! Static analysis of HP-41C programs
+//!
+//! Works on the instruction list alone, without running anything. The
+//! label cross-reference lists, for every label, where it is defined and
+//! which GTO/XEQ lines jump to it, and flags labels that are never used or
+//! used but never defined. The lint report adds duplicate labels and
+//! unreachable instruction ranges.
+//!
+//! `renumber_local_labels` is the one refactoring here: it rewrites the
+//! program in place.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use crate::programming::ProgramInstruction;
+
+/// A GTO or XEQ pointing at a label
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelReference {
+    pub line: i32,
+    pub command: String,
+}
+
+/// Everything known about one label
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LabelXref {
+    pub label: String,
+    /// Lines holding `LBL label` (more than one means a duplicate)
+    pub defined_at: Vec<i32>,
+    /// Lines that GTO/XEQ the label, in program order
+    pub references: Vec<LabelReference>,
+}
+
+impl LabelXref {
+    pub fn is_defined(&self) -> bool {
+        !self.defined_at.is_empty()
+    }
+
+    pub fn is_referenced(&self) -> bool {
+        !self.references.is_empty()
+    }
+}
+
+/// Label cross-reference for a whole program
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CrossReference {
+    labels: BTreeMap<String, LabelXref>,
+}
+
+impl CrossReference {
+    /// Analyze a program
+    pub fn build(program: &[ProgramInstruction]) -> Self {
+        let mut xref = CrossReference::default();
+        for instruction in program {
+            let Some(label) = instruction.arguments.first() else { continue };
+            let command = instruction.command.to_uppercase();
+            match command.as_str() {
+                "LBL" => xref.entry(label).defined_at.push(instruction.line_number),
+                "GTO" | "XEQ" => xref.entry(label).references.push(LabelReference {
+                    line: instruction.line_number,
+                    command,
+                }),
+                _ => {}
+            }
+        }
+        xref
+    }
+
+    fn entry(&mut self, label: &str) -> &mut LabelXref {
+        let label = label.to_uppercase();
+        self.labels.entry(label.clone()).or_insert_with(|| LabelXref {
+            label,
+            ..LabelXref::default()
+        })
+    }
+
+    /// Look up one label
+    pub fn get(&self, label: &str) -> Option<&LabelXref> {
+        self.labels.get(&label.to_uppercase())
+    }
+
+    /// All labels, sorted by name
+    pub fn labels(&self) -> impl Iterator<Item = &LabelXref> {
+        self.labels.values()
+    }
+
+    /// Labels that are defined but never jumped to
+    pub fn unreferenced(&self) -> Vec<&LabelXref> {
+        self.labels().filter(|x| x.is_defined() && !x.is_referenced()).collect()
+    }
+
+    /// Labels that are jumped to but never defined
+    pub fn undefined(&self) -> Vec<&LabelXref> {
+        self.labels().filter(|x| !x.is_defined()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+}
+
+/// One line per label, e.g. `LBL A @03 <- GTO 07, XEQ 12`
+impl fmt::Display for CrossReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "No labels");
+        }
+        let lines: Vec<String> = self.labels().map(|x| {
+            let defined = if x.is_defined() {
+                x.defined_at.iter().map(|l| format!("@{:02}", l)).collect::<Vec<_>>().join(",")
+            } else {
+                "@??".to_string()
+            };
+            let refs = if x.is_referenced() {
+                let list: Vec<String> = x.references.iter()
+                    .map(|r| format!("{} {:02}", r.command, r.line))
+                    .collect();
+                format!(" <- {}", list.join(", "))
+            } else {
+                String::new()
+            };
+            let note = match (x.is_defined(), x.is_referenced()) {
+                (false, _) => " (undefined)",
+                (true, false) => " (never referenced)",
+                _ => "",
+            };
+            format!("LBL {} {}{}{}", x.label, defined, refs, note)
+        }).collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// A problem found by `lint`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LintKind {
+    /// The label is defined again; jumps go to the first definition
+    DuplicateLabel { label: String, first: i32 },
+    /// Lines from here through `end` can never execute
+    Unreachable { end: i32 },
+    /// GTO/XEQ to a label that does not exist
+    UndefinedLabel(String),
+}
+
+/// A lint finding at a program line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintIssue {
+    pub line: i32,
+    pub kind: LintKind,
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            LintKind::DuplicateLabel { label, first } => {
+                write!(f, "{:02}: duplicate LBL {} (first at {:02})", self.line, label, first)
+            }
+            LintKind::Unreachable { end } if *end == self.line => {
+                write!(f, "{:02}: unreachable", self.line)
+            }
+            LintKind::Unreachable { end } => {
+                write!(f, "{:02}-{:02}: unreachable", self.line, end)
+            }
+            LintKind::UndefinedLabel(label) => {
+                write!(f, "{:02}: undefined label {}", self.line, label)
+            }
+        }
+    }
+}
+
+/// Local labels (00-99) can only be reached through GTO/XEQ; any other
+/// label can also be started from the keyboard.
+pub(crate) fn is_local_label(label: &str) -> bool {
+    !label.is_empty() && label.len() <= 2 && label.chars().all(|c| c.is_ascii_digit())
+}
+
+/// The letter labels A-J and a-e of the top key row: local to their
+/// program like the numbered ones, but started from the keyboard in USER
+/// mode. Upper and lower case are different labels.
+pub(crate) fn is_letter_label(label: &str) -> bool {
+    matches!(label.as_bytes(), [b'A'..=b'J' | b'a'..=b'e'])
+}
+
+/// Labels every program can reach: anything but a number or a top-key letter
+pub(crate) fn is_global_label(label: &str) -> bool {
+    !is_local_label(label) && !is_letter_label(label)
+}
+
+/// A quoted label as a step keeps it: its name, or the name in quotes when
+/// it would otherwise read as a local label (`LBL "A"`, `XEQ "01"`)
+pub(crate) fn quoted_label(name: &str) -> String {
+    if is_global_label(name) { name.to_string() } else { format!("\"{}\"", name) }
+}
+
+/// A label argument's name, without the quotes `quoted_label` keeps
+pub(crate) fn label_name(label: &str) -> &str {
+    label.strip_prefix('"').and_then(|name| name.strip_suffix('"')).unwrap_or(label)
+}
+
+/// Check a program for duplicate labels, unreachable code and jumps to
+/// missing labels, returning findings in line order
+///
+/// Programs are separated by END; labels and reachability are checked
+/// within each one.
+pub fn lint(program: &[ProgramInstruction]) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    let mut start = 0;
+    while start < program.len() {
+        let end = program[start..].iter()
+            .position(|i| i.command.eq_ignore_ascii_case("END"))
+            .map_or(program.len(), |p| start + p + 1);
+        lint_segment(&program[start..end], &mut issues);
+        start = end;
+    }
+    issues.sort_by_key(|issue| issue.line);
+    issues
+}
+
+fn lint_segment(program: &[ProgramInstruction], issues: &mut Vec<LintIssue>) {
+    // Label table: first definition wins, later ones are duplicates
+    let mut targets: HashMap<String, usize> = HashMap::new();
+    for (index, instruction) in program.iter().enumerate() {
+        if !instruction.command.eq_ignore_ascii_case("LBL") {
+            continue;
+        }
+        let Some(label) = instruction.arguments.first() else { continue };
+        let label = label.to_uppercase();
+        match targets.get(&label) {
+            Some(&first) => issues.push(LintIssue {
+                line: instruction.line_number,
+                kind: LintKind::DuplicateLabel { label, first: program[first].line_number },
+            }),
+            None => {
+                targets.insert(label, index);
+            }
+        }
+    }
+
+    // Walk the control flow from the top and from every keyboard entry point
+    let mut reachable = vec![false; program.len()];
+    let mut pending: Vec<usize> = std::iter::once(0)
+        .chain(targets.iter().filter(|(l, _)| !is_local_label(l)).map(|(_, &i)| i))
+        .filter(|&i| i < program.len())
+        .collect();
+    while let Some(index) = pending.pop() {
+        if reachable[index] {
+            continue;
+        }
+        reachable[index] = true;
+        let instruction = &program[index];
+        let command = instruction.command.to_uppercase();
+        let target = instruction.arguments.first().map(|l| l.to_uppercase());
+        let jump = target.as_ref().and_then(|l| targets.get(l)).copied();
+
+        let mut next = Vec::new();
+        match command.as_str() {
+            "GTO" => next.extend(jump),
+            "XEQ" => next.extend(jump.into_iter().chain(Some(index + 1))),
+            "RTN" | "END" => {}
+            // Conditional tests skip the next step when false
+            c if c.ends_with('?') => next.extend([index + 1, index + 2]),
+            _ => next.push(index + 1),
+        }
+        if matches!(command.as_str(), "GTO" | "XEQ") && jump.is_none() {
+            if let Some(label) = target {
+                issues.push(LintIssue {
+                    line: instruction.line_number,
+                    kind: LintKind::UndefinedLabel(label),
+                });
+            }
+        }
+        pending.extend(next.into_iter().filter(|&i| i < program.len()));
+    }
+
+    // Report maximal runs of unreachable lines
+    let mut index = 0;
+    while index < program.len() {
+        if reachable[index] {
+            index += 1;
+            continue;
+        }
+        let first = index;
+        while index < program.len() && !reachable[index] {
+            index += 1;
+        }
+        issues.push(LintIssue {
+            line: program[first].line_number,
+            kind: LintKind::Unreachable { end: program[index - 1].line_number },
+        });
+    }
+}
+
+/// Renumber local labels to 01, 02, ... in order of definition, rewriting
+/// every GTO/XEQ that points at them
+///
+/// Each END-separated program is numbered on its own. Jumps to labels that
+/// are not defined are left alone. Returns the `(old, new)` pairs that
+/// actually changed.
+pub fn renumber_local_labels(program: &mut [ProgramInstruction]) -> Vec<(String, String)> {
+    let mut changed = Vec::new();
+    let mut start = 0;
+    while start < program.len() {
+        let end = program[start..].iter()
+            .position(|i| i.command.eq_ignore_ascii_case("END"))
+            .map_or(program.len(), |p| start + p + 1);
+        let segment = &mut program[start..end];
+
+        let mut mapping: HashMap<String, String> = HashMap::new();
+        for instruction in segment.iter() {
+            if !instruction.command.eq_ignore_ascii_case("LBL") {
+                continue;
+            }
+            if let Some(label) = instruction.arguments.first().filter(|l| is_local_label(l)) {
+                let old = format!("{:02}", label.parse::<u8>().unwrap_or_default());
+                if !mapping.contains_key(&old) {
+                    let new = format!("{:02}", mapping.len() + 1);
+                    if new != old {
+                        changed.push((old.clone(), new.clone()));
+                    }
+                    mapping.insert(old, new);
+                }
+            }
+        }
+
+        for instruction in segment.iter_mut() {
+            let command = instruction.command.to_uppercase();
+            if !matches!(command.as_str(), "LBL" | "GTO" | "XEQ") {
+                continue;
+            }
+            if let Some(label) = instruction.arguments.first_mut().filter(|l| is_local_label(l)) {
+                let old = format!("{:02}", label.parse::<u8>().unwrap_or_default());
+                if let Some(new) = mapping.get(&old) {
+                    *label = new.clone();
+                }
+            }
+        }
+        start = end;
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(lines: &[(&str, &str)]) -> Vec<ProgramInstruction> {
+        lines.iter().enumerate().map(|(i, (cmd, arg))| {
+            let args = if arg.is_empty() { vec![] } else { vec![arg.to_string()] };
+            ProgramInstruction::new(i as i32 + 1, cmd.to_string(), args)
+        }).collect()
+    }
+
+    #[test]
+    fn test_cross_reference() {
+        let prog = program(&[
+            ("LBL", "A"), ("XEQ", "B"), ("GTO", "A"),
+            ("LBL", "B"), ("RTN", ""),
+            ("LBL", "C"), ("GTO", "D"),
+        ]);
+        let xref = CrossReference::build(&prog);
+
+        let a = xref.get("a").unwrap();
+        assert_eq!(a.defined_at, vec![1]);
+        assert_eq!(a.references, vec![LabelReference { line: 3, command: "GTO".to_string() }]);
+
+        let unreferenced: Vec<&str> = xref.unreferenced().iter().map(|x| x.label.as_str()).collect();
+        assert_eq!(unreferenced, vec!["C"]);
+        let undefined: Vec<&str> = xref.undefined().iter().map(|x| x.label.as_str()).collect();
+        assert_eq!(undefined, vec!["D"]);
+    }
+
+    #[test]
+    fn test_report() {
+        let prog = program(&[("LBL", "A"), ("GTO", "A"), ("XEQ", "Z")]);
+        let report = CrossReference::build(&prog).to_string();
+        assert_eq!(report, "LBL A @01 <- GTO 02\nLBL Z @?? <- XEQ 03 (undefined)");
+        assert_eq!(CrossReference::build(&[]).to_string(), "No labels");
+    }
+
+    #[test]
+    fn test_lint_duplicates_and_unreachable() {
+        let prog = program(&[
+            ("LBL", "A"), ("GTO", "01"),
+            ("SIN", ""), ("COS", ""),           // skipped by the GTO
+            ("LBL", "01"), ("XEQ", "A"),
+            ("LBL", "A"), ("RTN", ""),          // duplicate A, reached only by falling through
+            ("TAN", ""),                        // after the RTN
+        ]);
+        let issues: Vec<String> = lint(&prog).iter().map(|i| i.to_string()).collect();
+        assert_eq!(issues, vec![
+            "03-04: unreachable",
+            "07: duplicate LBL A (first at 01)",
+            "09: unreachable",
+        ]);
+    }
+
+    #[test]
+    fn test_renumber_local_labels() {
+        let mut prog = program(&[
+            ("LBL", "A"), ("GTO", "40"), ("LBL", "7"), ("XEQ", "40"),
+            ("LBL", "40"), ("GTO", "07"), ("GTO", "99"), ("END", ""),
+            ("LBL", "12"), ("GTO", "12"),
+        ]);
+        let changed = renumber_local_labels(&mut prog);
+
+        let text: Vec<String> = prog.iter().map(|i| i.to_string()).collect();
+        assert_eq!(text, vec![
+            "LBL A", "GTO 02", "LBL 01", "XEQ 02",
+            "LBL 02", "GTO 01", "GTO 99", "END",
+            "LBL 01", "GTO 01",
+        ]);
+        assert_eq!(changed, vec![
+            ("07".to_string(), "01".to_string()),
+            ("40".to_string(), "02".to_string()),
+            ("12".to_string(), "01".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_lint_conditionals_and_programs() {
+        let prog = program(&[
+            ("LBL", "A"), ("X=0?", ""), ("GTO", "02"), ("RTN", ""),
+            ("LBL", "02"), ("END", ""),
+            ("LBL", "02"), ("XEQ", "03"), ("END", ""),
+        ]);
+        let issues = lint(&prog);
+        // LBL 02 in the second program is not a duplicate of the first one
+        assert_eq!(issues, vec![LintIssue { line: 8, kind: LintKind::UndefinedLabel("03".to_string()) }]);
+    }
+}
@@ -0,0 +1,437 @@
+//! The interactive front end's event loop
+//!
+//! `App` reads keys from an `InputSource`, hands calculator keystrokes to
+//! `HP41CCalculator::process_input` and handles the front-end shortcuts
+//! itself: quitting, logging control, catalog browsing, the clipboard, key
+//! feedback and the paper tape. Whatever it shows goes to a `Screen`, so
+//! the terminal binary, other front ends and tests share one loop.
+//!
+//! While no key is waiting the loop does background work: a running
+//! program takes its next slice of steps, CAT scrolls, a watched listing
+//! reloads, lockstep followers are admitted, CLOCK ticks and alarms go
+//! off.
+//!
+//! Messages that go away by themselves are kept, the last
+//! `MESSAGE_HISTORY` of them, and Ctrl+R pages back through them.
+//! Ctrl+P pages through the paper tape the same way.
+
+use std::collections::VecDeque;
+use std::io;
+use std::time::Duration;
+use crate::calculator::HP41CCalculator;
+use crate::clipboard::CopyTarget;
+use crate::keyboard::{InputSource, Key};
+use crate::lcd::DisplaySink;
+use crate::lockstep::LockstepLeader;
+
+/// How often a watched listing and new followers are checked while no key is pressed
+pub const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often a scrolling catalog is moved on while waiting for a key
+pub const CATALOG_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often the status line's CLOCK is redrawn and alarms are checked,
+/// twice a second so no second is skipped
+pub const CLOCK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Program steps run between two looks at the keyboard
+pub const STEPS_PER_TICK: usize = 500;
+
+/// How long the outcome of a keystroke stays up
+pub const RESULT_HOLD: Duration = Duration::from_millis(500);
+
+/// How long a front-end notice (logging, key feedback) stays up
+pub const NOTICE_HOLD: Duration = Duration::from_millis(1000);
+
+/// Transient messages kept for review
+pub const MESSAGE_HISTORY: usize = 50;
+
+/// Messages shown at once while reviewing
+const REVIEW_LINES: usize = 5;
+
+/// Debug log written by Ctrl+F
+const DEBUG_LOG: &str = "hp41c_debug.log";
+
+/// Where the front end draws
+pub trait Screen {
+    /// Draw the whole screen for the calculator's current state
+    fn redraw(&mut self, calc: &HP41CCalculator) -> io::Result<()>;
+
+    /// Show a line below the display and keep it up for `hold`
+    fn message(&mut self, text: &str, hold: Duration) -> io::Result<()>;
+}
+
+/// Records what it is asked to show (tests, headless hosts)
+#[derive(Debug, Clone, Default)]
+pub struct MemoryScreen {
+    /// The calculator display at each redraw
+    pub frames: Vec<String>,
+    /// Messages with how long each was held
+    pub messages: Vec<(String, Duration)>,
+}
+
+impl MemoryScreen {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Screen for MemoryScreen {
+    fn redraw(&mut self, calc: &HP41CCalculator) -> io::Result<()> {
+        self.frames.push(calc.get_display());
+        Ok(())
+    }
+
+    fn message(&mut self, text: &str, hold: Duration) -> io::Result<()> {
+        self.messages.push((text.to_string(), hold));
+        Ok(())
+    }
+}
+
+/// Passes everything on to a screen, keeping the messages that are only
+/// held for a while
+struct RecordingScreen<'s> {
+    screen: &'s mut dyn Screen,
+    history: &'s mut VecDeque<String>,
+    /// More keys are queued, so messages aren't held
+    burst: bool,
+}
+
+impl Screen for RecordingScreen<'_> {
+    fn redraw(&mut self, calc: &HP41CCalculator) -> io::Result<()> {
+        self.screen.redraw(calc)
+    }
+
+    fn message(&mut self, text: &str, hold: Duration) -> io::Result<()> {
+        if !hold.is_zero() {
+            if self.history.len() == MESSAGE_HISTORY {
+                self.history.pop_front();
+            }
+            self.history.push_back(text.to_string());
+        }
+        self.screen.message(text, if self.burst { Duration::ZERO } else { hold })
+    }
+}
+
+/// The event loop, with optional display mirror and lockstep leader
+#[derive(Default)]
+pub struct App<'a> {
+    mirror: Option<Box<dyn DisplaySink + 'a>>,
+    leader: Option<&'a LockstepLeader>,
+    history: VecDeque<String>,
+}
+
+impl<'a> App<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send every frame to a second display as well (e.g. `MirrorServer`)
+    pub fn with_mirror(mut self, mirror: Box<dyn DisplaySink + 'a>) -> Self {
+        self.mirror = Some(mirror);
+        self
+    }
+
+    /// Admit lockstep followers while waiting for keys
+    pub fn with_leader(mut self, leader: &'a LockstepLeader) -> Self {
+        self.leader = Some(leader);
+        self
+    }
+
+    /// Messages shown so far, oldest first
+    pub fn message_history(&self) -> impl Iterator<Item = &str> {
+        self.history.iter().map(String::as_str)
+    }
+
+    /// Run until a quit key (q, Escape, Ctrl+C) or the end of the keys
+    pub fn run(
+        &mut self,
+        calc: &mut HP41CCalculator,
+        keys: &mut dyn InputSource,
+        screen: &mut dyn Screen,
+    ) -> io::Result<()> {
+        let mut history = std::mem::take(&mut self.history);
+        let result = self.run_loop(calc, keys, &mut RecordingScreen { screen, history: &mut history, burst: false });
+        self.history = history;
+        result
+    }
+
+    fn run_loop(
+        &mut self,
+        calc: &mut HP41CCalculator,
+        keys: &mut dyn InputSource,
+        screen: &mut RecordingScreen,
+    ) -> io::Result<()> {
+        calc.set_step_budget(Some(STEPS_PER_TICK));
+        'redraw: loop {
+            // A paste is typed through without drawing each key
+            screen.burst = keys.has_queued_keys();
+            if !screen.burst {
+                screen.redraw(calc)?;
+                if let Some(mirror) = self.mirror.as_mut() {
+                    calc.refresh_display(mirror.as_mut())?;
+                }
+            }
+
+            let ticking = calc.is_clock_shown() || calc.alarms().next().is_some();
+            let background = calc.is_watching_listing() || self.leader.is_some() || ticking;
+            if calc.is_program_running() || calc.is_catalog_running() || background {
+                loop {
+                    if let Some(leader) = self.leader {
+                        leader.admit(&calc.snapshot())?;
+                    }
+                    let interval = if calc.is_program_running() {
+                        Duration::ZERO
+                    } else if calc.is_catalog_running() {
+                        CATALOG_INTERVAL
+                    } else if ticking {
+                        CLOCK_INTERVAL
+                    } else {
+                        WATCH_INTERVAL
+                    };
+                    if keys.poll(interval)? {
+                        break;
+                    }
+                    if calc.is_program_running() {
+                        let result = calc.advance_program();
+                        if !calc.is_program_running() {
+                            show_result(screen, result)?;
+                            continue 'redraw;
+                        }
+                        continue;
+                    }
+                    if calc.advance_catalog() {
+                        continue 'redraw;
+                    }
+                    if let Some(result) = calc.check_listing_watch() {
+                        show_result(screen, result)?;
+                        continue 'redraw;
+                    }
+                    if let Some(result) = calc.check_alarms() {
+                        show_result(screen, result)?;
+                        continue 'redraw;
+                    }
+                    if calc.is_clock_shown() {
+                        continue 'redraw;
+                    }
+                }
+            }
+
+            let Some(key) = keys.next_key()? else { break };
+            if !self.handle_key(calc, key, keys, screen)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Act on one key; false for a quit key
+    fn handle_key(
+        &mut self,
+        calc: &mut HP41CCalculator,
+        key: Key,
+        keys: &mut dyn InputSource,
+        screen: &mut RecordingScreen,
+    ) -> io::Result<bool> {
+        match key {
+            // ALPHA mode types every character, q and L included
+            Key::Char(c) if calc.is_alpha_mode() => show_result(screen, calc.process_input(&c.to_string()))?,
+            Key::Ctrl('n') => show_result(screen, calc.process_input("alpha"))?,
+
+            Key::Ctrl('c') | Key::Char('q') | Key::Escape => return Ok(false),
+
+            // Logging control shortcuts
+            Key::Ctrl('l') | Key::Char('L') => notice(screen, calc.toggle_logging())?,
+            Key::Ctrl('a') => notice(screen, calc.configure_logger("all"))?,
+            Key::Ctrl('m') => notice(screen, calc.configure_logger("minimal"))?,
+            Key::Ctrl('o') => notice(screen, calc.configure_logger("off"))?,
+
+            // File logging controls
+            Key::Ctrl('f') => match calc.enable_file_logging(DEBUG_LOG) {
+                Ok(Some(msg)) => {
+                    screen.message(&msg, Duration::ZERO)?;
+                    let hint = format!("You can now run: tail -f {} (in another terminal)", DEBUG_LOG);
+                    screen.message(&hint, 2 * NOTICE_HOLD)?;
+                }
+                Ok(None) => screen.message("File logging enabled", NOTICE_HOLD)?,
+                Err(e) => screen.message(&format!("ERROR: {}", e), NOTICE_HOLD)?,
+            },
+            Key::Ctrl('d') => match calc.disable_file_logging() {
+                Ok(Some(msg)) => screen.message(&msg, NOTICE_HOLD)?,
+                Ok(None) => screen.message("File logging disabled", NOTICE_HOLD)?,
+                Err(e) => screen.message(&format!("ERROR: {}", e), NOTICE_HOLD)?,
+            },
+
+            // Catalog browsing
+            Key::Ctrl('g') => browse_catalog(calc, 1, keys, screen)?,
+            Key::Ctrl('k') => browse_catalog(calc, 6, keys, screen)?,
+            Key::Ctrl('e') => browse_catalog(calc, 5, keys, screen)?,
+
+            // Clipboard
+            Key::Ctrl('y') => show_result(screen, calc.copy_to_clipboard(CopyTarget::X))?,
+            Key::Ctrl('w') => show_result(screen, calc.copy_to_clipboard(CopyTarget::Stack))?,
+
+            // Audio/haptic key feedback
+            Key::Ctrl('b') => notice(screen, calc.cycle_key_feedback())?,
+
+            // Look back at messages that have gone
+            Key::Ctrl('r') => review_messages(screen.history, keys, screen.screen)?,
+
+            // Read the paper tape, newest line first
+            Key::Ctrl('p') => match calc.paper_tape().map(|tape| tape.lines()) {
+                Some(lines) if !lines.is_empty() => review_messages(&lines.into(), keys, screen.screen)?,
+                Some(_) => screen.message("Paper tape empty", NOTICE_HOLD)?,
+                None => screen.message("Printing to the attached printer", NOTICE_HOLD)?,
+            },
+
+            // Everything else is a calculator keystroke
+            other => {
+                if let Some(input) = other.to_input() {
+                    show_result(screen, calc.process_input(&input))?;
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Show a front-end notice, if there is one
+fn notice(screen: &mut dyn Screen, message: Option<String>) -> io::Result<()> {
+    match message {
+        Some(msg) => screen.message(&msg, NOTICE_HOLD),
+        None => Ok(()),
+    }
+}
+
+/// Show the outcome of a calculator keystroke
+fn show_result(screen: &mut dyn Screen, result: Result<Option<String>, String>) -> io::Result<()> {
+    match result {
+        Ok(Some(msg)) => screen.message(&msg, RESULT_HOLD),
+        Err(msg) => screen.message(&format!("ERROR: {}", msg), RESULT_HOLD),
+        Ok(None) => Ok(()),
+    }
+}
+
+/// Page back through past messages, newest first: Up/Down scroll, any
+/// other key closes the review
+fn review_messages(history: &VecDeque<String>, keys: &mut dyn InputSource, screen: &mut dyn Screen) -> io::Result<()> {
+    if history.is_empty() {
+        return screen.message("No messages", NOTICE_HOLD);
+    }
+    let mut newest = 0;
+    loop {
+        let page: Vec<&str> = history.iter().rev().skip(newest).take(REVIEW_LINES).map(String::as_str).collect();
+        screen.message(&page.join("\n"), Duration::ZERO)?;
+        match keys.next_key()? {
+            Some(Key::Up) => newest = newest.saturating_sub(1),
+            Some(Key::Down) if newest + REVIEW_LINES < history.len() => newest += 1,
+            Some(Key::Down) => {}
+            _ => break,
+        }
+    }
+    Ok(())
+}
+
+/// Step through a catalog: Enter/space next, Backspace previous, anything else exits
+fn browse_catalog(
+    calc: &HP41CCalculator,
+    number: u8,
+    keys: &mut dyn InputSource,
+    screen: &mut dyn Screen,
+) -> io::Result<()> {
+    let mut catalog = match calc.catalog(number) {
+        Ok(catalog) => catalog,
+        Err(e) => return screen.message(&e, NOTICE_HOLD),
+    };
+    if catalog.is_empty() {
+        return screen.message(&catalog.to_string(), NOTICE_HOLD);
+    }
+
+    loop {
+        screen.message(&catalog.to_string(), Duration::ZERO)?;
+        match keys.next_key()? {
+            Some(Key::Enter) | Some(Key::Char(' ')) => {
+                if !catalog.advance() {
+                    break;
+                }
+            }
+            Some(Key::Backspace) => {
+                catalog.back();
+            }
+            _ => break,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard::ReplaySource;
+
+    #[test]
+    fn test_event_loop() {
+        let mut calc = HP41CCalculator::new();
+        let mut screen = MemoryScreen::new();
+        let mut keys = ReplaySource::from_script("2 enter 3 + ^o q 4");
+        App::new().run(&mut calc, &mut keys, &mut screen).unwrap();
+        // q quit before the 4
+        assert_eq!(keys.remaining(), 1);
+        assert_eq!(calc.test_get_stack()[0], 5.0);
+        assert_eq!(screen.frames.len(), 6);
+        assert_eq!(screen.messages.len(), 1);
+        assert_eq!(screen.messages[0].1, NOTICE_HOLD);
+
+        // Errors show for the result hold; the end of the keys ends the loop
+        let mut keys = ReplaySource::from_script("0 /");
+        App::new().run(&mut calc, &mut keys, &mut screen).unwrap();
+        let (message, hold) = screen.messages.last().unwrap();
+        assert!(message.starts_with("ERROR: "));
+        assert_eq!(*hold, RESULT_HOLD);
+    }
+
+    #[test]
+    fn test_alpha_mode_keys() {
+        let mut calc = HP41CCalculator::new();
+        let mut screen = MemoryScreen::new();
+        let mut keys = ReplaySource::from_script("^n quiL ^n q");
+        App::new().run(&mut calc, &mut keys, &mut screen).unwrap();
+        assert_eq!(calc.alpha(), "quiL");
+        assert!(!calc.is_alpha_mode());
+        assert!(screen.frames[2].contains("Tab append"));
+    }
+
+    #[test]
+    fn test_paste_skips_redraws() {
+        let mut calc = HP41CCalculator::new();
+        let mut screen = MemoryScreen::new();
+        let mut keys = ReplaySource::from_paste("2\n3+0/4");
+        App::new().run(&mut calc, &mut keys, &mut screen).unwrap();
+        assert_eq!(calc.test_get_stack()[0], 4.0);
+        // Drawn once, after the last key
+        assert_eq!(screen.frames.len(), 1);
+        assert_eq!(screen.messages, [("ERROR: Stack error: Division by zero".to_string(), Duration::ZERO)]);
+    }
+
+    #[test]
+    fn test_message_review() {
+        let mut calc = HP41CCalculator::new();
+        let mut screen = MemoryScreen::new();
+        let mut app = App::new();
+        let mut keys = ReplaySource::from_script("^r 0 / ^o 0 / 0 / 0 / 0 / 0 / ^r down down down x");
+        app.run(&mut calc, &mut keys, &mut screen).unwrap();
+        assert_eq!(screen.messages[0].0, "No messages");
+        assert_eq!(app.message_history().count(), 7);
+        assert!(app.message_history().next().unwrap().starts_with("ERROR: "));
+
+        // Newest first, five at a time, stopping at the oldest
+        let reviews: Vec<&str> = screen.messages.iter()
+            .filter(|(_, hold)| hold.is_zero())
+            .map(|(text, _)| text.as_str())
+            .collect();
+        assert_eq!(reviews.len(), 4);
+        assert_eq!(reviews[0].lines().count(), 5);
+        assert_eq!(reviews[2], reviews[3]);
+        assert!(reviews[3].lines().last().unwrap().starts_with("ERROR: "));
+    }
+}
@@ -0,0 +1,163 @@
+//! ASSERT, a non-authentic debugging instruction
+//!
+//! `ASSERT X>0`, `ASSERT X<=Y` or `ASSERT R05=2.5` checks a condition while
+//! a program runs and halts it with a message naming the values when the
+//! condition fails. Assertions only run in debug mode; otherwise the step
+//! does nothing, so instrumented programs can stay instrumented.
+//!
+//! The left side is `X`, `Y` or a register `Rnn`; the right side is one of
+//! those or a number. Comparisons are `=`, `!=` (or `≠`), `<`, `<=`, `>`
+//! and `>=`. Spaces are ignored, so `ASSERT X > 0` works in listings.
+
+use std::fmt;
+use crate::error::{CalculatorError, CommandError, ProgrammingError, StorageError};
+use crate::stack::Stack;
+
+/// Comparison operators, longest spelling first for parsing
+const OPERATORS: [(&str, Comparison); 7] = [
+    ("<=", Comparison::LessOrEqual),
+    (">=", Comparison::GreaterOrEqual),
+    ("!=", Comparison::NotEqual),
+    ("≠", Comparison::NotEqual),
+    ("=", Comparison::Equal),
+    ("<", Comparison::Less),
+    (">", Comparison::Greater),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn holds(self, left: f64, right: f64) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    X,
+    Y,
+    Register(usize),
+    Number(f64),
+}
+
+impl Operand {
+    fn parse(text: &str) -> Option<Self> {
+        match text {
+            "X" => Some(Operand::X),
+            "Y" => Some(Operand::Y),
+            _ => match text.strip_prefix('R') {
+                Some(register) => register.parse().ok().map(Operand::Register),
+                None => text.parse().ok().map(Operand::Number),
+            },
+        }
+    }
+
+    fn value(&self, stack: &Stack, registers: &[f64]) -> Result<f64, CalculatorError> {
+        match *self {
+            Operand::X => Ok(stack.x()),
+            Operand::Y => Ok(stack.y()),
+            Operand::Register(r) => registers.get(r).copied().ok_or_else(|| StorageError::Nonexistent(r).into()),
+            Operand::Number(n) => Ok(n),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::X => write!(f, "X"),
+            Operand::Y => write!(f, "Y"),
+            Operand::Register(r) => write!(f, "R{:02}", r),
+            Operand::Number(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// A parsed ASSERT condition
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assertion {
+    text: String,
+    left: Operand,
+    comparison: Comparison,
+    right: Operand,
+}
+
+impl Assertion {
+    /// Parse the arguments of an ASSERT step
+    pub fn parse(args: &[String]) -> Result<Self, CalculatorError> {
+        let text: String = args.concat().split_whitespace().collect::<String>().to_uppercase();
+        let invalid = || CommandError::InvalidArgument { command: "ASSERT".to_string(), argument: text.clone() };
+        if text.is_empty() {
+            return Err(CommandError::MissingArgument("ASSERT".to_string()).into());
+        }
+        let (position, spelling, comparison) = OPERATORS.iter()
+            .filter_map(|&(spelling, comparison)| text.find(spelling).map(|at| (at, spelling, comparison)))
+            .min_by_key(|&(at, spelling, _)| (at, std::cmp::Reverse(spelling.len())))
+            .ok_or_else(invalid)?;
+        let left = Operand::parse(&text[..position]).filter(|left| !matches!(left, Operand::Number(_)));
+        let right = Operand::parse(&text[position + spelling.len()..]);
+        match (left, right) {
+            (Some(left), Some(right)) => Ok(Assertion { text, left, comparison, right }),
+            _ => Err(invalid().into()),
+        }
+    }
+
+    /// Check the condition against the machine
+    ///
+    /// A failed assertion is an error whose message shows the condition
+    /// and the values compared.
+    pub fn check(&self, stack: &Stack, registers: &[f64]) -> Result<(), CalculatorError> {
+        let left = self.left.value(stack, registers)?;
+        let right = self.right.value(stack, registers)?;
+        if self.comparison.holds(left, right) {
+            return Ok(());
+        }
+        let mut values = format!("{}={}", self.left, left);
+        if !matches!(self.right, Operand::Number(_)) {
+            values.push_str(&format!(", {}={}", self.right, right));
+        }
+        Err(ProgrammingError::AssertionFailed(format!("{} ({})", self.text, values)).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(text: &str) -> Vec<String> {
+        text.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_assertions() {
+        let mut stack = Stack::new();
+        stack.set_x(3.0);
+        let registers = [0.0, 2.5];
+
+        assert!(Assertion::parse(&args("x > 0")).unwrap().check(&stack, &registers).is_ok());
+        assert!(Assertion::parse(&args("R01<=X")).unwrap().check(&stack, &registers).is_ok());
+        assert_eq!(
+            Assertion::parse(&args("X<R01")).unwrap().check(&stack, &registers),
+            Err(ProgrammingError::AssertionFailed("X<R01 (X=3, R01=2.5)".to_string()).into()),
+        );
+        assert!(Assertion::parse(&args("R07=0")).unwrap().check(&stack, &registers).is_err());
+        for bad in ["", "X", "3>X", "X>>0", "Q=1"] {
+            assert!(Assertion::parse(&args(bad)).is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+//! Audio and haptic output for the HP-41C emulator
+//!
+//! Sound and vibration go to an `AudioSink` so the terminal can ring its
+//! bell, a desktop host can play real tones, a handheld can pulse a motor,
+//! and tests can assert on what was emitted.
+//!
+//! Programs sound with `TONE n` and `BEEP` while flag 26 is set.
+//!
+//! `KeyFeedback` configures per-keystroke feedback: every key produces a
+//! short click, tone or haptic tick, and errors and command completion get
+//! distinct dit/dah patterns so the calculator can be used eyes-free.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// A single sound or vibration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioEvent {
+    /// Very short mechanical click
+    Click,
+    /// One of the ten HP-41 tones (0 = lowest, 9 = highest)
+    Tone { tone: u8, millis: u32 },
+    /// Vibration pulse
+    Haptic { millis: u32 },
+    /// Silence between the elements of a pattern
+    Pause { millis: u32 },
+}
+
+/// Approximate frequency in Hz of HP-41 `TONE n`
+pub fn tone_frequency(tone: u8) -> u32 {
+    const FREQUENCIES: [u32; 10] = [80, 105, 160, 195, 245, 290, 340, 440, 580, 833];
+    FREQUENCIES[usize::from(tone.min(9))]
+}
+
+/// How long `TONE n` sounds, in milliseconds
+pub const TONE_MS: u32 = 250;
+
+/// `BEEP`: four short alternating high tones
+pub fn beep() -> Vec<AudioEvent> {
+    [7, 9, 7, 9].into_iter().map(|tone| AudioEvent::Tone { tone, millis: TONE_MS / 2 }).collect()
+}
+
+/// A device that plays audio events
+pub trait AudioSink: fmt::Debug + Send {
+    /// Play one event (sinks may block for its duration or queue it)
+    fn play(&mut self, event: AudioEvent);
+}
+
+/// Rings the terminal bell for tones; clicks and haptics are silent
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BellSink;
+
+impl AudioSink for BellSink {
+    fn play(&mut self, event: AudioEvent) {
+        if let AudioEvent::Tone { .. } = event {
+            use std::io::Write;
+            print!("\x07");
+            let _ = std::io::stdout().flush();
+        }
+    }
+}
+
+/// Records events for inspection (tests, headless hosts)
+///
+/// Clones share the same event list, so keep one handle and give the other
+/// to the calculator.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryAudioSink {
+    events: Arc<Mutex<Vec<AudioEvent>>>,
+}
+
+impl MemoryAudioSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All events played so far
+    pub fn events(&self) -> Vec<AudioEvent> {
+        self.events.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Forget recorded events
+    pub fn clear(&self) {
+        self.events.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+impl AudioSink for MemoryAudioSink {
+    fn play(&mut self, event: AudioEvent) {
+        self.events.lock().unwrap_or_else(|e| e.into_inner()).push(event);
+    }
+}
+
+/// How feedback is rendered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FeedbackStyle {
+    /// No feedback
+    #[default]
+    Off,
+    /// Clicks for keys, tones for errors and completion
+    Click,
+    /// Tones for everything
+    Tone,
+    /// Vibration pulses for everything
+    Haptic,
+}
+
+impl FeedbackStyle {
+    /// The next style in the Off → Click → Tone → Haptic cycle
+    pub fn next(self) -> Self {
+        match self {
+            FeedbackStyle::Off => FeedbackStyle::Click,
+            FeedbackStyle::Click => FeedbackStyle::Tone,
+            FeedbackStyle::Tone => FeedbackStyle::Haptic,
+            FeedbackStyle::Haptic => FeedbackStyle::Off,
+        }
+    }
+}
+
+impl fmt::Display for FeedbackStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FeedbackStyle::Off => "OFF",
+            FeedbackStyle::Click => "CLICK",
+            FeedbackStyle::Tone => "TONE",
+            FeedbackStyle::Haptic => "HAPTIC",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Things the calculator gives feedback about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedbackCue {
+    /// Any keystroke
+    Key,
+    /// A keystroke produced an error
+    Error,
+    /// A command finished executing
+    Complete,
+}
+
+/// Per-keystroke feedback configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KeyFeedback {
+    pub style: FeedbackStyle,
+    /// Give feedback on every keystroke
+    pub keys: bool,
+    /// Signal errors
+    pub errors: bool,
+    /// Signal command completion
+    pub completion: bool,
+}
+
+/// Length of a short pattern element (dit) in milliseconds
+const DIT_MS: u32 = 60;
+/// Length of a long pattern element (dah) in milliseconds
+const DAH_MS: u32 = 3 * DIT_MS;
+
+impl KeyFeedback {
+    /// Feedback in the given style for all cues
+    pub fn new(style: FeedbackStyle) -> Self {
+        KeyFeedback { style, keys: true, errors: true, completion: true }
+    }
+
+    /// No feedback at all
+    pub fn off() -> Self {
+        Self::default()
+    }
+
+    /// Whether any feedback is produced
+    pub fn is_enabled(&self) -> bool {
+        self.style != FeedbackStyle::Off && (self.keys || self.errors || self.completion)
+    }
+
+    /// The events to play for a cue (empty when the cue is disabled)
+    ///
+    /// Errors sound as two long low elements (dah-dah) and completion as a
+    /// single short high one (dit), in every style.
+    pub fn pattern(&self, cue: FeedbackCue) -> Vec<AudioEvent> {
+        let wanted = match cue {
+            FeedbackCue::Key => self.keys,
+            FeedbackCue::Error => self.errors,
+            FeedbackCue::Complete => self.completion,
+        };
+        if !wanted {
+            return Vec::new();
+        }
+
+        let element = |tone: u8, millis: u32| match self.style {
+            FeedbackStyle::Haptic => AudioEvent::Haptic { millis },
+            _ => AudioEvent::Tone { tone, millis },
+        };
+
+        match (self.style, cue) {
+            (FeedbackStyle::Off, _) => Vec::new(),
+            (FeedbackStyle::Click, FeedbackCue::Key) => vec![AudioEvent::Click],
+            (FeedbackStyle::Haptic, FeedbackCue::Key) => vec![AudioEvent::Haptic { millis: 10 }],
+            (_, FeedbackCue::Key) => vec![AudioEvent::Tone { tone: 6, millis: 15 }],
+            (_, FeedbackCue::Error) => vec![
+                element(0, DAH_MS),
+                AudioEvent::Pause { millis: DIT_MS },
+                element(0, DAH_MS),
+            ],
+            (_, FeedbackCue::Complete) => vec![element(9, DIT_MS)],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HP41CCalculator;
+
+    #[test]
+    fn test_patterns_are_distinct() {
+        let feedback = KeyFeedback::new(FeedbackStyle::Click);
+        let key = feedback.pattern(FeedbackCue::Key);
+        let error = feedback.pattern(FeedbackCue::Error);
+        let complete = feedback.pattern(FeedbackCue::Complete);
+
+        assert_eq!(key, vec![AudioEvent::Click]);
+        assert_eq!(error.len(), 3);
+        assert_ne!(error, complete);
+        assert!(KeyFeedback::off().pattern(FeedbackCue::Error).is_empty());
+    }
+
+    #[test]
+    fn test_haptic_style_uses_vibration_only() {
+        let feedback = KeyFeedback::new(FeedbackStyle::Haptic);
+        for cue in [FeedbackCue::Key, FeedbackCue::Error, FeedbackCue::Complete] {
+            assert!(feedback.pattern(cue).iter().all(|e| matches!(e, AudioEvent::Haptic { .. } | AudioEvent::Pause { .. })));
+        }
+    }
+
+    #[test]
+    fn test_calculator_emits_feedback() {
+        let sink = MemoryAudioSink::new();
+        let mut calc = HP41CCalculator::new().with_audio(Box::new(sink.clone()));
+        calc.set_key_feedback(KeyFeedback::new(FeedbackStyle::Click));
+
+        calc.process_input("5").unwrap();
+        assert_eq!(sink.events(), vec![AudioEvent::Click]);
+
+        sink.clear();
+        calc.process_input("enter").unwrap();
+        assert_eq!(sink.events(), vec![AudioEvent::Click, AudioEvent::Tone { tone: 9, millis: DIT_MS }]);
+
+        sink.clear();
+        assert!(calc.process_input("z").is_err());
+        let events = sink.events();
+        assert_eq!(events[0], AudioEvent::Click);
+        assert_eq!(events[1..], KeyFeedback::new(FeedbackStyle::Click).pattern(FeedbackCue::Error)[..]);
+    }
+
+    #[test]
+    fn test_tone_frequencies() {
+        assert!(tone_frequency(0) < tone_frequency(9));
+        assert_eq!(tone_frequency(42), tone_frequency(9));
+    }
+}
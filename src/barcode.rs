@@ -0,0 +1,169 @@
+//! Program bar code for the HP 82153A wand
+//!
+//! The wand reads a program from rows of bars, as printed in the
+//! application books: each row carries a checksum, the row's place in the
+//! sequence and up to 13 bytes of the program in `.raw` byte code. An
+//! instruction may start on one row and end on the next; the third header
+//! byte counts its bytes on either side so a row can be scanned again
+//! after a misread.
+//!
+//! ```text
+//! byte 1   running checksum: this row and all rows before it, summed
+//!          with end-around carry
+//! byte 2   type 1 (program) in the high nibble, row number mod 16
+//! byte 3   bytes finishing an instruction from the row before (high
+//!          nibble), bytes starting one that goes on in the next (low)
+//! 4-16     program bytes
+//! ```
+//!
+//! A bar is a 1 bit when wide, a 0 bit when narrow; a row starts with two
+//! narrow bars and ends with a wide one and a narrow one. `to_svg` draws
+//! the rows for printing and `to_text` lists their bytes.
+
+use crate::import::encode_instructions;
+use crate::programming::ProgramInstruction;
+
+/// Program bytes a row carries at most
+pub const ROW_BYTES: usize = 13;
+
+/// Bar code type of program rows
+const PROGRAM_TYPE: u8 = 0x10;
+
+/// Heights in SVG units, in which a narrow bar and a space are one wide
+/// and a wide bar two
+const ROW_HEIGHT: usize = 24;
+const LABEL_HEIGHT: usize = 14;
+
+/// One row of bar code
+#[derive(Debug, Clone, PartialEq)]
+pub struct BarcodeRow {
+    /// Row number, from 1
+    pub number: usize,
+    /// Program steps the row holds bytes of, first and last
+    pub steps: (i32, i32),
+    /// The header and program bytes
+    pub bytes: Vec<u8>,
+}
+
+impl BarcodeRow {
+    /// Bars from left to right, `true` for wide: start, data, stop
+    pub fn bars(&self) -> Vec<bool> {
+        let mut bars = vec![false, false];
+        for byte in &self.bytes {
+            bars.extend((0..8).rev().map(|bit| byte & (1 << bit) != 0));
+        }
+        bars.extend([true, false]);
+        bars
+    }
+
+    /// `ROW 1 (1 : 4)`, as the books label rows
+    pub fn label(&self) -> String {
+        format!("ROW {} ({} : {})", self.number, self.steps.0, self.steps.1)
+    }
+}
+
+/// The bar code rows of a program
+pub fn program_barcode(steps: &[ProgramInstruction]) -> Result<Vec<BarcodeRow>, String> {
+    let instructions = encode_instructions(steps)?;
+    let mut rows = Vec::new();
+    let mut checksum = 0u8;
+    // Bytes of each instruction, with where it starts and ends
+    let mut bytes = Vec::new();
+    for (step, instruction) in &instructions {
+        let last = instruction.len() - 1;
+        bytes.extend(instruction.iter().enumerate().map(|(at, &byte)| (*step, byte, at == 0, at == last)));
+    }
+    for (index, chunk) in bytes.chunks(ROW_BYTES).enumerate() {
+        let leading = chunk.iter().take_while(|&&(_, _, first, _)| !first).count();
+        let trailing = match chunk.iter().rposition(|&(_, _, first, _)| first) {
+            Some(start) if !chunk[chunk.len() - 1].3 => chunk.len() - start,
+            _ => 0,
+        };
+        let mut row = vec![0, PROGRAM_TYPE | (index % 16) as u8, (leading as u8) << 4 | trailing as u8];
+        row.extend(chunk.iter().map(|&(_, byte, _, _)| byte));
+        checksum = row[1..].iter().fold(checksum, |sum, &byte| add_with_carry(sum, byte));
+        row[0] = checksum;
+        rows.push(BarcodeRow {
+            number: index + 1,
+            steps: (chunk[0].0, chunk[chunk.len() - 1].0),
+            bytes: row,
+        });
+    }
+    Ok(rows)
+}
+
+/// Eight-bit sum with the carry added back in
+fn add_with_carry(sum: u8, byte: u8) -> u8 {
+    let (sum, carry) = sum.overflowing_add(byte);
+    sum + u8::from(carry)
+}
+
+/// The rows' bytes in hex, one row per line under its label
+pub fn to_text(rows: &[BarcodeRow]) -> String {
+    rows.iter().map(|row| {
+        let bytes: Vec<String> = row.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        format!("{}\n{}\n", row.label(), bytes.join(" "))
+    }).collect()
+}
+
+/// The rows drawn as an SVG image, each above its label
+pub fn to_svg(rows: &[BarcodeRow]) -> String {
+    let widths: Vec<usize> = rows.iter()
+        .map(|row| row.bars().iter().map(|&wide| if wide { 3 } else { 2 }).sum())
+        .collect();
+    let width = widths.iter().copied().max().unwrap_or_default();
+    let height = rows.len() * (ROW_HEIGHT + LABEL_HEIGHT);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {} {}\" width=\"{}\" height=\"{}\">\n",
+        width, height, width * 2, height * 2
+    );
+    for (index, row) in rows.iter().enumerate() {
+        let top = index * (ROW_HEIGHT + LABEL_HEIGHT);
+        svg.push_str(&format!(
+            "<text x=\"0\" y=\"{}\" font-family=\"monospace\" font-size=\"8\">{}</text>\n",
+            top + LABEL_HEIGHT - 4, row.label()
+        ));
+        let mut x = 0;
+        for wide in row.bars() {
+            let bar = if wide { 2 } else { 1 };
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/>\n",
+                x, top + LABEL_HEIGHT, bar, ROW_HEIGHT
+            ));
+            x += bar + 1;
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listing::parse_listing;
+
+    #[test]
+    fn test_program_barcode() {
+        // LBL "AREA" (8 bytes), X2, PI, STO 20 (2 bytes), then LBL "BB" across the rows
+        let steps = parse_listing("LBL \"AREA\"\nX2\nPI\nSTO 20\nLBL \"BB\"\nRTN").unwrap();
+        let rows = program_barcode(&steps).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].bytes[1..], [
+            0x10, 0x01, 0xC0, 0x00, 0xF5, 0x00, b'A', b'R', b'E', b'A', 0x51, 0x72, 0x91, 0x14, 0xC0,
+        ]);
+        assert_eq!(rows[1].bytes[1..], [0x11, 0x50, 0x00, 0xF3, 0x00, b'B', b'B', 0x85]);
+        assert_eq!((rows[0].steps, rows[1].steps), ((1, 5), (5, 6)));
+
+        // Checksums run on from row to row
+        let sum = |start: u8, bytes: &[u8]| bytes.iter().fold(start, |sum, &byte| add_with_carry(sum, byte));
+        assert_eq!(rows[0].bytes[0], sum(0, &rows[0].bytes[1..]));
+        assert_eq!(rows[1].bytes[0], sum(rows[0].bytes[0], &rows[1].bytes[1..]));
+        assert_eq!(add_with_carry(0xFF, 0x02), 0x02);
+
+        let bars = rows[1].bars();
+        assert_eq!(bars.len(), 2 + 8 * rows[1].bytes.len() + 2);
+        assert_eq!(bars[10..18], [false, false, false, true, false, false, false, true]);
+        assert!(to_text(&rows).starts_with("ROW 1 (1 : 5)\n"));
+        assert_eq!(to_svg(&rows).matches("<rect").count(), rows.iter().map(|r| r.bars().len()).sum::<usize>());
+    }
+}
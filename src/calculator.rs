@@ -1,21 +1,103 @@
-/// HP-41C Calculator Core - Clean and Focused with Comprehensive Logging
-/// 
-/// This module contains the main HP41CCalculator that coordinates between
-/// all calculator subsystems. The command system has been moved to separate
-/// modules for better organization. Now includes integrated logging for debugging.
+//! HP-41C Calculator Core - Clean and Focused with Comprehensive Logging
+//! 
+//! This module contains the main HP41CCalculator that coordinates between
+//! all calculator subsystems. The command system has been moved to separate
+//! modules for better organization. Now includes integrated logging for debugging.
 
-use crate::programming::ProgrammingMode;
-use crate::display::DisplayFormatter;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+use crate::programming::{is_programmable, unsupported_operand, MemorySpace, ProgramInstruction, ProgrammingMode, RunState, TEXT_LINE_LENGTH};
+use crate::display::{DisplayFormatter, NumberFormatter};
 #[cfg(test)]
 use crate::display::DisplayMode;
-use crate::stack::Stack;
+use crate::stack::{Stack, StackDepth, CLASSIC_DEPTH};
 use crate::input::InputState;
-use crate::execution::execute_command;
+use crate::execution::{execute_command, execute_flag_command, execute_statistics_command, register_arithmetic};
+use crate::statistics::{self, DEFAULT_SIGMA_REG};
+use crate::flags::{Flags, FLAG_AUDIO, FLAG_AUTO_EXECUTE, FLAG_DMY, FLAG_TRACE, FLAG_USER};
 use crate::parser::{CommandParser, ParseResult};
+use crate::registry::command_key;
 use crate::logger::Logger;  // NEW: Import logger
+use crate::storage::{default_storage, SharedStorage};
+use crate::clock::{default_clock, SharedClock};
+use crate::lcd::{Annunciators, DisplaySink, LcdFrame, LCD_WIDTH};
+use crate::audio::{self, AudioEvent, AudioSink, FeedbackCue, KeyFeedback, TONE_MS};
+use crate::printer::{self, PaperTape, Printer, PRINT_WIDTH};
+use crate::i18n::{Locale, MessageCatalog};
+use crate::analysis::{is_global_label, label_name, lint, quoted_label, CrossReference, LintIssue};
+use crate::assertion::Assertion;
+use crate::watchpoint::{WatchHit, Watchpoint};
+use crate::games;
+use crate::calendar::{execute_calendar_command, Date};
+use crate::time_module::{self, TimeModule};
+use crate::random::Rng;
+use crate::alpha::{self, AlphaEntry, AlphaRegister, KEY_MAP};
+use crate::guard::{RegisterGuard, RegisterProtection};
+use crate::config::Config;
+use crate::confirm::{ConfirmCategory, Confirmations};
+use crate::container::{self, ContainerOptions};
+use crate::statedir;
+use crate::timing;
+use crate::xmem;
+use crate::import;
+use crate::focal;
+use crate::barcode::{self, BarcodeRow};
+use crate::listing::{parse_listing, parse_listing_info, ListingWatcher};
+use crate::validate::{self, Diagnostic};
+use crate::metadata::ProgramInfo;
+use crate::menu::{self, MenuAction, MenuItem, SoftMenu};
+use crate::catalog::{Alarm, Catalog, CatalogRun, KeyAssignment, KeyAssignments};
+use crate::model::{Model, Module};
+use crate::usage::UsageStats;
+use crate::clipboard::{format_full_precision, ClipboardSink, CopyTarget};
+use crate::error::{CalculatorError, CommandError, ProgrammingError, StorageError};
+use crate::state::{DisplayState, ExecutionState, MachineState, StateFormat, STATE_FORMAT_VERSION};
+
+/// How long PSE pauses a running program unless configured otherwise
+pub const DEFAULT_PAUSE: Duration = Duration::from_secs(1);
+
+/// Steps one run may take unless configured otherwise, hours of work for
+/// a real HP-41 and well under a second here
+pub const DEFAULT_STEP_LIMIT: usize = 10_000_000;
+
+/// What the top two key rows do outside USER mode, by key code; shifted
+/// codes are negative
+const TOP_KEY_FUNCTIONS: [(i32, &str); 15] = [
+    (11, "σ+"), (12, "inv"), (13, "sqrt"), (14, "log"), (15, "ln"),
+    (-11, "σ-"), (-12, "^"), (-13, "x2"), (-14, "10x"), (-15, "exp"),
+    (21, "swap"), (22, "rdn"), (23, "sin"), (24, "cos"), (25, "tan"),
+];
+
+/// Key code of a top-row key: F1-F5 are the first row (11-15), F6-F10
+/// the second (21-25) and `shift-f1`-`shift-f5` the shifted first row
+fn top_row_keycode(key: &str) -> Option<i32> {
+    let (shifted, key) = key.strip_prefix("shift-").map_or((false, key), |key| (true, key));
+    let number: i32 = key.strip_prefix('f')?.parse().ok()?;
+    match number {
+        1..=5 => Some(if shifted { -10 - number } else { 10 + number }),
+        6..=10 if !shifted => Some(15 + number),
+        _ => None,
+    }
+}
+
+/// The letter label under a top-row key: A-E, F-J, and a-e shifted
+fn top_key_label(keycode: i32) -> String {
+    let column = (keycode.abs() % 10 - 1) as u8;
+    match keycode {
+        11..=15 => char::from(b'A' + column),
+        21..=25 => char::from(b'F' + column),
+        _ => char::from(b'a' + column),
+    }.to_string()
+}
+
+/// A display device the calculator refreshes itself, while a program runs
+struct AttachedDisplay(Box<dyn DisplaySink>);
 
-/// Maximum number of storage registers
-const NUM_STORAGE_REGISTERS: usize = 100;
+impl std::fmt::Debug for AttachedDisplay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("AttachedDisplay")
+    }
+}
 
 /// HP-41C Calculator State with Integrated Logging
 /// 
@@ -42,265 +124,2655 @@ pub struct HP41CCalculator {
     // Command processing
     command_parser: CommandParser,
     
-    // Storage
-    storage_registers: [f64; NUM_STORAGE_REGISTERS],
+    // Storage registers, sized by the model's default SIZE
+    storage_registers: Vec<f64>,
+    
+    // User and system flags 00-55
+    flags: Flags,
+    
+    // First of the six statistics registers (ΣREG)
+    sigma_reg: usize,
+    
+    // Seeded random numbers for the Games module
+    rng: Rng,
+    
+    // ALPHA register, and the text being typed in ALPHA mode
+    alpha: AlphaRegister,
+    alpha_mode: bool,
+    alpha_entry: Option<AlphaEntry>,
+    
+    // VIEW/AVIEW text shown in place of X until the next key
+    overlay: Option<String>,
+    
+    // Machine model and modules plugged in on top of its built-in ones
+    model: Model,
+    plugged_modules: BTreeSet<Module>,
     
     // UI state
     show_flags: bool,
     
     // NEW: Integrated logger
     logger: Logger,
+    
+    // File access provider shared by all storage-backed features
+    storage: SharedStorage,
+    
+    // Encoding, compression and encryption of saved state files
+    container: ContainerOptions,
+    state_format: StateFormat,
+    
+    // Listing file reloaded into program memory when it changes
+    listing_watch: Option<ListingWatcher>,
+    
+    // Time source for pauses and timing features
+    clock: SharedClock,
+    
+    // Display shown during PSE, and the length of the pause
+    display: Option<AttachedDisplay>,
+    pause: Duration,
+    
+    // Sound/haptic output and per-keystroke feedback settings
+    audio: Option<Box<dyn AudioSink>>,
+    key_feedback: KeyFeedback,
+    
+    // Where copied results go
+    clipboard: Option<Box<dyn ClipboardSink>>,
+    
+    // Printer peripheral, and the in-memory tape it is until another is attached
+    printer: Box<dyn Printer>,
+    paper_tape: Option<PaperTape>,
+    
+    // Localized help text and error messages
+    messages: MessageCatalog,
+    
+    // The last command failure, for callers that branch on error codes
+    last_error: Option<CalculatorError>,
+    
+    // Destructive commands that need a second keypress
+    confirmations: Confirmations,
+    
+    // Undoes program writes to protected registers (optional)
+    register_guard: Option<RegisterGuard>,
+    
+    // Debug mode: ASSERT steps are checked instead of skipped
+    debug_mode: bool,
+    
+    // Read-only registers (PROTECT)
+    protection: RegisterProtection,
+    
+    // USER mode key assignments and pending alarms
+    key_assignments: KeyAssignments,
+    alarms: Vec<Alarm>,
+    
+    // Time module clock
+    time_module: TimeModule,
+    
+    // Titles, descriptions and authors of programs, by global label
+    program_info: BTreeMap<String, ProgramInfo>,
+    
+    // Keystroke statistics for STATS, and when the current command's first key was pressed
+    usage: UsageStats,
+    entry_started: Option<Duration>,
+    
+    // Clock reading at the last TIC
+    tic: Option<Duration>,
+    
+    // What the program steps run so far would take on a real HP-41
+    machine_time: Duration,
+    
+    // Steps a run takes before handing back to the front end; None runs to the end
+    step_budget: Option<usize>,
+    
+    // Runaway protection: steps and time one run may take, and what it has taken
+    step_limit: Option<usize>,
+    time_limit: Option<Duration>,
+    run_steps: usize,
+    run_started: Duration,
+    
+    // WATCH: values that halt a run when a step changes them, and the last halt's changes
+    watchpoints: Vec<Watchpoint>,
+    watch_hits: Vec<WatchHit>,
+    
+    // Soft menus: the one built with KEY, a module's, and whether one is shown
+    user_menu: SoftMenu,
+    module_menu: Option<SoftMenu>,
+    menu_shown: bool,
+    
+    // A catalog CAT is scrolling through the display
+    catalog_run: Option<CatalogRun>,
 }
 
 impl HP41CCalculator {
     /// Create a new calculator instance
     pub fn new() -> Self {
+        let paper_tape = PaperTape::new();
         HP41CCalculator {
             stack: Stack::new(),
             input: InputState::new(),
-            programming: ProgrammingMode::new(),
+            programming: ProgrammingMode {
+                room: Model::default().total_registers() - Model::default().default_size(),
+                ..ProgrammingMode::new()
+            },
             display_formatter: DisplayFormatter::new(),
             command_parser: CommandParser::new(),
-            storage_registers: [0.0; NUM_STORAGE_REGISTERS],
+            storage_registers: vec![0.0; Model::default().default_size()],
+            flags: Flags::new(),
+            sigma_reg: DEFAULT_SIGMA_REG,
+            rng: Rng::default(),
+            alpha: AlphaRegister::new(),
+            alpha_mode: false,
+            alpha_entry: None,
+            overlay: None,
+            model: Model::default(),
+            plugged_modules: BTreeSet::new(),
             show_flags: false,
             logger: Logger::new(),  // Default: minimal logging
+            storage: default_storage(),
+            container: ContainerOptions::default(),
+            state_format: StateFormat::default(),
+            listing_watch: None,
+            clock: default_clock(),
+            display: None,
+            pause: DEFAULT_PAUSE,
+            audio: None,
+            printer: Box::new(paper_tape.clone()),
+            paper_tape: Some(paper_tape),
+            key_feedback: KeyFeedback::off(),
+            clipboard: None,
+            messages: MessageCatalog::default(),
+            confirmations: Confirmations::new(),
+            register_guard: None,
+            debug_mode: false,
+            protection: RegisterProtection::new(),
+            key_assignments: KeyAssignments::new(),
+            alarms: Vec::new(),
+            time_module: TimeModule::default(),
+            program_info: BTreeMap::new(),
+            usage: UsageStats::new(),
+            entry_started: None,
+            tic: None,
+            machine_time: Duration::ZERO,
+            step_budget: None,
+            step_limit: Some(DEFAULT_STEP_LIMIT),
+            time_limit: None,
+            run_steps: 0,
+            run_started: Duration::ZERO,
+            watchpoints: Vec::new(),
+            watch_hits: Vec::new(),
+            last_error: None,
+            user_menu: SoftMenu::new("USER"),
+            module_menu: None,
+            menu_shown: false,
+            catalog_run: None,
+        }
+    }
+    
+    /// Use a custom storage provider (e.g. `MemoryStorage` for WASM or sandboxed hosts)
+    /// 
+    /// All file access made by the calculator, including file logging, goes
+    /// through this provider.
+    pub fn with_storage(mut self, storage: SharedStorage) -> Self {
+        self.logger.set_storage(storage.clone());
+        self.storage = storage;
+        self
+    }
+    
+    /// Write state files in a format other than JSON (see `StateFormat`);
+    /// loading reads either
+    pub fn with_state_format(mut self, format: StateFormat) -> Self {
+        self.state_format = format;
+        self
+    }
+    
+    /// Get the storage provider used for file access
+    pub fn storage(&self) -> &SharedStorage {
+        &self.storage
+    }
+    
+    /// Use a custom clock (e.g. `MockClock` for deterministic tests)
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+    
+    /// Get the clock used for timing features
+    pub fn clock(&self) -> &SharedClock {
+        &self.clock
+    }
+    
+    /// Attach a display that running programs refresh at each PSE
+    /// 
+    /// Between keys the front end draws the display itself; this one is
+    /// for showing intermediate results without returning to it.
+    pub fn with_display(mut self, sink: Box<dyn DisplaySink>) -> Self {
+        self.display = Some(AttachedDisplay(sink));
+        self
+    }
+    
+    /// Format numbers with an embedder's formatter instead of the built-in one
+    /// 
+    /// The display settings stay with the calculator: FIX, SCI and ENG
+    /// change what the formatter is asked for, not which one is used.
+    pub fn with_number_formatter(mut self, formatter: Box<dyn NumberFormatter>) -> Self {
+        self.display_formatter.set_number_formatter(formatter);
+        self
+    }
+    
+    /// Set how long PSE pauses
+    pub fn set_pause(&mut self, pause: Duration) {
+        self.pause = pause;
+    }
+    
+    /// Attach an audio/haptic output device
+    pub fn with_audio(mut self, sink: Box<dyn AudioSink>) -> Self {
+        self.audio = Some(sink);
+        self
+    }
+    
+    /// Attach a printer in place of the in-memory paper tape
+    pub fn with_printer(mut self, printer: Box<dyn Printer>) -> Self {
+        self.printer = printer;
+        self.paper_tape = None;
+        self
+    }
+    
+    /// What has been printed, while no other printer is attached
+    pub fn paper_tape(&self) -> Option<&PaperTape> {
+        self.paper_tape.as_ref()
+    }
+    
+    /// Attach a clipboard for `copy_to_clipboard`
+    pub fn with_clipboard(mut self, clipboard: Box<dyn ClipboardSink>) -> Self {
+        self.clipboard = Some(clipboard);
+        self
+    }
+    
+    /// Text for copying X or the whole stack, at full precision
+    pub fn clipboard_text(&self, target: CopyTarget) -> String {
+        match target {
+            CopyTarget::X => format_full_precision(self.stack.x()),
+            CopyTarget::Stack => {
+                let names = ["X", "Y", "Z", "T"];
+                self.stack.levels().iter().enumerate().rev()
+                    .map(|(i, &value)| {
+                        let name = names.get(i).map_or_else(|| (i + 1).to_string(), |name| name.to_string());
+                        format!("{}: {}", name, format_full_precision(value))
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        }
+    }
+    
+    /// Copy X or the whole stack to the attached clipboard
+    pub fn copy_to_clipboard(&mut self, target: CopyTarget) -> Result<Option<String>, String> {
+        let text = self.clipboard_text(target);
+        let clipboard = self.clipboard.as_mut().ok_or("No clipboard available")?;
+        clipboard.set_text(&text)?;
+        Ok(Some(match target {
+            CopyTarget::X => format!("Copied {}", text),
+            CopyTarget::Stack => "Copied stack".to_string(),
+        }))
+    }
+    
+    /// Configure per-keystroke feedback
+    pub fn set_key_feedback(&mut self, feedback: KeyFeedback) {
+        self.key_feedback = feedback;
+    }
+    
+    /// Get the current key feedback configuration
+    pub fn key_feedback(&self) -> KeyFeedback {
+        self.key_feedback
+    }
+    
+    /// Cycle the key feedback style (Off → Click → Tone → Haptic)
+    pub fn cycle_key_feedback(&mut self) -> Option<String> {
+        let style = self.key_feedback.style.next();
+        self.key_feedback = KeyFeedback::new(style);
+        Some(format!("Key feedback: {}", style))
+    }
+    
+    /// Select the language for command descriptions and error messages
+    pub fn set_locale(&mut self, locale: Locale) {
+        self.messages = MessageCatalog::for_locale(locale);
+    }
+    
+    /// Get the current display language
+    pub fn locale(&self) -> Locale {
+        self.messages.locale()
+    }
+    
+    /// Get the message catalog for the current locale
+    pub fn messages(&self) -> &MessageCatalog {
+        &self.messages
+    }
+    
+    /// Localized one-line help for a command, e.g. "SIN: Sinus"
+    pub fn describe_command(&self, command: &str) -> Option<String> {
+        let spec = self.command_parser.registry().get_spec(command)?;
+        let description = self.messages.describe(spec)?;
+        Some(format!("{}: {}", command.to_uppercase(), description))
+    }
+    
+    /// Play an event on the audio device, if one is attached
+    pub fn play_audio(&mut self, event: AudioEvent) {
+        if let Some(sink) = self.audio.as_mut() {
+            sink.play(event);
+        }
+    }
+    
+    /// Play the configured feedback pattern for a cue
+    fn play_cue(&mut self, cue: FeedbackCue) {
+        if self.audio.is_none() || !self.key_feedback.is_enabled() {
+            return;
+        }
+        for event in self.key_feedback.pattern(cue) {
+            self.play_audio(event);
+        }
+    }
+    
+    /// Create a calculator with debug logging enabled
+    pub fn new_with_debug_logging() -> Self {
+        let mut calc = Self::new();
+        calc.logger = Logger::debug_all();
+        calc.logger.log_debug("INIT", "Calculator created with debug logging enabled");
+        calc
+    }
+    
+    /// Create a calculator with file logging enabled to a default location
+    pub fn new_with_file_logging() -> Result<Self, String> {
+        let mut calc = Self::new();
+        let log_path = "hp41c_debug.log";
+        calc.enable_file_logging(log_path)?;
+        calc.logger = crate::logger::Logger::debug_all();
+        calc.logger.set_storage(calc.storage.clone());
+        match calc.logger.enable_file_logging(log_path) {
+            Ok(()) => {
+                calc.logger.log_debug("INIT", "Calculator created with file logging enabled");
+                Ok(calc)
+            }
+            Err(e) => Err(format!("Failed to initialize file logging: {}", e))
+        }
+    }
+    
+    /// Get mutable reference to logger for configuration
+    pub fn logger_mut(&mut self) -> &mut Logger {
+        &mut self.logger
+    }
+    
+    /// Get reference to logger for status
+    pub fn logger(&self) -> &Logger {
+        &self.logger
+    }
+
+    /// Enable file logging to a specific path
+    pub fn enable_file_logging<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<Option<String>, String> {
+        match self.logger.enable_file_logging(path) {
+            Ok(()) => {
+                if let Some(path) = self.logger.get_log_file_path() {
+                    Ok(Some(format!("File logging enabled: {}", path.display())))
+                } else {
+                    Ok(Some("File logging enabled".to_string()))
+                }
+            }
+            Err(e) => Err(format!("Failed to enable file logging: {}", e))
+        }
+    }
+    
+    /// Disable file logging
+    pub fn disable_file_logging(&mut self) -> Result<Option<String>, String> {
+        match self.logger.disable_file_logging() {
+            Ok(()) => Ok(Some("File logging disabled".to_string())),
+            Err(e) => Err(format!("Failed to disable file logging: {}", e))
+        }
+    }
+    
+    /// Get current log file path
+    pub fn get_log_file_path(&self) -> Option<&std::path::Path> {
+        self.logger.get_log_file_path()
+    }
+    
+    /// Capture the continuous-memory state, including a run in progress
+    pub fn snapshot(&self) -> MachineState {
+        MachineState {
+            version: STATE_FORMAT_VERSION,
+            model: self.model,
+            modules: self.plugged_modules.iter().copied().collect(),
+            stack: self.stack.get_registers(),
+            stack_lift: self.stack.should_lift(),
+            last_x: self.stack.last_x(),
+            stack_depth: self.stack.depth(),
+            stack_upper: self.stack.levels()[CLASSIC_DEPTH..].to_vec(),
+            registers: self.storage_registers.to_vec(),
+            flags: self.flags,
+            sigma_reg: self.sigma_reg,
+            random: self.rng,
+            alpha: self.alpha.text().to_string(),
+            protected: self.protection.registers().collect(),
+            display: DisplayState {
+                mode: self.display_formatter.mode.clone(),
+                digits: self.display_formatter.digits,
+            },
+            program: self.programming.program.clone(),
+            program_info: self.program_info.clone(),
+            extended_memory: self.programming.extended.clone(),
+            execution: ExecutionState {
+                program_counter: self.programming.program_counter,
+                space: self.programming.space.clone(),
+                return_stack: self.programming.subroutine_stack.clone(),
+                interrupted: self.programming.is_running(),
+            },
+            key_assignments: self.key_assignments.clone(),
+            alarms: self.alarms.clone(),
+            clock_correction: self.time_module.correction,
+        }
+    }
+    
+    /// The full machine state as JSON (see `doc/state_schema.md`)
+    pub fn dump_state_json(&self) -> Result<String, String> {
+        self.snapshot().to_json()
+    }
+    
+    /// Stable hash of stack, registers, flags and program memory
+    /// 
+    /// Two calculators with equal fingerprints show the same machine
+    /// state; see `MachineState::fingerprint`.
+    pub fn state_fingerprint(&self) -> u64 {
+        self.snapshot().fingerprint()
+    }
+    
+    /// Replace the calculator state with a snapshot
+    /// 
+    /// An interrupted run comes back halted at its saved program counter
+    /// with its return stack intact, ready to be continued.
+    pub fn restore(&mut self, state: &MachineState) {
+        self.stack.set_depth(state.stack_depth);
+        let levels: Vec<f64> = state.stack.iter().chain(&state.stack_upper).copied().collect();
+        self.stack.set_levels(&levels);
+        self.stack.set_lift_flag(state.stack_lift);
+        self.stack.set_last_x(state.last_x);
+        self.model = state.model;
+        self.plugged_modules = state.modules.iter().copied().collect();
+        self.storage_registers = state.registers.clone();
+        self.protection = state.protected.iter().copied().collect();
+        self.flags = state.flags;
+        self.sigma_reg = state.sigma_reg;
+        self.rng = state.random;
+        self.alpha.set(&state.alpha);
+        self.display_formatter.mode = state.display.mode.clone();
+        self.display_formatter.digits = state.display.digits.min(9);
+        
+        self.programming.clear_program();
+        self.programming.program = state.program.clone();
+        self.programming.current_line = state.program.len() as i32 + 1;
+        self.programming.rebuild_label_table();
+        self.program_info = state.program_info.clone();
+        self.programming.extended = state.extended_memory.clone();
+        // A position in a file that is no longer there falls back to main memory
+        self.programming.space = match &state.execution.space {
+            MemorySpace::Extended(file) if state.extended_memory.program(file).is_none() => MemorySpace::Main,
+            space => space.clone(),
+        };
+        self.programming.program_counter = state.execution.program_counter.min(self.programming.running_steps().len());
+        self.programming.subroutine_stack = state.execution.return_stack.clone();
+        // An interrupted run comes back halted, for R/S to resume
+        self.programming.run_state = if state.execution.interrupted { RunState::Stopped } else { RunState::Idle };
+        self.programming.room = self.model.total_registers().saturating_sub(self.storage_registers.len());
+        self.programming.is_programming = false;
+        
+        self.key_assignments = state.key_assignments.clone();
+        self.alarms = state.alarms.clone();
+        self.time_module.correction = state.clock_correction;
+        
+        self.input.clear();
+        self.command_parser.clear();
+        self.logger.log_programming("restore", &format!("Restored state with {} program steps", state.program.len()));
+    }
+    
+    /// Turn the machine on over its continuous memory (a warm start)
+    /// 
+    /// Flags reset as `Flags::power_on` describes; registers, SIZE, the
+    /// display format and programs are kept. PRGM mode and any half-keyed
+    /// entry end. With flag 11 set, the program at the program counter
+    /// starts running, for the front end to carry on with `advance_program`.
+    pub fn power_on(&mut self) {
+        let auto_execute = self.flags.is_set(FLAG_AUTO_EXECUTE);
+        self.flags.power_on();
+        self.programming.is_programming = false;
+        self.input.clear();
+        self.command_parser.clear();
+        self.catalog_run = None;
+        self.menu_shown = false;
+        if auto_execute && !self.programming.running_steps().is_empty() {
+            self.programming.run();
+        }
+    }
+    
+    /// MEMORY LOST: clear continuous memory as on a new machine (a cold
+    /// start, or RESET from the keyboard)
+    /// 
+    /// Everything `snapshot` saves goes back to its MEMORY LOST value, with
+    /// the registers at the model's default SIZE. The model and its plugged
+    /// modules stay, as does host configuration such as storage and locale.
+    pub fn memory_lost(&mut self) {
+        let mut fresh = HP41CCalculator::new();
+        fresh.set_model(self.model);
+        fresh.plugged_modules = self.plugged_modules.clone();
+        self.restore(&fresh.snapshot());
+        self.user_menu = SoftMenu::new("USER");
+        self.module_menu = None;
+        self.menu_shown = false;
+        self.catalog_run = None;
+        self.overlay = Some("MEMORY LOST".to_string());
+    }
+    
+    /// Compress and/or encrypt state files from now on
+    /// 
+    /// The passphrase is also used to read encrypted files back.
+    pub fn set_container_options(&mut self, options: ContainerOptions) {
+        self.container = options;
+    }
+    
+    /// Compression and encryption used for state files
+    pub fn container_options(&self) -> &ContainerOptions {
+        &self.container
+    }
+    
+    /// Save the continuous-memory state through the storage provider, in
+    /// the state format, wrapped as set by `set_container_options`
+    pub fn save_state<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<Option<String>, String> {
+        let encoded = self.snapshot().encode(self.state_format)?;
+        let data = container::seal(&encoded, &self.container)?;
+        self.storage.write(path.as_ref(), &data)
+            .map_err(|e| format!("Failed to save state: {}", e))?;
+        Ok(Some(format!("State saved: {}", path.as_ref().display())))
+    }
+    
+    /// Load state saved by `save_state`, plain or in a container
+    pub fn load_state<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<Option<String>, String> {
+        let data = self.storage.read(path.as_ref())
+            .map_err(|e| format!("Failed to load state: {}", e))?;
+        let encoded = container::open(&data, self.container.passphrase.as_deref())
+            .map_err(|e| format!("Failed to load state: {}", e))?;
+        let state = MachineState::decode(&encoded)?;
+        self.restore(&state);
+        Ok(Some(self.loaded_message(&state)))
+    }
+    
+    /// Save the state as a directory of small files (see `statedir`)
+    pub fn save_state_dir<P: AsRef<std::path::Path>>(&mut self, dir: P) -> Result<Option<String>, String> {
+        statedir::save(self.storage.as_ref(), dir.as_ref(), &self.snapshot())?;
+        Ok(Some(format!("State saved: {}", dir.as_ref().display())))
+    }
+    
+    /// Load a state directory written by `save_state_dir`
+    pub fn load_state_dir<P: AsRef<std::path::Path>>(&mut self, dir: P) -> Result<Option<String>, String> {
+        let state = statedir::load(self.storage.as_ref(), dir.as_ref())
+            .map_err(|e| format!("Failed to load state: {}", e))?;
+        self.restore(&state);
+        Ok(Some(self.loaded_message(&state)))
+    }
+    
+    fn loaded_message(&self, state: &MachineState) -> String {
+        if state.execution.interrupted {
+            match self.programming.get_current_instruction() {
+                Some(instr) => format!("State loaded, program halted at {:02} {}", instr.line_number, instr),
+                None => "State loaded, program halted at .END.".to_string(),
+            }
+        } else {
+            "State loaded".to_string()
+        }
+    }
+
+    /// Replace program memory with the steps of a text listing
+    /// 
+    /// Registers, stack and flags are kept. Every step must be a number or
+    /// a known command; otherwise program memory is left as it was.
+    /// `# Title:`, `# Description:` and `# Author:` comments become the
+    /// info of the program whose label follows them.
+    pub fn load_listing(&mut self, text: &str) -> Result<Option<String>, String> {
+        let program = parse_listing(text)?;
+        self.check_commands(&program)?;
+        
+        let steps = program.len();
+        self.programming.clear_program();
+        self.programming.program = program;
+        self.programming.current_line = steps as i32 + 1;
+        self.programming.rebuild_label_table();
+        self.program_info.extend(parse_listing_info(text));
+        self.logger.log_programming("listing", &format!("Loaded {} steps", steps));
+        Ok(Some(format!("Loaded {} steps", steps)))
+    }
+    
+    /// Check command lines without running them (see `validate`)
+    /// 
+    /// GTO and XEQ targets are looked for in program memory and extended
+    /// memory, register numbers against the current SIZE.
+    pub fn validate_lines<S: AsRef<str>>(&self, lines: &[S]) -> Vec<Diagnostic> {
+        validate::check_steps(&validate::parse_lines(lines), &self.validation_context(&self.programming.program))
+    }
+    
+    /// Check program steps as if loaded in place of the program in memory,
+    /// so their own labels are the ones GTO and XEQ reach
+    pub fn validate_steps(&self, steps: &[ProgramInstruction]) -> Vec<Diagnostic> {
+        validate::check_steps(steps, &self.validation_context(steps))
+    }
+    
+    fn validation_context<'a>(&'a self, program: &'a [ProgramInstruction]) -> validate::Context<'a> {
+        validate::Context {
+            registry: self.command_parser.registry(),
+            program,
+            extended: &self.programming.extended,
+            size: self.storage_registers.len(),
+        }
+    }
+    
+    /// Fail on the first step whose command this calculator doesn't have
+    fn check_commands(&self, program: &[ProgramInstruction]) -> Result<(), String> {
+        let registry = self.command_parser.registry();
+        if let Some(step) = program.iter().find(|step| {
+            step.text.is_none() && step.command.parse::<f64>().is_err() && !registry.has_command(&command_key(&step.command))
+        }) {
+            return Err(format!("Step {:02}: {}", step.line_number,
+                               self.messages.error(&CommandError::UnknownCommand(step.command.clone()).into())));
+        }
+        Ok(())
+    }
+    
+    /// Add the programs of a `.raw` file from another emulator after
+    /// those in program memory (see `import`)
+    /// 
+    /// Steps with an indirect or stack register operand are refused, one
+    /// line each, as this calculator can't run them yet.
+    pub fn import_raw(&mut self, bytes: &[u8]) -> Result<Option<String>, String> {
+        let imported = import::decode_raw(bytes)?;
+        self.check_commands(&imported)?;
+        let refused: Vec<String> = imported.iter().filter_map(|step| {
+            let operand = unsupported_operand(&step.arguments)?;
+            let error = CommandError::InvalidArgument { command: step.command.clone(), argument: operand };
+            Some(format!("Step {:02}: {}", step.line_number, self.messages.error(&error.into())))
+        }).collect();
+        if !refused.is_empty() {
+            return Err(refused.join("\n"));
+        }
+        Ok(Some(self.append_programs(imported)))
+    }
+    
+    /// Add the programs of a Free42 or Plus42 export after those in
+    /// program memory (see `import`)
+    /// 
+    /// HP-42S instructions, HP-41 functions this calculator doesn't have
+    /// and steps with an `IND` or `ST` operand are listed as an error; with
+    /// `stub` they become STUB steps, which do nothing when run, and the
+    /// rest of the program loads.
+    pub fn import_free42(&mut self, bytes: &[u8], stub: bool) -> Result<Option<String>, String> {
+        let mut imported = import::decode_free42(bytes)?;
+        let registry = self.command_parser.registry();
+        let mut unsupported = Vec::new();
+        for (index, step) in imported.iter_mut().enumerate() {
+            let known = step.text.is_some() || step.command.parse::<f64>().is_ok() || registry.has_command(&command_key(&step.command));
+            let runs = known && unsupported_operand(&step.arguments).is_none();
+            if step.command == import::STUB || !runs {
+                let instruction = if step.command == import::STUB { step.arguments.join(" ") } else { step.to_string() };
+                unsupported.push(format!("Step {:02}: {}", index + 1, instruction));
+                *step = ProgramInstruction::new(step.line_number, import::STUB.to_string(), vec![instruction]);
+            }
+        }
+        if !stub && !unsupported.is_empty() {
+            return Err(format!("Not supported:\n{}", unsupported.join("\n")));
+        }
+        let message = self.append_programs(imported);
+        Ok(Some(match unsupported.len() {
+            0 => message,
+            stubbed => format!("{}, {} stubbed", message, stubbed),
+        }))
+    }
+    
+    /// Program memory as a `.raw` file for other emulators (see `import`)
+    pub fn export_raw(&self) -> Result<Vec<u8>, String> {
+        import::encode_raw(&self.programming.program)
+    }
+    
+    /// Wand bar code rows for program memory (see `barcode`)
+    pub fn program_barcode(&self) -> Result<Vec<BarcodeRow>, String> {
+        barcode::program_barcode(&self.programming.program)
+    }
+    
+    /// Add the programs of a FOCAL listing, with the HP-41's names for
+    /// commands, after those in program memory (see `focal`)
+    /// 
+    /// Every line that can't be read is reported, one per line of the
+    /// message; nothing is added then.
+    pub fn import_focal(&mut self, text: &str) -> Result<Option<String>, String> {
+        let imported = focal::parse_focal(text, self.command_parser.registry()).map_err(|errors| errors.join("\n"))?;
+        Ok(Some(self.append_programs(imported)))
+    }
+    
+    /// Program memory as a FOCAL listing with the HP-41's names
+    pub fn export_focal(&self) -> String {
+        focal::write_focal(&self.programming.program)
+    }
+    
+    /// Put imported steps after program memory, an END between them
+    fn append_programs(&mut self, imported: Vec<ProgramInstruction>) -> String {
+        let mut program = self.programming.program.clone();
+        // Programs in memory are kept apart by END
+        if program.last().is_some_and(|step| !step.command.eq_ignore_ascii_case("end")) {
+            program.push(ProgramInstruction::new(0, "END".to_string(), vec![]));
+        }
+        program.extend(imported.iter().cloned());
+        for (index, step) in program.iter_mut().enumerate() {
+            step.line_number = index as i32 + 1;
+        }
+        self.programming.current_line = program.len() as i32 + 1;
+        self.programming.program = program;
+        self.programming.rebuild_label_table();
+        self.logger.log_programming("import", &format!("Imported {} steps", imported.len()));
+        format!("Imported {} steps", imported.len())
+    }
+    
+    /// Store the registers of a PRREG printout from another emulator
+    /// 
+    /// Every register must exist at the current SIZE; nothing is stored
+    /// otherwise.
+    pub fn import_registers(&mut self, text: &str) -> Result<Option<String>, String> {
+        let registers = import::parse_register_dump(text)?;
+        if let Some(&(register, _)) = registers.iter().find(|&&(register, _)| register >= self.storage_registers.len()) {
+            return Err(self.messages.error(&StorageError::Nonexistent(register).into()));
+        }
+        for &(register, value) in &registers {
+            self.storage_registers[register] = value;
+        }
+        Ok(Some(format!("Imported {} registers", registers.len())))
+    }
+    
+    /// Reload a listing file into program memory whenever it changes
+    /// 
+    /// The front end calls `check_listing_watch` while waiting for keys.
+    pub fn watch_listing<P: AsRef<std::path::Path>>(&mut self, path: P) {
+        self.listing_watch = Some(ListingWatcher::new(path));
+    }
+    
+    /// Whether a listing file is being watched for changes
+    pub fn is_watching_listing(&self) -> bool {
+        self.listing_watch.is_some()
+    }
+    
+    /// Load the watched listing if it changed; `None` if nothing happened
+    /// 
+    /// The message lists what `validate_steps` finds in the new version.
+    pub fn check_listing_watch(&mut self) -> Option<Result<Option<String>, String>> {
+        let watcher = self.listing_watch.as_mut()?;
+        let path = watcher.path().display().to_string();
+        let text = match watcher.poll(self.storage.as_ref()) {
+            Ok(text) => text?,
+            Err(e) => return Some(Err(e)),
+        };
+        // The listing loads even with problems, which are listed under it
+        let problems: Vec<String> = parse_listing(&text)
+            .map(|steps| self.validate_steps(&steps).iter().map(ToString::to_string).collect())
+            .unwrap_or_default();
+        Some(self.load_listing(&text)
+            .map(|message| message.map(|m| {
+                std::iter::once(format!("{}: {}", path, m)).chain(problems).collect::<Vec<_>>().join("\n")
+            }))
+            .map_err(|e| format!("{}: {}", path, e)))
+    }
+    
+    /// Usage counts of commands keyed in so far
+    pub fn usage_stats(&self) -> &UsageStats {
+        &self.usage
+    }
+    
+    /// Forget all usage counts
+    pub fn reset_usage_stats(&mut self) {
+        self.usage.clear();
+    }
+    
+    /// Save usage counts through the storage provider
+    pub fn save_usage_stats<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), String> {
+        let json = self.usage.to_json()?;
+        self.storage.write(path.as_ref(), json.as_bytes())
+            .map_err(|e| format!("Failed to save stats: {}", e))
+    }
+    
+    /// Load usage counts saved by `save_usage_stats`
+    pub fn load_usage_stats<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<(), String> {
+        let json = self.storage.read_to_string(path.as_ref())
+            .map_err(|e| format!("Failed to load stats: {}", e))?;
+        self.usage = UsageStats::from_json(&json)?;
+        Ok(())
+    }
+    
+    /// Execute a command completed from the keyboard, counting it for STATS
+    /// 
+    /// Destructive commands whose category needs confirmation only ask the
+    /// first time.
+    fn execute_keyed(&mut self, command: &str, args: Option<Vec<String>>) -> Result<Option<String>, String> {
+        let entry_time = self.entry_started.take()
+            .map_or(Duration::ZERO, |start| self.clock.elapsed().saturating_sub(start));
+        // R/S answers a pending confirmation by repeating the command
+        if command.eq_ignore_ascii_case("r/s") {
+            if let Some(line) = self.confirmations.pending().map(str::to_lowercase) {
+                let mut words = line.split_whitespace().map(str::to_string);
+                let pending = words.next().unwrap_or_default();
+                let args: Vec<String> = words.collect();
+                return self.execute_keyed(&pending, (!args.is_empty()).then_some(args));
+            }
+        }
+        match self.confirm_category(command, args.as_deref()) {
+            Some(category) => {
+                let line = match &args {
+                    Some(args) => format!("{} {}", command.to_uppercase(), args.join(" ")),
+                    None => command.to_uppercase(),
+                };
+                if !self.confirmations.confirm(category, &line) {
+                    return Ok(Some(format!("{}: press again to confirm", line)));
+                }
+            }
+            None => self.confirmations.cancel(),
+        }
+        self.usage.record(command, entry_time);
+        let result = self.execute_command(command, args);
+        self.continue_run(result)
+    }
+
+    /// The confirmation category of a command, if it destroys data
+    fn confirm_category(&self, command: &str, args: Option<&[String]>) -> Option<ConfirmCategory> {
+        match command_key(command).as_str() {
+            // Keyed in PRGM mode, CLRG is only recorded
+            "clrg" if !self.programming.is_programming => Some(ConfirmCategory::Registers),
+            "prgm" => Some(ConfirmCategory::Program),
+            "reset" => Some(ConfirmCategory::Reset),
+            "size" => {
+                let size = args?.first()?.parse::<usize>().ok()?;
+                (size < self.storage_registers.len()).then_some(ConfirmCategory::Memory)
+            }
+            _ => None,
+        }
+    }
+    
+    /// Require (or stop requiring) a second keypress for a category of destructive commands
+    pub fn set_confirmation(&mut self, category: ConfirmCategory, required: bool) {
+        self.confirmations.set_required(category, required);
+    }
+    
+    /// Whether a category of destructive commands needs a second keypress
+    pub fn requires_confirmation(&self, category: ConfirmCategory) -> bool {
+        self.confirmations.is_required(category)
+    }
+
+    /// Execute a command with the given arguments (for internal use)
+    pub fn execute_command(&mut self, command: &str, args: Option<Vec<String>>) -> Result<Option<String>, String> {
+        // Log command execution attempt
+        self.logger.log_command_execution(command, &args, "starting");
+        
+        // Capture stack state before execution
+        let stack_before = self.stack.get_registers();
+        let was_running = self.programming.is_running();
+        let registers_before = ((was_running && self.register_guard.is_some()) || !self.protection.is_empty())
+            .then(|| self.storage_registers.clone());
+        
+        // R/S stops and restarts a scrolling catalog; any other command ends it
+        if let Some(run) = self.catalog_run.as_mut() {
+            if command.eq_ignore_ascii_case("r/s") {
+                run.run_stop(self.clock.elapsed());
+                self.show_catalog_entry();
+                return Ok(None);
+            }
+            self.catalog_run = None;
+        }
+        
+        // SST out of PRGM mode executes a step the way a running program does
+        if command.eq_ignore_ascii_case("sst") && !self.programming.is_programming {
+            return self.execute_single_step();
+        }
+        
+        let name = command_key(command);
+        let mut result = match name.as_str() {
+            // Module functions need their module
+            name if Module::for_command(name).is_some_and(|module| !self.has_module(module)) => {
+                Err(CommandError::Nonexistent(name.to_uppercase()).into())
+            }
+            
+            // In PRGM mode every programmable command is recorded, not run
+            name if self.programming.is_programming && is_programmable(name, args.as_deref()) => {
+                self.programming.add_instruction(command, args.clone(), command)
+                    .map(|_| None).map_err(Into::into)
+            }
+            
+            // Storing into a protected register fails even if the value wouldn't change
+            "sto" | "sto+" | "sto-" | "sto*" | "sto/" | "x<>" | "asto" if self.stores_into_protected(args.as_deref()) => {
+                let register = args.as_deref().and_then(|args| args.first()?.parse().ok()).unwrap_or_default();
+                Err(StorageError::Protected(register).into())
+            }
+
+            // Commands that work on calculator-level state
+            "asn" => self.execute_assign(args.clone()),
+            "stats" => Ok(Some(self.usage.to_string())),
+            "protect" | "unprotect" => self.execute_protect(&name, args.clone()),
+            "sf" | "cf" | "fs?" | "fc?" | "fs?c" | "fc?c" => {
+                execute_flag_command(&name, args.clone(), &mut self.flags, &mut self.programming)
+            }
+            // Statistics (Σ names, with S spellings for the keyboard)
+            "σ+" | "s+" | "σ-" | "s-" | "mean" | "sdev" | "clσ" | "cls" => execute_statistics_command(
+                &name,
+                &mut self.stack,
+                &mut self.input,
+                &mut self.storage_registers,
+                self.sigma_reg,
+            ),
+            "σreg" | "sreg" => self.execute_sigma_reg(args.as_deref()),
+            "assert" => self.execute_assert(args.as_deref()),
+            "watch" => self.execute_watch(args.as_deref()),
+            "unwatch" => {
+                self.clear_watchpoints();
+                Ok(None)
+            }
+            // An imported instruction this calculator can't run: skipped
+            "stub" => {
+                let instruction = args.as_deref().unwrap_or_default().join(" ");
+                self.logger.log_debug("STUB", &format!("Skipped {}", instruction));
+                Ok(None)
+            }
+            "size" => self.execute_size(args.as_deref()),
+            "pack" => {
+                self.programming.pack();
+                Ok(None)
+            }
+            "mem" => Ok(Some(self.memory_message())),
+            "reset" => {
+                self.memory_lost();
+                Ok(None)
+            }
+            "asto" | "arcl" => self.execute_alpha_transfer(&name, args.as_deref()),
+            "view" | "aview" => self.execute_view(&name, args.as_deref()),
+            "r/s" => self.execute_run_stop(),
+            "tone" | "beep" => self.execute_sound(&name, args.as_deref()),
+            "adv" => {
+                self.printer.advance();
+                Ok(None)
+            }
+            "prx" | "pra" | "prstk" | "prreg" | "prp" => self.execute_print(&name, args.as_deref()),
+            "cla" => {
+                self.clear_alpha();
+                Ok(None)
+            }
+            "cld" => {
+                self.clear_overlay();
+                Ok(None)
+            }
+            "stop" => {
+                if self.programming.is_running() {
+                    self.programming.stop();
+                }
+                Ok(None)
+            }
+            "prompt" => {
+                self.overlay = Some(self.alpha.text().to_string());
+                if self.programming.is_running() {
+                    self.programming.prompt();
+                }
+                Ok(None)
+            }
+            "pse" => {
+                self.execute_pause();
+                Ok(None)
+            }
+            "tic" => {
+                self.tic = Some(self.clock.elapsed());
+                Ok(None)
+            }
+            "toc" => self.execute_toc(),
+            "cat" => self.execute_catalog(args.as_deref()),
+            "sst" if self.programming.is_programming => Ok(Some(self.programming.sst_edit())),
+            "bst" if self.programming.is_programming => Ok(Some(self.programming.bst_edit())),
+            "savep" | "purfl" | "emdir" => self.execute_extended_memory(&name),
+            "key" => self.execute_menu_key(args.as_deref()),
+            "menu" => self.execute_menu(),
+            "exitm" => {
+                self.menu_shown = false;
+                self.module_menu = None;
+                Ok(None)
+            }
+            "clmenu" => {
+                self.user_menu = SoftMenu::new("USER");
+                Ok(None)
+            }
+            "user" => {
+                self.flags.set(FLAG_USER, !self.flags.is_set(FLAG_USER));
+                Ok(None)
+            }
+            "rndm" | "seed" | "die" | "shuffle" | "delay" => self.execute_games(&name, args.as_deref()),
+            "date" | "time" | "setdate" | "settime" | "clock" | "dmy" | "mdy" => self.execute_time(&name),
+            "xyzalm" => self.execute_xyzalm(),
+            "almcat" => self.execute_catalog(Some(&["5".to_string()])),
+            "dow" | "jdn" | "ddays" | "date+" => execute_calendar_command(
+                &name,
+                &mut self.stack,
+                &mut self.input,
+                self.flags.is_set(FLAG_DMY),
+            ),
+            _ => execute_command(
+                command,
+                args.clone(),
+                &mut self.stack,
+                &mut self.input,
+                &mut self.programming,
+                &mut self.display_formatter,
+                &mut self.storage_registers,
+            ),
+        };
+        
+        // Any other write to a protected register (CLRG etc.) is undone and fails the command
+        if let (Ok(_), Some(before)) = (&result, &registers_before) {
+            if let Some(register) = self.protection.first_violation(before, &self.storage_registers) {
+                self.storage_registers = before.clone();
+                result = Err(StorageError::Protected(register).into());
+            }
+        }
+        let mut result = result.map_err(|e| self.report_error(e));
+        
+        if let Some(guard) = self.register_guard.as_mut() {
+            if let Some(before) = registers_before.filter(|_| was_running) {
+                guard.observe(&before, &self.storage_registers);
+            }
+        }
+        if was_running && !self.programming.is_running() {
+            self.finish_guarded_run(&mut result);
+        }
+        
+        // Log the result and any stack changes
+        match &result {
+            Ok(Some(msg)) => {
+                self.logger.log_command_execution(command, &args, msg);
+                self.play_cue(FeedbackCue::Complete);
+            }
+            Ok(None) => {
+                self.logger.log_command_execution(command, &args, "completed");
+                self.play_cue(FeedbackCue::Complete);
+            }
+            Err(e) => {
+                self.logger.log_command_execution(command, &args, &format!("ERROR: {}", e));
+            }
+        }
+        
+        // Log stack changes if they occurred
+        let stack_after = self.stack.get_registers();
+        if stack_before != stack_after {
+            self.logger.log_stack_operation(&format!("{} command", command), &stack_before, &stack_after);
+        }
+        
+        result
+    }
+
+    /// Run one command line such as `FIX 2`, `XEQ "MAIN"` or `1.5`
+    /// 
+    /// Used for startup macros and scripts rather than interactive typing:
+    /// the whole command and its arguments arrive at once. A bare number is
+    /// put in X as if keyed in and terminated, so consecutive numbers stack
+    /// up without an ENTER in between.
+    pub fn run_command_line(&mut self, line: &str) -> Result<Option<String>, String> {
+        let mut tokens = line.split_whitespace();
+        let Some(first) = tokens.next() else { return Ok(None) };
+        
+        if let Ok(value) = first.parse::<f64>() {
+            self.enter_number(value);
+            return Ok(None);
+        }
+        
+        let command = command_key(first);
+        if !self.command_parser.registry().has_command(&command) {
+            return Err(self.report_error(CommandError::UnknownCommand(first.to_string()).into()));
+        }
+        // A quoted label is global even when its name looks local
+        let takes_label = matches!(command.as_str(), "lbl" | "gto" | "xeq");
+        let args: Vec<String> = tokens.map(|t| match t.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+            Some(name) if takes_label => quoted_label(name),
+            _ => t.trim_matches('"').to_string(),
+        }).collect();
+        let result = self.execute_command(&command, (!args.is_empty()).then_some(args));
+        self.continue_run(result)
+    }
+    
+    /// Remember a failure for `last_error` and word it in the current language
+    fn report_error(&mut self, error: CalculatorError) -> String {
+        let message = self.messages.error(&error);
+        self.last_error = Some(error);
+        message
+    }
+    
+    /// The error behind the last failed command, until `clear_last_error`
+    /// 
+    /// Failures report a localized message; this keeps the error itself
+    /// so scripts can branch on its `code`.
+    pub fn last_error(&self) -> Option<&CalculatorError> {
+        self.last_error.as_ref()
+    }
+    
+    /// Forget the error kept by `last_error`
+    pub fn clear_last_error(&mut self) {
+        self.last_error = None;
+    }
+    
+    /// Put a number in X as if keyed in and terminated
+    fn enter_number(&mut self, value: f64) {
+        let stack_before = self.stack.get_registers();
+        if self.stack.should_lift() {
+            self.stack.lift();
+        }
+        self.stack.set_x(value);
+        self.stack.set_lift_flag(true);
+        self.input.clear();
+        self.logger.log_stack_operation("number", &stack_before, &self.stack.get_registers());
+    }
+    
+    /// Run the program on after a command that started it (XEQ, R/S)
+    fn continue_run(&mut self, result: Result<Option<String>, String>) -> Result<Option<String>, String> {
+        match result {
+            Ok(message) if self.programming.is_running() => {
+                self.run_steps = 0;
+                self.run_started = self.clock.elapsed();
+                self.run_program().map(|m| m.or(message))
+            }
+            result => result,
+        }
+    }
+    
+    /// Execute steps from the program counter until the program halts or
+    /// ends, or the step budget is used up
+    /// 
+    /// A failing step halts the run with the program counter left on it,
+    /// as the HP-41 does, so the error can be fixed and the step retried.
+    /// With a budget the program may still be running on return, and
+    /// `advance_program` carries it on. A run past its step or time limit
+    /// halts with `StepLimitExceeded`, so an endless loop can't hang a
+    /// front end without a budget.
+    fn run_program(&mut self) -> Result<Option<String>, String> {
+        let mut result = self.run_slice();
+        if !self.programming.is_running() {
+            self.finish_guarded_run(&mut result);
+        }
+        result
+    }
+    
+    /// The steps of one `run_program` call, without the guard
+    fn run_slice(&mut self) -> Result<Option<String>, String> {
+        let mut steps = 0;
+        while self.programming.is_running() {
+            if self.step_budget.is_some_and(|budget| steps >= budget) {
+                break;
+            }
+            if self.run_limit_reached() {
+                self.programming.stop();
+                return Err(self.report_error(ProgrammingError::StepLimitExceeded(self.run_steps).into()));
+            }
+            steps += 1;
+            self.run_steps += 1;
+            let Some(step) = self.programming.fetch_step() else { break };
+            if let Some(message) = self.execute_step(&step)? {
+                return Ok(Some(message));
+            }
+        }
+        Ok(None)
+    }
+    
+    /// End a run under the register guard: put back the registers outside
+    /// its range and note them in the run's message
+    /// 
+    /// Runs end here however they halt, so calling it again is harmless.
+    fn finish_guarded_run(&mut self, result: &mut Result<Option<String>, String>) {
+        let Some(guard) = self.register_guard.as_mut() else { return };
+        let restored = guard.finish(&mut self.storage_registers);
+        if restored.is_empty() {
+            return;
+        }
+        let names: Vec<String> = restored.iter().map(|r| format!("R{:02}", r)).collect();
+        let note = format!("Guard restored {}", names.join(" "));
+        for &register in &restored {
+            self.logger.log_storage_operation("guard restore", register, self.storage_registers[register]);
+        }
+        match result {
+            Ok(message) => {
+                *message = Some(match message.take() {
+                    Some(msg) => format!("{} ({})", msg, note),
+                    None => note,
+                });
+            }
+            Err(message) => *message = format!("{} ({})", message, note),
+        }
+    }
+    
+    /// Execute one fetched program step, halting on it if it fails, and
+    /// print it in TRACE mode
+    /// 
+    /// A step that changes a watched value halts the run after it, with
+    /// the changes as the message.
+    fn execute_step(&mut self, step: &ProgramInstruction) -> Result<Option<String>, String> {
+        let watched = self.watched_values();
+        self.perform_step(step)?;
+        if self.flags.is_set(FLAG_TRACE) {
+            self.trace_step(step);
+        }
+        Ok(self.check_watchpoints(step, &watched))
+    }
+    
+    fn watched_values(&self) -> Vec<f64> {
+        self.watchpoints.iter()
+            .map(|w| w.value(&self.stack, &self.storage_registers, &self.flags))
+            .collect()
+    }
+    
+    /// Log what a step changed among the watched values and halt the run
+    /// if it changed any
+    fn check_watchpoints(&mut self, step: &ProgramInstruction, before: &[f64]) -> Option<String> {
+        let hits: Vec<WatchHit> = self.watchpoints.iter().zip(before.iter().zip(self.watched_values()))
+            .filter_map(|(&watchpoint, (&old, new))| WatchHit::changed(watchpoint, old, new))
+            .collect();
+        if hits.is_empty() {
+            return None;
+        }
+        for hit in &hits {
+            self.logger.log_debug("WATCH", &format!("{:02} {}: {}", step.line_number, step.display_text(), hit));
+        }
+        self.programming.stop();
+        let message = hits.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ");
+        self.watch_hits = hits;
+        Some(message)
+    }
+    
+    /// Halt runs when a step changes this value (`WATCH`)
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+    
+    /// Stop watching a value; false if it wasn't watched
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|&w| w != watchpoint);
+        self.watchpoints.len() < count
+    }
+    
+    /// Remove every watchpoint (`UNWATCH`)
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+    
+    /// Registers and flags being watched, in the order they were added
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+    
+    /// What the step that last halted on a watchpoint changed
+    pub fn watch_hits(&self) -> &[WatchHit] {
+        &self.watch_hits
+    }
+    
+    /// The step and the X it left, on the printer and in the log
+    fn trace_step(&mut self, step: &ProgramInstruction) {
+        let x = self.printed_value(self.stack.x());
+        for line in printer::trace_lines(&step.display_text(), &x) {
+            self.logger.log_debug("TRACE", &line);
+            self.printer.print_line(&line);
+        }
+    }
+    
+    /// A number as the printer shows it, alpha data in quotes
+    fn printed_value(&self, value: f64) -> String {
+        if alpha::unpack(value).is_some() {
+            alpha::describe(value)
+        } else {
+            self.display_formatter.format_number(value, LCD_WIDTH).trim().to_string()
+        }
+    }
+    
+    /// The printer functions: PRX, PRA, PRSTK, PRREG and PRP (see `printer`)
+    fn execute_print(&mut self, command: &str, args: Option<&[String]>) -> Result<Option<String>, CalculatorError> {
+        let right = |label: &str, value: String| format!("{}{:>width$}", label, value, width = PRINT_WIDTH - label.len());
+        let lines = match command {
+            "prx" => vec![right("", self.printed_value(self.stack.x()))],
+            "pra" => printer::wrap(self.alpha.text()),
+            "prstk" => [("T=", self.stack.t()), ("Z=", self.stack.z()), ("Y=", self.stack.y()), ("X=", self.stack.x())]
+                .into_iter()
+                .map(|(label, value)| right(label, self.printed_value(value)))
+                .collect(),
+            "prreg" => self.storage_registers.iter().enumerate()
+                .map(|(register, &value)| right(&format!("R{:02}=", register), self.printed_value(value)))
+                .collect(),
+            _ => {
+                let at = match args.and_then(<[String]>::first) {
+                    Some(label) => xmem::find_label(&self.programming.program, label)
+                        .ok_or_else(|| ProgrammingError::LabelNotFound(label.to_uppercase()))?,
+                    None => self.programming.program_counter,
+                };
+                let segment = self.programming.program_segment(at);
+                // The program's END, or .END. after the last program
+                let lines = self.programming.list(segment.start + 1..=segment.end + 1);
+                lines.iter().flat_map(|line| printer::wrap(line)).collect()
+            }
+        };
+        for line in &lines {
+            self.printer.print_line(line);
+        }
+        Ok(None)
+    }
+    
+    fn perform_step(&mut self, step: &ProgramInstruction) -> Result<(), String> {
+        self.machine_time += timing::duration(step);
+        if let Some(line) = &step.text {
+            if line.append {
+                self.alpha.append(&line.text);
+            } else {
+                self.alpha.set(&line.text);
+            }
+            return Ok(());
+        }
+        if let Ok(value) = step.command.parse::<f64>() {
+            self.enter_number(value);
+            return Ok(());
+        }
+        let args = (!step.arguments.is_empty()).then(|| step.arguments.clone());
+        if let Err(e) = self.execute_command(&step.command, args) {
+            self.programming.program_counter -= 1;
+            self.programming.stop();
+            return Err(e);
+        }
+        Ok(())
+    }
+    
+    /// SST out of PRGM mode: execute the step at the program counter and
+    /// halt after it
+    /// 
+    /// The step runs as in a program, so a false test skips the next
+    /// step and XEQ steps into its subroutine. After the last step SST
+    /// starts again at the top.
+    fn execute_single_step(&mut self) -> Result<Option<String>, String> {
+        if self.programming.running_steps().is_empty() {
+            return Err(self.report_error(ProgrammingError::NoProgram.into()));
+        }
+        if self.programming.program_counter >= self.programming.running_steps().len() {
+            self.programming.program_counter = 0;
+        }
+        self.programming.run();
+        let mut result = match self.programming.fetch_step() {
+            Some(step) => self.execute_step(&step),
+            None => Ok(None),
+        };
+        if self.programming.is_running() {
+            self.programming.stop();
+        }
+        self.finish_guarded_run(&mut result);
+        result
+    }
+    
+    /// Limit how many steps a run takes before returning (`None`: no limit)
+    /// 
+    /// An interactive front end sets a budget so it can read the keyboard
+    /// while a long program runs: each call that starts a run, and each
+    /// `advance_program`, executes at most this many steps.
+    pub fn set_step_budget(&mut self, steps: Option<usize>) {
+        self.step_budget = steps;
+    }
+    
+    /// Stop runs after this many steps (`None`: no limit); the default is
+    /// `DEFAULT_STEP_LIMIT`
+    /// 
+    /// The count starts again each time a command starts a run, and goes
+    /// on across the slices of a budgeted one.
+    pub fn set_step_limit(&mut self, steps: Option<usize>) {
+        self.step_limit = steps;
+    }
+    
+    /// Stop runs after this much time on the clock (`None`, the default: no limit)
+    pub fn set_time_limit(&mut self, limit: Option<Duration>) {
+        self.time_limit = limit;
+    }
+    
+    fn run_limit_reached(&self) -> bool {
+        self.step_limit.is_some_and(|limit| self.run_steps >= limit)
+            || self.time_limit.is_some_and(|limit| self.clock.elapsed().saturating_sub(self.run_started) >= limit)
+    }
+    
+    /// Whether a program is running
+    pub fn is_program_running(&self) -> bool {
+        self.programming.is_running()
+    }
+    
+    /// Run the next slice of a running program, up to the step budget
+    pub fn advance_program(&mut self) -> Result<Option<String>, String> {
+        self.run_program()
+    }
+    
+    /// Estimated time the program steps run so far would take on a real
+    /// HP-41, from the table in `timing`
+    pub fn machine_time(&self) -> Duration {
+        self.machine_time
+    }
+    
+    /// Start the `machine_time` estimate again from zero
+    pub fn reset_machine_time(&mut self) {
+        self.machine_time = Duration::ZERO;
+    }
+    
+    /// R/S: halt a running program, or start one at the program counter
+    /// 
+    /// A number keyed in at a PROMPT is terminated first, so it stays in X
+    /// for the program to use.
+    fn execute_run_stop(&mut self) -> Result<Option<String>, CalculatorError> {
+        if self.programming.is_running() {
+            self.programming.stop();
+            return Ok(None);
+        }
+        if self.programming.running_steps().is_empty() {
+            return Err(ProgrammingError::NoProgram.into());
+        }
+        if self.input.is_entering() {
+            self.input.clear();
+            self.stack.set_lift_flag(true);
+        }
+        self.programming.run();
+        Ok(None)
+    }
+    
+    /// Apply a config file: model and modules, locale, then the startup
+    /// commands in order
+    /// 
+    /// A failing startup command doesn't stop the rest; the returned list
+    /// holds one message per failure.
+    pub fn apply_config(&mut self, config: &Config) -> Vec<String> {
+        let mut errors = Vec::new();
+        if let Some(name) = &config.model {
+            match Model::from_name(name) {
+                // Only a change of model resets memory; a loaded state of the same model is kept
+                Some(model) if model != self.model => self.set_model(model),
+                Some(_) => {}
+                None => errors.push(format!("Unknown model: {}", name)),
+            }
+        }
+        if let Some(depth) = &config.stack_depth {
+            match StackDepth::from_name(depth) {
+                Some(depth) => self.set_stack_depth(depth),
+                None => errors.push(format!("Invalid stack depth: {}", depth)),
+            }
+        }
+        for name in &config.confirm {
+            match ConfirmCategory::from_name(name) {
+                Some(category) => self.set_confirmation(category, true),
+                None => errors.push(format!("Unknown confirmation category: {}", name)),
+            }
+        }
+        if config.compress {
+            self.container.compress = true;
+        }
+        if config.debug {
+            self.debug_mode = true;
+        }
+        if config.strict_returns {
+            self.set_strict_returns(true);
+        }
+        if let Some(seconds) = config.pause {
+            match Duration::try_from_secs_f64(seconds) {
+                Ok(pause) => self.set_pause(pause),
+                Err(_) => errors.push(format!("Invalid pause: {}", seconds)),
+            }
+        }
+        if let Some(steps) = config.step_limit {
+            self.set_step_limit((steps > 0).then_some(steps));
+        }
+        if let Some(seconds) = config.time_limit {
+            match Duration::try_from_secs_f64(seconds) {
+                Ok(limit) => self.set_time_limit(Some(limit)),
+                Err(_) => errors.push(format!("Invalid time limit: {}", seconds)),
+            }
+        }
+        for name in &config.modules {
+            match Module::from_name(name) {
+                Some(module) => self.plug_module(module),
+                None => errors.push(format!("Unknown module: {}", name)),
+            }
+        }
+        if let Some(tag) = &config.locale {
+            match Locale::from_tag(tag) {
+                Some(locale) => self.set_locale(locale),
+                None => errors.push(format!("Unknown locale: {}", tag)),
+            }
+        }
+        for line in &config.startup {
+            if let Err(e) = self.run_command_line(line) {
+                errors.push(format!("{}: {}", line, e));
+            }
+        }
+        errors
+    }
+    
+    /// Process a single keystroke with comprehensive logging
+    /// 
+    /// ## CRITICAL: Single Keystroke Processing
+    /// 
+    /// This method processes exactly ONE keystroke at a time, just like the original HP-41C:
+    /// 
+    /// - `process_input("f")` - User pressed the 'f' key
+    /// - `process_input("i")` - User pressed the 'i' key  
+    /// - `process_input("x")` - User pressed the 'x' key → "fix" command ready
+    /// - `process_input("4")` - User pressed the '4' key → "fix 4" executes
+    /// 
+    /// NOT like a command line where you'd call `process_input("fix 4")` all at once.
+    pub fn process_input(&mut self, key: &str) -> Result<Option<String>, String> {
+        // Log every keystroke
+        self.logger.log_keystroke(key);
+        self.play_cue(FeedbackCue::Key);
+        self.overlay = None;
+        
+        // Log current state before processing
+        self.log_current_state("before processing");
+        
+        if let Some(result) = self.catalog_key(key) {
+            return result;
+        }
+        
+        // A running program hears only R/S (space), which halts it
+        if self.programming.is_running() {
+            return if key == " " { self.execute_command("r/s", None) } else { Ok(None) };
+        }
+        
+        let result = match key {
+            // ALPHA mode: keys type text until it is left
+            "alpha" => self.toggle_alpha_mode(),
+            _ if self.alpha_mode => self.alpha_key(key),
+            
+            // A quoted label takes every character key until it is closed
+            _ if self.command_parser.is_quoting() && !matches!(key, "\u{8}" | "\u{7f}") => self.handle_command_input(key),
+            
+            // Special keys that bypass command parsing
+            ":" => self.toggle_programming_mode(),
+            "F" => Ok(self.toggle_flags()),
+            "\u{8}" | "\u{7f}" => self.handle_backspace(),
+            
+            // The top key rows, and paging under a soft menu
+            _ if top_row_keycode(key).is_some() => self.press_top_key(top_row_keycode(key).unwrap_or_default()),
+            "up" | "down" => {
+                if let Some(menu) = self.menu_mut() {
+                    if key == "up" { menu.prev_page() } else { menu.next_page() }
+                }
+                Ok(None)
+            }
+            
+            // Numbers and decimal go to number entry
+            "." | "0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" => {
+                self.handle_digit(key)
+            }
+            
+            // Everything else goes through unified command processing
+            _ => self.handle_command_input(key),
+        };
+        
+        // Log state after processing
+        self.log_current_state("after processing");
+        
+        if result.is_err() {
+            self.play_cue(FeedbackCue::Error);
+        }
+        
+        result
+    }
+    
+    /// Log current calculator state (helper method)
+    fn log_current_state(&mut self, context: &str) {
+        self.logger.log_stack_state(&self.stack.get_registers(), context);
+        self.logger.log_input_state(
+            self.input.is_entering(), 
+            self.input.is_eex_mode(), 
+            &self.input.get_display_string()
+        );
+        self.logger.log_command_state(&self.command_parser.get_current_state(), context);
+    }
+
+    /// Handle command input using the unified parser
+    fn handle_command_input(&mut self, input: &str) -> Result<Option<String>, String> {
+        self.logger.log_command_state(&self.command_parser.get_current_state(), "before input");
+        
+        match input {
+            " " => {
+                // Space forces manual completion or acts as argument separator
+                if self.command_parser.is_building() {
+                    self.logger.log_debug("PARSER", "Space pressed - forcing completion");
+                    match self.command_parser.force_complete() {
+                        ParseResult::Complete { command, args } => {
+                            self.execute_keyed(&command, args)
+                        }
+                        ParseResult::Invalid(msg) => Err(msg),
+                        ParseResult::Incomplete => Ok(None),
+                    }
+                } else {
+                    self.logger.log_debug("PARSER", "Space pressed with no command - ignored");
+                    Ok(None) // Space with no command does nothing
+                }
+            }
+            
+            "tab" => {
+                // Tab takes the first label a pending GTO/XEQ could complete to
+                match self.command_parser.accept_completion() {
+                    ParseResult::Complete { command, args } => self.execute_keyed(&command, args),
+                    _ => Ok(None),
+                }
+            }
+            
+            "enter" => {
+                // Enter can either complete a command or do ENTER operation
+                if self.command_parser.is_building() {
+                    self.logger.log_debug("PARSER", "Enter pressed - forcing command completion");
+                    match self.command_parser.force_complete() {
+                        ParseResult::Complete { command, args } => {
+                            self.execute_keyed(&command, args)
+                        }
+                        ParseResult::Invalid(msg) => Err(msg),
+                        ParseResult::Incomplete => Ok(None),
+                    }
+                } else {
+                    // No command building, do regular ENTER
+                    self.logger.log_debug("STACK", "Enter pressed - performing ENTER operation");
+                    self.handle_enter()
+                }
+            }
+            
+            _ => {
+                // All other input goes to the command parser
+                if !self.command_parser.is_building() {
+                    self.entry_started = Some(self.clock.elapsed());
+                }
+                self.command_parser.set_labels(self.labels_in_scope());
+                match self.command_parser.add_input(input) {
+                    ParseResult::Complete { command, args } => {
+                        self.logger.log_debug("PARSER", &format!("Command completed: {} {:?}", command, args));
+                        self.execute_keyed(&command, args)
+                    }
+                    ParseResult::Invalid(msg) => {
+                        self.entry_started = None;
+                        self.logger.log_debug("PARSER", &format!("Invalid input: {}", msg));
+                        Err(msg)
+                    }
+                    ParseResult::Incomplete => {
+                        self.logger.log_debug("PARSER", "Command building continues");
+                        Ok(None) // Keep building
+                    }
+                }
+            }
+        }
+    }
+
+    /// Get the current display (for UI)
+    pub fn get_display(&self) -> String {
+        let mut lines = Vec::with_capacity(8);
+        
+        // Stack display (4 lines)
+        self.add_stack_display(&mut lines);
+        
+        // Status line (now includes logging status)
+        lines.push(self.build_status_line());
+        
+        // Program line
+        lines.push(self.build_program_line());
+        
+        // Command reference (2 lines), the first giving way to a soft menu;
+        // ALPHA mode shows its key map instead
+        if self.alpha_mode {
+            lines.extend(KEY_MAP.iter().map(|line| line.to_string()));
+            return lines.join("\n");
+        }
+        lines.push(match self.menu() {
+            Some(menu) => menu.row(),
+            None => "sin cos tan asin acos atan log ln exp sqrt".to_string(),
+        });
+        let cmd_line = if self.show_flags {
+            "pi inv arc  clx clr chs  +/-*^ ! ⌫  : lbl gto xeq sto rcl  F L"
+        } else {
+            "pi inv arc  clx clr chs  +/-*^ ! ⌫  : fix sci eng sto rcl  F L(log)"
+        };
+        lines.push(cmd_line.to_string());
+        
+        lines.join("\n")
+    }
+
+    /// Let running programs write only the registers in `allowed`
+    /// 
+    /// Writes to any other register are recorded and undone when the run
+    /// ends. `None` turns the guard off.
+    pub fn set_register_guard(&mut self, allowed: Option<std::ops::RangeInclusive<usize>>) {
+        self.register_guard = allowed.map(RegisterGuard::new);
+    }
+    
+    /// The active register guard, if any
+    pub fn register_guard(&self) -> Option<&RegisterGuard> {
+        self.register_guard.as_ref()
+    }
+    
+    /// PSE: show the display for a moment and carry on
+    /// 
+    /// The frame is the one a halt would show, X or a VIEW/AVIEW message.
+    /// From the keyboard PSE does nothing.
+    fn execute_pause(&mut self) {
+        if !self.programming.is_running() {
+            return;
+        }
+        let frame = self.lcd_frame();
+        if let Some(display) = self.display.as_mut() {
+            // A display that fails doesn't stop the program
+            let _ = display.0.refresh(&frame);
+        }
+        self.clock.sleep(self.pause);
+    }
+    
+    /// TOC: seconds since the last TIC into X
+    /// 
+    /// The start is kept, so several TOCs time laps from the same TIC.
+    fn execute_toc(&mut self) -> Result<Option<String>, CalculatorError> {
+        let start = self.tic.ok_or_else(|| CommandError::NotAllowed("TOC without TIC".to_string()))?;
+        let seconds = self.clock.elapsed().saturating_sub(start).as_secs_f64();
+        if self.stack.should_lift() {
+            self.stack.lift();
+        }
+        self.stack.set_x(seconds);
+        self.stack.set_lift_flag(true);
+        self.input.clear();
+        Ok(None)
+    }
+    
+    /// Games module: random numbers, dice, dealing and pauses
+    fn execute_games(&mut self, command: &str, args: Option<&[String]>) -> Result<Option<String>, CalculatorError> {
+        let register = || -> Result<usize, CalculatorError> {
+            let arg = args.and_then(<[String]>::first)
+                .ok_or_else(|| CommandError::MissingArgument(command.to_uppercase()))?;
+            arg.parse().map_err(|_| CommandError::InvalidArgument {
+                command: command.to_uppercase(),
+                argument: arg.clone(),
+            }.into())
+        };
+        let result = match command {
+            "rndm" => Some(self.rng.next_f64()),
+            "die" => Some(games::die(&mut self.rng, register()?)?),
+            "seed" => {
+                self.rng.seed(self.stack.x());
+                None
+            }
+            "shuffle" => {
+                games::shuffle(&mut self.rng, &mut self.storage_registers, register()?, self.stack.x())?;
+                None
+            }
+            "delay" => {
+                let seconds = games::delay_seconds(self.stack.x())?;
+                self.clock.sleep(Duration::from_secs_f64(seconds));
+                None
+            }
+            _ => unreachable!(),
+        };
+        if let Some(value) = result {
+            if self.stack.should_lift() {
+                self.stack.lift();
+            }
+            self.stack.set_x(value);
+        }
+        self.stack.set_lift_flag(true);
+        self.input.clear();
+        Ok(None)
+    }
+    
+    /// The Time module's clock functions and date order (see `time_module`)
+    fn execute_time(&mut self, command: &str) -> Result<Option<String>, CalculatorError> {
+        let dmy = self.flags.is_set(FLAG_DMY);
+        let clock = self.clock.as_ref();
+        let (value, message) = match command {
+            "date" => {
+                let date = self.time_module.date(clock)?;
+                (date.to_number(dmy), time_module::format_date(date, dmy))
+            }
+            "time" => {
+                let time = self.time_module.time_of_day(clock);
+                (time_module::time_to_number(time), time_module::format_time(time))
+            }
+            "setdate" => {
+                self.time_module.set_date(clock, Date::from_number(self.stack.x(), dmy)?);
+                return Ok(None);
+            }
+            "settime" => {
+                self.time_module.set_time(clock, time_module::time_from_number(self.stack.x())?);
+                return Ok(None);
+            }
+            "clock" => {
+                self.time_module.clock_shown = !self.time_module.clock_shown;
+                return Ok(None);
+            }
+            "dmy" | "mdy" => {
+                self.flags.set(FLAG_DMY, command == "dmy");
+                return Ok(None);
+            }
+            _ => unreachable!(),
+        };
+        if self.stack.should_lift() {
+            self.stack.lift();
+        }
+        self.stack.set_x(value);
+        self.stack.set_lift_flag(true);
+        self.input.clear();
+        Ok(Some(message))
+    }
+    
+    /// XYZALM: an alarm at the time in X on the date in Y (0 for today),
+    /// repeating every Z (0 for once), with ALPHA as its message
+    fn execute_xyzalm(&mut self) -> Result<Option<String>, CalculatorError> {
+        let [x, y, z, _] = self.stack.get_registers();
+        let time = time_module::time_from_number(x)?;
+        let date = if y == 0.0 { None } else { Some(Date::from_number(y, self.flags.is_set(FLAG_DMY))?) };
+        let repeat = time_module::hms_from_number(z.abs())? / 1000;
+        let due = self.time_module.alarm_due(self.clock.as_ref(), date, time)?;
+        self.add_alarm(Alarm { due, message: self.alpha.text().to_string(), repeat: (repeat > 0).then_some(repeat as u64) });
+        Ok(None)
+    }
+    
+    /// Set off the earliest alarm if it is due, for the front end to call
+    /// while it waits for keys (see `time_module`)
+    /// 
+    /// Gives the alarm's message, or the outcome of the program it runs.
+    pub fn check_alarms(&mut self) -> Option<Result<Option<String>, String>> {
+        let now = self.time_module.now(self.clock.as_ref()).div_euclid(1000);
+        if self.alarms.first()?.due as i64 > now {
+            return None;
+        }
+        let alarm = self.alarms.remove(0);
+        if let Some(repeat) = alarm.repeat.filter(|&repeat| repeat > 0) {
+            let missed = (now - alarm.due as i64) as u64 / repeat;
+            self.add_alarm(Alarm { due: alarm.due + (missed + 1) * repeat, ..alarm.clone() });
+        }
+        self.logger.log_debug("ALARM", &alarm.to_string());
+        if self.flags.is_set(FLAG_AUDIO) {
+            for event in audio::beep() {
+                self.play_audio(event);
+            }
+        }
+        if self.programming.labels.contains_key(&alarm.message) {
+            let result = self.execute_command("xeq", Some(vec![alarm.message]));
+            return Some(self.continue_run(result));
+        }
+        Some(Ok(Some(if alarm.message.is_empty() {
+            time_module::format_time(alarm.due as i64 % 86_400 * 1000)
+        } else {
+            alarm.message
+        })))
+    }
+    
+    /// Whether CLOCK has the time running in the status line, so the
+    /// front end should redraw it every second
+    pub fn is_clock_shown(&self) -> bool {
+        self.time_module.clock_shown
+    }
+    
+    /// Make XEQ nested deeper than six levels an error instead of losing
+    /// the oldest return, as the HP-41 does
+    pub fn set_strict_returns(&mut self, strict: bool) {
+        self.programming.strict_returns = strict;
+    }
+    
+    /// Check ASSERT steps (debug mode) or skip them
+    pub fn set_debug_mode(&mut self, enabled: bool) {
+        self.debug_mode = enabled;
+    }
+    
+    /// Whether ASSERT steps are checked
+    pub fn debug_mode(&self) -> bool {
+        self.debug_mode
+    }
+    
+    /// ASSERT: check a condition in debug mode, halting a running program when it fails
+    fn execute_assert(&mut self, args: Option<&[String]>) -> Result<Option<String>, CalculatorError> {
+        if !self.debug_mode {
+            return Ok(None);
+        }
+        let assertion = Assertion::parse(args.unwrap_or_default())?;
+        if let Err(e) = assertion.check(&self.stack, &self.storage_registers) {
+            if self.programming.is_running() {
+                self.programming.stop();
+            }
+            return Err(e);
+        }
+        Ok(None)
+    }
+    
+    fn execute_watch(&mut self, args: Option<&[String]>) -> Result<Option<String>, CalculatorError> {
+        let watchpoint = Watchpoint::parse(args.unwrap_or_default())?;
+        self.add_watchpoint(watchpoint);
+        Ok(Some(format!("Watching {}", watchpoint)))
+    }
+    
+    /// Assign a function to a key (`ASN`); see `catalog::is_valid_keycode`
+    pub fn assign_key(&mut self, keycode: i32, function: &str) -> Result<(), String> {
+        self.key_assignments.assign(keycode, function)
+    }
+    
+    /// Current key assignments in key code order
+    pub fn key_assignments(&self) -> impl Iterator<Item = KeyAssignment> + '_ {
+        self.key_assignments.iter()
+    }
+    
+    /// Add a pending alarm
+    pub fn add_alarm(&mut self, alarm: Alarm) {
+        let position = self.alarms.partition_point(|a| a.due <= alarm.due);
+        self.alarms.insert(position, alarm);
+    }
+    
+    /// Pending alarms, earliest first
+    pub fn alarms(&self) -> impl Iterator<Item = &Alarm> {
+        self.alarms.iter()
+    }
+    
+    /// Title, description and author of the program at a global label
+    pub fn program_info(&self, label: &str) -> Option<&ProgramInfo> {
+        self.program_info.get(&label.to_uppercase())
+    }
+    
+    /// Describe the program at a global label; empty info removes it
+    /// 
+    /// The info is kept outside program memory, so it takes no registers.
+    pub fn set_program_info(&mut self, label: &str, info: ProgramInfo) {
+        if info.is_empty() {
+            self.program_info.remove(&label.to_uppercase());
+        } else {
+            self.program_info.insert(label.to_uppercase(), info);
+        }
+    }
+    
+    /// Global labels in program memory order
+    fn global_labels(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.programming.program.iter().enumerate().filter_map(|(index, step)| {
+            let label = step.arguments.first()?;
+            (step.command.eq_ignore_ascii_case("lbl") && is_global_label(label)).then_some((index, label.as_str()))
+        })
+    }
+    
+    /// Labels GTO and XEQ can reach from the keyboard: every global label,
+    /// and the local ones of the program at the program counter
+    fn labels_in_scope(&self) -> Vec<String> {
+        let program = &self.programming.program;
+        let segment = self.programming.program_segment(self.programming.program_counter);
+        let mut labels: Vec<String> = Vec::new();
+        for (index, step) in program.iter().enumerate() {
+            let Some(label) = step.arguments.first().map(|label| label.to_uppercase()) else { continue };
+            let in_scope = is_global_label(&label) || segment.contains(&index);
+            if step.command.eq_ignore_ascii_case("lbl") && in_scope && !labels.contains(&label) {
+                labels.push(label);
+            }
+        }
+        labels
+    }
+    
+    /// Extended memory files: SAVEP, PURFL and EMDIR (see `xmem`)
+    fn execute_extended_memory(&mut self, command: &str) -> Result<Option<String>, CalculatorError> {
+        let alpha = self.alpha.text().trim().to_uppercase();
+        match command {
+            "savep" => {
+                let (label, file) = alpha.split_once(',').map_or((alpha.as_str(), alpha.as_str()), |(label, file)| (label.trim(), file.trim()));
+                let label = quoted_label(label);
+                let index = xmem::find_label(&self.programming.program, &label)
+                    .ok_or_else(|| ProgrammingError::LabelNotFound(label_name(&label).to_string()))?;
+                let steps = self.programming.program[self.programming.program_segment(index)].to_vec();
+                self.programming.extended.save_program(file, &steps).map_err(|_| CommandError::InvalidArgument {
+                    command: "SAVEP".to_string(),
+                    argument: file.to_string(),
+                })?;
+                Ok(None)
+            }
+            "purfl" => match self.programming.extended.remove(&alpha) {
+                Some(_) => Ok(None),
+                None => Err(CommandError::Nonexistent(alpha).into()),
+            },
+            _ => {
+                let files: Vec<String> = self.programming.extended.files()
+                    .map(|(name, file)| format!("{} {}{:03}", name, file.type_letter(), file.registers()))
+                    .collect();
+                Ok(Some(if files.is_empty() { "DIR EMPTY".to_string() } else { files.join("\n") }))
+            }
+        }
+    }
+    
+    /// `LBL'NAME` for CAT 1, followed by the program's title if it has one
+    fn describe_label(&self, label: &str) -> String {
+        match self.program_info(label).map(ProgramInfo::summary).filter(|s| !s.is_empty()) {
+            Some(summary) => format!("LBL'{} {}", label_name(label), summary),
+            None => format!("LBL'{}", label_name(label)),
+        }
+    }
+    
+    /// Title of the program holding a step index, for the PRGM header
+    fn program_title_at(&self, index: usize) -> Option<&str> {
+        let (_, label) = self.global_labels().take_while(|&(at, _)| at <= index).last()?;
+        self.program_info(label).map(ProgramInfo::summary).filter(|s| !s.is_empty())
+    }
+    
+    /// Open a catalog for browsing: 1 lists programs, 2 module functions,
+    /// 3 built-in functions, 5 alarms, 6 key assignments
+    pub fn catalog(&self, number: u8) -> Result<Catalog, String> {
+        self.build_catalog(number).map_err(|e| self.messages.error(&e))
+    }
+    
+    fn build_catalog(&self, number: u8) -> Result<Catalog, CalculatorError> {
+        let entries = match number {
+            1 => {
+                let mut entries: Vec<String> = self.programming.program.iter().filter_map(|step| match step.arguments.first() {
+                    Some(label) if step.command.eq_ignore_ascii_case("lbl") && is_global_label(label) => {
+                        Some(self.describe_label(label))
+                    }
+                    None if step.command.eq_ignore_ascii_case("end") => Some("END".to_string()),
+                    _ => None,
+                }).collect();
+                entries.push(".END.".to_string());
+                entries
+            }
+            // Each module's header, as `-GAMES`, then its functions
+            2 => self.modules()
+                .flat_map(|module| {
+                    std::iter::once(format!("-{}", module)).chain(module.commands().iter().map(|name| name.to_uppercase()))
+                })
+                .collect(),
+            3 => {
+                let mut names: Vec<String> = self.command_parser.registry().get_command_names().into_iter()
+                    .filter(|name| Module::for_command(name).is_none())
+                    .map(|name| name.to_uppercase())
+                    .collect();
+                names.sort();
+                names
+            }
+            5 if !self.has_module(Module::Time) => {
+                return Err(CommandError::Nonexistent("CAT 5".to_string()).into());
+            }
+            5 => self.alarms().map(|a| a.to_string()).collect(),
+            6 => self.key_assignments().map(|a| a.to_string()).collect(),
+            _ => {
+                return Err(CommandError::InvalidArgument { command: "CAT".to_string(), argument: number.to_string() }.into());
+            }
+        };
+        Ok(Catalog::new(number, entries))
+    }
+    
+    /// CAT n: scroll a catalog through the display
+    fn execute_catalog(&mut self, args: Option<&[String]>) -> Result<Option<String>, CalculatorError> {
+        let arg = args.and_then(|args| args.first())
+            .ok_or_else(|| CommandError::MissingArgument("CAT".to_string()))?;
+        let number = arg.parse().map_err(|_| CommandError::InvalidArgument {
+            command: "CAT".to_string(),
+            argument: arg.clone(),
+        })?;
+        let catalog = self.build_catalog(number)?;
+        if catalog.is_empty() {
+            return Ok(Some(catalog.to_string()));
+        }
+        self.catalog_run = Some(CatalogRun::new(catalog, self.clock.elapsed()));
+        self.show_catalog_entry();
+        Ok(None)
+    }
+    
+    /// Keys while a catalog scrolls: space is R/S, Enter SST and ⌫ BST
+    /// 
+    /// Letters pass on, since they may be spelling R/S; the command they
+    /// complete decides whether the catalog goes on. Any other key ends it
+    /// and then acts as usual.
+    fn catalog_key(&mut self, key: &str) -> Option<Result<Option<String>, String>> {
+        self.catalog_run.as_ref()?;
+        if self.command_parser.is_building() {
+            self.show_catalog_entry();
+            return None;
+        }
+        let now = self.clock.elapsed();
+        let run = self.catalog_run.as_mut()?;
+        match key {
+            " " => run.run_stop(now),
+            "enter" => run.step(true),
+            "\u{8}" => run.step(false),
+            _ if key.chars().all(char::is_alphabetic) => {
+                self.show_catalog_entry();
+                return None;
+            }
+            _ => {
+                self.catalog_run = None;
+                return None;
+            }
+        }
+        self.show_catalog_entry();
+        Some(Ok(None))
+    }
+    
+    fn show_catalog_entry(&mut self) {
+        self.overlay = self.catalog_run.as_ref().and_then(|run| run.catalog().current()).map(str::to_string);
+    }
+    
+    /// Whether CAT is scrolling, so the front end should call
+    /// `advance_catalog` while it waits for keys
+    pub fn is_catalog_running(&self) -> bool {
+        self.catalog_run.as_ref().is_some_and(|run| !run.is_stopped())
+    }
+    
+    /// Move a scrolling catalog on when its entry has been shown long
+    /// enough; true when the display changed
+    /// 
+    /// The catalog ends after its last entry.
+    pub fn advance_catalog(&mut self) -> bool {
+        let now = self.clock.elapsed();
+        let Some(run) = self.catalog_run.as_mut() else { return false };
+        let position = run.catalog().current().map(str::to_string);
+        if !run.tick(now) {
+            self.catalog_run = None;
+            self.overlay = None;
+            return true;
+        }
+        let moved = run.catalog().current().map(str::to_string) != position;
+        self.show_catalog_entry();
+        moved
+    }
+    
+    /// The user and system flags
+    pub fn flags(&self) -> &Flags {
+        &self.flags
+    }
+    
+    /// First register of the statistics block
+    pub fn sigma_reg(&self) -> usize {
+        self.sigma_reg
+    }
+    
+    /// ΣREG nn: move the statistics block, which must fit the current SIZE
+    fn execute_sigma_reg(&mut self, args: Option<&[String]>) -> Result<Option<String>, CalculatorError> {
+        let arg = args.and_then(|args| args.first())
+            .ok_or_else(|| CommandError::MissingArgument("ΣREG".to_string()))?;
+        let base: usize = arg.parse().map_err(|_| CommandError::InvalidArgument {
+            command: "ΣREG".to_string(),
+            argument: arg.clone(),
+        })?;
+        statistics::check_base(base, self.storage_registers.len())?;
+        self.sigma_reg = base;
+        self.input.clear();
+        Ok(None)
+    }
+    
+    /// Stack depth: classic four levels, more, or unlimited
+    pub fn stack_depth(&self) -> StackDepth {
+        self.stack.depth()
+    }
+    
+    /// Use a non-authentic stack depth
+    pub fn set_stack_depth(&mut self, depth: StackDepth) {
+        self.stack.set_depth(depth);
+    }
+    
+    /// The calculator model
+    pub fn model(&self) -> Model {
+        self.model
+    }
+    
+    /// Switch to another model
+    /// 
+    /// Like fitting the memory into a different machine: the data
+    /// registers are cleared to the new model's default SIZE. Plugged
+    /// modules stay plugged.
+    pub fn set_model(&mut self, model: Model) {
+        self.model = model;
+        self.storage_registers = vec![0.0; model.default_size()];
+        self.programming.room = model.total_registers() - model.default_size();
+        self.plugged_modules.retain(|module| !model.builtin_modules().contains(module));
+    }
+    
+    /// The ALPHA register's text
+    pub fn alpha(&self) -> &str {
+        self.alpha.text()
+    }
+    
+    /// Replace the ALPHA register's text (the last 24 characters are kept)
+    pub fn set_alpha(&mut self, text: &str) {
+        self.alpha.set(text);
+    }
+    
+    /// Whether keys type into ALPHA
+    pub fn is_alpha_mode(&self) -> bool {
+        self.alpha_mode
+    }
+    
+    /// Switch ALPHA mode (the ALPHA key); leaving it ends the text being typed
+    fn toggle_alpha_mode(&mut self) -> Result<Option<String>, String> {
+        self.end_alpha_entry()?;
+        self.alpha_mode = !self.alpha_mode;
+        Ok(None)
+    }
+    
+    /// A key in ALPHA mode
+    /// 
+    /// A character starts new text in ALPHA, or adds to it after Tab
+    /// (APPEND). ⌫ takes back the last character typed; with nothing being
+    /// typed it clears ALPHA, or deletes the step in PRGM mode. Enter ends
+    /// the text, which PRGM mode records as a text line.
+    fn alpha_key(&mut self, key: &str) -> Result<Option<String>, String> {
+        match key {
+            "tab" => {
+                self.end_alpha_entry()?;
+                self.alpha_entry = Some(AlphaEntry { text: String::new(), append: true });
+            }
+            "enter" => self.end_alpha_entry()?,
+            "\u{8}" | "\u{7f}" => match self.alpha_entry.as_mut() {
+                Some(entry) => {
+                    if entry.text.pop().is_some() && !self.programming.is_programming {
+                        self.alpha.pop();
+                    }
+                }
+                None if self.programming.is_programming => return self.handle_backspace(),
+                None => self.alpha.clear(),
+            },
+            _ => {
+                let mut chars = key.chars();
+                let (Some(c), None) = (chars.next(), chars.next()) else { return Ok(None) };
+                let programming = self.programming.is_programming;
+                let entry = self.alpha_entry.get_or_insert_with(AlphaEntry::default);
+                if programming {
+                    let limit = TEXT_LINE_LENGTH - usize::from(entry.append);
+                    if entry.text.chars().count() < limit {
+                        entry.text.push(c);
+                    }
+                } else {
+                    if entry.text.is_empty() && !entry.append {
+                        self.alpha.clear();
+                    }
+                    entry.text.push(c);
+                    self.alpha.append(&c.to_string());
+                }
+            }
+        }
+        Ok(None)
+    }
+    
+    /// Finish the text being typed, recording it as a step in PRGM mode
+    fn end_alpha_entry(&mut self) -> Result<(), String> {
+        if let Some(entry) = self.alpha_entry.take() {
+            if !entry.text.is_empty() || entry.append {
+                self.programming.add_text_line(&entry.text, entry.append)
+                    .map_err(|e| self.report_error(e.into()))?;
+            }
+        }
+        Ok(())
+    }
+    
+    /// ALPHA as ALPHA mode shows it, with a cursor while text is typed
+    /// 
+    /// The newest characters are kept when it is wider than `width`.
+    fn alpha_line(&self, width: usize) -> String {
+        let mut text = match &self.alpha_entry {
+            Some(entry) if self.programming.is_programming => {
+                format!("{}\"{}_", if entry.append { "⊢" } else { "" }, entry.text)
+            }
+            Some(_) => format!("{}_", self.alpha.text()),
+            None => self.alpha.text().to_string(),
+        };
+        let excess = text.chars().count().saturating_sub(width);
+        if excess > 0 {
+            text = text.chars().skip(excess).collect();
+        }
+        text
+    }
+    
+    /// Empty the ALPHA register (`CLA`)
+    pub fn clear_alpha(&mut self) {
+        self.alpha.clear();
+    }
+    
+    /// ASTO nn: the first six ALPHA characters into Rnn; ARCL nn: Rnn appended to ALPHA
+    fn execute_alpha_transfer(&mut self, command: &str, args: Option<&[String]>) -> Result<Option<String>, CalculatorError> {
+        let register = self.register_argument(command, args)?;
+        if command == "asto" {
+            self.storage_registers[register] = self.alpha.to_register();
+        } else {
+            self.alpha.recall(self.storage_registers[register], &self.display_formatter);
+        }
+        Ok(None)
+    }
+    
+    /// KEY n: slot n of the user menu runs XEQ of the label in ALPHA, or
+    /// is cleared when ALPHA is empty
+    fn execute_menu_key(&mut self, args: Option<&[String]>) -> Result<Option<String>, CalculatorError> {
+        let arg = args.and_then(|args| args.first())
+            .ok_or_else(|| CommandError::MissingArgument("KEY".to_string()))?;
+        let slot = arg.parse().ok().filter(|slot| (1..=menu::SLOTS).contains(slot))
+            .ok_or_else(|| CommandError::InvalidArgument { command: "KEY".to_string(), argument: arg.clone() })?;
+        let label = self.alpha.text().trim();
+        self.user_menu.set(slot, (!label.is_empty()).then(|| MenuItem::xeq(label)));
+        Ok(None)
+    }
+    
+    /// MENU: show the menu of the module named in ALPHA, else the user menu
+    fn execute_menu(&mut self) -> Result<Option<String>, CalculatorError> {
+        self.module_menu = match Module::from_name(self.alpha.text()) {
+            Some(module) if !self.has_module(module) => {
+                return Err(CommandError::Nonexistent(format!("MENU {}", module)).into());
+            }
+            Some(module) => Some(SoftMenu::for_module(module)),
+            None => None,
+        };
+        self.menu_shown = true;
+        Ok(None)
+    }
+    
+    /// The soft menu on the top key row, if one is shown
+    pub fn menu(&self) -> Option<&SoftMenu> {
+        self.menu_shown.then(|| self.module_menu.as_ref().unwrap_or(&self.user_menu))
+    }
+    
+    fn menu_mut(&mut self) -> Option<&mut SoftMenu> {
+        self.menu_shown.then(|| self.module_menu.as_mut().unwrap_or(&mut self.user_menu))
+    }
+    
+    /// A key of the top two rows, by key code
+    /// 
+    /// Under a soft menu the first six keys are its slots. In USER mode a
+    /// key assignment comes first, then a letter label of the program at
+    /// the program counter (A-E and F-J on the two rows, a-e shifted on
+    /// the first). Otherwise the key does what its legend says.
+    fn press_top_key(&mut self, keycode: i32) -> Result<Option<String>, String> {
+        if self.menu().is_some() {
+            return match keycode {
+                11..=15 | 21 => self.press_menu_key(if keycode == 21 { 6 } else { keycode as usize - 10 }),
+                _ => Ok(None),
+            };
+        }
+        if self.flags.is_set(FLAG_USER) {
+            if let Some(function) = self.key_assignments.get(keycode).map(str::to_lowercase) {
+                self.command_parser.clear();
+                return if self.command_parser.registry().has_command(&function) {
+                    self.handle_command_input(&function)
+                } else {
+                    self.execute_keyed("xeq", Some(vec![function.to_uppercase()]))
+                };
+            }
+            let label = top_key_label(keycode);
+            if self.has_local_label(&label) {
+                self.command_parser.clear();
+                return self.execute_keyed("xeq", Some(vec![label]));
+            }
+        }
+        let Some(&(_, function)) = TOP_KEY_FUNCTIONS.iter().find(|&&(code, _)| code == keycode) else {
+            return Ok(None);
+        };
+        self.command_parser.clear();
+        self.handle_command_input(function)
+    }
+    
+    /// Whether the program at the program counter has `LBL label`
+    fn has_local_label(&self, label: &str) -> bool {
+        let program = &self.programming.program;
+        self.programming.program_segment(self.programming.program_counter).any(|index| {
+            program[index].command.eq_ignore_ascii_case("lbl") && program[index].arguments.first().is_some_and(|arg| arg == label)
+        })
+    }
+    
+    /// A top-row key (1-6) under a soft menu: a function starts as if
+    /// typed, so one with arguments waits for them; a label is run
+    fn press_menu_key(&mut self, key: usize) -> Result<Option<String>, String> {
+        let Some(item) = self.menu().and_then(|menu| menu.slot(key)).cloned() else {
+            return Ok(None);
+        };
+        match item.action {
+            MenuAction::Command(name) => {
+                self.command_parser.clear();
+                self.handle_command_input(&name)
+            }
+            MenuAction::Xeq(label) => self.execute_keyed("xeq", Some(vec![label])),
+        }
+    }
+    
+    /// TONE n and BEEP, on the audio device while flag 26 is set
+    fn execute_sound(&mut self, command: &str, args: Option<&[String]>) -> Result<Option<String>, CalculatorError> {
+        let events = if command == "tone" {
+            let tone = args.and_then(|args| args.first()).ok_or(CommandError::MissingArgument("TONE".to_string()))?;
+            let tone = tone.parse::<u8>().ok().filter(|&tone| tone <= 9).ok_or_else(|| CommandError::InvalidArgument {
+                command: "TONE".to_string(),
+                argument: tone.clone(),
+            })?;
+            vec![AudioEvent::Tone { tone, millis: TONE_MS }]
+        } else {
+            audio::beep()
+        };
+        if self.flags.is_set(FLAG_AUDIO) {
+            for event in events {
+                self.play_audio(event);
+            }
         }
+        Ok(None)
     }
     
-    /// Create a calculator with debug logging enabled
-    pub fn new_with_debug_logging() -> Self {
-        let mut calc = Self::new();
-        calc.logger = Logger::debug_all();
-        calc.logger.log_debug("INIT", "Calculator created with debug logging enabled");
-        calc
+    /// VIEW nn shows a register, AVIEW the ALPHA register, without touching the stack
+    fn execute_view(&mut self, command: &str, args: Option<&[String]>) -> Result<Option<String>, CalculatorError> {
+        let text = if command == "view" {
+            let register = self.register_argument(command, args)?;
+            self.display_formatter.format_number(self.storage_registers[register], LCD_WIDTH)
+        } else {
+            self.alpha.text().to_string()
+        };
+        self.overlay = Some(text);
+        Ok(None)
     }
     
-    /// Create a calculator with file logging enabled to a default location
-    pub fn new_with_file_logging() -> Result<Self, String> {
-        let mut calc = Self::new();
-        let log_path = "hp41c_debug.log";
-        calc.enable_file_logging(log_path)?;
-        calc.logger = crate::logger::Logger::debug_all();
-        match calc.logger.enable_file_logging(log_path) {
-            Ok(()) => {
-                calc.logger.log_debug("INIT", "Calculator created with file logging enabled");
-                Ok(calc)
-            }
-            Err(e) => Err(format!("Failed to initialize file logging: {}", e))
+    /// The text VIEW or AVIEW put in the display, until the next key clears it
+    pub fn overlay(&self) -> Option<&str> {
+        self.overlay.as_deref()
+    }
+    
+    /// Take a VIEW/AVIEW message down, showing X again (`CLD`)
+    pub fn clear_overlay(&mut self) {
+        self.overlay = None;
+    }
+    
+    /// The data register named by a command's argument
+    fn register_argument(&self, command: &str, args: Option<&[String]>) -> Result<usize, CalculatorError> {
+        let arg = args.and_then(<[String]>::first)
+            .ok_or_else(|| CommandError::MissingArgument(command.to_uppercase()))?;
+        let register: usize = arg.parse().map_err(|_| CommandError::InvalidArgument {
+            command: command.to_uppercase(),
+            argument: arg.clone(),
+        })?;
+        if register >= self.storage_registers.len() {
+            return Err(StorageError::Nonexistent(register).into());
         }
+        Ok(register)
     }
     
-    /// Get mutable reference to logger for configuration
-    pub fn logger_mut(&mut self) -> &mut Logger {
-        &mut self.logger
+    /// Number of data registers (SIZE)
+    pub fn size(&self) -> usize {
+        self.storage_registers.len()
     }
     
-    /// Get reference to logger for status
-    pub fn logger(&self) -> &Logger {
-        &self.logger
+    /// Main memory registers left between the data registers and the program
+    /// 
+    /// Registers holding only nulls of deleted steps count as used until
+    /// PACK.
+    pub fn free_registers(&self) -> usize {
+        self.model.total_registers()
+            .saturating_sub(self.storage_registers.len() + self.programming.registers_used())
     }
-
-    /// Enable file logging to a specific path
-    pub fn enable_file_logging<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<Option<String>, String> {
-        match self.logger.enable_file_logging(path) {
-            Ok(()) => {
-                if let Some(path) = self.logger.get_log_file_path() {
-                    Ok(Some(format!("File logging enabled: {}", path.display())))
-                } else {
-                    Ok(Some("File logging enabled".to_string()))
-                }
-            }
-            Err(e) => Err(format!("Failed to enable file logging: {}", e))
+    
+    /// `REG 38`, the free registers as PRGM mode shows them at `.END.`
+    pub fn memory_message(&self) -> String {
+        format!("REG {:02}", self.free_registers())
+    }
+    
+    /// SIZE nnn: move the curtain between program memory and data registers
+    /// 
+    /// Registers below the new size keep their values; registers removed by
+    /// a smaller SIZE are lost (and unprotected). Program memory is packed
+    /// first.
+    fn execute_size(&mut self, args: Option<&[String]>) -> Result<Option<String>, CalculatorError> {
+        let arg = args.and_then(<[String]>::first).ok_or_else(|| CommandError::MissingArgument("SIZE".to_string()))?;
+        let size: usize = arg.parse().map_err(|_| CommandError::InvalidArgument {
+            command: "SIZE".to_string(),
+            argument: arg.clone(),
+        })?;
+        self.programming.pack();
+        if size + self.programming.registers_used() > self.model.total_registers() {
+            return Err(StorageError::NoRoom.into());
         }
+        self.storage_registers.resize(size, 0.0);
+        self.programming.room = self.model.total_registers() - size;
+        if size < self.model.total_registers() {
+            self.protection.unprotect(size..=self.model.total_registers());
+        }
+        Ok(None)
     }
     
-    /// Disable file logging
-    pub fn disable_file_logging(&mut self) -> Result<Option<String>, String> {
-        match self.logger.disable_file_logging() {
-            Ok(()) => Ok(Some("File logging disabled".to_string())),
-            Err(e) => Err(format!("Failed to disable file logging: {}", e))
+    /// Whether a module's functions are available
+    pub fn has_module(&self, module: Module) -> bool {
+        self.model.builtin_modules().contains(&module) || self.plugged_modules.contains(&module)
+    }
+    
+    /// All available modules, built in or plugged
+    pub fn modules(&self) -> impl Iterator<Item = Module> + '_ {
+        Module::ALL.into_iter().filter(|&module| self.has_module(module))
+    }
+    
+    /// Plug in a module; plugging a built-in module does nothing
+    pub fn plug_module(&mut self, module: Module) {
+        if !self.model.builtin_modules().contains(&module) {
+            self.plugged_modules.insert(module);
         }
     }
     
-    /// Get current log file path
-    pub fn get_log_file_path(&self) -> Option<&std::path::Path> {
-        self.logger.get_log_file_path()
+    /// Remove a plugged module; built-in modules can't be removed
+    pub fn unplug_module(&mut self, module: Module) -> Result<(), String> {
+        if self.model.builtin_modules().contains(&module) {
+            return Err(format!("{} is built into the {}", module, self.model));
+        }
+        self.plugged_modules.remove(&module);
+        Ok(())
     }
-
-    /// Execute a command with the given arguments (for internal use)
-    pub fn execute_command(&mut self, command: &str, args: Option<Vec<String>>) -> Result<Option<String>, String> {
-        // Log command execution attempt
-        self.logger.log_command_execution(command, &args, "starting");
-        
-        // Capture stack state before execution
-        let stack_before = self.stack.get_registers();
-        
-        let result = execute_command(
-            command,
-            args.clone(),
-            &mut self.stack,
-            &mut self.input,
-            &mut self.programming,
-            &mut self.display_formatter,
-            &mut self.storage_registers,
-        ).map_err(|e| e.to_string());
-        
-        // Log the result and any stack changes
-        match &result {
-            Ok(Some(msg)) => {
-                self.logger.log_command_execution(command, &args, msg);
-            }
-            Ok(None) => {
-                self.logger.log_command_execution(command, &args, "completed");
+    
+    /// ASN function keycode; an empty function name clears the key
+    fn execute_assign(&mut self, args: Option<Vec<String>>) -> Result<Option<String>, CalculatorError> {
+        let args = args.ok_or(CommandError::MissingArgument("ASN".to_string()))?;
+        let invalid = || CommandError::InvalidArgument {
+            command: "ASN".to_string(),
+            argument: args.join(" "),
+        };
+        let (function, keycode) = match args.as_slice() {
+            [code] => (None, code),
+            [function, code] => (Some(function), code),
+            _ => return Err(invalid().into()),
+        };
+        let keycode: i32 = keycode.parse().map_err(|_| invalid())?;
+        match function {
+            Some(function) => {
+                self.key_assignments.assign(keycode, function).map_err(|_| invalid())?;
+                Ok(Some(format!("ASN {} {}", function.to_uppercase(), keycode)))
             }
-            Err(e) => {
-                self.logger.log_command_execution(command, &args, &format!("ERROR: {}", e));
+            None => {
+                self.key_assignments.clear(keycode);
+                Ok(Some(format!("ASN {} cleared", keycode)))
             }
         }
-        
-        // Log stack changes if they occurred
-        let stack_after = self.stack.get_registers();
-        if stack_before != stack_after {
-            self.logger.log_stack_operation(&format!("{} command", command), &stack_before, &stack_after);
-        }
-        
-        result
     }
-
-    /// Process a single keystroke with comprehensive logging
-    /// 
-    /// ## CRITICAL: Single Keystroke Processing
-    /// 
-    /// This method processes exactly ONE keystroke at a time, just like the original HP-41C:
-    /// 
-    /// - `process_input("f")` - User pressed the 'f' key
-    /// - `process_input("i")` - User pressed the 'i' key  
-    /// - `process_input("x")` - User pressed the 'x' key → "fix" command ready
-    /// - `process_input("4")` - User pressed the '4' key → "fix 4" executes
-    /// 
-    /// NOT like a command line where you'd call `process_input("fix 4")` all at once.
-    pub fn process_input(&mut self, key: &str) -> Result<Option<String>, String> {
-        // Log every keystroke
-        self.logger.log_keystroke(key);
-        
-        // Log current state before processing
-        self.log_current_state("before processing");
-        
-        let result = match key {
-            // Special keys that bypass command parsing
-            ":" => self.toggle_programming_mode(),
-            "F" => Ok(self.toggle_flags()),
-            "\u{8}" | "\u{7f}" => self.handle_backspace(),
-            
-            // Numbers and decimal go to number entry
-            "." | "0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" => {
-                self.handle_digit(key)
-            }
-            
-            // Everything else goes through unified command processing
-            _ => self.handle_command_input(key),
+    
+    fn stores_into_protected(&self, args: Option<&[String]>) -> bool {
+        args.and_then(|args| args.first()?.parse::<usize>().ok())
+            .is_some_and(|register| self.protection.is_protected(register))
+    }
+    
+    /// PROTECT / UNPROTECT with a first and last register, one register,
+    /// or nothing (list the protected registers / unprotect all)
+    fn execute_protect(&mut self, command: &str, args: Option<Vec<String>>) -> Result<Option<String>, CalculatorError> {
+        let Some(args) = args else {
+            return Ok(Some(if command == "unprotect" {
+                self.protection.clear();
+                "No protected registers".to_string()
+            } else if self.protection.is_empty() {
+                "No protected registers".to_string()
+            } else {
+                format!("Protected {}", self.protection)
+            }));
         };
-        
-        // Log state after processing
-        self.log_current_state("after processing");
-        
-        result
+        let invalid = || CommandError::InvalidArgument {
+            command: command.to_uppercase(),
+            argument: args.join(" "),
+        };
+        let registers: Vec<usize> = args.iter()
+            .map(|arg| arg.parse::<usize>().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        let (first, last) = match registers.as_slice() {
+            [register] => (*register, *register),
+            [first, last] if first <= last => (*first, *last),
+            _ => return Err(invalid().into()),
+        };
+        if last >= self.storage_registers.len() {
+            return Err(StorageError::InvalidRegister(last).into());
+        }
+        if command == "protect" {
+            self.protection.protect(first..=last);
+        } else {
+            self.protection.unprotect(first..=last);
+        }
+        Ok(Some(match (command, first == last) {
+            ("protect", true) => format!("Protected R{:02}", first),
+            ("protect", false) => format!("Protected R{:02}-R{:02}", first, last),
+            (_, true) => format!("Unprotected R{:02}", first),
+            (_, false) => format!("Unprotected R{:02}-R{:02}", first, last),
+        }))
     }
     
-    /// Log current calculator state (helper method)
-    fn log_current_state(&mut self, context: &str) {
-        self.logger.log_stack_state(&self.stack.get_registers(), context);
-        self.logger.log_input_state(
-            self.input.is_entering(), 
-            self.input.is_eex_mode(), 
-            &self.input.get_display_string()
-        );
-        self.logger.log_command_state(&self.command_parser.get_current_state(), context);
+    /// Read-only registers
+    pub fn protection(&self) -> &RegisterProtection {
+        &self.protection
+    }
+    
+    /// Numbered listing lines for a range of step numbers; see
+    /// `ProgrammingMode::list`
+    pub fn list_program<R: std::ops::RangeBounds<usize>>(&self, range: R) -> Vec<String> {
+        self.programming.list(range)
+    }
+    
+    /// Label cross-reference for the program in memory
+    pub fn cross_reference(&self) -> CrossReference {
+        CrossReference::build(&self.programming.program)
     }
 
-    /// Handle command input using the unified parser
-    fn handle_command_input(&mut self, input: &str) -> Result<Option<String>, String> {
-        self.logger.log_command_state(&self.command_parser.get_current_state(), "before input");
-        
-        match input {
-            " " => {
-                // Space forces manual completion or acts as argument separator
-                if self.command_parser.is_building() {
-                    self.logger.log_debug("PARSER", "Space pressed - forcing completion");
-                    match self.command_parser.force_complete() {
-                        ParseResult::Complete { command, args } => {
-                            self.execute_command(&command, args)
-                        }
-                        ParseResult::Invalid(msg) => Err(msg),
-                        ParseResult::Incomplete => Ok(None),
-                    }
-                } else {
-                    self.logger.log_debug("PARSER", "Space pressed with no command - ignored");
-                    Ok(None) // Space with no command does nothing
-                }
-            }
-            
-            "enter" => {
-                // Enter can either complete a command or do ENTER operation
-                if self.command_parser.is_building() {
-                    self.logger.log_debug("PARSER", "Enter pressed - forcing command completion");
-                    match self.command_parser.force_complete() {
-                        ParseResult::Complete { command, args } => {
-                            self.execute_command(&command, args)
-                        }
-                        ParseResult::Invalid(msg) => Err(msg),
-                        ParseResult::Incomplete => Ok(None),
-                    }
-                } else {
-                    // No command building, do regular ENTER
-                    self.logger.log_debug("STACK", "Enter pressed - performing ENTER operation");
-                    self.handle_enter()
-                }
-            }
-            
-            _ => {
-                // All other input goes to the command parser
-                match self.command_parser.add_input(input) {
-                    ParseResult::Complete { command, args } => {
-                        self.logger.log_debug("PARSER", &format!("Command completed: {} {:?}", command, args));
-                        self.execute_command(&command, args)
-                    }
-                    ParseResult::Invalid(msg) => {
-                        self.logger.log_debug("PARSER", &format!("Invalid input: {}", msg));
-                        Err(msg)
-                    }
-                    ParseResult::Incomplete => {
-                        self.logger.log_debug("PARSER", "Command building continues");
-                        Ok(None) // Keep building
-                    }
-                }
-            }
-        }
+    /// Duplicate labels, unreachable code and missing labels in the program
+    pub fn lint_program(&self) -> Vec<LintIssue> {
+        lint(&self.programming.program)
     }
 
-    /// Get the current display (for UI)
-    pub fn get_display(&self) -> String {
-        let mut lines = Vec::with_capacity(8);
-        
-        // Stack display (4 lines)
-        self.add_stack_display(&mut lines);
-        
-        // Status line (now includes logging status)
-        lines.push(self.build_status_line());
-        
-        // Program line
-        lines.push(self.build_program_line());
-        
-        // Command reference (2 lines)
-        lines.push("sin cos tan asin acos atan log ln exp sqrt".to_string());
-        let cmd_line = if self.show_flags {
-            "pi inv arc  clx clr chs  +/-*^ ! ⌫  : lbl gto xeq sto rcl  F L"
+    /// Build the 12-character LCD frame for the current state
+    /// 
+    /// Shows the program step in PRGM mode, a VIEW or AVIEW message, the
+    /// number being keyed in during entry, and otherwise the X register in
+    /// the active display format.
+    pub fn lcd_frame(&self) -> LcdFrame {
+        let text = if self.programming.is_programming && self.alpha_entry.is_some() {
+            self.alpha_line(LCD_WIDTH)
+        } else if self.programming.is_programming {
+            self.programming.get_current_step_display()
+        } else if let Some(overlay) = &self.overlay {
+            overlay.clone()
+        } else if self.alpha_mode {
+            self.alpha_line(LCD_WIDTH)
+        } else if self.input.is_entering() {
+            self.input.get_display_string()
         } else {
-            "pi inv arc  clx clr chs  +/-*^ ! ⌫  : fix sci eng sto rcl  F L(log)"
+            self.display_formatter.format_number(self.stack.x(), LCD_WIDTH)
         };
-        lines.push(cmd_line.to_string());
         
-        lines.join("\n")
+        let mut annunciators = Annunciators::default();
+        annunciators.set(Annunciators::ALPHA, self.alpha_mode);
+        annunciators.set(Annunciators::PRGM, self.programming.is_programming);
+        annunciators.set(Annunciators::USER, self.flags.is_set(FLAG_USER));
+        // Flags 0-4 have indicators of their own
+        for flag in 0..=4 {
+            annunciators.set(Annunciators::FLAG_0 << flag, self.flags.is_set(flag));
+        }
+        
+        LcdFrame::new(&text, annunciators)
+    }
+    
+    /// Send the current LCD frame to a display device
+    pub fn refresh_display(&self, sink: &mut dyn DisplaySink) -> std::io::Result<()> {
+        sink.refresh(&self.lcd_frame())
     }
 
     // === Private Implementation Details ===
@@ -355,7 +2827,7 @@ impl HP41CCalculator {
     fn handle_digit(&mut self, key: &str) -> Result<Option<String>, String> {
         if self.programming.is_programming && !self.command_parser.is_building() {
             self.logger.log_programming("digit_entry", &format!("Adding digit '{}' to program", key));
-            self.programming.add_instruction(key, None, key);
+            self.programming.add_instruction(key, None, key).map_err(|e| self.report_error(e.into()))?;
             Ok(None)
         } else if self.command_parser.is_building() {
             // Digit might be an argument to a command
@@ -364,6 +2836,7 @@ impl HP41CCalculator {
         } else {
             // Regular number entry
             self.logger.log_debug("INPUT", &format!("Number entry: digit '{}'", key));
+            self.confirmations.cancel();
             
             let stack_before = self.stack.get_registers();
             let should_lift_before = self.stack.should_lift();
@@ -394,26 +2867,33 @@ impl HP41CCalculator {
 
     fn handle_enter(&mut self) -> Result<Option<String>, String> {
         self.logger.log_debug("STACK", "ENTER operation");
-        self.execute_command("enter", None)
+        self.execute_keyed("enter", None)
     }
 
     fn add_stack_display(&self, lines: &mut Vec<String>) {
-        let registers = self.stack.get_registers();
-        let names = ["T:", "Z:", "Y:", "X:"];
+        let levels = self.stack.levels();
+        let names = ["X:", "Y:", "Z:", "T:"];
         
-        for i in 0..4 {
-            let value = registers[3 - i];
-            let formatted = if i == 3 && self.input.is_entering() {
-                self.input.get_display_string()
+        // Deeper stacks show their extra levels above T, numbered from 5
+        for (i, &value) in levels.iter().enumerate().rev() {
+            let name = names.get(i).map_or_else(|| format!("{}:", i + 1), |name| name.to_string());
+            let (name, formatted) = if i == 0 && self.alpha_mode && !self.programming.is_programming {
+                ("α:".to_string(), self.alpha_line(35))
+            } else if let (0, Some(overlay)) = (i, &self.overlay) {
+                (name, overlay.clone())
+            } else if i == 0 && self.input.is_entering() {
+                (name, self.input.get_display_string())
             } else {
-                self.display_formatter.format_number(value, 35)
+                (name, self.display_formatter.format_number(value, 35))
             };
-            lines.push(format!("{} {:<35}", names[i], formatted));
+            lines.push(format!("{:<2} {:<35}", name, formatted));
         }
     }
 
     fn build_status_line(&self) -> String {
         let mut parts = vec![self.command_parser.get_current_state()];
+        parts.extend(self.register_arithmetic_preview());
+        parts.extend(self.label_completion_preview());
         
         if self.show_flags {
             parts.push(format!("EN:{}", if self.input.is_entering() { 1 } else { 0 }));
@@ -423,9 +2903,26 @@ impl HP41CCalculator {
         
         parts.push(self.display_formatter.get_mode_string());
         
+        if !self.stack.depth().is_classic() {
+            parts.push(format!("STK:{}", self.stack.depth()));
+        }
+        
+        if self.alpha_mode {
+            parts.push("ALPHA".to_string());
+        }
+        
         if self.programming.is_programming {
             parts.push("PRGM".to_string());
             parts.push(format!("L{:02}", self.programming.current_line));
+            // The step before the insertion point is the one being edited
+            let edited = (self.programming.current_line as usize).checked_sub(2);
+            if let Some(title) = edited.and_then(|index| self.program_title_at(index)) {
+                parts.push(format!("\"{}\"", title));
+            }
+        }
+        
+        if self.time_module.clock_shown {
+            parts.push(time_module::format_time(self.time_module.time_of_day(self.clock.as_ref())));
         }
         
         // Add logging status (compact format)
@@ -434,16 +2931,56 @@ impl HP41CCalculator {
         parts.join(" ")
     }
 
+    /// What a pending STO+ / RCL+ etc. would leave behind, e.g. `R05→8.0000`
+    /// 
+    /// Shown while the register number is still being typed; a single
+    /// digit addresses R00-R09, as it would if the command were completed
+    /// with ENTER.
+    fn register_arithmetic_preview(&self) -> Option<String> {
+        let (command, args) = self.command_parser.pending()?;
+        if !matches!(command, "sto+" | "sto-" | "sto*" | "sto/" | "rcl+" | "rcl-" | "rcl*" | "rcl/") {
+            return None;
+        }
+        let register = args.first()?.parse::<usize>().ok()?;
+        let value = *self.storage_registers.get(register)?;
+        let target = if command.starts_with("sto") { format!("R{:02}", register) } else { "X".to_string() };
+        Some(match register_arithmetic(command, self.stack.x(), value) {
+            Ok(result) => format!("{}→{}", target, self.display_formatter.format_number(result, LCD_WIDTH)),
+            Err(_) => format!("{}→ERR", target),
+        })
+    }
+
+    /// Labels a pending GTO/XEQ can still complete to, e.g. `→AREA|ARC`;
+    /// Tab takes the first
+    fn label_completion_preview(&self) -> Option<String> {
+        const SHOWN: usize = 4;
+        let completions = self.command_parser.completions();
+        if completions.is_empty() {
+            return None;
+        }
+        let mut preview = format!("→{}", completions[..completions.len().min(SHOWN)].join("|"));
+        if completions.len() > SHOWN {
+            preview.push('…');
+        }
+        Some(preview)
+    }
+
     fn build_program_line(&self) -> String {
         if self.programming.is_programming {
-            if let Some(instr) = self.programming.get_current_instruction() {
-                format!(">{:02} {}", instr.line_number, instr)
+            // A command being keyed shows with blanks for its missing
+            // argument, a text line with a cursor
+            if self.alpha_entry.is_some() {
+                format!(">{:02} {}", self.programming.current_line, self.alpha_line(TEXT_LINE_LENGTH + 2))
+            } else if let Some(prompt) = self.command_parser.prompt() {
+                format!(">{:02} {}", self.programming.current_line, prompt)
+            } else if let Some(instr) = self.programming.get_current_instruction() {
+                format!(">{:02} {}", instr.line_number, instr.display_text())
             } else {
                 format!(">{:02} _", self.programming.current_line)
             }
         } else if !self.programming.program.is_empty() {
             if let Some(instr) = self.programming.get_current_instruction() {
-                format!(" {:02} {}", instr.line_number, instr)
+                format!(" {:02} {}", instr.line_number, instr.display_text())
             } else {
                 format!(" {:02} END", self.programming.program_counter + 1)
             }
@@ -461,22 +2998,24 @@ impl HP41CCalculator {
     
     /// NEW: Configure logger with preset configurations
     pub fn configure_logger(&mut self, preset: &str) -> Option<String> {
-        match preset {
+        let message = match preset {
             "all" => {
                 self.logger = Logger::debug_all();
-                Some("Debug logging: ALL enabled".to_string())
+                "Debug logging: ALL enabled"
             }
             "minimal" => {
                 self.logger = Logger::minimal();
-                Some("Debug logging: MINIMAL (flags + stack)".to_string())
+                "Debug logging: MINIMAL (flags + stack)"
             }
             "off" => {
                 self.logger = Logger::new();
                 self.logger.enabled = false;
-                Some("Debug logging: DISABLED".to_string())
+                "Debug logging: DISABLED"
             }
-            _ => Some("Unknown logging preset".to_string())
-        }
+            _ => return Some("Unknown logging preset".to_string()),
+        };
+        self.logger.set_storage(self.storage.clone());
+        Some(message.to_string())
     }
 }
 
@@ -545,7 +3084,7 @@ impl HP41CCalculator {
     }
     
     pub fn test_add_program_instruction(&mut self, cmd: &str, args: Option<Vec<String>>) {
-        self.programming.add_instruction(cmd, args, cmd);
+        self.programming.add_instruction(cmd, args, cmd).unwrap();
     }
 
     pub fn process_command_string(&mut self, cmd: &str) -> Result<Option<String>, String> {
@@ -0,0 +1,165 @@
+//! Calendar arithmetic without a clock
+//!
+//! Dates are numbers in MM.DDYYYY form, or DD.MMYYYY while flag 31 is set,
+//! as in the Time module, whose DMY sets the flag and MDY clears it:
+//! 7.041776 is July 4, 1776, or 4.071776 after DMY. The calendar is the
+//! proleptic Gregorian one, over the Time module's range of October 15,
+//! 1582 to September 10, 4320.
+//!
+//! | Command | Stack in | Effect |
+//! |---|---|---|
+//! | `DOW` | X: date | X: day of the week, 0 (Sunday) to 6, shown by name |
+//! | `JDN` | X: date | X: Julian day number |
+//! | `DDAYS` | Y: date 1, X: date 2 | X: days from date 1 to date 2 |
+//! | `DATE+` | Y: date, X: days | X: the date that many days later |
+
+use crate::error::{CalculatorError, StackError};
+use crate::input::InputState;
+use crate::stack::Stack;
+
+/// Day names for DOW, Sunday first
+pub const DAY_NAMES: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// Julian day numbers of the first and last dates handled
+const FIRST_DAY: i64 = 2_299_161;
+const LAST_DAY: i64 = 3_299_160;
+
+/// A calendar date
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Date {
+    pub year: i64,
+    pub month: i64,
+    pub day: i64,
+}
+
+impl Date {
+    /// A date, checked against the month lengths and the supported range
+    pub fn new(year: i64, month: i64, day: i64) -> Result<Self, StackError> {
+        let date = Date { year, month, day };
+        if !(1..=12).contains(&month) || !(1..=date.month_length()).contains(&day) {
+            return Err(invalid_date());
+        }
+        if !(FIRST_DAY..=LAST_DAY).contains(&date.julian_day()) {
+            return Err(StackError::MathError("Date out of range".to_string()));
+        }
+        Ok(date)
+    }
+
+    /// Read a date in MM.DDYYYY form, or DD.MMYYYY when `dmy` is set
+    pub fn from_number(value: f64, dmy: bool) -> Result<Self, StackError> {
+        let scaled = (value * 1e6).round();
+        if !(0.0..1e9).contains(&scaled) || (value * 1e6 - scaled).abs() > 1e-3 {
+            return Err(invalid_date());
+        }
+        let scaled = scaled as i64;
+        let (first, second, year) = (scaled / 1_000_000, scaled / 10_000 % 100, scaled % 10_000);
+        let (month, day) = if dmy { (second, first) } else { (first, second) };
+        Date::new(year, month, day)
+    }
+
+    pub fn to_number(self, dmy: bool) -> f64 {
+        let (first, second) = if dmy { (self.day, self.month) } else { (self.month, self.day) };
+        ((first * 1_000_000 + second * 10_000 + self.year) as f64) / 1e6
+    }
+
+    /// Julian day number, the count of days since January 1, 4713 BC (Julian)
+    pub fn julian_day(self) -> i64 {
+        let a = (14 - self.month) / 12;
+        let y = self.year + 4800 - a;
+        let m = self.month + 12 * a - 3;
+        self.day + (153 * m + 2) / 5 + 365 * y + y / 4 - y / 100 + y / 400 - 32045
+    }
+
+    pub fn from_julian_day(jdn: i64) -> Result<Self, StackError> {
+        if !(FIRST_DAY..=LAST_DAY).contains(&jdn) {
+            return Err(StackError::MathError("Date out of range".to_string()));
+        }
+        let a = jdn + 32044;
+        let b = (4 * a + 3) / 146_097;
+        let c = a - 146_097 * b / 4;
+        let d = (4 * c + 3) / 1461;
+        let e = c - 1461 * d / 4;
+        let m = (5 * e + 2) / 153;
+        Ok(Date {
+            year: 100 * b + d - 4800 + m / 10,
+            month: m + 3 - 12 * (m / 10),
+            day: e - (153 * m + 2) / 5 + 1,
+        })
+    }
+
+    /// Day of the week, 0 for Sunday through 6 for Saturday
+    pub fn day_of_week(self) -> usize {
+        ((self.julian_day() + 1) % 7) as usize
+    }
+
+    fn month_length(self) -> i64 {
+        match self.month {
+            2 if self.year % 4 == 0 && (self.year % 100 != 0 || self.year % 400 == 0) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+}
+
+fn invalid_date() -> StackError {
+    StackError::MathError("Invalid date".to_string())
+}
+
+/// Run one of the calendar commands; `dmy` is flag 31
+pub fn execute_calendar_command(
+    command: &str,
+    stack: &mut Stack,
+    input: &mut InputState,
+    dmy: bool,
+) -> Result<Option<String>, CalculatorError> {
+    let [x, y, z, t] = stack.get_registers();
+    let mut message = None;
+    match command {
+        "dow" => {
+            let day = Date::from_number(x, dmy)?.day_of_week();
+            stack.set_x(day as f64);
+            message = Some(DAY_NAMES[day].to_string());
+        }
+        "jdn" => stack.set_x(Date::from_number(x, dmy)?.julian_day() as f64),
+        "ddays" => {
+            let days = Date::from_number(x, dmy)?.julian_day() - Date::from_number(y, dmy)?.julian_day();
+            stack.set_registers([days as f64, z, t, t]);
+        }
+        "date+" => {
+            let start = Date::from_number(y, dmy)?.julian_day();
+            let date = Date::from_julian_day(start.saturating_add(x.trunc() as i64))?;
+            stack.set_registers([date.to_number(dmy), z, t, t]);
+        }
+        _ => unreachable!(),
+    }
+    stack.set_last_x(x);
+    stack.set_lift_flag(true);
+    input.clear();
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dates() {
+        let date = Date::from_number(7.041776, false).unwrap();
+        assert_eq!(date, Date { year: 1776, month: 7, day: 4 });
+        assert_eq!(date.day_of_week(), 4);
+        assert_eq!(Date::from_number(4.071776, true), Ok(date));
+        assert_eq!(date.to_number(true), 4.071776);
+
+        let y2k = Date::new(2000, 1, 1).unwrap();
+        assert_eq!(y2k.julian_day(), 2_451_545);
+        assert_eq!(Date::from_julian_day(2_451_545), Ok(y2k));
+        assert_eq!(Date::from_julian_day(y2k.julian_day() + 59).unwrap(), Date::new(2000, 2, 29).unwrap());
+
+        assert!(Date::from_number(2.291900, false).is_err());
+        assert!(Date::from_number(13.012000, false).is_err());
+        assert!(Date::from_number(10.141582, false).is_err());
+        assert!(Date::from_number(10.151582, false).is_ok());
+        assert!(Date::from_number(1.5, false).is_err());
+    }
+}
@@ -0,0 +1,301 @@
+//! Catalogs of programs, functions, user assignments and alarms
+//!
+//! Mirrors the HP-41CX catalogs: CAT 1 lists the global labels and ENDs
+//! of program memory, CAT 2 the functions of plug-in modules, CAT 3 the
+//! built-in functions, CAT 5 pending alarms and CAT 6 key assignments. The
+//! data for the last two lives in `KeyAssignments` and a list of `Alarm`s;
+//! a `Catalog` is a snapshot of one listing with a cursor that steps
+//! through it one entry at a time, the way the real catalogs do.
+//!
+//! `CAT n` scrolls a catalog through the display on its own, an entry every
+//! `CATALOG_STEP`, as a `CatalogRun`. R/S stops the scroll and starts it
+//! again; while it is stopped, SST and BST step by hand.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+
+/// Check an HP-41 key code: row 1-8 and column 1-5 as `RC`, negative
+/// for the shifted key (e.g. `11` is Σ+, `-11` is shift Σ+)
+pub fn is_valid_keycode(keycode: i32) -> bool {
+    let code = keycode.abs();
+    let (row, col) = (code / 10, code % 10);
+    (1..=8).contains(&row) && (1..=5).contains(&col)
+}
+
+/// One key assignment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyAssignment {
+    pub keycode: i32,
+    pub function: String,
+}
+
+impl fmt::Display for KeyAssignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.function, self.keycode)
+    }
+}
+
+/// Functions assigned to keys for USER mode, ordered by key code
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyAssignments {
+    keys: BTreeMap<i32, String>,
+}
+
+impl KeyAssignments {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Assign a function to a key, replacing any previous assignment
+    pub fn assign(&mut self, keycode: i32, function: &str) -> Result<(), String> {
+        if !is_valid_keycode(keycode) {
+            return Err(format!("Invalid key code: {}", keycode));
+        }
+        self.keys.insert(keycode, function.to_uppercase());
+        Ok(())
+    }
+
+    /// Remove an assignment, returning the function that was assigned
+    pub fn clear(&mut self, keycode: i32) -> Option<String> {
+        self.keys.remove(&keycode)
+    }
+
+    /// Function assigned to a key
+    pub fn get(&self, keycode: i32) -> Option<&str> {
+        self.keys.get(&keycode).map(String::as_str)
+    }
+
+    /// All assignments in key code order
+    pub fn iter(&self) -> impl Iterator<Item = KeyAssignment> + '_ {
+        self.keys.iter().map(|(&keycode, function)| KeyAssignment {
+            keycode,
+            function: function.clone(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+/// A pending alarm
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alarm {
+    /// When the alarm is due, in seconds since the Unix epoch on the Time
+    /// module's clock
+    pub due: u64,
+    /// Message shown when it goes off
+    pub message: String,
+    /// Repeat interval in seconds
+    pub repeat: Option<u64>,
+}
+
+/// Convert days since 1970-01-01 to a (year, month, day) date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // Howard Hinnant's days_from_civil, inverted
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+impl fmt::Display for Alarm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.due as i64;
+        let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+        let time = secs.rem_euclid(86_400);
+        write!(f, "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+               year, month, day, time / 3600, time / 60 % 60, time % 60)?;
+        if let Some(repeat) = self.repeat {
+            write!(f, " every {}s", repeat)?;
+        }
+        if !self.message.is_empty() {
+            write!(f, " {}", self.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// A catalog listing with a cursor for stepping through it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Catalog {
+    number: u8,
+    entries: Vec<String>,
+    position: usize,
+}
+
+impl Catalog {
+    /// Create a catalog positioned at its first entry
+    pub fn new(number: u8, entries: Vec<String>) -> Self {
+        Catalog { number, entries, position: 0 }
+    }
+
+    /// Catalog number (1 = programs, 2 = modules, 3 = functions, 5 = alarms,
+    /// 6 = key assignments)
+    pub fn number(&self) -> u8 {
+        self.number
+    }
+
+    /// All entries in order
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entry under the cursor
+    pub fn current(&self) -> Option<&str> {
+        self.entries.get(self.position).map(String::as_str)
+    }
+
+    /// Step forward; returns false at the end
+    pub fn advance(&mut self) -> bool {
+        if self.position + 1 < self.entries.len() {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Step back; returns false at the start
+    pub fn back(&mut self) -> bool {
+        if self.position > 0 {
+            self.position -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// How long a scrolling catalog shows each entry
+pub const CATALOG_STEP: Duration = Duration::from_millis(600);
+
+/// A catalog scrolling through the display
+///
+/// Times are those of the calculator's clock, so a test clock can drive
+/// the scroll.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogRun {
+    catalog: Catalog,
+    stopped: bool,
+    /// When the entry shown came up
+    shown_at: Duration,
+}
+
+impl CatalogRun {
+    pub fn new(catalog: Catalog, now: Duration) -> Self {
+        CatalogRun { catalog, stopped: false, shown_at: now }
+    }
+
+    pub fn catalog(&self) -> &Catalog {
+        &self.catalog
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// R/S: stop the scroll, or carry on from the entry shown
+    pub fn run_stop(&mut self, now: Duration) {
+        self.stopped = !self.stopped;
+        self.shown_at = now;
+    }
+
+    /// SST or BST: stop and move one entry
+    pub fn step(&mut self, forward: bool) {
+        self.stopped = true;
+        if forward { self.catalog.advance() } else { self.catalog.back() };
+    }
+
+    /// Move on if the entry has been shown for a step; false once the
+    /// scroll runs past the last entry
+    pub fn tick(&mut self, now: Duration) -> bool {
+        if self.stopped || now.saturating_sub(self.shown_at) < CATALOG_STEP {
+            return true;
+        }
+        self.shown_at = now;
+        self.catalog.advance()
+    }
+}
+
+/// The entry under the cursor, e.g. `CAT 6 2/3: SIN 11`
+impl fmt::Display for Catalog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.current() {
+            Some(entry) => write!(f, "CAT {} {}/{}: {}", self.number, self.position + 1, self.entries.len(), entry),
+            None => write!(f, "CAT {}: empty", self.number),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_assignments() {
+        let mut keys = KeyAssignments::new();
+        keys.assign(-24, "sqrt").unwrap();
+        keys.assign(11, "sin").unwrap();
+        assert!(keys.assign(19, "cos").is_err());
+        assert!(keys.assign(91, "cos").is_err());
+
+        let listed: Vec<String> = keys.iter().map(|a| a.to_string()).collect();
+        assert_eq!(listed, vec!["SQRT -24", "SIN 11"]);
+        assert_eq!(keys.clear(11), Some("SIN".to_string()));
+        assert_eq!(keys.len(), 1);
+    }
+
+    #[test]
+    fn test_alarm_display() {
+        let alarm = Alarm { due: 1_792_153_800, message: "TEA".to_string(), repeat: Some(3600) };
+        assert_eq!(alarm.to_string(), "2026-10-16 12:30:00 every 3600s TEA");
+    }
+
+    #[test]
+    fn test_catalog_cursor() {
+        let mut cat = Catalog::new(6, vec!["SIN 11".to_string(), "COS 12".to_string()]);
+        assert_eq!(cat.to_string(), "CAT 6 1/2: SIN 11");
+        assert!(!cat.back());
+        assert!(cat.advance());
+        assert_eq!(cat.current(), Some("COS 12"));
+        assert!(!cat.advance());
+        assert_eq!(Catalog::new(5, vec![]).to_string(), "CAT 5: empty");
+    }
+
+    #[test]
+    fn test_catalog_run() {
+        let entries = vec!["LBL'A".to_string(), "END".to_string(), ".END.".to_string()];
+        let mut run = CatalogRun::new(Catalog::new(1, entries), Duration::ZERO);
+        assert!(run.tick(CATALOG_STEP / 2));
+        assert_eq!(run.catalog().current(), Some("LBL'A"));
+        assert!(run.tick(CATALOG_STEP));
+        assert_eq!(run.catalog().current(), Some("END"));
+
+        run.run_stop(CATALOG_STEP);
+        assert!(run.tick(CATALOG_STEP * 5));
+        assert_eq!(run.catalog().current(), Some("END"));
+        run.step(false);
+        assert_eq!(run.catalog().current(), Some("LBL'A"));
+        run.run_stop(CATALOG_STEP * 5);
+        assert!(run.tick(CATALOG_STEP * 6));
+        assert!(run.tick(CATALOG_STEP * 7));
+        assert!(!run.tick(CATALOG_STEP * 8));
+    }
+}
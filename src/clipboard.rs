@@ -0,0 +1,112 @@
+//! Copying results out of the calculator
+//!
+//! Text goes to a `ClipboardSink`: the system clipboard when the crate is
+//! built with the `clipboard` feature, or `MemoryClipboard` for tests and
+//! hosts that handle copying themselves. Numbers are copied with the full
+//! ten significant digits the HP-41 keeps, not the rounded display.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// What to copy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyTarget {
+    /// The X register
+    X,
+    /// Every stack level, top first, one per line
+    Stack,
+}
+
+/// A place copied text can go
+pub trait ClipboardSink: fmt::Debug + Send {
+    fn set_text(&mut self, text: &str) -> Result<(), String>;
+}
+
+/// Keeps the last copied text (tests, headless hosts)
+///
+/// Clones share the same contents, so keep one handle and give the other
+/// to the calculator.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryClipboard {
+    text: Arc<Mutex<Option<String>>>,
+}
+
+impl MemoryClipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The last copied text
+    pub fn text(&self) -> Option<String> {
+        self.text.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl ClipboardSink for MemoryClipboard {
+    fn set_text(&mut self, text: &str) -> Result<(), String> {
+        *self.text.lock().unwrap_or_else(|e| e.into_inner()) = Some(text.to_string());
+        Ok(())
+    }
+}
+
+/// The operating system clipboard
+#[cfg(feature = "clipboard")]
+pub struct SystemClipboard {
+    clipboard: arboard::Clipboard,
+}
+
+#[cfg(feature = "clipboard")]
+impl SystemClipboard {
+    /// Connect to the system clipboard; fails without a desktop session
+    pub fn new() -> Result<Self, String> {
+        arboard::Clipboard::new()
+            .map(|clipboard| SystemClipboard { clipboard })
+            .map_err(|e| format!("Clipboard unavailable: {}", e))
+    }
+}
+
+#[cfg(feature = "clipboard")]
+impl fmt::Debug for SystemClipboard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SystemClipboard")
+    }
+}
+
+#[cfg(feature = "clipboard")]
+impl ClipboardSink for SystemClipboard {
+    fn set_text(&mut self, text: &str) -> Result<(), String> {
+        self.clipboard.set_text(text).map_err(|e| format!("Copy failed: {}", e))
+    }
+}
+
+/// A number rounded to ten significant digits, without trailing zeros
+pub fn format_full_precision(value: f64) -> String {
+    if value == 0.0 || !value.is_finite() {
+        return value.to_string();
+    }
+    let rounded: f64 = format!("{:.9e}", value).parse().unwrap_or(value);
+    let exponent = rounded.abs().log10().floor() as i32;
+    if (-10..10).contains(&exponent) {
+        rounded.to_string()
+    } else {
+        let text = format!("{:.9e}", rounded);
+        let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, "0"));
+        let mantissa = mantissa.trim_end_matches('0').trim_end_matches('.');
+        format!("{}E{}", mantissa, exponent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_full_precision() {
+        assert_eq!(format_full_precision(std::f64::consts::PI), "3.141592654");
+        assert_eq!(format_full_precision(1.0 / 3.0), "0.3333333333");
+        assert_eq!(format_full_precision(-42.0), "-42");
+        assert_eq!(format_full_precision(6.02214076e23), "6.02214076E23");
+        assert_eq!(format_full_precision(1.5e-12), "1.5E-12");
+        assert_eq!(format_full_precision(0.0), "0");
+    }
+}
@@ -0,0 +1,154 @@
+//! Virtual clock for timing-dependent features
+//!
+//! Anything that waits or reads the time (PSE pauses, the Time module,
+//! stopwatch and alarms) asks the calculator's `Clock` instead of calling
+//! `std::time` directly. `SystemClock` is the default; `MockClock` lets tests
+//! and headless runs advance time deterministically, and WASM hosts can
+//! implement the trait on top of `performance.now()`.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A source of monotonic and wall-clock time
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Monotonic time elapsed since the clock was created
+    fn elapsed(&self) -> Duration;
+
+    /// Wall-clock time as a duration since the Unix epoch
+    fn unix_time(&self) -> Duration;
+
+    /// Wait for the given duration
+    fn sleep(&self, duration: Duration);
+}
+
+/// Clock handle shared between calculator subsystems
+pub type SharedClock = Arc<dyn Clock>;
+
+/// Clock backed by the host operating system
+#[derive(Debug, Clone)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    /// Create a system clock starting now
+    pub fn new() -> Self {
+        SystemClock { start: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn unix_time(&self) -> Duration {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// Manually driven clock for tests and deterministic headless runs
+///
+/// Time only moves when `advance` is called or when something sleeps on it,
+/// in which case the sleep returns immediately after advancing the clock.
+/// Clones share the same time, so a test can keep a handle while the
+/// calculator owns another.
+#[derive(Debug, Clone, Default)]
+pub struct MockClock {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    elapsed: Duration,
+    epoch: Duration,
+}
+
+impl MockClock {
+    /// Create a mock clock at elapsed zero and Unix time zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a mock clock whose wall-clock time starts at the given Unix time
+    pub fn at_unix_time(epoch: Duration) -> Self {
+        let clock = Self::new();
+        clock.lock().epoch = epoch;
+        clock
+    }
+
+    /// Move time forward
+    pub fn advance(&self, duration: Duration) {
+        self.lock().elapsed += duration;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Clock for MockClock {
+    fn elapsed(&self) -> Duration {
+        self.lock().elapsed
+    }
+
+    fn unix_time(&self) -> Duration {
+        let state = self.lock();
+        state.epoch + state.elapsed
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+/// Create the default clock for desktop builds
+pub fn default_clock() -> SharedClock {
+    Arc::new(SystemClock::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_clock_advances_only_on_demand() {
+        let clock = MockClock::at_unix_time(Duration::from_secs(1_000));
+        assert_eq!(clock.elapsed(), Duration::ZERO);
+
+        clock.advance(Duration::from_millis(250));
+        clock.sleep(Duration::from_secs(1));
+
+        assert_eq!(clock.elapsed(), Duration::from_millis(1_250));
+        assert_eq!(clock.unix_time(), Duration::from_millis(1_001_250));
+    }
+
+    #[test]
+    fn test_mock_clock_clones_share_time() {
+        let clock = MockClock::new();
+        let shared: SharedClock = Arc::new(clock.clone());
+
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(shared.elapsed(), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_system_clock_is_monotonic() {
+        let clock = SystemClock::new();
+        let first = clock.elapsed();
+        let second = clock.elapsed();
+        assert!(second >= first);
+        assert!(clock.unix_time() > Duration::from_secs(1_500_000_000));
+    }
+}
@@ -1,18 +1,19 @@
-/// Updated commands module - clean version using modular system
-/// 
-/// This module provides helper functions that use the new modular command system.
-/// The command system is now split into registry.rs and parser.rs for better organization.
+//! Updated commands module - clean version using modular system
+//! 
+//! This module provides helper functions that use the new modular command system.
+//! The command system is now split into registry.rs and parser.rs for better organization.
 
 // Re-export the command system types from their new locations
 pub use crate::registry::{
     CommandSpec, ArgumentPattern, AutoExecuteRule, CommandRegistry
 };
 pub use crate::parser::{CommandParser, ParseResult};
+use crate::registry::command_key;
 
 /// Helper function to check if a string is a valid HP-41C command
 pub fn is_valid_command(command: &str) -> bool {
     let registry = CommandRegistry::new();
-    registry.get_spec(&command.to_lowercase()).is_some()
+    registry.get_spec(&command_key(command)).is_some()
 }
 
 /// Get all available command names
@@ -24,7 +25,7 @@ pub fn get_all_commands() -> Vec<String> {
 /// Get command specification for a given command
 pub fn get_command_spec(command: &str) -> Option<CommandSpec> {
     let registry = CommandRegistry::new();
-    registry.get_spec(&command.to_lowercase()).cloned()
+    registry.get_spec(&command_key(command)).cloned()
 }
 
 #[cfg(test)]
@@ -0,0 +1,123 @@
+//! User configuration file
+//!
+//! `hp41c.toml` (or the file named by `HP41C_CONFIG`) sets preferences and
+//! a list of startup commands, run in order when the emulator starts:
+//!
+//! ```toml
+//! locale = "de"
+//! model = "41CV"
+//! modules = ["time"]
+//! stack_depth = "8"
+//! confirm = ["registers", "program"]
+//! compress = true
+//! state_dir = "sync/hp41c"
+//! mirror = "0.0.0.0:4141"
+//! debug = true
+//! strict_returns = true
+//! pause = 0.5
+//! step_limit = 5000000
+//! time_limit = 60
+//! startup = [
+//!     "FIX 2",
+//!     "1.08",
+//!     "STO 01",
+//! ]
+//! ```
+//!
+//! Each startup entry is one command line as accepted by
+//! `HP41CCalculator::run_command_line`: a command name followed by its
+//! arguments, or a number to put in X. This reproduces a preferred machine
+//! setup without keeping a saved state file around.
+
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::storage::Storage;
+
+/// Default config file name, looked up in the working directory
+pub const DEFAULT_CONFIG_FILE: &str = "hp41c.toml";
+
+/// Settings read from the config file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Language tag for messages, e.g. "de"
+    pub locale: Option<String>,
+    /// Calculator model: "41C", "41CV" or "41CX"
+    pub model: Option<String>,
+    /// Modules to plug in, e.g. "time" or "xfunctions"
+    pub modules: Vec<String>,
+    /// Non-authentic stack depth: a number of levels (at least 4) or "unlimited"
+    pub stack_depth: Option<String>,
+    /// Command categories that need a second keypress: "registers", "program", "memory", "reset"
+    pub confirm: Vec<String>,
+    /// Compress the saved state file
+    pub compress: bool,
+    /// Keep continuous memory as a directory of small files (for syncing)
+    /// instead of a single state file
+    pub state_dir: Option<String>,
+    /// Address to mirror the display on for browsers and terminals, e.g. "0.0.0.0:4141"
+    pub mirror: Option<String>,
+    /// Debug mode: check ASSERT steps in programs
+    pub debug: bool,
+    /// XEQ nested past six levels is an error instead of losing the oldest return
+    pub strict_returns: bool,
+    /// Seconds PSE pauses a running program (default 1)
+    pub pause: Option<f64>,
+    /// Steps one run may take before it is stopped, 0 for no limit
+    pub step_limit: Option<usize>,
+    /// Seconds one run may take before it is stopped
+    pub time_limit: Option<f64>,
+    /// Command lines executed at startup, in order
+    pub startup: Vec<String>,
+}
+
+impl Config {
+    /// Parse TOML config text
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| format!("Invalid config: {}", e))
+    }
+
+    /// Config file path: `HP41C_CONFIG` if set, else `hp41c.toml`
+    pub fn default_path() -> PathBuf {
+        std::env::var_os("HP41C_CONFIG")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_FILE))
+    }
+
+    /// Load a config file; a missing file is not an error
+    pub fn load(storage: &dyn Storage, path: &Path) -> Result<Option<Self>, String> {
+        if !storage.exists(path) {
+            return Ok(None);
+        }
+        let text = storage.read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::from_toml(&text).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::from_toml("locale = \"de\"\nmodel = \"41C\"\nstartup = [\"FIX 2\", \"SCI 3\"]\n").unwrap();
+        assert_eq!(config.locale.as_deref(), Some("de"));
+        assert_eq!(config.model.as_deref(), Some("41C"));
+        assert_eq!(config.startup, vec!["FIX 2", "SCI 3"]);
+
+        assert_eq!(Config::from_toml("").unwrap(), Config::default());
+        assert!(Config::from_toml("startp = []").is_err());
+    }
+
+    #[test]
+    fn test_missing_file_is_none() {
+        let storage = MemoryStorage::new();
+        assert_eq!(Config::load(&storage, Path::new("hp41c.toml")).unwrap(), None);
+
+        storage.write(Path::new("hp41c.toml"), b"startup = [\"FIX 1\"]").unwrap();
+        let config = Config::load(&storage, Path::new("hp41c.toml")).unwrap().unwrap();
+        assert_eq!(config.startup, vec!["FIX 1"]);
+    }
+}
@@ -0,0 +1,133 @@
+//! Confirmation for destructive commands
+//!
+//! A few commands throw data away in a single keystroke: CLRG clears every
+//! data register, PRGM wipes program memory, a smaller SIZE drops the
+//! registers above it and RESET clears continuous memory (MEMORY LOST).
+//! When confirmation is turned on for a command's category, the first
+//! time the command is keyed in only asks; keying the same command again
+//! carries it out. Any other command or number entry in
+//! between cancels the request. Programs and startup commands are never
+//! asked.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Groups of destructive commands that can require confirmation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ConfirmCategory {
+    /// CLRG
+    Registers,
+    /// PRGM (clear program)
+    Program,
+    /// SIZE reductions
+    Memory,
+    /// RESET (MEMORY LOST)
+    Reset,
+}
+
+impl ConfirmCategory {
+    pub const ALL: [ConfirmCategory; 4] = [
+        ConfirmCategory::Registers,
+        ConfirmCategory::Program,
+        ConfirmCategory::Memory,
+        ConfirmCategory::Reset,
+    ];
+
+    /// Parse a category name as used in the config file
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "registers" => Some(ConfirmCategory::Registers),
+            "program" => Some(ConfirmCategory::Program),
+            "memory" => Some(ConfirmCategory::Memory),
+            "reset" => Some(ConfirmCategory::Reset),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ConfirmCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ConfirmCategory::Registers => "registers",
+            ConfirmCategory::Program => "program",
+            ConfirmCategory::Memory => "memory",
+            ConfirmCategory::Reset => "reset",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Which categories need confirmation, and the command waiting for it
+#[derive(Debug, Clone, Default)]
+pub struct Confirmations {
+    required: BTreeSet<ConfirmCategory>,
+    pending: Option<String>,
+}
+
+impl Confirmations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Turn confirmation on or off for a category
+    pub fn set_required(&mut self, category: ConfirmCategory, required: bool) {
+        if required {
+            self.required.insert(category);
+        } else {
+            self.required.remove(&category);
+        }
+    }
+
+    pub fn is_required(&self, category: ConfirmCategory) -> bool {
+        self.required.contains(&category)
+    }
+
+    /// Check a keyed command line (e.g. `CLRG`) in a category
+    ///
+    /// Returns true if it may run now: either its category doesn't need
+    /// confirmation or this is the second time in a row it was keyed.
+    pub fn confirm(&mut self, category: ConfirmCategory, command_line: &str) -> bool {
+        if !self.is_required(category) {
+            return true;
+        }
+        if self.pending.as_deref() == Some(command_line) {
+            self.pending = None;
+            true
+        } else {
+            self.pending = Some(command_line.to_string());
+            false
+        }
+    }
+
+    /// The command waiting to be confirmed
+    pub fn pending(&self) -> Option<&str> {
+        self.pending.as_deref()
+    }
+
+    /// Drop a pending request
+    pub fn cancel(&mut self) {
+        self.pending = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_second_press_confirms() {
+        let mut confirmations = Confirmations::new();
+        assert!(confirmations.confirm(ConfirmCategory::Registers, "CLRG"));
+
+        confirmations.set_required(ConfirmCategory::Registers, true);
+        assert!(!confirmations.confirm(ConfirmCategory::Registers, "CLRG"));
+        assert_eq!(confirmations.pending(), Some("CLRG"));
+        assert!(confirmations.confirm(ConfirmCategory::Registers, "CLRG"));
+        assert_eq!(confirmations.pending(), None);
+
+        assert!(!confirmations.confirm(ConfirmCategory::Registers, "CLRG"));
+        confirmations.cancel();
+        assert!(!confirmations.confirm(ConfirmCategory::Registers, "CLRG"));
+        assert_eq!(ConfirmCategory::from_name("Program"), Some(ConfirmCategory::Program));
+    }
+}
@@ -0,0 +1,201 @@
+//! Compressed and encrypted interchange files
+//!
+//! State and program files are plain JSON by default. For syncing through
+//! shared storage they can be wrapped in a small container instead:
+//!
+//! | Bytes | Content |
+//! |---|---|
+//! | 4 | Magic `HP41` |
+//! | 1 | Container version (1) |
+//! | 1 | Flags: bit 0 compressed (raw DEFLATE), bit 1 encrypted |
+//! | 16 | Argon2id salt (encrypted only) |
+//! | 12 | ChaCha20-Poly1305 nonce (encrypted only) |
+//! | rest | Payload, compressed before it is encrypted |
+//!
+//! Reading accepts both plain files and containers, so turning compression
+//! on or off never strands an existing file. Encryption needs the
+//! `encryption` feature; builds without it can still read and write
+//! compressed files and report encrypted ones as such.
+
+/// Leading bytes of a container; plain files start with `{` or a CBOR map
+pub const MAGIC: &[u8; 4] = b"HP41";
+
+/// Container format version
+pub const CONTAINER_VERSION: u8 = 1;
+
+const FLAG_COMPRESSED: u8 = 0x01;
+const FLAG_ENCRYPTED: u8 = 0x02;
+const HEADER_LEN: usize = 6;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// DEFLATE level used for compressed files
+const COMPRESSION_LEVEL: u8 = 9;
+
+/// How files are written
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContainerOptions {
+    /// Compress the payload
+    pub compress: bool,
+    /// Encrypt with a key derived from this passphrase; also used to read
+    /// encrypted files
+    pub passphrase: Option<String>,
+}
+
+impl ContainerOptions {
+    /// Whether files are written as plain JSON
+    pub fn is_plain(&self) -> bool {
+        !self.compress && self.passphrase.is_none()
+    }
+}
+
+/// Whether data is a container rather than a plain file
+pub fn is_container(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Whether data is an encrypted container
+pub fn is_encrypted(data: &[u8]) -> bool {
+    is_container(data) && data.get(5).is_some_and(|flags| flags & FLAG_ENCRYPTED != 0)
+}
+
+/// Wrap file contents according to the options; plain options return the
+/// data unchanged
+pub fn seal(data: &[u8], options: &ContainerOptions) -> Result<Vec<u8>, String> {
+    if options.is_plain() {
+        return Ok(data.to_vec());
+    }
+    let mut flags = 0;
+    let mut payload = data.to_vec();
+    if options.compress {
+        flags |= FLAG_COMPRESSED;
+        payload = miniz_oxide::deflate::compress_to_vec(&payload, COMPRESSION_LEVEL);
+    }
+    let mut sealed = Vec::with_capacity(HEADER_LEN + SALT_LEN + NONCE_LEN + payload.len());
+    sealed.extend_from_slice(MAGIC);
+    sealed.push(CONTAINER_VERSION);
+    if let Some(passphrase) = &options.passphrase {
+        flags |= FLAG_ENCRYPTED;
+        sealed.push(flags);
+        sealed.extend(cipher::encrypt(&payload, passphrase)?);
+    } else {
+        sealed.push(flags);
+        sealed.extend(payload);
+    }
+    Ok(sealed)
+}
+
+/// Unwrap file contents written by `seal`; plain files are returned as is
+pub fn open(data: &[u8], passphrase: Option<&str>) -> Result<Vec<u8>, String> {
+    if !is_container(data) {
+        return Ok(data.to_vec());
+    }
+    let header = data.get(..HEADER_LEN).ok_or("Truncated container")?;
+    if header[4] > CONTAINER_VERSION {
+        return Err(format!("Unsupported container version {}", header[4]));
+    }
+    let flags = header[5];
+    let mut payload = data[HEADER_LEN..].to_vec();
+    if flags & FLAG_ENCRYPTED != 0 {
+        let passphrase = passphrase.ok_or("File is encrypted; a passphrase is needed")?;
+        payload = cipher::decrypt(&payload, passphrase)?;
+    }
+    if flags & FLAG_COMPRESSED != 0 {
+        payload = miniz_oxide::inflate::decompress_to_vec(&payload)
+            .map_err(|e| format!("Corrupt compressed data: {:?}", e.status))?;
+    }
+    Ok(payload)
+}
+
+/// Unwrap a container holding text, such as a state file
+pub fn open_to_string(data: &[u8], passphrase: Option<&str>) -> Result<String, String> {
+    String::from_utf8(open(data, passphrase)?).map_err(|e| format!("Invalid text: {}", e))
+}
+
+#[cfg(feature = "encryption")]
+mod cipher {
+    use super::{NONCE_LEN, SALT_LEN};
+    use argon2::Argon2;
+    use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+    use chacha20poly1305::aead::rand_core::RngCore;
+    use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+    fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key, String> {
+        let mut key = Key::default();
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| format!("Key derivation failed: {}", e))?;
+        Ok(key)
+    }
+
+    /// Salt, nonce and ciphertext
+    pub fn encrypt(payload: &[u8], passphrase: &str) -> Result<Vec<u8>, String> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?)
+            .encrypt(&nonce, payload)
+            .map_err(|_| "Encryption failed".to_string())?;
+
+        let mut sealed = Vec::with_capacity(SALT_LEN + NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(&salt);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend(ciphertext);
+        Ok(sealed)
+    }
+
+    pub fn decrypt(sealed: &[u8], passphrase: &str) -> Result<Vec<u8>, String> {
+        if sealed.len() < SALT_LEN + NONCE_LEN {
+            return Err("Truncated container".to_string());
+        }
+        let (salt, rest) = sealed.split_at(SALT_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        ChaCha20Poly1305::new(&derive_key(passphrase, salt)?)
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "Wrong passphrase or corrupt file".to_string())
+    }
+}
+
+#[cfg(not(feature = "encryption"))]
+mod cipher {
+    const UNAVAILABLE: &str = "Encrypted files need the encryption feature";
+
+    pub fn encrypt(_payload: &[u8], _passphrase: &str) -> Result<Vec<u8>, String> {
+        Err(UNAVAILABLE.to_string())
+    }
+
+    pub fn decrypt(_sealed: &[u8], _passphrase: &str) -> Result<Vec<u8>, String> {
+        Err(UNAVAILABLE.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compressed_round_trip() {
+        let json = format!("{{\"registers\": [{}]}}", vec!["0.0"; 100].join(", "));
+        let options = ContainerOptions { compress: true, passphrase: None };
+        let sealed = seal(json.as_bytes(), &options).unwrap();
+        assert!(is_container(&sealed) && !is_encrypted(&sealed));
+        assert!(sealed.len() < json.len());
+        assert_eq!(open_to_string(&sealed, None).unwrap(), json);
+
+        // Plain files pass through both ways
+        assert_eq!(seal(b"{}", &ContainerOptions::default()).unwrap(), b"{}");
+        assert_eq!(open(b"{}", None).unwrap(), b"{}");
+        assert!(open(b"HP41\x09\x00", None).is_err());
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_encrypted_round_trip() {
+        let options = ContainerOptions { compress: true, passphrase: Some("beam".to_string()) };
+        let sealed = seal(b"LBL \"SPAN\"", &options).unwrap();
+        assert!(is_encrypted(&sealed));
+        assert_eq!(open(&sealed, Some("beam")).unwrap(), b"LBL \"SPAN\"");
+        assert_eq!(open(&sealed, Some("truss")), Err("Wrong passphrase or corrupt file".to_string()));
+        assert!(open(&sealed, None).is_err());
+    }
+}
@@ -1,14 +1,30 @@
-#[derive(Debug, Clone, PartialEq)]
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum DisplayMode {
     Fix,  // FIX mode - fixed decimal places
     Sci,  // SCI mode - scientific notation
     Eng,  // ENG mode - engineering notation (powers of 3)
 }
 
+/// Turns numbers into the text the display shows
+/// 
+/// `DisplayFormatter` is the built-in implementation. Embedders can
+/// install their own with `HP41CCalculator::with_number_formatter`, e.g.
+/// for a decimal comma or a fixed-width seven-segment panel; the display
+/// settings (FIX/SCI/ENG and digits) are still kept by `DisplayFormatter`
+/// and passed in. Alpha data never reaches the formatter.
+pub trait NumberFormatter: fmt::Debug + Send {
+    /// `value` in `mode` with `digits` digits, in at most `width` characters
+    fn format(&self, value: f64, mode: &DisplayMode, digits: usize, width: usize) -> String;
+}
+
 #[derive(Debug)]
 pub struct DisplayFormatter {
     pub mode: DisplayMode,
     pub digits: usize,
+    custom: Option<Box<dyn NumberFormatter>>,
 }
 
 impl DisplayFormatter {
@@ -16,38 +32,97 @@ impl DisplayFormatter {
         DisplayFormatter {
             mode: DisplayMode::Fix,
             digits: 4,  // HP-41C default
+            custom: None,
         }
     }
 
+    /// Format numbers with `formatter` instead of the built-in rules
+    pub fn set_number_formatter(&mut self, formatter: Box<dyn NumberFormatter>) {
+        self.custom = Some(formatter);
+    }
+
     pub fn format_number(&self, value: f64, width: usize) -> String {
+        // Alpha data (RCL of an ASTO register) shows as its text
+        if let Some(text) = crate::alpha::unpack(value) {
+            return text;
+        }
+        let formatter: &dyn NumberFormatter = self.custom.as_deref().unwrap_or(self);
+        formatter.format(value, &self.mode, self.digits, width)
+    }
+
+    /// Round a value to the digits the current mode shows (RND)
+    ///
+    /// FIX keeps `digits` decimals; SCI and ENG keep `digits + 1`
+    /// significant digits. Rounding works on the shortest decimal form of
+    /// the value and rounds halves away from zero, as the calculator's
+    /// decimal arithmetic does: 1.005 in FIX 2 gives 1.01.
+    pub fn round(&self, value: f64) -> f64 {
+        if value == 0.0 || !value.is_finite() {
+            return value;
+        }
+        let shortest = format!("{:e}", value.abs());
+        let (mantissa, exponent) = shortest.split_once('e').expect("{:e} has an exponent");
+        let exponent: i32 = exponent.parse().expect("{:e} exponent is an integer");
+        let digits: Vec<u8> = mantissa.bytes().filter(u8::is_ascii_digit).map(|d| d - b'0').collect();
+
+        // Number of leading digits to keep
+        let keep = match self.mode {
+            DisplayMode::Fix => exponent + 1 + self.digits as i32,
+            DisplayMode::Sci | DisplayMode::Eng => 1 + self.digits as i32,
+        };
+        if keep < 0 {
+            return 0.0;
+        }
+        let keep = keep as usize;
+        if keep >= digits.len() {
+            return value;
+        }
+        let kept = digits[..keep].iter().fold(0u64, |n, &d| n * 10 + d as u64);
+        let rounded = kept + u64::from(digits[keep] >= 5);
+        let magnitude: f64 = format!("{}e{}", rounded, exponent + 1 - keep as i32).parse()
+            .expect("formatted number parses");
+        magnitude.copysign(value)
+    }
+
+    pub fn get_mode_string(&self) -> String {
+        match self.mode {
+            DisplayMode::Fix => format!("FIX {}", self.digits),
+            DisplayMode::Sci => format!("SCI {}", self.digits),
+            DisplayMode::Eng => format!("ENG {}", self.digits),
+        }
+    }
+}
+
+impl NumberFormatter for DisplayFormatter {
+    fn format(&self, value: f64, mode: &DisplayMode, digits: usize, width: usize) -> String {
         // Standard number formatting using HP-41C display modes
         if value == 0.0 {
-            return match self.mode {
+            return match mode {
                 DisplayMode::Fix => {
-                    if self.digits == 0 {
+                    if digits == 0 {
                         "0".to_string()
                     } else {
-                        format!("0.{}", "0".repeat(self.digits))
+                        format!("0.{}", "0".repeat(digits))
                     }
                 }
-                DisplayMode::Sci => format!("0.{}E+00", "0".repeat(self.digits)),
-                DisplayMode::Eng => format!("0.{}E+00", "0".repeat(self.digits)),
+                DisplayMode::Sci => format!("0.{}E+00", "0".repeat(digits)),
+                DisplayMode::Eng => format!("0.{}E+00", "0".repeat(digits)),
             };
         }
 
-        let formatted = match self.mode {
+        let formatted = match mode {
             DisplayMode::Fix => {
-                format!("{:.1$}", value, self.digits)
+                format!("{:.1$}", value, digits)
             }
             DisplayMode::Sci => {
-                format!("{:.1$e}", value, self.digits)
+                format!("{:.1$e}", value, digits)
             }
             DisplayMode::Eng => {
                 // Engineering notation: exponent is multiple of 3
                 let log_val = value.abs().log10();
                 let exp_eng = (log_val / 3.0).floor() as i32 * 3;
                 let mantissa = value / 10.0_f64.powi(exp_eng);
-                format!("{:.1$}E{2:+03}", mantissa, self.digits, exp_eng)
+                format!("{:.1$}E{2:+03}", mantissa, digits, exp_eng)
             }
         };
 
@@ -58,12 +133,10 @@ impl DisplayFormatter {
             formatted
         }
     }
+}
 
-    pub fn get_mode_string(&self) -> String {
-        match self.mode {
-            DisplayMode::Fix => format!("FIX {}", self.digits),
-            DisplayMode::Sci => format!("SCI {}", self.digits),
-            DisplayMode::Eng => format!("ENG {}", self.digits),
-        }
+impl Default for DisplayFormatter {
+    fn default() -> Self {
+        Self::new()
     }
-}
\ No newline at end of file
+}
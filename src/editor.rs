@@ -0,0 +1,354 @@
+//! Editor support for program listings (`hp41c lsp`)
+//!
+//! `Document` reads a listing line by line, as `listing::parse_listing`
+//! does, and answers what an editor asks while a program is typed: what is
+//! wrong with it (layout errors, then `validate_steps` and `lint` from the
+//! calculator that will run it), what the command on a line does, and
+//! which line holds the LBL a GTO or XEQ goes to.
+//!
+//! `serve` speaks enough of the Language Server Protocol over a pair of
+//! streams for VS Code and other LSP clients: whole-document sync,
+//! published diagnostics, hover and go-to-definition. `hp41c lsp` runs it
+//! on standard input and output. Lines and characters count from 0;
+//! listings keep to the Basic Multilingual Plane, so a character is one
+//! UTF-16 unit as the protocol counts them.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use serde_json::{json, Value};
+use crate::analysis::{is_global_label, lint, LintKind};
+use crate::calculator::HP41CCalculator;
+use crate::listing::parse_listing;
+use crate::programming::ProgramInstruction;
+use crate::registry::command_key;
+use crate::xmem;
+
+/// JSON-RPC error for a request the server doesn't handle
+const METHOD_NOT_FOUND: i64 = -32601;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A finding on one line of a listing
+#[derive(Debug, Clone, PartialEq)]
+pub struct EditorDiagnostic {
+    /// Source line, from 0
+    pub line: usize,
+    pub severity: Severity,
+    /// `CalculatorError::code`, for what the calculator would reject
+    pub code: Option<u8>,
+    pub message: String,
+}
+
+/// A listing open in an editor
+#[derive(Debug, Clone, Default)]
+pub struct Document {
+    lines: Vec<String>,
+    steps: Vec<ProgramInstruction>,
+    /// Source line of each step
+    step_lines: Vec<usize>,
+    /// Lines that aren't steps, with what's wrong with them
+    layout_errors: Vec<(usize, String)>,
+}
+
+impl Document {
+    /// Read a listing; a line that doesn't parse is kept as an error and
+    /// the rest still become steps
+    pub fn parse(text: &str) -> Self {
+        let mut document = Document { lines: text.lines().map(str::to_string).collect(), ..Self::default() };
+        for (index, line) in text.lines().enumerate() {
+            match parse_listing(line) {
+                Ok(mut steps) => {
+                    if let Some(mut step) = steps.pop() {
+                        step.line_number = document.steps.len() as i32 + 1;
+                        document.steps.push(step);
+                        document.step_lines.push(index);
+                    }
+                }
+                Err(e) => {
+                    let message = e.strip_prefix("Line 1: ").unwrap_or(&e).to_string();
+                    document.layout_errors.push((index, message));
+                }
+            }
+        }
+        document
+    }
+
+    /// The program steps, numbered from 1
+    pub fn steps(&self) -> &[ProgramInstruction] {
+        &self.steps
+    }
+
+    /// Layout errors, what the calculator would reject and lint warnings,
+    /// in line order
+    pub fn diagnostics(&self, calc: &HP41CCalculator) -> Vec<EditorDiagnostic> {
+        let mut found: Vec<EditorDiagnostic> = self.layout_errors.iter()
+            .map(|(line, message)| EditorDiagnostic {
+                line: *line,
+                severity: Severity::Error,
+                code: None,
+                message: message.clone(),
+            })
+            .collect();
+        found.extend(calc.validate_steps(&self.steps).into_iter().map(|d| EditorDiagnostic {
+            line: self.step_line(d.line as i32),
+            severity: Severity::Error,
+            code: Some(d.error.code()),
+            message: d.error.to_string(),
+        }));
+        for issue in lint(&self.steps) {
+            let message = match issue.kind {
+                LintKind::DuplicateLabel { label, first } => {
+                    format!("Duplicate LBL {}; jumps go to line {}", label, self.step_line(first) + 1)
+                }
+                LintKind::Unreachable { end } if end == issue.line => "Unreachable".to_string(),
+                LintKind::Unreachable { end } => format!("Unreachable through line {}", self.step_line(end) + 1),
+                // validate_steps reports these, labels in extended memory included
+                LintKind::UndefinedLabel(_) => continue,
+            };
+            found.push(EditorDiagnostic {
+                line: self.step_line(issue.line),
+                severity: Severity::Warning,
+                code: None,
+                message,
+            });
+        }
+        found.sort_by_key(|d| d.line);
+        found
+    }
+
+    /// Help for the command on a line, e.g. `SIN: Sine`
+    pub fn hover(&self, calc: &HP41CCalculator, line: usize) -> Option<String> {
+        let step = &self.steps[self.step_at(line)?];
+        if step.text.is_some() {
+            return None;
+        }
+        calc.describe_command(&command_key(&step.command))
+    }
+
+    /// The line of the LBL that a GTO or XEQ on a line goes to
+    ///
+    /// Local labels are looked for in the same program (up to END),
+    /// global labels anywhere in the listing.
+    pub fn definition(&self, line: usize) -> Option<usize> {
+        let index = self.step_at(line)?;
+        let step = &self.steps[index];
+        if !matches!(step.command.as_str(), "GTO" | "XEQ") {
+            return None;
+        }
+        let [label] = step.arguments.as_slice() else { return None };
+        let target = if is_global_label(label) {
+            xmem::find_label(&self.steps, label)?
+        } else {
+            let is_end = |s: &ProgramInstruction| s.command == "END";
+            let start = self.steps[..index].iter().rposition(is_end).map_or(0, |p| p + 1);
+            let end = self.steps[index..].iter().position(is_end).map_or(self.steps.len(), |p| index + p);
+            start + xmem::find_label(&self.steps[start..end], label)?
+        };
+        Some(self.step_lines[target])
+    }
+
+    fn step_at(&self, line: usize) -> Option<usize> {
+        self.step_lines.iter().position(|&l| l == line)
+    }
+
+    /// Source line of a step number
+    fn step_line(&self, step: i32) -> usize {
+        let index = (step.max(1) - 1) as usize;
+        self.step_lines.get(index).copied().unwrap_or_default()
+    }
+
+    /// A whole line as an LSP range
+    fn line_range(&self, line: usize) -> Value {
+        let width = self.lines.get(line).map_or(0, |l| l.encode_utf16().count());
+        json!({ "start": { "line": line, "character": 0 }, "end": { "line": line, "character": width } })
+    }
+}
+
+/// Answer LSP requests from `input` on `output` until the client sends
+/// `exit` or closes the stream
+pub fn serve<R: BufRead, W: Write>(calc: &HP41CCalculator, mut input: R, mut output: W) -> io::Result<()> {
+    let mut documents: HashMap<String, Document> = HashMap::new();
+    while let Some(message) = read_message(&mut input)? {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_string();
+        let line = params["position"]["line"].as_u64().unwrap_or_default() as usize;
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": { "textDocumentSync": 1, "hoverProvider": true, "definitionProvider": true },
+                "serverInfo": { "name": "hp41c" },
+            }),
+            "textDocument/didOpen" | "textDocument/didChange" => {
+                let text = match method {
+                    "textDocument/didOpen" => &params["textDocument"]["text"],
+                    _ => &params["contentChanges"][0]["text"],
+                };
+                let document = Document::parse(text.as_str().unwrap_or_default());
+                publish(&mut output, &uri, calc, &document)?;
+                documents.insert(uri, document);
+                continue;
+            }
+            "textDocument/didClose" => {
+                documents.remove(&uri);
+                publish(&mut output, &uri, calc, &Document::default())?;
+                continue;
+            }
+            "textDocument/hover" => match documents.get(&uri).and_then(|d| d.hover(calc, line)) {
+                Some(text) => json!({ "contents": { "kind": "plaintext", "value": text } }),
+                None => Value::Null,
+            },
+            "textDocument/definition" => match documents.get(&uri).and_then(|d| Some((d, d.definition(line)?))) {
+                Some((document, target)) => json!({ "uri": uri, "range": document.line_range(target) }),
+                None => Value::Null,
+            },
+            "shutdown" => Value::Null,
+            "exit" => return Ok(()),
+            _ if message.get("id").is_some() => {
+                let error = json!({ "code": METHOD_NOT_FOUND, "message": format!("Unknown method: {}", method) });
+                write_message(&mut output, &json!({ "jsonrpc": "2.0", "id": message["id"], "error": error }))?;
+                continue;
+            }
+            // Notifications the server has no use for
+            _ => continue,
+        };
+        write_message(&mut output, &json!({ "jsonrpc": "2.0", "id": message["id"], "result": result }))?;
+    }
+    Ok(())
+}
+
+/// Send a document's diagnostics
+fn publish<W: Write>(output: &mut W, uri: &str, calc: &HP41CCalculator, document: &Document) -> io::Result<()> {
+    let diagnostics: Vec<Value> = document.diagnostics(calc).iter().map(|d| {
+        let mut diagnostic = json!({
+            "range": document.line_range(d.line),
+            "severity": if d.severity == Severity::Error { 1 } else { 2 },
+            "source": "hp41c",
+            "message": d.message,
+        });
+        if let Some(code) = d.code {
+            diagnostic["code"] = json!(code);
+        }
+        diagnostic
+    }).collect();
+    write_message(output, &json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    }))
+}
+
+/// One message: `Content-Length` and other headers, a blank line, the JSON
+/// body; `None` at the end of the stream
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "message without Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message<W: Write>(output: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LISTING: &str = "# Area of a circle\n\
+        01 LBL \"AREA\"\n\
+        02 X2\n\
+        03 PI\n\
+        04 *\n\
+        05 XEQ 01\n\
+        06 RTN\n\
+        07 SIN\n\
+        08 FOO\n\
+        \"UNCLOSED\n\
+        09 LBL 01\n\
+        10 STO 150\n\
+        11 GTO \"AREA\"\n\
+        12 END\n";
+
+    #[test]
+    fn test_document() {
+        let calc = HP41CCalculator::new();
+        let document = Document::parse(LISTING);
+        assert_eq!(document.steps().len(), 12);
+
+        let found: Vec<(usize, Severity, Option<u8>)> = document.diagnostics(&calc).iter()
+            .map(|d| (d.line, d.severity, d.code))
+            .collect();
+        assert_eq!(found, [
+            (7, Severity::Warning, None),
+            (8, Severity::Error, Some(30)),
+            (9, Severity::Error, None),
+            (11, Severity::Error, Some(53)),
+            (13, Severity::Warning, None),
+        ]);
+        assert_eq!(document.diagnostics(&calc)[0].message, "Unreachable through line 9");
+
+        assert_eq!(document.hover(&calc, 7), calc.describe_command("sin"));
+        assert!(document.hover(&calc, 7).unwrap().starts_with("SIN: "));
+        assert_eq!(document.hover(&calc, 0), None);
+        assert_eq!(document.definition(5), Some(10));
+        assert_eq!(document.definition(12), Some(1));
+        assert_eq!(document.definition(2), None);
+    }
+
+    fn frame(message: Value) -> String {
+        let body = message.to_string();
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    #[test]
+    fn test_serve() {
+        let calc = HP41CCalculator::new();
+        let uri = "file:///area.txt";
+        let input = [
+            frame(json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} })),
+            frame(json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen",
+                "params": { "textDocument": { "uri": uri, "text": LISTING } } })),
+            frame(json!({ "jsonrpc": "2.0", "id": 2, "method": "textDocument/definition",
+                "params": { "textDocument": { "uri": uri }, "position": { "line": 5, "character": 4 } } })),
+            frame(json!({ "jsonrpc": "2.0", "id": 3, "method": "workspace/symbol", "params": {} })),
+            frame(json!({ "jsonrpc": "2.0", "method": "exit" })),
+        ].concat();
+        let mut output = Vec::new();
+        serve(&calc, input.as_bytes(), &mut output).unwrap();
+
+        let mut replies = Vec::new();
+        let mut reader = output.as_slice();
+        while let Some(message) = read_message(&mut reader).unwrap() {
+            replies.push(message);
+        }
+        assert_eq!(replies.len(), 4);
+        assert_eq!(replies[0]["result"]["capabilities"]["hoverProvider"], true);
+        assert_eq!(replies[1]["params"]["diagnostics"].as_array().unwrap().len(), 5);
+        assert_eq!(replies[1]["params"]["diagnostics"][1]["code"], 30);
+        assert_eq!(replies[2]["result"]["range"]["start"]["line"], 10);
+        assert_eq!(replies[2]["result"]["range"]["end"]["character"], 9);
+        assert_eq!(replies[3]["error"]["code"], METHOD_NOT_FOUND);
+    }
+}
@@ -0,0 +1,315 @@
+//! embedded-hal integration for handheld replica builds
+//!
+//! Enabled with the `embedded` feature. `KeyMatrix` scans a physical key
+//! matrix through GPIO pins and implements `InputSource`; `Hd44780Sink`
+//! drives an HD44780-compatible character LCD over a 4-bit bus and
+//! implements `DisplaySink`. Both only depend on the embedded-hal 1.0 traits,
+//! so any board HAL can supply the pins and delay.
+//!
+//! The calculator core still links `std`; this layer is written against
+//! embedded-hal alone so it carries over unchanged once the core can be
+//! built without it.
+
+use std::collections::VecDeque;
+use std::io;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::{InputPin, OutputPin};
+use crate::keyboard::{InputSource, Key};
+use crate::lcd::{DisplaySink, LcdFrame};
+
+/// Keymap for the HP-41C keyboard wired as 8 rows by 5 columns
+///
+/// Each entry is a keystroke script token (see `keyboard`); empty entries
+/// are unused matrix positions. Keys with no emulator equivalent yet are
+/// left empty.
+pub const HP41_KEYMAP: [[&str; 5]; 8] = [
+    ["",      "inv",  "sqrt", "log", "ln"],
+    ["swap",  "",     "sin",  "cos", "tan"],
+    ["",      "xeq",  "sto",  "rcl", "sst"],
+    ["enter", "chs",  "eex",  "bksp", ""],
+    ["-",     "7",    "8",    "9",   ""],
+    ["+",     "4",    "5",    "6",   ""],
+    ["*",     "1",    "2",    "3",   ""],
+    ["/",     "0",    ".",    "",    ""],
+];
+
+/// Time allowed for a driven column to settle before reading rows
+const SETTLE_US: u32 = 10;
+/// Pause between full matrix scans while waiting for a key
+const SCAN_INTERVAL_MS: u32 = 5;
+
+fn pin_error<E: embedded_hal::digital::Error>(e: E) -> io::Error {
+    io::Error::other(format!("GPIO error: {:?}", e.kind()))
+}
+
+/// Scans a key matrix with active-low columns and pulled-up rows
+///
+/// A key is reported once when it is seen pressed on two consecutive scans
+/// (a simple debounce) and is not reported again until released.
+pub struct KeyMatrix<R, C, D, const ROWS: usize, const COLS: usize> {
+    rows: [R; ROWS],
+    cols: [C; COLS],
+    delay: D,
+    keymap: [[&'static str; COLS]; ROWS],
+    previous: [[bool; COLS]; ROWS],
+    reported: [[bool; COLS]; ROWS],
+    pending: VecDeque<Key>,
+}
+
+impl<R, C, D, const ROWS: usize, const COLS: usize> KeyMatrix<R, C, D, ROWS, COLS>
+where
+    R: InputPin,
+    C: OutputPin,
+    D: DelayNs,
+{
+    /// Create a scanner; all column pins are driven high (idle)
+    pub fn new(rows: [R; ROWS], mut cols: [C; COLS], delay: D, keymap: [[&'static str; COLS]; ROWS]) -> io::Result<Self> {
+        for col in cols.iter_mut() {
+            col.set_high().map_err(pin_error)?;
+        }
+        Ok(KeyMatrix {
+            rows,
+            cols,
+            delay,
+            keymap,
+            previous: [[false; COLS]; ROWS],
+            reported: [[false; COLS]; ROWS],
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Scan the matrix once, queueing keystrokes for newly pressed keys
+    pub fn scan(&mut self) -> io::Result<()> {
+        for c in 0..COLS {
+            self.cols[c].set_low().map_err(pin_error)?;
+            self.delay.delay_us(SETTLE_US);
+            for r in 0..ROWS {
+                let pressed = self.rows[r].is_low().map_err(pin_error)?;
+                if pressed && self.previous[r][c] && !self.reported[r][c] {
+                    self.reported[r][c] = true;
+                    self.pending.extend(Key::parse_token(self.keymap[r][c]));
+                } else if !pressed {
+                    self.reported[r][c] = false;
+                }
+                self.previous[r][c] = pressed;
+            }
+            self.cols[c].set_high().map_err(pin_error)?;
+        }
+        Ok(())
+    }
+}
+
+impl<R, C, D, const ROWS: usize, const COLS: usize> InputSource for KeyMatrix<R, C, D, ROWS, COLS>
+where
+    R: InputPin,
+    C: OutputPin,
+    D: DelayNs,
+{
+    fn next_key(&mut self) -> io::Result<Option<Key>> {
+        while self.pending.is_empty() {
+            self.scan()?;
+            if self.pending.is_empty() {
+                self.delay.delay_ms(SCAN_INTERVAL_MS);
+            }
+        }
+        Ok(self.pending.pop_front())
+    }
+}
+
+/// HD44780 character LCD on a 4-bit bus (RS, E, D4-D7)
+///
+/// The 12 display characters go on the first line and the lit annunciator
+/// names on the second, matching the HP-41C layout on a 16x2 module.
+pub struct Hd44780Sink<P, D> {
+    rs: P,
+    en: P,
+    data: [P; 4],
+    delay: D,
+    columns: usize,
+}
+
+impl<P: OutputPin, D: DelayNs> Hd44780Sink<P, D> {
+    /// Initialize the controller in 4-bit, 2-line mode
+    pub fn new(rs: P, en: P, data: [P; 4], delay: D, columns: usize) -> io::Result<Self> {
+        let mut lcd = Hd44780Sink { rs, en, data, delay, columns };
+        lcd.delay.delay_ms(50);
+        // Reset sequence from the HD44780 datasheet, then switch to 4-bit
+        for nibble in [0x03, 0x03, 0x03, 0x02] {
+            lcd.write_nibble(nibble, false)?;
+            lcd.delay.delay_ms(5);
+        }
+        lcd.command(0x28)?; // 4-bit, 2 lines, 5x8 font
+        lcd.command(0x0C)?; // display on, cursor off
+        lcd.command(0x06)?; // increment, no shift
+        lcd.command(0x01)?; // clear
+        lcd.delay.delay_ms(2);
+        Ok(lcd)
+    }
+
+    fn write_nibble(&mut self, nibble: u8, is_data: bool) -> io::Result<()> {
+        if is_data {
+            self.rs.set_high().map_err(pin_error)?;
+        } else {
+            self.rs.set_low().map_err(pin_error)?;
+        }
+        for (bit, pin) in self.data.iter_mut().enumerate() {
+            if nibble & (1 << bit) != 0 {
+                pin.set_high().map_err(pin_error)?;
+            } else {
+                pin.set_low().map_err(pin_error)?;
+            }
+        }
+        self.en.set_high().map_err(pin_error)?;
+        self.delay.delay_us(1);
+        self.en.set_low().map_err(pin_error)?;
+        self.delay.delay_us(50);
+        Ok(())
+    }
+
+    fn write_byte(&mut self, byte: u8, is_data: bool) -> io::Result<()> {
+        self.write_nibble(byte >> 4, is_data)?;
+        self.write_nibble(byte & 0x0F, is_data)
+    }
+
+    fn command(&mut self, command: u8) -> io::Result<()> {
+        self.write_byte(command, false)
+    }
+
+    fn write_line(&mut self, address: u8, text: &str) -> io::Result<()> {
+        self.command(0x80 | address)?;
+        let padded = text.chars().chain(std::iter::repeat(' ')).take(self.columns);
+        for ch in padded {
+            // The HD44780 ROM covers ASCII; show anything else as a block
+            let byte = if ch.is_ascii() { ch as u8 } else { 0xFF };
+            self.write_byte(byte, true)?;
+        }
+        Ok(())
+    }
+}
+
+impl<P: OutputPin, D: DelayNs> DisplaySink for Hd44780Sink<P, D> {
+    fn refresh(&mut self, frame: &LcdFrame) -> io::Result<()> {
+        self.write_line(0x00, frame.text())?;
+        self.write_line(0x40, &frame.annunciators.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::convert::Infallible;
+    use embedded_hal::digital::ErrorType;
+    use crate::lcd::Annunciators;
+
+    struct NoDelay;
+    impl DelayNs for NoDelay {
+        fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    /// Shared level of every column pin plus which (row, col) keys are held
+    #[derive(Default)]
+    struct Board {
+        cols: [bool; 2],
+        held: Vec<(usize, usize)>,
+    }
+
+    struct RowPin(usize, Rc<RefCell<Board>>);
+    struct ColPin(usize, Rc<RefCell<Board>>);
+
+    impl ErrorType for RowPin { type Error = Infallible; }
+    impl ErrorType for ColPin { type Error = Infallible; }
+
+    impl InputPin for RowPin {
+        fn is_high(&mut self) -> Result<bool, Infallible> {
+            self.is_low().map(|low| !low)
+        }
+        fn is_low(&mut self) -> Result<bool, Infallible> {
+            let board = self.1.borrow();
+            Ok(board.held.iter().any(|&(r, c)| r == self.0 && !board.cols[c]))
+        }
+    }
+
+    impl OutputPin for ColPin {
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            self.1.borrow_mut().cols[self.0] = false;
+            Ok(())
+        }
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            self.1.borrow_mut().cols[self.0] = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_key_matrix_debounces_and_reports_once() {
+        let board = Rc::new(RefCell::new(Board::default()));
+        let rows = [RowPin(0, board.clone()), RowPin(1, board.clone())];
+        let cols = [ColPin(0, board.clone()), ColPin(1, board.clone())];
+        let mut matrix = KeyMatrix::new(rows, cols, NoDelay, [["sin", "5"], ["enter", ""]]).unwrap();
+
+        board.borrow_mut().held.push((0, 0));
+        assert_eq!(matrix.next_key().unwrap(), Some(Key::Char('s')));
+        assert_eq!(matrix.next_key().unwrap(), Some(Key::Char('i')));
+        assert_eq!(matrix.next_key().unwrap(), Some(Key::Char('n')));
+
+        // Still held: no repeat until released and pressed again
+        matrix.scan().unwrap();
+        assert!(matrix.pending.is_empty());
+
+        board.borrow_mut().held = vec![(1, 0)];
+        assert_eq!(matrix.next_key().unwrap(), Some(Key::Enter));
+    }
+
+    /// Pin levels on the LCD bus plus the nibbles latched on each E pulse
+    #[derive(Default)]
+    struct Bus {
+        levels: [bool; 6],
+        latched: Vec<(bool, u8)>,
+    }
+
+    /// Pin 0 = RS, 1 = E, 2..6 = D4..D7
+    struct BusPin(usize, Rc<RefCell<Bus>>);
+    impl ErrorType for BusPin { type Error = Infallible; }
+    impl OutputPin for BusPin {
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            let mut bus = self.1.borrow_mut();
+            if self.0 == 1 && bus.levels[1] {
+                let nibble = (0..4).fold(0u8, |acc, bit| acc | ((bus.levels[2 + bit] as u8) << bit));
+                let rs = bus.levels[0];
+                bus.latched.push((rs, nibble));
+            }
+            bus.levels[self.0] = false;
+            Ok(())
+        }
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            self.1.borrow_mut().levels[self.0] = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_hd44780_writes_frame_text() {
+        let bus = Rc::new(RefCell::new(Bus::default()));
+        let pin = |i| BusPin(i, bus.clone());
+        let mut lcd = Hd44780Sink::new(pin(0), pin(1), [pin(2), pin(3), pin(4), pin(5)], NoDelay, 16).unwrap();
+        bus.borrow_mut().latched.clear();
+
+        let frame = LcdFrame::new("1.2345", Annunciators::from_bits(Annunciators::PRGM));
+        lcd.refresh(&frame).unwrap();
+
+        let latched = &bus.borrow().latched;
+        let data: Vec<u8> = latched.chunks(2)
+            .filter(|pair| pair[0].0)
+            .map(|pair| (pair[0].1 << 4) | pair[1].1)
+            .collect();
+        let text = String::from_utf8(data).unwrap();
+        assert_eq!(text.len(), 32);
+        assert!(text.starts_with("1.2345          PRGM"));
+
+        // Line addresses are sent as commands with RS low
+        assert_eq!((latched[0].1 << 4) | latched[1].1, 0x80);
+        assert!(!latched[0].0);
+    }
+}
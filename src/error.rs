@@ -1,7 +1,7 @@
-/// Error types for the HP-41C calculator emulator
-/// 
-/// Consolidates all error handling into proper Rust error types
-/// instead of using String errors throughout.
+//! Error types for the HP-41C calculator emulator
+//! 
+//! Consolidates all error handling into proper Rust error types
+//! instead of using String errors throughout.
 
 use std::fmt;
 
@@ -53,6 +53,8 @@ pub enum CommandError {
     InvalidArgument { command: String, argument: String },
     /// Command not allowed in current mode
     NotAllowed(String),
+    /// Function from a module that isn't present (NONEXISTENT)
+    Nonexistent(String),
 }
 
 /// Errors specific to programming mode
@@ -68,6 +70,13 @@ pub enum ProgrammingError {
     InvalidLine(i32),
     /// Stack overflow in subroutine calls
     SubroutineStackOverflow,
+    /// An ASSERT step's condition was false (condition and values)
+    AssertionFailed(String),
+    /// A run went past its step or time limit (steps taken)
+    StepLimitExceeded(usize),
+    /// No register free for a new step until memory was packed (PACKING,
+    /// TRY AGAIN)
+    Packed,
 }
 
 /// Errors related to storage registers
@@ -77,6 +86,85 @@ pub enum StorageError {
     InvalidRegister(usize),
     /// Register arithmetic error
     ArithmeticError(String),
+    /// Write to a register marked read-only with PROTECT
+    Protected(usize),
+    /// Register at or beyond the current SIZE (NONEXISTENT)
+    Nonexistent(usize),
+    /// Not enough free memory for the requested SIZE (NO ROOM)
+    NoRoom,
+}
+
+impl CalculatorError {
+    /// A stable number for the error, for scripts to branch on
+    ///
+    /// The tens digit is the kind of error: 1x stack, 2x input, 3x command,
+    /// 4x programming, 5x storage registers. Numbers are never reused;
+    /// `hp41c exec` exits with them.
+    ///
+    /// | Code | Error |
+    /// |---|---|
+    /// | 10 | Division by zero |
+    /// | 11 | Math error |
+    /// | 12 | Stack underflow |
+    /// | 20 | Invalid number |
+    /// | 21 | Number overflow |
+    /// | 22 | Invalid digit |
+    /// | 30 | Unknown command |
+    /// | 31 | Missing argument |
+    /// | 32 | Invalid argument |
+    /// | 33 | Not allowed |
+    /// | 34 | Nonexistent function |
+    /// | 40 | Label not found |
+    /// | 41 | Program memory full |
+    /// | 42 | No program |
+    /// | 43 | Invalid line number |
+    /// | 44 | Subroutine stack overflow |
+    /// | 45 | Assertion failed |
+    /// | 46 | Step limit exceeded |
+    /// | 47 | Packing, try again |
+    /// | 50 | Invalid register |
+    /// | 51 | Register arithmetic |
+    /// | 52 | Protected register |
+    /// | 53 | Nonexistent register |
+    /// | 54 | No room |
+    pub fn code(&self) -> u8 {
+        match self {
+            CalculatorError::Stack(e) => match e {
+                StackError::DivisionByZero => 10,
+                StackError::MathError(_) => 11,
+                StackError::Underflow => 12,
+            },
+            CalculatorError::Input(e) => match e {
+                InputError::InvalidNumber(_) => 20,
+                InputError::Overflow => 21,
+                InputError::InvalidDigit(_) => 22,
+            },
+            CalculatorError::Command(e) => match e {
+                CommandError::UnknownCommand(_) => 30,
+                CommandError::MissingArgument(_) => 31,
+                CommandError::InvalidArgument { .. } => 32,
+                CommandError::NotAllowed(_) => 33,
+                CommandError::Nonexistent(_) => 34,
+            },
+            CalculatorError::Programming(e) => match e {
+                ProgrammingError::LabelNotFound(_) => 40,
+                ProgrammingError::MemoryFull => 41,
+                ProgrammingError::NoProgram => 42,
+                ProgrammingError::InvalidLine(_) => 43,
+                ProgrammingError::SubroutineStackOverflow => 44,
+                ProgrammingError::AssertionFailed(_) => 45,
+                ProgrammingError::StepLimitExceeded(_) => 46,
+                ProgrammingError::Packed => 47,
+            },
+            CalculatorError::Storage(e) => match e {
+                StorageError::InvalidRegister(_) => 50,
+                StorageError::ArithmeticError(_) => 51,
+                StorageError::Protected(_) => 52,
+                StorageError::Nonexistent(_) => 53,
+                StorageError::NoRoom => 54,
+            },
+        }
+    }
 }
 
 // Display implementations for all error types
@@ -122,6 +210,7 @@ impl fmt::Display for CommandError {
                 write!(f, "Invalid argument '{}' for {}", argument, command)
             }
             CommandError::NotAllowed(msg) => write!(f, "Not allowed: {}", msg),
+            CommandError::Nonexistent(cmd) => write!(f, "Nonexistent: {}", cmd),
         }
     }
 }
@@ -134,6 +223,9 @@ impl fmt::Display for ProgrammingError {
             ProgrammingError::NoProgram => write!(f, "No program in memory"),
             ProgrammingError::InvalidLine(n) => write!(f, "Invalid line number: {}", n),
             ProgrammingError::SubroutineStackOverflow => write!(f, "Subroutine stack overflow"),
+            ProgrammingError::AssertionFailed(detail) => write!(f, "Assertion failed: {}", detail),
+            ProgrammingError::StepLimitExceeded(steps) => write!(f, "Run stopped after {} steps", steps),
+            ProgrammingError::Packed => write!(f, "Packing, try again"),
         }
     }
 }
@@ -143,6 +235,9 @@ impl fmt::Display for StorageError {
         match self {
             StorageError::InvalidRegister(n) => write!(f, "Invalid register: {}", n),
             StorageError::ArithmeticError(msg) => write!(f, "Register arithmetic: {}", msg),
+            StorageError::Protected(n) => write!(f, "Protected register: {}", n),
+            StorageError::Nonexistent(n) => write!(f, "Nonexistent register: R{:02}", n),
+            StorageError::NoRoom => write!(f, "No room"),
         }
     }
 }
@@ -0,0 +1,105 @@
+//! Headless command runs for scripts (`hp41c exec`)
+//!
+//! `run_lines` executes command lines one after another, as
+//! `HP41CCalculator::run_command_line` takes them, and stops at the first
+//! that fails. The report says what the calculator ended with or how it
+//! failed; as JSON it reads
+//!
+//! ```text
+//! {"ok":true,"x":5.0,"display":"5.0000","messages":[]}
+//! {"ok":false,"x":0.0,"display":"0.0000","messages":[],
+//!  "error":{"code":10,"line":2,"message":"Stack error: Division by zero"}}
+//! ```
+//!
+//! The error code is `CalculatorError::code`, and the process exits with
+//! it, so a wrapper can branch on the kind of failure without parsing the
+//! message. A failure that isn't a calculator error (a malformed argument
+//! the parser turns down) has code 1.
+
+use serde::Serialize;
+use crate::calculator::HP41CCalculator;
+
+/// Exit status and error code of a failure without a calculator error
+pub const GENERAL_FAILURE: u8 = 1;
+
+/// How a run failed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExecError {
+    pub code: u8,
+    /// Command line that failed, from 1
+    pub line: usize,
+    pub message: String,
+}
+
+/// What a run of command lines left behind
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExecReport {
+    pub ok: bool,
+    pub x: f64,
+    /// X as the display shows it
+    pub display: String,
+    /// Messages the commands returned, in order
+    pub messages: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ExecError>,
+}
+
+impl ExecReport {
+    /// The process exit status: 0, or the failure's error code
+    pub fn exit_code(&self) -> u8 {
+        self.error.as_ref().map_or(0, |error| error.code)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Run command lines until one fails
+pub fn run_lines<S: AsRef<str>>(calc: &mut HP41CCalculator, lines: &[S]) -> ExecReport {
+    let mut messages = Vec::new();
+    let mut error = None;
+    for (index, line) in lines.iter().enumerate() {
+        calc.clear_last_error();
+        match calc.run_command_line(line.as_ref()) {
+            Ok(Some(message)) => messages.push(message),
+            Ok(None) => {}
+            Err(message) => {
+                let code = calc.last_error().map_or(GENERAL_FAILURE, |e| e.code());
+                error = Some(ExecError { code, line: index + 1, message });
+                break;
+            }
+        }
+    }
+    ExecReport {
+        ok: error.is_none(),
+        x: calc.snapshot().stack[0],
+        display: calc.lcd_frame().text().trim().to_string(),
+        messages,
+        error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes() {
+        let mut calc = HP41CCalculator::new();
+        let report = run_lines(&mut calc, &["2", "3", "+"]);
+        assert!(report.ok);
+        assert_eq!((report.x, report.exit_code()), (5.0, 0));
+        assert!(!report.to_json().contains("error"));
+
+        // The first failure stops the run and gives its code
+        let report = run_lines(&mut calc, &["0", "/", "9"]);
+        let error = report.error.as_ref().unwrap();
+        assert_eq!((error.code, error.line), (10, 2));
+        assert_eq!(report.exit_code(), 10);
+        assert!(report.to_json().contains(r#""error":{"code":10,"line":2"#));
+        assert_eq!(run_lines(&mut calc, &["FOO"]).exit_code(), 30);
+        assert_eq!(run_lines(&mut calc, &["XEQ NOWHERE"]).exit_code(), 40);
+        assert_eq!(run_lines(&mut calc, &["SIZE 005", "RCL 07"]).exit_code(), 53);
+    }
+}
@@ -1,14 +1,21 @@
-/// Command execution for the HP-41C calculator
-/// 
-/// Handles the execution of all calculator commands including math functions,
-/// stack operations, programming commands, and storage operations.
-/// Now includes hooks for external logging of storage operations.
+//! Command execution for the HP-41C calculator
+//! 
+//! Handles the execution of all calculator commands including math functions,
+//! stack operations, programming commands, and storage operations.
+//! Now includes hooks for external logging of storage operations.
 
 use crate::stack::Stack;
 use crate::input::InputState;
 use crate::math::{execute_math_function, factorial};
-use crate::programming::ProgrammingMode;
+use crate::programming::{unsupported_operand, ProgrammingMode};
+use crate::registry::command_key;
+use crate::analysis::{lint, CrossReference};
 use crate::display::{DisplayMode, DisplayFormatter};
+use crate::flags::{Flags, FLAG_COUNT, USER_FLAG_COUNT};
+use crate::statistics;
+use crate::navigation::execute_navigation_command;
+use crate::surveying::execute_surveying_command;
+use crate::number_theory::execute_number_theory_command;
 use crate::error::{CalculatorError, CommandError, StorageError, ProgrammingError};
 
 /// Execute a calculator command
@@ -24,7 +31,7 @@ pub fn execute_command(
     display: &mut DisplayFormatter,
     storage: &mut [f64],
 ) -> Result<Option<String>, CalculatorError> {
-    let command = command.to_lowercase();
+    let command = command_key(command);
     
     match command.as_str() {
         // Arithmetic operators
@@ -53,41 +60,105 @@ pub fn execute_command(
             input.clear();
             Ok(None)
         }
+        "mod" => {
+            stack.modulo()?;
+            input.clear();
+            Ok(None)
+        }
+        "%" => {
+            stack.percent()?;
+            input.clear();
+            Ok(None)
+        }
+        "%ch" => {
+            stack.percent_change()?;
+            input.clear();
+            Ok(None)
+        }
 
         // Math functions
         "sin" | "cos" | "tan" | "asin" | "acos" | "atan" | 
-        "log" | "ln" | "exp" | "sqrt" | "inv" => {
+        "log" | "ln" | "exp" | "10x" | "sqrt" | "x2" | "inv" | "abs" | "int" | "frc" | "sign" => {
             execute_math_command(&command, stack, input)
         }
         
+        // Navigation module sailings
+        "gc" | "rhumb" | "dr" => execute_navigation_command(&command, stack, input),
+        
+        // Surveying module: bearings and traverses in register blocks
+        "azb" | "baz" | "stpt" | "trav" | "close" | "area" => {
+            execute_surveying_command(&command, args, stack, input, storage)
+        }
+        
+        // Comparisons of X with Y or zero, with keyboard spellings for ≠ ≤ ≥
+        "x=y?" | "x≠y?" | "x#y?" | "x<y?" | "x≤y?" | "x<=y?" | "x>y?" | "x≥y?" | "x>=y?" => {
+            let answer = compare(&command, stack.x(), stack.y());
+            stack.set_lift_flag(true);
+            input.clear();
+            Ok(do_if_true(answer, programming))
+        }
+        "x=0?" | "x≠0?" | "x#0?" | "x<0?" | "x≤0?" | "x<=0?" | "x>0?" | "x≥0?" | "x>=0?" => {
+            let answer = compare(&command, stack.x(), 0.0);
+            stack.set_lift_flag(true);
+            input.clear();
+            Ok(do_if_true(answer, programming))
+        }
+        
+        // Integer functions
+        "gcd" | "lcm" | "prime?" | "factor" => {
+            execute_number_theory_command(&command, stack, input, programming)
+        }
+        
+        "rnd" => {
+            stack.set_x(display.round(stack.x()));
+            stack.set_lift_flag(true);
+            input.clear();
+            Ok(None)
+        }
+        
         // Stack operations
         "enter" => execute_enter(stack, input),
         "swap" => execute_swap(stack),
+        "rdn" => execute_roll(stack, input, Stack::roll_down),
+        "r^" => execute_roll(stack, input, Stack::roll_up),
         "clx" => execute_clear_x(stack, input),
         "clr" => execute_clear_all(stack, input),
         "chs" => execute_change_sign(stack),
+        "lastx" => execute_last_x(stack, input),
         
         // Constants
         "pi" => execute_pi(stack, input),
         "pow" => execute_power(stack, input),
         
         // Programming
-        "lbl" | "gto" | "xeq" | "rtn" | "sst" | "bst" | "prgm" => {
+        "lbl" | "gto" | "xeq" | "rtn" | "end" | "bst" | "prgm" | "del" => {
             execute_programming_command(&command, args, programming, stack)
         }
         
+        "xref" => Ok(Some(CrossReference::build(&programming.program).to_string())),
+        "lint" => execute_lint(programming),
+        "list" if programming.program.is_empty() => Err(ProgrammingError::NoProgram.into()),
+        "list" => Ok(Some(programming.list(..).join("\n"))),
+        "renum" => execute_renumber(programming),
+        
         // Display modes
         "fix" | "sci" | "eng" => {
             execute_display_command(&command, args, display)
         }
         
         // Storage - NOTE: External logging should capture these operations
-        "sto" | "rcl" => {
+        "sto" | "rcl" | "sto+" | "sto-" | "sto*" | "sto/" | "rcl+" | "rcl-" | "rcl*" | "rcl/" | "x<>" => {
 	    let result = execute_storage_command(&command, args, stack, storage)?;
 	    input.clear();
 	    Ok(result)
         }
         
+        "clrg" => {
+            storage.fill(0.0);
+            input.clear();
+            Ok(None)
+        }
+        
         // Special
 	 "!" => execute_factorial(stack, input),
         "eex" => execute_eex(input),
@@ -103,7 +174,9 @@ fn execute_math_command(
     stack: &mut Stack,
     input: &mut InputState,
 ) -> Result<Option<String>, CalculatorError> {
-    let result = execute_math_function(function, stack.x())?;
+    let x = stack.x();
+    let result = execute_math_function(function, x)?;
+    stack.set_last_x(x);
     stack.set_x(result);
     stack.set_lift_flag(true);
     input.clear();
@@ -123,6 +196,17 @@ fn execute_swap(stack: &mut Stack) -> Result<Option<String>, CalculatorError> {
     Ok(None)
 }
 
+fn execute_roll(
+    stack: &mut Stack,
+    input: &mut InputState,
+    roll: fn(&mut Stack),
+) -> Result<Option<String>, CalculatorError> {
+    roll(stack);
+    stack.set_lift_flag(true);
+    input.clear();
+    Ok(None)
+}
+
 fn execute_clear_x(stack: &mut Stack, input: &mut InputState) -> Result<Option<String>, CalculatorError> {
     stack.clear_x();
     input.clear();
@@ -140,6 +224,12 @@ fn execute_change_sign(stack: &mut Stack) -> Result<Option<String>, CalculatorEr
     Ok(None)
 }
 
+fn execute_last_x(stack: &mut Stack, input: &mut InputState) -> Result<Option<String>, CalculatorError> {
+    stack.recall_last_x();
+    input.clear();
+    Ok(None)
+}
+
 // Constants and special operations
 fn execute_pi(stack: &mut Stack, input: &mut InputState) -> Result<Option<String>, CalculatorError> {
     if stack.should_lift() {
@@ -163,7 +253,9 @@ fn execute_eex(input: &mut InputState) -> Result<Option<String>, CalculatorError
 }
 
 fn execute_factorial(stack: &mut Stack, input: &mut InputState) -> Result<Option<String>, CalculatorError> {
-    let result = factorial(stack.x())?;
+    let x = stack.x();
+    let result = factorial(x)?;
+    stack.set_last_x(x);
     stack.set_x(result);
     stack.set_lift_flag(true);
     input.clear();
@@ -178,18 +270,27 @@ fn execute_programming_command(
     _stack: &mut Stack,
 ) -> Result<Option<String>, CalculatorError> {
     match command {
-        "lbl" => {
-            if programming.is_programming {
-                let args = args.ok_or(CommandError::MissingArgument("LBL".to_string()))?;
-                programming.add_instruction("LBL", Some(args.clone()), &format!("LBL {}", args[0]));
-                Ok(None)
-            } else {
-                Ok(None)
-            }
-        }
+        // Recorded in PRGM mode; a label does nothing otherwise
+        "lbl" => Ok(None),
         
         "gto" => {
             let args = args.ok_or(CommandError::MissingArgument("GTO".to_string()))?;
+            // GTO .. and GTO .nnn address memory rather than a label
+            if args[0] == ".." {
+                programming.goto_end();
+                return Ok(None);
+            }
+            if let Some(line) = args[0].strip_prefix('.') {
+                let number = line.parse::<usize>().map_err(|_| CommandError::InvalidArgument {
+                    command: "GTO".to_string(),
+                    argument: args[0].clone(),
+                })?;
+                return if programming.goto_line(number) {
+                    Ok(None)
+                } else {
+                    Err(ProgrammingError::InvalidLine(number as i32).into())
+                };
+            }
             if programming.goto_label(&args[0]) {
                 Ok(None)
             } else {
@@ -199,34 +300,28 @@ fn execute_programming_command(
         
         "xeq" => {
             let args = args.ok_or(CommandError::MissingArgument("XEQ".to_string()))?;
-            if programming.execute_subroutine(&args[0]) {
-                programming.is_running = true;
-                Ok(None)
-            } else {
-                Err(ProgrammingError::LabelNotFound(args[0].clone()).into())
-            }
+            programming.execute_subroutine(&args[0])?;
+            programming.run();
+            Ok(None)
         }
         
-        "rtn" => {
-            if programming.is_programming {
-                programming.add_instruction("RTN", None, "RTN");
-            } else {
-                programming.return_from_subroutine();
+        "del" => {
+            if !programming.is_programming {
+                return Err(CommandError::NotAllowed("DEL".to_string()).into());
             }
+            let args = args.ok_or(CommandError::MissingArgument("DEL".to_string()))?;
+            let count = args[0].parse::<usize>().map_err(|_| CommandError::InvalidArgument {
+                command: "DEL".to_string(),
+                argument: args[0].clone(),
+            })?;
+            programming.delete_steps(count);
             Ok(None)
         }
         
-        "sst" => {
-            if programming.program.is_empty() {
-                Err(ProgrammingError::NoProgram.into())
-            } else {
-                programming.program_counter = (programming.program_counter + 1) % programming.program.len();
-                if let Some(instr) = programming.get_current_instruction() {
-                    Ok(Some(format!("{:02} {}", instr.line_number, instr)))
-                } else {
-                    Ok(Some("End of program".to_string()))
-                }
-            }
+        // A running END returns like RTN
+        "rtn" | "end" => {
+            programming.return_from_subroutine();
+            Ok(None)
         }
         
         "bst" => {
@@ -255,6 +350,32 @@ fn execute_programming_command(
     }
 }
 
+// Program analysis
+fn execute_lint(programming: &ProgrammingMode) -> Result<Option<String>, CalculatorError> {
+    if programming.program.is_empty() {
+        return Err(ProgrammingError::NoProgram.into());
+    }
+    let issues = lint(&programming.program);
+    if issues.is_empty() {
+        Ok(Some("No problems found".to_string()))
+    } else {
+        let lines: Vec<String> = issues.iter().map(|issue| issue.to_string()).collect();
+        Ok(Some(lines.join("\n")))
+    }
+}
+
+fn execute_renumber(programming: &mut ProgrammingMode) -> Result<Option<String>, CalculatorError> {
+    if programming.program.is_empty() {
+        return Err(ProgrammingError::NoProgram.into());
+    }
+    let changed = programming.renumber_local_labels();
+    Ok(Some(match changed.len() {
+        0 => "Labels already compact".to_string(),
+        1 => "Renumbered 1 label".to_string(),
+        n => format!("Renumbered {} labels", n),
+    }))
+}
+
 // Display mode commands
 fn execute_display_command(
     command: &str,
@@ -286,8 +407,134 @@ fn execute_display_command(
     Ok(Some(format!("{} {}", command.to_uppercase(), digits)))
 }
 
+/// Execute Σ+, Σ-, MEAN, SDEV and CLΣ on the registers starting at `base`
+pub fn execute_statistics_command(
+    command: &str,
+    stack: &mut Stack,
+    input: &mut InputState,
+    storage: &mut [f64],
+    base: usize,
+) -> Result<Option<String>, CalculatorError> {
+    match command {
+        "σ+" | "s+" | "σ-" | "s-" => {
+            // n replaces X and the next number overwrites it, as after ENTER
+            let x = stack.x();
+            let sign = if command.ends_with('+') { 1.0 } else { -1.0 };
+            let n = statistics::accumulate(storage, base, x, stack.y(), sign)?;
+            stack.set_last_x(x);
+            stack.set_x(n);
+            stack.set_lift_flag(false);
+        }
+        "mean" | "sdev" => {
+            let (x, y) = if command == "mean" {
+                statistics::mean(storage, base)?
+            } else {
+                statistics::standard_deviation(storage, base)?
+            };
+            // The old X and Y move up to Z and T
+            stack.lift();
+            stack.set_x(y);
+            stack.lift();
+            stack.set_x(x);
+            stack.set_lift_flag(true);
+        }
+        "clσ" | "cls" => statistics::clear(storage, base)?,
+        _ => unreachable!(),
+    }
+    input.clear();
+    Ok(None)
+}
+
+/// Execute SF, CF and the flag tests (the flags live in the calculator)
+pub fn execute_flag_command(
+    command: &str,
+    args: Option<Vec<String>>,
+    flags: &mut Flags,
+    programming: &mut ProgrammingMode,
+) -> Result<Option<String>, CalculatorError> {
+    let args = args.ok_or(CommandError::MissingArgument(command.to_uppercase()))?;
+    if let Some(operand) = unsupported_operand(&args) {
+        return Err(CommandError::InvalidArgument { command: command.to_uppercase(), argument: operand }.into());
+    }
+    let flag = args[0].parse::<u8>()
+        .map_err(|_| CommandError::InvalidArgument {
+            command: command.to_uppercase(),
+            argument: args[0].clone(),
+        })?;
+    
+    // System flags can be tested but not changed
+    let limit = if matches!(command, "fs?" | "fc?") { FLAG_COUNT } else { USER_FLAG_COUNT };
+    if flag >= limit {
+        return Err(CommandError::Nonexistent(format!("{} {:02}", command.to_uppercase(), flag)).into());
+    }
+
+    let was_set = flags.is_set(flag);
+    let answer = match command {
+        "sf" => {
+            flags.set(flag, true);
+            return Ok(None);
+        }
+        "cf" => {
+            flags.set(flag, false);
+            return Ok(None);
+        }
+        "fs?" => was_set,
+        "fc?" => !was_set,
+        "fs?c" => {
+            flags.set(flag, false);
+            was_set
+        }
+        "fc?c" => {
+            flags.set(flag, false);
+            !was_set
+        }
+        _ => unreachable!(),
+    };
+    
+    Ok(do_if_true(answer, programming))
+}
+
+/// Finish a test: a running program skips the next step when the answer
+/// is false, the keyboard shows YES or NO
+pub(crate) fn do_if_true(answer: bool, programming: &mut ProgrammingMode) -> Option<String> {
+    if programming.is_running() {
+        if !answer {
+            programming.skip_next_step();
+        }
+        None
+    } else {
+        Some(if answer { "YES" } else { "NO" }.to_string())
+    }
+}
+
+/// Outcome of an X?Y or X?0 test, with `y` the value compared against;
+/// alpha data (a NaN) is only equal to itself
+fn compare(command: &str, x: f64, y: f64) -> bool {
+    // The relation sits between the X and the "Y?" or "0?"
+    match &command[1..command.len() - 2] {
+        "=" => x == y || x.to_bits() == y.to_bits(),
+        "≠" | "#" => !compare("x=y?", x, y),
+        "<" => x < y,
+        "≤" | "<=" => x <= y,
+        ">" => x > y,
+        "≥" | ">=" => x >= y,
+        _ => unreachable!(),
+    }
+}
+
 // Storage commands - IMPORTANT: These operations should be logged externally
 // The caller (calculator.rs) should log these storage operations
+/// The data register a STO, RCL, their arithmetic or X<> step addresses
+///
+/// `IND nn` and `ST x` fail as invalid arguments; nothing runs them yet.
+pub(crate) fn register_operand(command: &str, args: &[String]) -> Result<usize, CalculatorError> {
+    let first = args.first().ok_or_else(|| CommandError::MissingArgument(command.to_uppercase()))?;
+    if let Some(operand) = unsupported_operand(args) {
+        return Err(CommandError::InvalidArgument { command: command.to_uppercase(), argument: operand }.into());
+    }
+    first.parse::<usize>().map_err(|_| StorageError::InvalidRegister(0).into())
+}
+
 fn execute_storage_command(
     command: &str,
     args: Option<Vec<String>>,
@@ -295,11 +542,10 @@ fn execute_storage_command(
     storage: &mut [f64],
 ) -> Result<Option<String>, CalculatorError> {
     let args = args.ok_or(CommandError::MissingArgument(command.to_uppercase()))?;
-    let register = args[0].parse::<usize>()
-        .map_err(|_| StorageError::InvalidRegister(0))?;
+    let register = register_operand(command, &args)?;
     
     if register >= storage.len() {
-        return Err(StorageError::InvalidRegister(register).into());
+        return Err(StorageError::Nonexistent(register).into());
     }
 
     match command {
@@ -318,6 +564,49 @@ fn execute_storage_command(
             stack.set_lift_flag(true);
             Ok(Some(format!("RCL {:02}", register)))
         }
+        "sto+" | "sto-" | "sto*" | "sto/" => {
+            storage[register] = register_arithmetic(command, stack.x(), storage[register])?;
+            stack.set_lift_flag(true);
+            Ok(Some(format!("{} {:02}", command.to_uppercase(), register)))
+        }
+        "rcl+" | "rcl-" | "rcl*" | "rcl/" => {
+            // Combines into X in place: no lift, and X is saved in LASTX like any arithmetic
+            let x = stack.x();
+            let result = register_arithmetic(command, x, storage[register])?;
+            stack.set_last_x(x);
+            stack.set_x(result);
+            stack.set_lift_flag(true);
+            Ok(Some(format!("{} {:02}", command.to_uppercase(), register)))
+        }
+        "x<>" => {
+            let x = stack.x();
+            stack.set_x(storage[register]);
+            storage[register] = x;
+            stack.set_lift_flag(true);
+            Ok(Some(format!("X<> {:02}", register)))
+        }
         _ => unreachable!(),
     }
-}
\ No newline at end of file
+}
+
+/// Result of STO+ etc. (the new register value) or RCL+ etc. (the new X)
+///
+/// STO arithmetic computes `register op x`, RCL arithmetic `x op register`.
+pub fn register_arithmetic(command: &str, x: f64, register: f64) -> Result<f64, StorageError> {
+    let (left, right) = if command.starts_with("sto") { (register, x) } else { (x, register) };
+    let result = match command.chars().last() {
+        Some('+') => left + right,
+        Some('-') => left - right,
+        Some('*') => left * right,
+        Some('/') if right == 0.0 => {
+            return Err(StorageError::ArithmeticError("Division by zero".to_string()));
+        }
+        Some('/') => left / right,
+        _ => return Err(StorageError::ArithmeticError(format!("Not register arithmetic: {}", command))),
+    };
+    if result.is_finite() {
+        Ok(result)
+    } else {
+        Err(StorageError::ArithmeticError("Overflow".to_string()))
+    }
+}
@@ -0,0 +1,164 @@
+//! User and system flags
+//!
+//! The HP-41 has 56 flags. Flags 00-29 are user flags that SF and CF can
+//! change (21-29 also steer printing, audio and number formatting); flags
+//! 30-55 are system flags that the machine maintains itself and programs
+//! may only test. The test functions FS?, FC?, FS?C and FC?C follow the
+//! "do if true" rule: in a running program a false test skips the next
+//! step, and from the keyboard the answer is shown as YES or NO.
+//!
+//! Continuous memory keeps only some flags when the machine is turned on:
+//! user flags 00-10, the number format (27-29), the date format (31) and
+//! the display and angle modes (36-43). The rest are cleared, except audio
+//! (26), which is set again.
+
+use std::fmt;
+use std::ops::RangeInclusive;
+use serde::{Deserialize, Serialize};
+
+/// Total number of flags
+pub const FLAG_COUNT: u8 = 56;
+
+/// Flags 00 up to this number (exclusive) can be set and cleared by the user
+pub const USER_FLAG_COUNT: u8 = 30;
+
+/// Run the program at the program counter when the machine is turned on
+pub const FLAG_AUTO_EXECUTE: u8 = 11;
+
+/// Printer TRACE mode: each program step run is printed with X
+pub const FLAG_TRACE: u8 = 15;
+
+/// Audio enable (BEEP and TONE are silent while clear)
+pub const FLAG_AUDIO: u8 = 26;
+
+/// USER mode: the top-row keys run key assignments and letter labels
+pub const FLAG_USER: u8 = 27;
+
+/// Decimal point is a period rather than a comma
+pub const FLAG_DECIMAL_POINT: u8 = 28;
+
+/// Digit grouping separators shown
+pub const FLAG_DIGIT_GROUPING: u8 = 29;
+
+/// Dates are DD.MMYYYY rather than MM.DDYYYY
+pub const FLAG_DMY: u8 = 31;
+
+/// Flags that survive turning the machine off and on
+const KEPT_AT_POWER_ON: [RangeInclusive<u8>; 4] = [0..=10, FLAG_USER..=29, FLAG_DMY..=FLAG_DMY, 36..=43];
+
+/// The 56 flags, stored as one bit each
+///
+/// Serialized as the list of set flag numbers, e.g. `[26, 28, 29]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "Vec<u8>", try_from = "Vec<u8>")]
+pub struct Flags(u64);
+
+impl Flags {
+    /// Flags after MEMORY LOST: audio on, period and grouping for numbers
+    pub fn new() -> Self {
+        let mut flags = Flags(0);
+        for flag in [FLAG_AUDIO, FLAG_DECIMAL_POINT, FLAG_DIGIT_GROUPING] {
+            flags.set(flag, true);
+        }
+        flags
+    }
+
+    pub fn is_set(&self, flag: u8) -> bool {
+        flag < FLAG_COUNT && self.0 & (1 << flag) != 0
+    }
+
+    /// Set or clear any flag, system flags included (out of range is ignored)
+    pub fn set(&mut self, flag: u8, value: bool) {
+        if flag < FLAG_COUNT {
+            if value {
+                self.0 |= 1 << flag;
+            } else {
+                self.0 &= !(1 << flag);
+            }
+        }
+    }
+
+    /// The flags as the machine is turned on: those continuous memory
+    /// keeps stay, the others clear, and audio is enabled
+    pub fn power_on(&mut self) {
+        for flag in 0..FLAG_COUNT {
+            if !KEPT_AT_POWER_ON.iter().any(|kept| kept.contains(&flag)) {
+                self.set(flag, false);
+            }
+        }
+        self.set(FLAG_AUDIO, true);
+    }
+
+    /// Numbers of the flags that are set, in ascending order
+    pub fn set_flags(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..FLAG_COUNT).filter(|&flag| self.is_set(flag))
+    }
+
+    /// All 56 flags as bits, flag 00 in the lowest bit
+    pub fn bits(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for Flags {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Flags> for Vec<u8> {
+    fn from(flags: Flags) -> Self {
+        flags.set_flags().collect()
+    }
+}
+
+impl TryFrom<Vec<u8>> for Flags {
+    type Error = String;
+
+    fn try_from(set: Vec<u8>) -> Result<Self, Self::Error> {
+        let mut flags = Flags(0);
+        for flag in set {
+            if flag >= FLAG_COUNT {
+                return Err(format!("Flag {} out of range", flag));
+            }
+            flags.set(flag, true);
+        }
+        Ok(flags)
+    }
+}
+
+/// The set flags, e.g. `26 28 29`
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let set: Vec<String> = self.set_flags().map(|flag| format!("{:02}", flag)).collect();
+        write!(f, "{}", set.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flag_bits() {
+        let mut flags = Flags::new();
+        assert_eq!(flags.to_string(), "26 28 29");
+        flags.set(0, true);
+        flags.set(55, true);
+        flags.set(26, false);
+        flags.set(56, true);
+        assert!(flags.is_set(55) && !flags.is_set(56));
+        assert_eq!(flags.set_flags().collect::<Vec<_>>(), [0, 28, 29, 55]);
+
+        let json = serde_json::to_string(&flags).unwrap();
+        assert_eq!(json, "[0,28,29,55]");
+        assert_eq!(serde_json::from_str::<Flags>(&json).unwrap(), flags);
+        assert!(serde_json::from_str::<Flags>("[60]").is_err());
+
+        for flag in [5, 11, 25, 31, 40, 48] {
+            flags.set(flag, true);
+        }
+        flags.power_on();
+        assert_eq!(flags.to_string(), "00 05 26 28 29 31 40");
+    }
+}
@@ -0,0 +1,220 @@
+//! FOCAL listings as printed in books and posted on forums
+//!
+//! Program listings found in the wild follow the HP-41's own spelling
+//! rather than this calculator's: `X<>Y` and `1/X` for `SWAP` and `INV`,
+//! `ST+ 01`, `RCL Z` for a stack register, `01♦LBL "ABC"` with the mark
+//! the printer puts on global labels, `1.5 E-3` with the exponent apart.
+//! `parse_focal` reads all of those, maps each mnemonic to the command
+//! here, and reports every line it can't make sense of rather than just
+//! the first. `write_focal` goes the other way, with the HP-41 names, so a
+//! listing can be posted where other emulators' users will read it.
+//!
+//! Blank lines, `#` comments and anything after a `;` outside quotes are
+//! skipped; so is the closing `.END.`.
+
+use crate::error::{CalculatorError, CommandError};
+use crate::listing::parse_listing;
+use crate::programming::ProgramInstruction;
+use crate::registry::{command_key, CommandRegistry};
+
+/// HP-41 names of commands that have another name here, both ways
+const HP_NAMES: [(&str, &str); 8] = [
+    ("X<>Y", "SWAP"),
+    ("1/X", "INV"),
+    ("X^2", "X2"),
+    ("10^X", "10X"),
+    ("Y^X", "^"),
+    ("E^X", "EXP"),
+    ("ENTER^", "ENTER"),
+    ("FACT", "!"),
+];
+
+/// Other spellings listings use, read but not written
+const ALIASES: [(&str, &str); 17] = [
+    ("X↑2", "X2"),
+    ("10↑X", "10X"),
+    ("Y↑X", "^"),
+    ("E↑X", "EXP"),
+    ("ENTER↑", "ENTER"),
+    ("R↑", "R^"),
+    ("R↓", "RDN"),
+    ("√X", "SQRT"),
+    ("N!", "!"),
+    ("ST+", "STO+"),
+    ("ST-", "STO-"),
+    ("ST*", "STO*"),
+    ("ST/", "STO/"),
+    ("STO×", "STO*"),
+    ("STO÷", "STO/"),
+    ("×", "*"),
+    ("÷", "/"),
+];
+
+/// Commands whose argument may be a stack register written without `ST`
+const REGISTER_COMMANDS: [&str; 14] = [
+    "STO", "RCL", "STO+", "STO-", "STO*", "STO/", "RCL+", "RCL-", "RCL*", "RCL/", "X<>", "VIEW", "ASTO", "ARCL",
+];
+
+/// Marks a printer puts between the step number and a global LBL
+const LABEL_MARKS: [char; 4] = ['♦', '◆', '*', '•'];
+
+/// Read a FOCAL listing into steps numbered from 1
+///
+/// Fails with one message per line that isn't a step of a command the
+/// registry has, `Line 7: ...`.
+pub fn parse_focal(text: &str, registry: &CommandRegistry) -> Result<Vec<ProgramInstruction>, Vec<String>> {
+    let mut steps = Vec::new();
+    let mut errors = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let located = |e: &dyn std::fmt::Display| format!("Line {}: {}", index + 1, e);
+        let line = normalize(line);
+        let mut parsed = match parse_listing(&line) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                errors.push(located(&e.trim_start_matches("Line 1: ")));
+                continue;
+            }
+        };
+        let Some(mut step) = parsed.pop() else { continue };
+        if step.text.is_none() {
+            map_step(&mut step);
+            let known = step.command.parse::<f64>().is_ok() || registry.has_command(&command_key(&step.command));
+            if !known {
+                errors.push(located(&CalculatorError::from(CommandError::UnknownCommand(step.command.clone()))));
+                continue;
+            }
+        }
+        step.line_number = steps.len() as i32 + 1;
+        steps.push(step);
+    }
+    if errors.is_empty() { Ok(steps) } else { Err(errors) }
+}
+
+/// A numbered listing with the HP-41 names, ending in `.END.`
+pub fn write_focal(steps: &[ProgramInstruction]) -> String {
+    let mut lines: Vec<String> = steps.iter().enumerate().map(|(index, step)| {
+        let mut step = step.clone();
+        if step.text.is_none() {
+            if let Some((name, _)) = HP_NAMES.iter().find(|(_, command)| *command == step.command) {
+                step.command = name.to_string();
+            }
+        }
+        format!("{:02} {}", index + 1, step.display_text())
+    }).collect();
+    lines.push(format!("{:02} .END.", steps.len() + 1));
+    lines.join("\n") + "\n"
+}
+
+/// A line as `parse_listing` reads it: no step number, label mark or
+/// comment, a quote apart from the command before it, `├` as `⊢`
+fn normalize(line: &str) -> String {
+    let mut line = line.trim();
+    if let Some(comment) = comment_start(line) {
+        line = line[..comment].trim_end();
+    }
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits > 0 {
+        let rest = line[digits..].trim_start_matches(LABEL_MARKS);
+        let marked = rest.len() < line.len() - digits;
+        if !rest.trim().is_empty() && (marked || rest.starts_with(char::is_whitespace)) {
+            line = rest.trim_start();
+        }
+    }
+    let line = line.replacen('├', "⊢", 1);
+    // LBL"ABC" and LBL'ABC, as some printouts have them
+    match line.find(['"', '\'']) {
+        Some(at) if at > 0 && !line[..at].ends_with(char::is_whitespace) && !line.starts_with(['⊢', '>']) => {
+            let quoted = line[at + 1..].trim_end_matches(['"', '\'']);
+            format!("{} \"{}\"", &line[..at], quoted)
+        }
+        Some(0) if line.starts_with('\'') => format!("\"{}\"", line.trim_matches('\'')),
+        _ => line,
+    }
+}
+
+/// Where a `;` comment starts, outside quotes
+fn comment_start(line: &str) -> Option<usize> {
+    let mut quoted = false;
+    for (at, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return Some(at),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// This calculator's command name and argument forms for a step
+fn map_step(step: &mut ProgramInstruction) {
+    if let Some((_, command)) = HP_NAMES.iter().chain(&ALIASES).find(|(name, _)| *name == step.command) {
+        step.command = command.to_string();
+    }
+    // 1.5 E-3, and E3 for 1 E3
+    if step.command.parse::<f64>().is_ok() {
+        if let [exponent] = step.arguments.as_slice() {
+            if exponent.starts_with('E') {
+                step.command = format!("{}{}", step.command, exponent);
+                step.arguments.clear();
+            }
+        }
+    } else if step.command.starts_with('E') && format!("1{}", step.command).parse::<f64>().is_ok() {
+        step.command.insert(0, '1');
+    }
+    // RCL Z for RCL ST Z
+    if REGISTER_COMMANDS.contains(&step.command.as_str()) {
+        if let Some(last) = step.arguments.last() {
+            let after_st = step.arguments.len() > 1 && step.arguments[step.arguments.len() - 2] == "ST";
+            if matches!(last.as_str(), "X" | "Y" | "Z" | "T" | "L") && !after_st {
+                let at = step.arguments.len() - 1;
+                step.arguments.insert(at, "ST".to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_focal() {
+        let registry = CommandRegistry::new();
+        let text = "01♦LBL \"HYP\"   ; hypotenuse\n\
+            02 X^2\n\
+            03 X<>Y\n\
+            04 X↑2\n\
+            05 +\n\
+            06 √X\n\
+            07 ST+ 01\n\
+            08 RCL Z\n\
+            09 1.5 E-3\n\
+            10 E3\n\
+            11 ├\"A;B\"\n\
+            12 XEQ'HYP\n\
+            13 RTN\n\
+            14 .END.\n";
+        let steps = parse_focal(text, &registry).unwrap();
+        let shown: Vec<String> = steps.iter().map(ToString::to_string).collect();
+        assert_eq!(shown, [
+            "LBL HYP", "X2", "SWAP", "X2", "+", "SQRT", "STO+ 01", "RCL ST Z", "1.5E-3", "1E3", "⊢\"A;B\"",
+            "XEQ HYP", "RTN",
+        ]);
+        assert_eq!(steps[12].line_number, 13);
+
+        // Every bad line is reported
+        let errors = parse_focal("01 LBL 01\n02 FOO\n03 \"OPEN\n04 BAR 2", &registry).unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[0], "Line 2: Command error: Unknown command: FOO");
+        assert!(errors[1].starts_with("Line 3: "));
+    }
+
+    #[test]
+    fn test_write_focal() {
+        let registry = CommandRegistry::new();
+        let steps = parse_focal("LBL \"SQ\"\nSWAP\nINV\nX2\nSTO+ 01\n\"ABC\"\nRTN", &registry).unwrap();
+        let text = write_focal(&steps);
+        assert_eq!(text, "01 LBL \"SQ\"\n02 X<>Y\n03 1/X\n04 X^2\n05 STO+ 01\n06 \"ABC\"\n07 RTN\n08 .END.\n");
+        assert_eq!(parse_focal(&text, &registry).unwrap(), steps);
+    }
+}
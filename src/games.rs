@@ -0,0 +1,71 @@
+//! Games Pac functions
+//!
+//! The Games module adds the helpers classic HP-41 games are built from,
+//! all drawing on the calculator's seeded `Rng`:
+//!
+//! - `RNDM`: a random number 0 <= x < 1 into X
+//! - `SEED`: restart the random sequence from X
+//! - `DIE nn`: roll an nn-sided die into X
+//! - `SHUFFLE nn`: deal the numbers 1..X without replacement into Rnn and up
+//! - `DELAY`: wait X seconds, for games that show something briefly
+
+use crate::error::{CalculatorError, CommandError, StackError, StorageError};
+use crate::random::Rng;
+
+/// Longest DELAY, so a stray large X doesn't hang the calculator
+pub const MAX_DELAY_SECONDS: f64 = 60.0;
+
+/// A roll of a die with `sides` sides, 1 to `sides`
+pub fn die(rng: &mut Rng, sides: usize) -> Result<f64, CalculatorError> {
+    if sides == 0 {
+        return Err(CommandError::InvalidArgument { command: "DIE".to_string(), argument: "00".to_string() }.into());
+    }
+    Ok((rng.below(sides as u64) + 1) as f64)
+}
+
+/// Deal 1..=count in random order into `registers[base..base + count]`
+pub fn shuffle(rng: &mut Rng, registers: &mut [f64], base: usize, count: f64) -> Result<(), CalculatorError> {
+    if count < 1.0 || count.fract() != 0.0 {
+        return Err(StackError::MathError(format!("Cannot deal {} cards", count)).into());
+    }
+    let count = count as usize;
+    let deck = registers.get_mut(base..base.saturating_add(count))
+        .ok_or(StorageError::InvalidRegister(base.saturating_add(count) - 1))?;
+    for (i, card) in deck.iter_mut().enumerate() {
+        *card = (i + 1) as f64;
+    }
+    // Fisher-Yates
+    for i in (1..count).rev() {
+        let j = rng.below(i as u64 + 1) as usize;
+        deck.swap(i, j);
+    }
+    Ok(())
+}
+
+/// Seconds for DELAY, checked against `MAX_DELAY_SECONDS`
+pub fn delay_seconds(x: f64) -> Result<f64, CalculatorError> {
+    if !(0.0..=MAX_DELAY_SECONDS).contains(&x) {
+        return Err(StackError::MathError(format!("DELAY must be 0 to {} seconds", MAX_DELAY_SECONDS)).into());
+    }
+    Ok(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shuffle_deals_every_card_once() {
+        let mut rng = Rng::default();
+        let mut registers = [0.0; 10];
+        shuffle(&mut rng, &mut registers, 2, 6.0).unwrap();
+        let mut dealt = registers[2..8].to_vec();
+        dealt.sort_by(f64::total_cmp);
+        assert_eq!(dealt, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(registers[8], 0.0);
+
+        assert!(shuffle(&mut rng, &mut registers, 5, 6.0).is_err());
+        assert!(shuffle(&mut rng, &mut registers, 0, 2.5).is_err());
+        assert!(die(&mut rng, 0).is_err());
+    }
+}
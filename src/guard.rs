@@ -0,0 +1,193 @@
+//! Register write guard for running untrusted programs
+//!
+//! While a program runs, the guard records every storage register it
+//! writes. Registers inside the declared range are the program's to use;
+//! any write outside it is undone when the run ends (an error or a run
+//! limit included), so a library program can't silently clobber the
+//! user's data registers.
+//!
+//! `RegisterProtection` is the stricter, always-on variant: registers
+//! marked with PROTECT are read-only from the keyboard and from programs
+//! alike, and a command that would change one fails instead.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Tracks register writes during one program run
+#[derive(Debug, Clone)]
+pub struct RegisterGuard {
+    allowed: RangeInclusive<usize>,
+    /// Original values of protected registers written during the run
+    originals: BTreeMap<usize, f64>,
+    /// Every register written during the run
+    written: BTreeSet<usize>,
+}
+
+impl RegisterGuard {
+    /// Guard all registers outside `allowed`
+    pub fn new(allowed: RangeInclusive<usize>) -> Self {
+        RegisterGuard {
+            allowed,
+            originals: BTreeMap::new(),
+            written: BTreeSet::new(),
+        }
+    }
+
+    /// Registers the program may write freely
+    pub fn allowed(&self) -> &RangeInclusive<usize> {
+        &self.allowed
+    }
+
+    /// Registers written so far in this run
+    pub fn written(&self) -> impl Iterator<Item = usize> + '_ {
+        self.written.iter().copied()
+    }
+
+    /// Compare registers before and after one program step
+    pub fn observe(&mut self, before: &[f64], after: &[f64]) {
+        for (register, (&old, &new)) in before.iter().zip(after).enumerate() {
+            if old.to_bits() == new.to_bits() {
+                continue;
+            }
+            self.written.insert(register);
+            if !self.allowed.contains(&register) {
+                self.originals.entry(register).or_insert(old);
+            }
+        }
+    }
+
+    /// Restore protected registers and reset for the next run
+    ///
+    /// Returns the registers that were put back.
+    pub fn finish(&mut self, registers: &mut [f64]) -> Vec<usize> {
+        let restored: Vec<usize> = std::mem::take(&mut self.originals).into_iter()
+            .filter_map(|(register, value)| {
+                registers.get_mut(register).map(|slot| {
+                    *slot = value;
+                    register
+                })
+            })
+            .collect();
+        self.written.clear();
+        restored
+    }
+}
+
+/// Registers marked read-only
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegisterProtection {
+    registers: BTreeSet<usize>,
+}
+
+impl RegisterProtection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn protect(&mut self, range: RangeInclusive<usize>) {
+        self.registers.extend(range);
+    }
+
+    pub fn unprotect(&mut self, range: RangeInclusive<usize>) {
+        self.registers.retain(|register| !range.contains(register));
+    }
+
+    pub fn clear(&mut self) {
+        self.registers.clear();
+    }
+
+    pub fn is_protected(&self, register: usize) -> bool {
+        self.registers.contains(&register)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty()
+    }
+
+    /// Protected register numbers in ascending order
+    pub fn registers(&self) -> impl Iterator<Item = usize> + '_ {
+        self.registers.iter().copied()
+    }
+
+    /// Protected registers as contiguous ranges
+    pub fn ranges(&self) -> Vec<RangeInclusive<usize>> {
+        let mut ranges: Vec<RangeInclusive<usize>> = Vec::new();
+        for &register in &self.registers {
+            match ranges.last_mut() {
+                Some(range) if *range.end() + 1 == register => *range = *range.start()..=register,
+                _ => ranges.push(register..=register),
+            }
+        }
+        ranges
+    }
+
+    /// The first protected register that differs between two register sets
+    pub fn first_violation(&self, before: &[f64], after: &[f64]) -> Option<usize> {
+        self.registers().find(|&register| {
+            before.get(register).map(|value| value.to_bits()) != after.get(register).map(|value| value.to_bits())
+        })
+    }
+}
+
+impl FromIterator<usize> for RegisterProtection {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        RegisterProtection { registers: iter.into_iter().collect() }
+    }
+}
+
+/// Ranges such as `R10-R19 R25`
+impl fmt::Display for RegisterProtection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ranges: Vec<String> = self.ranges().into_iter()
+            .map(|range| if range.start() == range.end() {
+                format!("R{:02}", range.start())
+            } else {
+                format!("R{:02}-R{:02}", range.start(), range.end())
+            })
+            .collect();
+        write!(f, "{}", ranges.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restores_writes_outside_range() {
+        let mut guard = RegisterGuard::new(10..=19);
+        let mut registers = vec![1.0; 25];
+
+        let before = registers.clone();
+        registers[3] = 7.0;
+        registers[12] = 7.0;
+        guard.observe(&before, &registers);
+
+        let before = registers.clone();
+        registers[3] = 8.0;
+        guard.observe(&before, &registers);
+
+        assert_eq!(guard.written().collect::<Vec<_>>(), vec![3, 12]);
+        assert_eq!(guard.finish(&mut registers), vec![3]);
+        assert_eq!(registers[3], 1.0);
+        assert_eq!(registers[12], 7.0);
+        assert_eq!(guard.written().count(), 0);
+    }
+
+    #[test]
+    fn test_protected_ranges() {
+        let mut protection = RegisterProtection::new();
+        protection.protect(10..=19);
+        protection.protect(25..=25);
+        protection.unprotect(15..=16);
+        assert_eq!(protection.to_string(), "R10-R14 R17-R19 R25");
+
+        let before = vec![0.0; 30];
+        let mut after = before.clone();
+        after[3] = 1.0;
+        assert_eq!(protection.first_violation(&before, &after), None);
+        after[18] = 1.0;
+        assert_eq!(protection.first_violation(&before, &after), Some(18));
+    }
+}
@@ -0,0 +1,395 @@
+//! Message catalogs for localized help and error text
+//!
+//! Command descriptions and error prompts are looked up by message key in a
+//! `MessageCatalog` for the selected `Locale`. English text comes straight
+//! from the `CommandSpec` descriptions and error `Display` impls, so a
+//! translated catalog only needs the keys it overrides; anything missing
+//! falls back to English.
+//!
+//! Keys are `cmd.<name>` for command descriptions and `error.<kind>` for
+//! errors. Templates use `{0}`, `{1}` ... for arguments.
+
+use std::collections::HashMap;
+use crate::error::{CalculatorError, CommandError, InputError, ProgrammingError, StackError, StorageError};
+use crate::registry::CommandSpec;
+
+/// Supported display languages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+pub enum Locale {
+    #[default]
+    English,
+    German,
+}
+
+impl Locale {
+    /// Parse a language tag such as `de`, `de-DE` or `de_DE.UTF-8`
+    pub fn from_tag(tag: &str) -> Option<Locale> {
+        let language = tag.split(['-', '_', '.']).next()?.to_lowercase();
+        match language.as_str() {
+            "en" | "c" | "posix" => Some(Locale::English),
+            "de" => Some(Locale::German),
+            _ => None,
+        }
+    }
+
+    /// Pick a locale from `HP41C_LOCALE`, then `LANG`, defaulting to English
+    pub fn from_env() -> Locale {
+        ["HP41C_LOCALE", "LANG"].iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find_map(|tag| Locale::from_tag(&tag))
+            .unwrap_or_default()
+    }
+
+    /// Short language tag
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Locale::English => "en",
+            Locale::German => "de",
+        }
+    }
+}
+
+/// German translations
+const GERMAN: &[(&str, &str)] = &[
+    // Command descriptions
+    ("cmd.sin", "Sinus"),
+    ("cmd.cos", "Kosinus"),
+    ("cmd.tan", "Tangens"),
+    ("cmd.asin", "Arkussinus"),
+    ("cmd.acos", "Arkuskosinus"),
+    ("cmd.atan", "Arkustangens"),
+    ("cmd.log", "Zehnerlogarithmus"),
+    ("cmd.ln", "Natürlicher Logarithmus"),
+    ("cmd.exp", "Exponentialfunktion"),
+    ("cmd.sqrt", "Quadratwurzel"),
+    ("cmd.inv", "Kehrwert"),
+    ("cmd.chs", "Vorzeichenwechsel"),
+    ("cmd.abs", "Absolutbetrag"),
+    ("cmd.int", "Ganzzahliger Anteil"),
+    ("cmd.frc", "Nachkommaanteil"),
+    ("cmd.sign", "Vorzeichen"),
+    ("cmd.x2", "Quadrat"),
+    ("cmd.10x", "Zehnerpotenz"),
+    ("cmd.rnd", "Auf Anzeige runden"),
+    ("cmd.enter", "Stapel anheben (ENTER)"),
+    ("cmd.swap", "X und Y tauschen"),
+    ("cmd.clx", "X-Register löschen"),
+    ("cmd.clr", "Stapel löschen"),
+    ("cmd.rdn", "Stapel abwärts rollen"),
+    ("cmd.r^", "Stapel aufwärts rollen"),
+    ("cmd.+", "Addition"),
+    ("cmd.-", "Subtraktion"),
+    ("cmd.*", "Multiplikation"),
+    ("cmd./", "Division"),
+    ("cmd.^", "Potenz"),
+    ("cmd.!", "Fakultät"),
+    ("cmd.mod", "Divisionsrest"),
+    ("cmd.%", "Prozent"),
+    ("cmd.%ch", "Prozentuale Änderung"),
+    ("cmd.fix", "Festkommaanzeige"),
+    ("cmd.sci", "Wissenschaftliche Anzeige"),
+    ("cmd.eng", "Technische Anzeige"),
+    ("cmd.tone", "Ton ausgeben"),
+    ("cmd.beep", "Signalton ausgeben"),
+    ("cmd.adv", "Papiervorschub"),
+    ("cmd.prx", "X drucken"),
+    ("cmd.pra", "ALPHA drucken"),
+    ("cmd.prstk", "Stack drucken"),
+    ("cmd.prreg", "Datenregister drucken"),
+    ("cmd.prp", "Programm drucken"),
+    ("cmd.sto", "In Register speichern"),
+    ("cmd.rcl", "Aus Register abrufen"),
+    ("cmd.asto", "ALPHA in Register speichern"),
+    ("cmd.arcl", "Register an ALPHA anhängen"),
+    ("cmd.x<>", "X mit Register tauschen"),
+    ("cmd.σ+", "Datenpunkt hinzufügen"),
+    ("cmd.σ-", "Datenpunkt entfernen"),
+    ("cmd.clσ", "Statistikregister löschen"),
+    ("cmd.s+", "Datenpunkt hinzufügen"),
+    ("cmd.s-", "Datenpunkt entfernen"),
+    ("cmd.cls", "Statistikregister löschen"),
+    ("cmd.mean", "Mittelwert"),
+    ("cmd.sdev", "Standardabweichung"),
+    ("cmd.σreg", "Statistikregister verlegen"),
+    ("cmd.sreg", "Statistikregister verlegen"),
+    ("cmd.rndm", "Zufallszahl"),
+    ("cmd.seed", "Zufallsfolge neu starten"),
+    ("cmd.delay", "X Sekunden warten"),
+    ("cmd.die", "Würfeln"),
+    ("cmd.shuffle", "Karten mischen und austeilen"),
+    ("cmd.savep", "Programm in den Erweiterungsspeicher sichern"),
+    ("cmd.purfl", "Datei im Erweiterungsspeicher löschen"),
+    ("cmd.emdir", "Verzeichnis des Erweiterungsspeichers"),
+    ("cmd.gc", "Großkreis: Distanz und Kurs"),
+    ("cmd.rhumb", "Loxodrome: Distanz und Kurs"),
+    ("cmd.dr", "Koppelnavigation"),
+    ("cmd.date", "Heutiges Datum"),
+    ("cmd.time", "Uhrzeit"),
+    ("cmd.setdate", "Datum aus X stellen"),
+    ("cmd.settime", "Uhrzeit aus X stellen"),
+    ("cmd.clock", "Uhrzeit in der Statuszeile anzeigen"),
+    ("cmd.dmy", "Datum als TT.MMJJJJ"),
+    ("cmd.mdy", "Datum als MM.TTJJJJ"),
+    ("cmd.xyzalm", "Alarm aus X, Y, Z und ALPHA stellen"),
+    ("cmd.almcat", "Alarmkatalog"),
+    ("cmd.dow", "Wochentag"),
+    ("cmd.jdn", "Julianische Tageszahl"),
+    ("cmd.ddays", "Tage zwischen zwei Daten"),
+    ("cmd.date+", "Datum plus Tage"),
+    ("cmd.x=y?", "Ist X gleich Y?"),
+    ("cmd.x≠y?", "Ist X ungleich Y?"),
+    ("cmd.x#y?", "Ist X ungleich Y?"),
+    ("cmd.x<y?", "Ist X kleiner als Y?"),
+    ("cmd.x≤y?", "Ist X kleiner oder gleich Y?"),
+    ("cmd.x<=y?", "Ist X kleiner oder gleich Y?"),
+    ("cmd.x>y?", "Ist X größer als Y?"),
+    ("cmd.x≥y?", "Ist X größer oder gleich Y?"),
+    ("cmd.x>=y?", "Ist X größer oder gleich Y?"),
+    ("cmd.x=0?", "Ist X gleich null?"),
+    ("cmd.x≠0?", "Ist X ungleich null?"),
+    ("cmd.x#0?", "Ist X ungleich null?"),
+    ("cmd.x<0?", "Ist X negativ?"),
+    ("cmd.x≤0?", "Ist X kleiner oder gleich null?"),
+    ("cmd.x<=0?", "Ist X kleiner oder gleich null?"),
+    ("cmd.x>0?", "Ist X positiv?"),
+    ("cmd.x≥0?", "Ist X größer oder gleich null?"),
+    ("cmd.x>=0?", "Ist X größer oder gleich null?"),
+    ("cmd.gcd", "Größter gemeinsamer Teiler"),
+    ("cmd.lcm", "Kleinstes gemeinsames Vielfaches"),
+    ("cmd.prime?", "Primzahltest"),
+    ("cmd.factor", "Primfaktorzerlegung"),
+    ("cmd.view", "Register anzeigen"),
+    ("cmd.aview", "ALPHA anzeigen"),
+    ("cmd.cla", "ALPHA löschen"),
+    ("cmd.cld", "Anzeige löschen"),
+    ("cmd.r/s", "Programm starten oder anhalten"),
+    ("cmd.stop", "Programm anhalten"),
+    ("cmd.prompt", "ALPHA zeigen und anhalten"),
+    ("cmd.pse", "Kurze Pause"),
+    ("cmd.tic", "Zeitmessung starten"),
+    ("cmd.toc", "Sekunden seit TIC"),
+    ("cmd.cat", "Katalog durchlaufen"),
+    ("cmd.key", "Menütaste belegen"),
+    ("cmd.menu", "Menü anzeigen"),
+    ("cmd.exitm", "Menü verlassen"),
+    ("cmd.clmenu", "Benutzermenü löschen"),
+    ("cmd.user", "USER-Modus ein- oder ausschalten"),
+    ("cmd.size", "Anzahl der Datenregister festlegen"),
+    ("cmd.pack", "Programmspeicher packen"),
+    ("cmd.mem", "Freie Register anzeigen"),
+    ("cmd.azb", "Azimut in Richtungswinkel"),
+    ("cmd.baz", "Richtungswinkel in Azimut"),
+    ("cmd.stpt", "Punkt speichern"),
+    ("cmd.trav", "Polygonzug fortsetzen"),
+    ("cmd.close", "Polygonzug-Abschlussfehler"),
+    ("cmd.area", "Fläche aus Koordinaten"),
+    ("cmd.clrg", "Alle Register löschen"),
+    ("cmd.reset", "Dauerspeicher löschen (MEMORY LOST)"),
+    ("cmd.sto+", "X zum Register addieren"),
+    ("cmd.sto-", "X vom Register subtrahieren"),
+    ("cmd.sto*", "Register mit X multiplizieren"),
+    ("cmd.sto/", "Register durch X dividieren"),
+    ("cmd.rcl+", "Register zu X addieren"),
+    ("cmd.rcl-", "Register von X subtrahieren"),
+    ("cmd.rcl*", "X mit Register multiplizieren"),
+    ("cmd.rcl/", "X durch Register dividieren"),
+    ("cmd.protect", "Register schreibschützen"),
+    ("cmd.unprotect", "Registerschutz aufheben"),
+    ("cmd.sf", "Flag setzen"),
+    ("cmd.cf", "Flag löschen"),
+    ("cmd.fs?", "Ist Flag gesetzt?"),
+    ("cmd.fc?", "Ist Flag gelöscht?"),
+    ("cmd.fs?c", "Ist Flag gesetzt? Danach löschen"),
+    ("cmd.fc?c", "Ist Flag gelöscht? Danach löschen"),
+    ("cmd.lbl", "Marke setzen"),
+    ("cmd.gto", "Zu Marke springen"),
+    ("cmd.xeq", "Programm ausführen"),
+    ("cmd.del", "Programmschritte löschen"),
+    ("cmd.rtn", "Rücksprung"),
+    ("cmd.end", "Programmende"),
+    ("cmd.sst", "Einzelschritt vorwärts"),
+    ("cmd.bst", "Einzelschritt rückwärts"),
+    ("cmd.prgm", "Programm löschen"),
+    ("cmd.asn", "Funktion einer Taste zuweisen"),
+    ("cmd.stats", "Befehlsstatistik"),
+    ("cmd.xref", "Querverweis der Marken"),
+    ("cmd.lint", "Programm prüfen"),
+    ("cmd.list", "Programmspeicher auflisten"),
+    ("cmd.assert", "Bedingung prüfen (Debug-Modus)"),
+    ("cmd.watch", "Bei Änderung von Register oder Flag anhalten"),
+    ("cmd.unwatch", "Alle Überwachungen entfernen"),
+    ("cmd.stub", "Importierter Befehl, übersprungen"),
+    ("cmd.renum", "Lokale Marken neu nummerieren"),
+    ("cmd.lastx", "Letzten X-Wert zurückholen"),
+    ("cmd.pi", "Kreiszahl Pi"),
+    ("cmd.eex", "Exponent eingeben"),
+    ("cmd.arc", "Arkus-Präfix"),
+    // Errors
+    ("error.stack.division_by_zero", "Stapelfehler: Division durch Null"),
+    ("error.stack.math", "Stapelfehler: Rechenfehler: {0}"),
+    ("error.stack.underflow", "Stapelfehler: Stapelunterlauf"),
+    ("error.input.invalid_number", "Eingabefehler: Ungültige Zahl: {0}"),
+    ("error.input.overflow", "Eingabefehler: Zahlenüberlauf"),
+    ("error.input.invalid_digit", "Eingabefehler: Ungültige Ziffer: '{0}'"),
+    ("error.command.unknown", "Befehlsfehler: Unbekannter Befehl: {0}"),
+    ("error.command.missing_argument", "Befehlsfehler: {0} benötigt ein Argument"),
+    ("error.command.invalid_argument", "Befehlsfehler: Ungültiges Argument '{1}' für {0}"),
+    ("error.command.not_allowed", "Befehlsfehler: Nicht erlaubt: {0}"),
+    ("error.command.nonexistent", "Befehlsfehler: Nicht vorhanden: {0}"),
+    ("error.programming.label_not_found", "Programmierfehler: Marke {0} nicht gefunden"),
+    ("error.programming.memory_full", "Programmierfehler: Programmspeicher voll"),
+    ("error.programming.no_program", "Programmierfehler: Kein Programm im Speicher"),
+    ("error.programming.invalid_line", "Programmierfehler: Ungültige Zeilennummer: {0}"),
+    ("error.programming.subroutine_overflow", "Programmierfehler: Unterprogrammstapel voll"),
+    ("error.programming.assertion_failed", "Programmierfehler: ASSERT verletzt: {0}"),
+    ("error.programming.step_limit", "Programmierfehler: Lauf nach {0} Schritten angehalten"),
+    ("error.programming.packed", "Programmierfehler: Speicher gepackt, erneut versuchen"),
+    ("error.storage.invalid_register", "Registerfehler: Ungültiges Register: {0}"),
+    ("error.storage.arithmetic", "Registerfehler: Registerarithmetik: {0}"),
+    ("error.storage.protected", "Registerfehler: Geschütztes Register: {0}"),
+    ("error.storage.nonexistent", "Registerfehler: Register R{0} existiert nicht"),
+    ("error.storage.no_room", "Registerfehler: Kein Platz"),
+];
+
+/// Localized messages for one locale
+#[derive(Debug, Clone, Default)]
+pub struct MessageCatalog {
+    locale: Locale,
+    messages: HashMap<&'static str, &'static str>,
+}
+
+impl MessageCatalog {
+    /// Load the built-in catalog for a locale
+    pub fn for_locale(locale: Locale) -> Self {
+        let table: &[(&str, &str)] = match locale {
+            Locale::English => &[],
+            Locale::German => GERMAN,
+        };
+        MessageCatalog {
+            locale,
+            messages: table.iter().copied().collect(),
+        }
+    }
+
+    /// The catalog's locale
+    pub fn locale(&self) -> Locale {
+        self.locale
+    }
+
+    /// Look up a message template by key
+    pub fn get(&self, key: &str) -> Option<&'static str> {
+        self.messages.get(key).copied()
+    }
+
+    /// Look up a message and fill in `{n}` placeholders
+    pub fn format(&self, key: &str, args: &[String]) -> Option<String> {
+        self.get(key).map(|template| fill(template, args))
+    }
+
+    /// Localized description of a command, falling back to the spec's own
+    pub fn describe(&self, spec: &CommandSpec) -> Option<String> {
+        self.get(&spec.description_key())
+            .map(str::to_string)
+            .or_else(|| spec.description.clone())
+    }
+
+    /// Localized text for an error, falling back to its English `Display`
+    pub fn error(&self, error: &CalculatorError) -> String {
+        let (key, args) = error_key(error);
+        self.format(key, &args).unwrap_or_else(|| error.to_string())
+    }
+}
+
+/// Replace `{0}`, `{1}` ... in a template
+fn fill(template: &str, args: &[String]) -> String {
+    args.iter().enumerate().fold(template.to_string(), |text, (i, arg)| {
+        text.replace(&format!("{{{}}}", i), arg)
+    })
+}
+
+/// Message key and arguments for an error
+fn error_key(error: &CalculatorError) -> (&'static str, Vec<String>) {
+    match error {
+        CalculatorError::Stack(e) => match e {
+            StackError::DivisionByZero => ("error.stack.division_by_zero", vec![]),
+            StackError::MathError(msg) => ("error.stack.math", vec![msg.clone()]),
+            StackError::Underflow => ("error.stack.underflow", vec![]),
+        },
+        CalculatorError::Input(e) => match e {
+            InputError::InvalidNumber(s) => ("error.input.invalid_number", vec![s.clone()]),
+            InputError::Overflow => ("error.input.overflow", vec![]),
+            InputError::InvalidDigit(c) => ("error.input.invalid_digit", vec![c.to_string()]),
+        },
+        CalculatorError::Command(e) => match e {
+            CommandError::UnknownCommand(cmd) => ("error.command.unknown", vec![cmd.clone()]),
+            CommandError::MissingArgument(cmd) => ("error.command.missing_argument", vec![cmd.clone()]),
+            CommandError::InvalidArgument { command, argument } => {
+                ("error.command.invalid_argument", vec![command.clone(), argument.clone()])
+            }
+            CommandError::NotAllowed(msg) => ("error.command.not_allowed", vec![msg.clone()]),
+            CommandError::Nonexistent(cmd) => ("error.command.nonexistent", vec![cmd.clone()]),
+        },
+        CalculatorError::Programming(e) => match e {
+            ProgrammingError::LabelNotFound(lbl) => ("error.programming.label_not_found", vec![lbl.clone()]),
+            ProgrammingError::MemoryFull => ("error.programming.memory_full", vec![]),
+            ProgrammingError::NoProgram => ("error.programming.no_program", vec![]),
+            ProgrammingError::InvalidLine(n) => ("error.programming.invalid_line", vec![n.to_string()]),
+            ProgrammingError::SubroutineStackOverflow => ("error.programming.subroutine_overflow", vec![]),
+            ProgrammingError::AssertionFailed(detail) => ("error.programming.assertion_failed", vec![detail.clone()]),
+            ProgrammingError::StepLimitExceeded(steps) => ("error.programming.step_limit", vec![steps.to_string()]),
+            ProgrammingError::Packed => ("error.programming.packed", vec![]),
+        },
+        CalculatorError::Storage(e) => match e {
+            StorageError::InvalidRegister(n) => ("error.storage.invalid_register", vec![n.to_string()]),
+            StorageError::ArithmeticError(msg) => ("error.storage.arithmetic", vec![msg.clone()]),
+            StorageError::Protected(n) => ("error.storage.protected", vec![n.to_string()]),
+            StorageError::Nonexistent(n) => ("error.storage.nonexistent", vec![format!("{:02}", n)]),
+            StorageError::NoRoom => ("error.storage.no_room", vec![]),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::CommandRegistry;
+
+    #[test]
+    fn test_locale_tags() {
+        assert_eq!(Locale::from_tag("de_DE.UTF-8"), Some(Locale::German));
+        assert_eq!(Locale::from_tag("en-US"), Some(Locale::English));
+        assert_eq!(Locale::from_tag("C"), Some(Locale::English));
+        assert_eq!(Locale::from_tag("fr"), None);
+    }
+
+    #[test]
+    fn test_command_descriptions() {
+        let registry = CommandRegistry::new();
+        let sin = registry.get_spec("sin").unwrap();
+
+        assert_eq!(MessageCatalog::for_locale(Locale::English).describe(sin), Some("SIN function".to_string()));
+        assert_eq!(MessageCatalog::for_locale(Locale::German).describe(sin), Some("Sinus".to_string()));
+    }
+
+    #[test]
+    fn test_german_catalog_covers_all_commands() {
+        let registry = CommandRegistry::new();
+        let german = MessageCatalog::for_locale(Locale::German);
+        for spec in registry.get_all_specs().values() {
+            assert!(german.get(&spec.description_key()).is_some(), "missing translation for {}", spec.name);
+        }
+    }
+
+    #[test]
+    fn test_error_messages() {
+        let error = CalculatorError::from(CommandError::InvalidArgument {
+            command: "FIX".to_string(),
+            argument: "12".to_string(),
+        });
+        assert_eq!(MessageCatalog::for_locale(Locale::English).error(&error), error.to_string());
+        assert_eq!(
+            MessageCatalog::for_locale(Locale::German).error(&error),
+            "Befehlsfehler: Ungültiges Argument '12' für FIX"
+        );
+    }
+}
@@ -0,0 +1,479 @@
+//! Programs and registers from other HP-41 emulators
+//!
+//! V41, i41CX and most other emulators exchange programs as `.raw` files:
+//! the program's bytes exactly as they sit in the real machine's memory.
+//! `decode_raw` turns those bytes into program steps. Functions with a
+//! different name here (`X<>Y` is `SWAP`, `1/X` is `INV`) get this
+//! calculator's name; functions it doesn't have keep their HP-41 name, so
+//! loading the steps reports them by that name. `encode_raw` writes
+//! steps back as such a file, for other emulators or a real machine.
+//!
+//! Free42 and Plus42 export HP-42S programs in the same byte code, which
+//! the 42S extends: functions the HP-41 lacks sit in the XROM range, and
+//! commands with a named argument in text bytes. `decode_free42` reads
+//! those as `STUB` steps naming the instruction, so the calculator can
+//! list what it can't run, or load the program with the stubs in place.
+//!
+//! Data registers come from a PRREG printout, which the same emulators'
+//! printers produce, one register per line:
+//!
+//! ```text
+//! R00= 1.500000000
+//! R01= "ABC"
+//! ```
+
+use crate::alpha;
+use crate::analysis::{is_global_label, label_name, quoted_label};
+use crate::programming::ProgramInstruction;
+
+/// Single-byte functions 0x40-0x8F, by this calculator's names where they differ
+const FUNCTIONS: [&str; 80] = [
+    "+", "-", "*", "/", "X<Y?", "X>Y?", "X<=Y?", "Σ+", "Σ-", "HMS+", "HMS-", "MOD", "%", "%CH", "P-R", "R-P",
+    "LN", "X2", "SQRT", "^", "CHS", "EXP", "LOG", "10X", "E^X-1", "SIN", "COS", "TAN", "ASIN", "ACOS", "ATAN", "DEC",
+    "INV", "ABS", "!", "X≠0?", "X>0?", "LN1+X", "X<0?", "X=0?", "INT", "FRC", "D-R", "R-D", "HMS", "HR", "RND", "OCT",
+    "CLΣ", "SWAP", "PI", "CLR", "R^", "RDN", "LASTX", "CLX", "X=Y?", "X≠Y?", "SIGN", "X<=0?", "MEAN", "SDEV", "AVIEW", "CLD",
+    "DEG", "RAD", "GRAD", "ENTER", "STOP", "RTN", "BEEP", "CLA", "ASHF", "PSE", "CLRG", "AOFF", "AON", "OFF", "PROMPT", "ADV",
+];
+
+/// Functions 0x90-0x9F, which take a register or digit in the next byte
+const REGISTER_FUNCTIONS: [&str; 16] = [
+    "RCL", "STO", "STO+", "STO-", "STO*", "STO/", "ISG", "DSE",
+    "VIEW", "ΣREG", "ASTO", "ARCL", "FIX", "SCI", "ENG", "TONE",
+];
+
+/// Flag functions 0xA8-0xAD
+const FLAG_FUNCTIONS: [&str; 6] = ["SF", "CF", "FS?C", "FC?C", "FS?", "FC?"];
+
+/// Other names of functions in the tables above
+const SYNONYMS: [(&str, &str); 9] = [
+    ("X#Y?", "X≠Y?"), ("X≤Y?", "X<=Y?"), ("X#0?", "X≠0?"), ("X≤0?", "X<=0?"),
+    ("S+", "Σ+"), ("S-", "Σ-"), ("CLS", "CLΣ"), ("SREG", "ΣREG"), ("R/S", "STOP"),
+];
+
+/// Stack registers by postfix value from 112
+const STACK_REGISTERS: [&str; 11] = ["T", "Z", "Y", "X", "L", "M", "N", "O", "P", "Q", "⊢"];
+
+/// Command of a placeholder for an instruction this calculator can't run,
+/// with the instruction as its argument
+pub const STUB: &str = "STUB";
+
+/// Decode a `.raw` program file into steps numbered from 1
+pub fn decode_raw(bytes: &[u8]) -> Result<Vec<ProgramInstruction>, String> {
+    decode(bytes, false)
+}
+
+/// Decode a program exported by Free42 or Plus42, with `STUB` steps for
+/// the HP-42S instructions
+pub fn decode_free42(bytes: &[u8]) -> Result<Vec<ProgramInstruction>, String> {
+    decode(bytes, true)
+}
+
+fn decode(bytes: &[u8], free42: bool) -> Result<Vec<ProgramInstruction>, String> {
+    let mut steps = Vec::new();
+    let mut number = String::new();
+    let mut at = 0;
+    while at < bytes.len() {
+        let op = bytes[at];
+        // Digit entry bytes run together into one number step
+        if (0x10..=0x1C).contains(&op) {
+            push_digit(&mut number, op);
+            at += 1;
+            continue;
+        }
+        if !number.is_empty() {
+            steps.push(number_step(std::mem::take(&mut number)));
+        }
+        let operand = |offset: usize| {
+            bytes.get(at + offset).copied().ok_or_else(|| format!("Byte {}: instruction cut short", at))
+        };
+        let (command, arguments, length): (&str, Vec<String>, usize) = match op {
+            0x00 => ("", vec![], 1),
+            0x01..=0x0F => ("LBL", vec![format!("{:02}", op - 0x01)], 1),
+            0x1D | 0x1E => {
+                let text = alpha_text(bytes, at + 1)?;
+                let length = 2 + text.len();
+                (if op == 0x1D { "GTO" } else { "XEQ" }, vec![quoted_label(&decode_text(&text))], length)
+            }
+            0x20..=0x2F => ("RCL", vec![format!("{:02}", op - 0x20)], 1),
+            0x30..=0x3F => ("STO", vec![format!("{:02}", op - 0x30)], 1),
+            0x40..=0x8F => (FUNCTIONS[usize::from(op - 0x40)], vec![], 1),
+            0x90..=0x9F => {
+                let name = REGISTER_FUNCTIONS[usize::from(op - 0x90)];
+                let arguments = if (0x9C..=0x9F).contains(&op) && operand(1)? < 0x80 {
+                    vec![operand(1)?.to_string()]
+                } else {
+                    postfix(operand(1)?)
+                };
+                (name, arguments, 2)
+            }
+            0xA0..=0xA7 => {
+                let code = u16::from(op & 0x07) << 8 | u16::from(operand(1)?);
+                let xrom = format!("XROM {:02},{:02}", code >> 6, code & 0x3F);
+                if !free42 {
+                    return Err(format!("Byte {}: {} needs a plug-in module", at, xrom));
+                }
+                (STUB, vec![xrom], 2)
+            }
+            0xA8..=0xAD => (FLAG_FUNCTIONS[usize::from(op - 0xA8)], postfix(operand(1)?), 2),
+            // GTO IND and XEQ IND share an opcode; the high bit picks XEQ
+            0xAE => {
+                let target = operand(1)?;
+                let mut arguments = vec!["IND".to_string()];
+                arguments.extend(postfix(target & 0x7F));
+                (if target & 0x80 == 0 { "GTO" } else { "XEQ" }, arguments, 2)
+            }
+            0xB1..=0xBF => ("GTO", vec![format!("{:02}", op - 0xB1)], 2),
+            0xC0..=0xCD => {
+                let kind = operand(2)?;
+                if kind < 0xF0 {
+                    ("END", vec![], 3)
+                } else {
+                    // Fn counts the key assignment byte before the name
+                    let length = usize::from(kind & 0x0F).saturating_sub(1);
+                    let name = bytes.get(at + 4..at + 4 + length)
+                        .ok_or_else(|| format!("Byte {}: label cut short", at))?;
+                    ("LBL", vec![quoted_label(&decode_text(name))], 4 + length)
+                }
+            }
+            0xCE => ("X<>", postfix(operand(1)?), 2),
+            0xCF => ("LBL", postfix(operand(1)?), 2),
+            0xD0..=0xDF => ("GTO", postfix(operand(2)? & 0x7F), 3),
+            0xE0..=0xEF => ("XEQ", postfix(operand(2)? & 0x7F), 3),
+            0xF0..=0xFF => {
+                let text = alpha_text(bytes, at)?;
+                let length = 1 + text.len();
+                match text.split_first() {
+                    Some((0x7F, rest)) => steps.push(ProgramInstruction::text_line(0, &decode_text(rest), true)),
+                    // A 42S command with a named argument, e.g. STO "ABC"
+                    Some((&command, rest)) if free42 && command >= 0x80 => {
+                        let instruction = format!("{:02X} \"{}\"", command, decode_text(rest));
+                        steps.push(ProgramInstruction::new(0, STUB.to_string(), vec![instruction]));
+                    }
+                    _ => steps.push(ProgramInstruction::text_line(0, &decode_text(&text), false)),
+                }
+                at += length;
+                continue;
+            }
+            _ if free42 => (STUB, vec![format!("{:02X}", op)], 1),
+            _ => return Err(format!("Byte {}: unknown instruction {:02X}", at, op)),
+        };
+        if !command.is_empty() {
+            steps.push(ProgramInstruction::new(0, command.to_string(), arguments));
+        }
+        at += length;
+    }
+    if !number.is_empty() {
+        steps.push(number_step(number));
+    }
+    for (index, step) in steps.iter_mut().enumerate() {
+        step.line_number = index as i32 + 1;
+    }
+    Ok(steps)
+}
+
+/// Add one digit entry byte (0-9, point, EEX, NEG) to a number being built
+fn push_digit(number: &mut String, op: u8) {
+    match op {
+        0x1A => number.push('.'),
+        0x1B => number.push('E'),
+        // NEG changes the sign of the exponent once EEX has been keyed
+        0x1C => match number.find('E') {
+            Some(e) => number.insert(e + 1, '-'),
+            None => number.insert(0, '-'),
+        },
+        digit => number.push(char::from(b'0' + digit - 0x10)),
+    }
+}
+
+/// A number step; EEX without a mantissa means 1 times the power of ten
+fn number_step(mut number: String) -> ProgramInstruction {
+    let mantissa = number.trim_start_matches('-');
+    if mantissa.starts_with('E') {
+        number.insert(number.len() - mantissa.len(), '1');
+    }
+    ProgramInstruction::new(0, number, vec![])
+}
+
+/// The characters after an Fn length byte
+fn alpha_text(bytes: &[u8], at: usize) -> Result<Vec<u8>, String> {
+    let length = usize::from(bytes.get(at).ok_or_else(|| format!("Byte {}: text cut short", at))? & 0x0F);
+    bytes.get(at + 1..at + 1 + length)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| format!("Byte {}: text cut short", at))
+}
+
+/// HP-41 characters as text: ASCII, with Σ at 7E
+fn decode_text(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| match b {
+        0x7E => 'Σ',
+        0x20..=0x7D => char::from(b),
+        _ => '?',
+    }).collect()
+}
+
+/// The argument in a postfix byte: a register or label number, a letter
+/// label, a stack register, with IND for the high bit
+fn postfix(byte: u8) -> Vec<String> {
+    let mut arguments = Vec::new();
+    if byte & 0x80 != 0 {
+        arguments.push("IND".to_string());
+    }
+    let value = byte & 0x7F;
+    match value {
+        0..=101 => arguments.push(format!("{:02}", value)),
+        102..=111 => arguments.push(char::from(b'A' + value - 102).to_string()),
+        112..=122 => {
+            arguments.push("ST".to_string());
+            arguments.push(STACK_REGISTERS[usize::from(value - 112)].to_string());
+        }
+        _ => arguments.push(char::from(b'a' + value - 123).to_string()),
+    }
+    arguments
+}
+
+/// Encode steps as a `.raw` program file, the inverse of `decode_raw`
+///
+/// Fails on the first step the HP-41 has no code for: a command only this
+/// calculator has, or a label or text too long for the machine.
+pub fn encode_raw(steps: &[ProgramInstruction]) -> Result<Vec<u8>, String> {
+    Ok(encode_instructions(steps)?.into_iter().flat_map(|(_, bytes)| bytes).collect())
+}
+
+/// The machine instructions of the steps, each with the number of the step
+/// it belongs to
+///
+/// A number is one instruction per digit entry byte; the null between two
+/// numbers belongs to the second.
+pub(crate) fn encode_instructions(steps: &[ProgramInstruction]) -> Result<Vec<(i32, Vec<u8>)>, String> {
+    let mut instructions = Vec::new();
+    let mut after_number = false;
+    for step in steps {
+        let failed = |e: String| format!("Step {:02}: {}", step.line_number, e);
+        let is_number = step.is_number();
+        // Two numbers in a row would run together into one
+        if is_number && after_number {
+            instructions.push((step.line_number, vec![0x00]));
+        }
+        after_number = is_number;
+        if let Some(line) = &step.text {
+            let text = encode_text(&line.text).map_err(failed)?;
+            let length = text.len() + usize::from(line.append);
+            if length > 15 {
+                return Err(failed(format!("text \"{}\" too long", line.text)));
+            }
+            let mut bytes = vec![0xF0 | length as u8];
+            if line.append {
+                bytes.push(0x7F);
+            }
+            bytes.extend(text);
+            instructions.push((step.line_number, bytes));
+        } else if is_number {
+            let mut digits = Vec::new();
+            encode_number(&step.command, &mut digits);
+            instructions.extend(digits.into_iter().map(|digit| (step.line_number, vec![digit])));
+        } else {
+            instructions.push((step.line_number, encode_step(step).map_err(failed)?));
+        }
+    }
+    Ok(instructions)
+}
+
+/// Digit entry bytes for a number, NEG after the part it negates
+fn encode_number(number: &str, bytes: &mut Vec<u8>) {
+    let upper = number.to_uppercase();
+    let (mantissa, exponent) = match upper.split_once('E') {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+        None => (upper.as_str(), None),
+    };
+    let digits = |part: &str, bytes: &mut Vec<u8>| {
+        for c in part.chars() {
+            match c {
+                '.' => bytes.push(0x1A),
+                '0'..='9' => bytes.push(0x10 + c as u8 - b'0'),
+                _ => {}
+            }
+        }
+        if part.starts_with('-') {
+            bytes.push(0x1C);
+        }
+    };
+    digits(mantissa, bytes);
+    if let Some(exponent) = exponent {
+        bytes.push(0x1B);
+        digits(exponent, bytes);
+    }
+}
+
+/// Bytes of a function step
+fn encode_step(step: &ProgramInstruction) -> Result<Vec<u8>, String> {
+    let upper = step.command.to_uppercase();
+    let command = SYNONYMS.iter().find(|(name, _)| *name == upper).map_or(upper.as_str(), |(_, name)| name);
+    let args = step.arguments.as_slice();
+    let number = |arg: &String| arg.parse::<u8>().ok();
+    let missing = || format!("{} needs an argument", command);
+    match (command, args) {
+        ("END", []) => Ok(vec![0xC0, 0x00, 0x0D]),
+        ("LBL", [label]) if number(label).is_some_and(|n| n <= 14) => Ok(vec![0x01 + number(label).unwrap_or_default()]),
+        ("LBL", [label]) if is_global_label(label) => {
+            let name = encode_text(label_name(label))?;
+            if name.len() > 14 {
+                return Err(format!("label \"{}\" too long", label_name(label)));
+            }
+            Ok([vec![0xC0, 0x00, 0xF1 + name.len() as u8, 0x00], name].concat())
+        }
+        ("LBL", [_]) => Ok(vec![0xCF, encode_postfix(args)?]),
+        ("GTO" | "XEQ", [ind, address @ ..]) if ind == "IND" => {
+            let target = encode_postfix(address)?;
+            Ok(vec![0xAE, if command == "XEQ" { target | 0x80 } else { target }])
+        }
+        ("GTO" | "XEQ", [label]) if is_global_label(label) => {
+            let name = encode_text(label_name(label))?;
+            if name.len() > 14 {
+                return Err(format!("label \"{}\" too long", label_name(label)));
+            }
+            let op = if command == "GTO" { 0x1D } else { 0x1E };
+            Ok([vec![op, 0xF0 | name.len() as u8], name].concat())
+        }
+        ("GTO", [label]) if number(label).is_some_and(|n| n <= 14) => Ok(vec![0xB1 + number(label).unwrap_or_default(), 0x00]),
+        ("GTO", [_]) => Ok(vec![0xD0, 0x00, encode_postfix(args)?]),
+        ("XEQ", [_]) => Ok(vec![0xE0, 0x00, encode_postfix(args)?]),
+        ("RCL", [register]) if number(register).is_some_and(|n| n <= 15) => Ok(vec![0x20 + number(register).unwrap_or_default()]),
+        ("STO", [register]) if number(register).is_some_and(|n| n <= 15) => Ok(vec![0x30 + number(register).unwrap_or_default()]),
+        ("X<>", _) => Ok(vec![0xCE, encode_postfix(args)?]),
+        _ => {
+            if let Some(op) = FUNCTIONS.iter().position(|&name| name == command) {
+                return if args.is_empty() {
+                    Ok(vec![0x40 + op as u8])
+                } else {
+                    Err(format!("{} takes no argument", command))
+                };
+            }
+            if let Some(op) = REGISTER_FUNCTIONS.iter().position(|&name| name == command) {
+                if args.is_empty() {
+                    return Err(missing());
+                }
+                // FIX, SCI, ENG and TONE take a plain digit
+                let postfix = match args {
+                    [digit] if op >= 12 && digit.len() == 1 => number(digit).ok_or_else(|| format!("bad argument {}", digit))?,
+                    _ => encode_postfix(args)?,
+                };
+                return Ok(vec![0x90 + op as u8, postfix]);
+            }
+            if let Some(op) = FLAG_FUNCTIONS.iter().position(|&name| name == command) {
+                if args.is_empty() {
+                    return Err(missing());
+                }
+                return Ok(vec![0xA8 + op as u8, encode_postfix(args)?]);
+            }
+            Err(format!("{} has no HP-41 code", command))
+        }
+    }
+}
+
+/// A postfix byte: `nn`, a letter label, `ST X`, any of those after IND
+fn encode_postfix(args: &[String]) -> Result<u8, String> {
+    let (indirect, args) = match args {
+        [ind, rest @ ..] if ind == "IND" => (0x80, rest),
+        _ => (0x00, args),
+    };
+    let value = match args {
+        [st, name] if st == "ST" => STACK_REGISTERS.iter().position(|r| r == name).map(|r| 112 + r as u8),
+        [arg] => match arg.as_bytes() {
+            [letter @ b'A'..=b'J'] => Some(102 + letter - b'A'),
+            [letter @ b'a'..=b'e'] => Some(123 + letter - b'a'),
+            _ => arg.parse::<u8>().ok().filter(|&n| n <= 101),
+        },
+        _ => None,
+    };
+    value.map(|value| value | indirect).ok_or_else(|| format!("bad argument {}", args.join(" ")))
+}
+
+/// HP-41 characters for text: ASCII, with Σ at 7E
+fn encode_text(text: &str) -> Result<Vec<u8>, String> {
+    text.chars().map(|c| match c {
+        'Σ' => Ok(0x7E),
+        ' '..='}' => Ok(c as u8),
+        _ => Err(format!("no HP-41 character for {}", c)),
+    }).collect()
+}
+
+/// Registers from a PRREG printout, as (number, value) pairs
+///
+/// Lines that aren't `Rnn= value` are skipped, so a printout with headers
+/// or blank lines between registers reads as well.
+pub fn parse_register_dump(text: &str) -> Result<Vec<(usize, f64)>, String> {
+    let mut registers = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let Some((name, value)) = line.trim().split_once('=') else { continue };
+        let Some(number) = name.trim().strip_prefix('R').and_then(|n| n.parse::<usize>().ok()) else { continue };
+        let value = value.trim();
+        let value = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            Some(text) => alpha::pack(text),
+            None => value.replace(' ', "").parse::<f64>()
+                .map_err(|_| format!("Line {}: not a number: {}", index + 1, value))?,
+        };
+        registers.push((number, value));
+    }
+    Ok(registers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_raw() {
+        // LBL "AREA", 1.5 E-3, STO 05, XEQ IND 12, "AB", ⊢"C", X<>Y, ISG IND X, END
+        let raw = [
+            0xC0, 0x00, 0xF5, 0x00, b'A', b'R', b'E', b'A',
+            0x11, 0x1A, 0x15, 0x1B, 0x1C, 0x13, 0x00, 0x12,
+            0x35, 0xAE, 0x8C, 0xF2, b'A', b'B', 0xF2, 0x7F, b'C',
+            0x71, 0x96, 0xF3, 0xC0, 0x00, 0x2F,
+        ];
+        let steps = decode_raw(&raw).unwrap();
+        let shown: Vec<String> = steps.iter().map(ToString::to_string).collect();
+        assert_eq!(shown, [
+            "LBL AREA", "1.5E-3", "2", "STO 05", "XEQ IND 12", "\"AB\"", "⊢\"C\"", "SWAP", "ISG IND ST X", "END",
+        ]);
+        assert_eq!(steps[9].line_number, 10);
+        assert!(decode_raw(&[0x91]).is_err());
+        assert!(decode_raw(&[0xA7, 0x41]).unwrap_err().contains("XROM"));
+    }
+
+    #[test]
+    fn test_encode_raw() {
+        let listing = "LBL \"AREA\"\n1.5E-3\n2\n-3\nSTO 05\nRCL 20\nSTO+ ST Y\nXEQ IND 12\nGTO IND ST X\n\
+            \"AB\"\n⊢\"CΣ\"\nSWAP\nISG IND ST X\nFIX 4\nSF 12\nLBL 03\nLBL 20\nLBL a\nGTO 03\nGTO 20\n\
+            XEQ A\nXEQ \"AREA\"\nX<> 07\nRTN\nEND";
+        let steps = crate::listing::parse_listing(listing).unwrap();
+        let raw = encode_raw(&steps).unwrap();
+        assert_eq!(raw[..12], [0xC0, 0x00, 0xF5, 0x00, b'A', b'R', b'E', b'A', 0x11, 0x1A, 0x15, 0x1B]);
+        // The decoder reads back the same steps
+        let shown = |steps: &[ProgramInstruction]| steps.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(shown(&decode_raw(&raw).unwrap()), shown(&steps));
+
+        let failed = encode_raw(&crate::listing::parse_listing("RTN\nXREF").unwrap()).unwrap_err();
+        assert_eq!(failed, "Step 02: XREF has no HP-41 code");
+        assert!(encode_raw(&crate::listing::parse_listing("LBL \"ABCDEFGHIJKLMNO\"").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_decode_free42() {
+        // LBL "T", 42S XROM function, STO "ABC", SIN, END
+        let raw = [
+            0xC0, 0x00, 0xF2, 0x00, b'T', 0xA7, 0x5E, 0xF4, 0x81, b'A', b'B', b'C', 0x59, 0xC0, 0x00, 0x0D,
+        ];
+        let steps = decode_free42(&raw).unwrap();
+        let shown: Vec<String> = steps.iter().map(ToString::to_string).collect();
+        assert_eq!(shown, ["LBL T", "STUB XROM 29,30", "STUB 81 \"ABC\"", "SIN", "END"]);
+        assert!(decode_raw(&raw).is_err());
+    }
+
+    #[test]
+    fn test_register_dump() {
+        let registers = parse_register_dump("PRREG\n\nR00= 1.500\nR12= -2.5 E3\nR03= \"ABC\"\n").unwrap();
+        assert_eq!(registers[..2], [(0, 1.5), (12, -2500.0)]);
+        assert_eq!(alpha::unpack(registers[2].1).as_deref(), Some("ABC"));
+        assert!(parse_register_dump("R01= ?").is_err());
+    }
+}
@@ -1,7 +1,7 @@
-/// Input processing for HP-41C calculator
-/// 
-/// Handles number entry including decimal points and EEX (Enter Exponent) mode.
-/// Maintains the state of number entry and provides display formatting.
+//! Input processing for HP-41C calculator
+//! 
+//! Handles number entry including decimal points and EEX (Enter Exponent) mode.
+//! Maintains the state of number entry and provides display formatting.
 
 //use std::fmt;
 use crate::error::InputError;
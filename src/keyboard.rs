@@ -0,0 +1,375 @@
+//! Keystroke sources for the HP-41C run loop
+//!
+//! The run loop pulls keys from an `InputSource` instead of reading the
+//! terminal directly, so the same loop can be driven by a terminal, a replay
+//! script, a socket or a physical keypad matrix.
+//!
+//! Scripts use whitespace-separated tokens. Named tokens map to special keys
+//! (`enter`, `space`, `tab`, `bksp`, `del`, `esc`, `f1`-`f10`, `shift-f1`-`shift-f5`, `up`, `down`,
+//! `^x` for Ctrl+X); any other token
+//! is typed one character at a time, so `5 enter 3 +` and `sto05` both work.
+//! `RecordingSource` captures the keys of a live session so they can be
+//! written back out as a script with `format_script`.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::time::Duration;
+use crate::storage::Storage;
+
+/// A single keystroke delivered by an input device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// A printable character key
+    Char(char),
+    /// A character pressed with Ctrl (front-end shortcuts)
+    Ctrl(char),
+    /// The ENTER key
+    Enter,
+    /// Tab (accept a label completion)
+    Tab,
+    /// A key of the top two rows, 1-10 (F1-F10): soft menu slots, or
+    /// letter labels in USER mode
+    Menu(u8),
+    /// A shifted key of the top row, 1-5 (Shift+F1-F5)
+    ShiftMenu(u8),
+    /// Previous and next soft menu page
+    Up,
+    Down,
+    /// Backspace (the HP-41C ← key)
+    Backspace,
+    /// Delete
+    Delete,
+    /// Escape (leave the emulator)
+    Escape,
+}
+
+impl Key {
+    /// Convert to the keystroke string accepted by `HP41CCalculator::process_input`
+    ///
+    /// Returns `None` for keys that are handled by the front end rather than
+    /// the calculator (Ctrl shortcuts and Escape).
+    pub fn to_input(self) -> Option<String> {
+        match self {
+            Key::Char(c) => Some(c.to_string()),
+            Key::Enter => Some("enter".to_string()),
+            Key::Tab => Some("tab".to_string()),
+            Key::Menu(n) => Some(format!("f{}", n)),
+            Key::ShiftMenu(n) => Some(format!("shift-f{}", n)),
+            Key::Up => Some("up".to_string()),
+            Key::Down => Some("down".to_string()),
+            Key::Backspace => Some("\u{8}".to_string()),
+            Key::Delete => Some("\u{7f}".to_string()),
+            Key::Ctrl(_) | Key::Escape => None,
+        }
+    }
+
+    /// The script token for this key on its own
+    pub fn to_token(self) -> String {
+        match self {
+            Key::Char(' ') => "space".to_string(),
+            Key::Char(c) => c.to_string(),
+            Key::Ctrl(c) => format!("^{}", c),
+            Key::Enter => "enter".to_string(),
+            Key::Tab => "tab".to_string(),
+            Key::Menu(n) => format!("f{}", n),
+            Key::ShiftMenu(n) => format!("shift-f{}", n),
+            Key::Up => "up".to_string(),
+            Key::Down => "down".to_string(),
+            Key::Backspace => "bksp".to_string(),
+            Key::Delete => "del".to_string(),
+            Key::Escape => "esc".to_string(),
+        }
+    }
+
+    /// Parse one script token into the keys it represents
+    pub fn parse_token(token: &str) -> Vec<Key> {
+        match token.to_lowercase().as_str() {
+            "enter" => vec![Key::Enter],
+            "space" => vec![Key::Char(' ')],
+            "tab" => vec![Key::Tab],
+            "f1" | "f2" | "f3" | "f4" | "f5" | "f6" | "f7" | "f8" | "f9" | "f10" => vec![Key::Menu(token[1..].parse().unwrap_or(1))],
+            "shift-f1" | "shift-f2" | "shift-f3" | "shift-f4" | "shift-f5" => vec![Key::ShiftMenu(token.as_bytes()[7] - b'0')],
+            "up" => vec![Key::Up],
+            "down" => vec![Key::Down],
+            "bksp" => vec![Key::Backspace],
+            "del" => vec![Key::Delete],
+            "esc" => vec![Key::Escape],
+            _ => {
+                let mut chars = token.chars();
+                match (chars.next(), chars.next(), chars.next()) {
+                    (Some('^'), Some(c), None) => vec![Key::Ctrl(c.to_ascii_lowercase())],
+                    _ => token.chars().map(Key::Char).collect(),
+                }
+            }
+        }
+    }
+}
+
+/// The keys that type pasted text: line breaks are ENTER, tabs Tab, and
+/// other control characters are dropped
+pub fn paste_keys(text: &str) -> Vec<Key> {
+    text.replace("\r\n", "\n").chars().filter_map(|c| match c {
+        '\n' | '\r' => Some(Key::Enter),
+        '\t' => Some(Key::Tab),
+        c if c.is_control() => None,
+        c => Some(Key::Char(c)),
+    }).collect()
+}
+
+/// Parse a whole script into keys
+pub fn parse_script(script: &str) -> Vec<Key> {
+    script.split_whitespace().flat_map(Key::parse_token).collect()
+}
+
+/// Write keys as a script that `parse_script` turns back into the same keys
+///
+/// Runs of typed characters become one word (`sto05`) unless the word
+/// would read as a named key, in which case its characters are spaced out.
+pub fn format_script(keys: &[Key]) -> String {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let flush = |word: &mut String, tokens: &mut Vec<String>| {
+        if word.is_empty() {
+            return;
+        }
+        let typed: Vec<Key> = word.chars().map(Key::Char).collect();
+        if Key::parse_token(word) == typed {
+            tokens.push(std::mem::take(word));
+        } else {
+            tokens.extend(word.drain(..).map(String::from));
+        }
+    };
+    for &key in keys {
+        match key {
+            Key::Char(c) if c != ' ' => word.push(c),
+            other => {
+                flush(&mut word, &mut tokens);
+                tokens.push(other.to_token());
+            }
+        }
+    }
+    flush(&mut word, &mut tokens);
+    tokens.join(" ")
+}
+
+/// A device that produces keystrokes for the run loop
+pub trait InputSource {
+    /// Wait for the next keystroke
+    ///
+    /// Returns `Ok(None)` once the source is exhausted (end of a replay
+    /// file, closed socket), which ends the run loop.
+    fn next_key(&mut self) -> io::Result<Option<Key>>;
+
+    /// Wait up to `timeout` for a keystroke; true if one may be ready
+    ///
+    /// Lets the run loop do background work (such as reloading a watched
+    /// listing) between keys. Sources that can't tell always say yes.
+    fn poll(&mut self, _timeout: Duration) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    /// Whether more keys are already waiting, as in a paste or a burst
+    /// faster than anyone types, so the run loop can skip drawing and
+    /// holding messages between them
+    fn has_queued_keys(&mut self) -> bool {
+        false
+    }
+}
+
+impl<S: InputSource + ?Sized> InputSource for Box<S> {
+    fn next_key(&mut self) -> io::Result<Option<Key>> {
+        (**self).next_key()
+    }
+
+    fn poll(&mut self, timeout: Duration) -> io::Result<bool> {
+        (**self).poll(timeout)
+    }
+
+    fn has_queued_keys(&mut self) -> bool {
+        (**self).has_queued_keys()
+    }
+}
+
+/// Replays a fixed sequence of keystrokes
+#[derive(Debug, Clone, Default)]
+pub struct ReplaySource {
+    keys: VecDeque<Key>,
+    pasted: bool,
+}
+
+impl ReplaySource {
+    /// Create a source from a list of keys
+    pub fn new(keys: Vec<Key>) -> Self {
+        ReplaySource { keys: keys.into(), pasted: false }
+    }
+
+    /// Create a source that delivers text as one paste
+    pub fn from_paste(text: &str) -> Self {
+        ReplaySource { keys: paste_keys(text).into(), pasted: true }
+    }
+
+    /// Create a source from script text
+    pub fn from_script(script: &str) -> Self {
+        Self::new(parse_script(script))
+    }
+
+    /// Load a replay script through a storage provider
+    pub fn from_storage(storage: &dyn Storage, path: &Path) -> io::Result<Self> {
+        Ok(Self::from_script(&storage.read_to_string(path)?))
+    }
+
+    /// Number of keystrokes still queued
+    pub fn remaining(&self) -> usize {
+        self.keys.len()
+    }
+}
+
+impl InputSource for ReplaySource {
+    fn next_key(&mut self) -> io::Result<Option<Key>> {
+        Ok(self.keys.pop_front())
+    }
+
+    fn has_queued_keys(&mut self) -> bool {
+        self.pasted && !self.keys.is_empty()
+    }
+}
+
+/// Passes keys through from another source and keeps a copy of each
+#[derive(Debug)]
+pub struct RecordingSource<S: InputSource> {
+    inner: S,
+    keys: Vec<Key>,
+}
+
+impl<S: InputSource> RecordingSource<S> {
+    pub fn new(inner: S) -> Self {
+        RecordingSource { inner, keys: Vec::new() }
+    }
+
+    /// Keys delivered so far
+    pub fn keys(&self) -> &[Key] {
+        &self.keys
+    }
+}
+
+impl<S: InputSource> InputSource for RecordingSource<S> {
+    fn next_key(&mut self) -> io::Result<Option<Key>> {
+        let key = self.inner.next_key()?;
+        self.keys.extend(key);
+        Ok(key)
+    }
+
+    fn poll(&mut self, timeout: Duration) -> io::Result<bool> {
+        self.inner.poll(timeout)
+    }
+
+    fn has_queued_keys(&mut self) -> bool {
+        self.inner.has_queued_keys()
+    }
+}
+
+/// Reads script lines lazily from any buffered reader (pipes, sockets)
+#[derive(Debug)]
+pub struct StreamSource<R: BufRead> {
+    reader: R,
+    pending: VecDeque<Key>,
+}
+
+impl<R: BufRead> StreamSource<R> {
+    /// Wrap a reader such as `BufReader<TcpStream>`
+    pub fn new(reader: R) -> Self {
+        StreamSource { reader, pending: VecDeque::new() }
+    }
+}
+
+impl<R: BufRead> InputSource for StreamSource<R> {
+    fn next_key(&mut self) -> io::Result<Option<Key>> {
+        while self.pending.is_empty() {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            self.pending.extend(parse_script(&line));
+        }
+        Ok(self.pending.pop_front())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HP41CCalculator;
+
+    #[test]
+    fn test_parse_script_tokens() {
+        let keys = parse_script("12 enter sin ^L bksp space");
+        assert_eq!(keys, vec![
+            Key::Char('1'), Key::Char('2'), Key::Enter,
+            Key::Char('s'), Key::Char('i'), Key::Char('n'),
+            Key::Ctrl('l'), Key::Backspace, Key::Char(' '),
+        ]);
+    }
+
+    #[test]
+    fn test_recorded_keys_round_trip() {
+        let mut source = RecordingSource::new(ReplaySource::from_script("12 enter sto05 ^k e n t e r space bksp"));
+        while source.next_key().unwrap().is_some() {}
+        let script = format_script(source.keys());
+        assert_eq!(script, "12 enter sto05 ^k e n t e r space bksp");
+        assert_eq!(parse_script(&script), source.keys());
+    }
+
+    #[test]
+    fn test_key_to_input() {
+        assert_eq!(Key::Char('5').to_input(), Some("5".to_string()));
+        assert_eq!(Key::Enter.to_input(), Some("enter".to_string()));
+        assert_eq!(Key::Tab.to_input(), Some("tab".to_string()));
+        assert_eq!(Key::Menu(3).to_input(), Some("f3".to_string()));
+        assert_eq!(Key::parse_token("F3"), [Key::Menu(3)]);
+        assert_eq!(Key::parse_token("f10"), [Key::Menu(10)]);
+        assert_eq!(Key::ShiftMenu(2).to_input(), Some("shift-f2".to_string()));
+        assert_eq!(Key::parse_token("Shift-F2"), [Key::ShiftMenu(2)]);
+        assert_eq!(Key::Backspace.to_input(), Some("\u{8}".to_string()));
+        assert_eq!(Key::Ctrl('l').to_input(), None);
+        assert_eq!(Key::Escape.to_input(), None);
+    }
+
+    #[test]
+    fn test_paste_keys() {
+        assert_eq!(paste_keys("1.5\r\nSIN\t\u{7}"), [
+            Key::Char('1'), Key::Char('.'), Key::Char('5'), Key::Enter,
+            Key::Char('S'), Key::Char('I'), Key::Char('N'), Key::Tab,
+        ]);
+        let mut source = ReplaySource::from_paste("12");
+        assert!(source.has_queued_keys());
+        source.next_key().unwrap();
+        source.next_key().unwrap();
+        assert!(!source.has_queued_keys());
+        assert!(!ReplaySource::from_script("12").has_queued_keys());
+    }
+
+    #[test]
+    fn test_stream_source_reads_lines() {
+        let data = io::Cursor::new("5 enter\n\n3 +\n");
+        let mut source = StreamSource::new(data);
+        let mut keys = Vec::new();
+        while let Some(key) = source.next_key().unwrap() {
+            keys.push(key);
+        }
+        assert_eq!(keys, vec![Key::Char('5'), Key::Enter, Key::Char('3'), Key::Char('+')]);
+    }
+
+    #[test]
+    fn test_replay_drives_calculator() {
+        let mut source = ReplaySource::from_script("6 enter 7 *");
+        let mut calc = HP41CCalculator::new();
+        while let Some(key) = source.next_key().unwrap() {
+            if let Some(input) = key.to_input() {
+                calc.process_input(&input).unwrap();
+            }
+        }
+        assert_eq!(calc.test_get_stack()[0], 42.0);
+        assert_eq!(source.remaining(), 0);
+    }
+}
@@ -0,0 +1,212 @@
+//! Display sinks for the HP-41C liquid crystal display
+//!
+//! The counterpart of `keyboard::InputSource`: each refresh the calculator
+//! produces an `LcdFrame` holding the 12-character display text plus the
+//! annunciator bits, and hands it to a `DisplaySink`. Makers can implement
+//! the trait to drive a real character LCD or LED matrix from the engine.
+
+use std::fmt;
+use std::io::{self, Write};
+
+/// Number of character cells on the HP-41C display
+pub const LCD_WIDTH: usize = 12;
+
+/// Annunciator segments shown below the HP-41C display
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+pub struct Annunciators(u16);
+
+impl Annunciators {
+    pub const BAT: u16 = 1 << 0;
+    pub const USER: u16 = 1 << 1;
+    pub const GRAD: u16 = 1 << 2;
+    pub const RAD: u16 = 1 << 3;
+    pub const SHIFT: u16 = 1 << 4;
+    pub const FLAG_0: u16 = 1 << 5;
+    pub const FLAG_1: u16 = 1 << 6;
+    pub const FLAG_2: u16 = 1 << 7;
+    pub const FLAG_3: u16 = 1 << 8;
+    pub const FLAG_4: u16 = 1 << 9;
+    pub const PRGM: u16 = 1 << 10;
+    pub const ALPHA: u16 = 1 << 11;
+
+    /// Annunciator names in display order, left to right
+    const NAMES: [(u16, &'static str); 12] = [
+        (Self::BAT, "BAT"), (Self::USER, "USER"), (Self::GRAD, "G"), (Self::RAD, "RAD"),
+        (Self::SHIFT, "SHIFT"), (Self::FLAG_0, "0"), (Self::FLAG_1, "1"), (Self::FLAG_2, "2"),
+        (Self::FLAG_3, "3"), (Self::FLAG_4, "4"), (Self::PRGM, "PRGM"), (Self::ALPHA, "ALPHA"),
+    ];
+
+    /// Create from raw bits
+    pub fn from_bits(bits: u16) -> Self {
+        Annunciators(bits)
+    }
+
+    /// Get the raw bits (one per segment)
+    pub fn bits(self) -> u16 {
+        self.0
+    }
+
+    /// Check whether all of the given segments are lit
+    pub fn contains(self, bits: u16) -> bool {
+        self.0 & bits == bits
+    }
+
+    /// Turn segments on or off
+    pub fn set(&mut self, bits: u16, on: bool) {
+        if on {
+            self.0 |= bits;
+        } else {
+            self.0 &= !bits;
+        }
+    }
+
+    /// Names of the lit segments in display order
+    pub fn names(self) -> Vec<&'static str> {
+        Self::NAMES.iter()
+            .filter(|(bit, _)| self.contains(*bit))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
+impl fmt::Display for Annunciators {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.names().join(" "))
+    }
+}
+
+/// One refresh of the display: 12 characters plus annunciators
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LcdFrame {
+    text: String,
+    pub annunciators: Annunciators,
+}
+
+impl LcdFrame {
+    /// Create a frame, padding or truncating the text to the display width
+    pub fn new(text: &str, annunciators: Annunciators) -> Self {
+        let mut text: String = text.chars().take(LCD_WIDTH).collect();
+        let len = text.chars().count();
+        text.extend(std::iter::repeat_n(' ', LCD_WIDTH - len));
+        LcdFrame { text, annunciators }
+    }
+
+    /// The display text, always exactly `LCD_WIDTH` characters
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl fmt::Display for LcdFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.text, self.annunciators)
+    }
+}
+
+/// A device that shows calculator display frames
+pub trait DisplaySink {
+    /// Show a new frame
+    fn refresh(&mut self, frame: &LcdFrame) -> io::Result<()>;
+}
+
+/// Records every frame it receives (tests, headless hosts)
+#[derive(Debug, Clone, Default)]
+pub struct MemorySink {
+    pub frames: Vec<LcdFrame>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The most recent frame, if any
+    pub fn last(&self) -> Option<&LcdFrame> {
+        self.frames.last()
+    }
+}
+
+impl DisplaySink for MemorySink {
+    fn refresh(&mut self, frame: &LcdFrame) -> io::Result<()> {
+        self.frames.push(frame.clone());
+        Ok(())
+    }
+}
+
+/// Writes one line per frame to any writer (serial ports, pipes, stdout)
+#[derive(Debug)]
+pub struct TextSink<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> TextSink<W> {
+    pub fn new(writer: W) -> Self {
+        TextSink { writer }
+    }
+
+    /// Recover the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> DisplaySink for TextSink<W> {
+    fn refresh(&mut self, frame: &LcdFrame) -> io::Result<()> {
+        writeln!(self.writer, "{}", frame)?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HP41CCalculator;
+
+    #[test]
+    fn test_frame_is_padded_and_truncated() {
+        let frame = LcdFrame::new("1.5", Annunciators::default());
+        assert_eq!(frame.text(), "1.5         ");
+
+        let frame = LcdFrame::new("HELLO WORLD, HP-41C", Annunciators::default());
+        assert_eq!(frame.text(), "HELLO WORLD,");
+    }
+
+    #[test]
+    fn test_annunciator_bits() {
+        let mut ann = Annunciators::default();
+        ann.set(Annunciators::PRGM | Annunciators::FLAG_1, true);
+        assert!(ann.contains(Annunciators::PRGM));
+        assert_eq!(ann.names(), vec!["1", "PRGM"]);
+
+        ann.set(Annunciators::FLAG_1, false);
+        assert_eq!(ann.bits(), Annunciators::PRGM);
+    }
+
+    #[test]
+    fn test_calculator_refreshes_sink() {
+        let mut calc = HP41CCalculator::new();
+        let mut sink = MemorySink::new();
+
+        calc.process_input("4").unwrap();
+        calc.refresh_display(&mut sink).unwrap();
+        assert_eq!(sink.last().unwrap().text(), "4_          ");
+
+        calc.process_input("enter").unwrap();
+        calc.refresh_display(&mut sink).unwrap();
+        assert_eq!(sink.last().unwrap().text(), "4.0000      ");
+
+        calc.process_input(":").unwrap();
+        calc.refresh_display(&mut sink).unwrap();
+        let frame = sink.last().unwrap();
+        assert!(frame.annunciators.contains(Annunciators::PRGM));
+        assert_eq!(frame.text().trim_end(), "01 .END.");
+    }
+
+    #[test]
+    fn test_text_sink_output() {
+        let mut sink = TextSink::new(Vec::new());
+        sink.refresh(&LcdFrame::new("0.0000", Annunciators::from_bits(Annunciators::USER))).unwrap();
+        let output = String::from_utf8(sink.into_inner()).unwrap();
+        assert_eq!(output, "[0.0000      ] USER\n");
+    }
+}
@@ -4,9 +4,13 @@ pub mod commands;
 pub mod calculator;
 pub mod stack;
 pub mod math;
+pub mod calendar;
+pub mod number_theory;
+pub mod statistics;
 pub mod input;
 pub mod error;
 pub mod execution;
+pub mod flags;
 
 // Modular command system
 pub mod registry;
@@ -15,6 +19,86 @@ pub mod parser;
 // NEW: Logging system
 pub mod logger;
 
+// Sandboxed file access
+pub mod storage;
+
+// Virtual clock
+pub mod clock;
+
+// Instruction timing of the real machine
+pub mod timing;
+
+// Device abstractions
+pub mod keyboard;
+pub mod lcd;
+#[cfg(feature = "embedded")]
+pub mod embedded;
+pub mod audio;
+pub mod clipboard;
+pub mod printer;
+pub mod mirror;
+pub mod lockstep;
+
+// The front end's event loop, and headless runs for scripts
+pub mod app;
+pub mod exec;
+
+// Calculator models and plug-in modules
+pub mod model;
+pub mod random;
+pub mod games;
+pub mod time_module;
+pub mod navigation;
+pub mod surveying;
+
+// ALPHA register and alpha data
+pub mod alpha;
+
+// Extended memory files
+pub mod xmem;
+
+// Continuous memory snapshots
+pub mod state;
+pub mod container;
+pub mod statedir;
+pub mod statediff;
+
+// Program listings, hot reload, program descriptions and imports
+pub mod listing;
+pub mod metadata;
+pub mod import;
+pub mod focal;
+pub mod barcode;
+
+// Soft menus on the top key row
+pub mod menu;
+
+// Register write guard for program runs
+pub mod guard;
+
+// Key assignment and alarm catalogs
+pub mod catalog;
+
+// User configuration
+pub mod config;
+pub mod confirm;
+
+// Program analysis and debugging
+pub mod analysis;
+pub mod assertion;
+pub mod watchpoint;
+pub mod validate;
+pub mod editor;
+
+// Keystroke statistics
+pub mod usage;
+
+// Regression tests from recorded sessions
+pub mod testgen;
+
+// Localized messages
+pub mod i18n;
+
 #[cfg(test)]
 mod tests;
 
@@ -26,12 +110,39 @@ pub use registry::{CommandRegistry, CommandSpec, ArgumentPattern, AutoExecuteRul
 pub use parser::{CommandParser, ParseResult};
 
 // Core components
-pub use programming::{ProgrammingMode, ProgramInstruction};
-pub use display::{DisplayMode, DisplayFormatter};
+pub use programming::{MemorySpace, ProgrammingMode, ProgramInstruction, ReturnAddress, TextLine};
+pub use display::{DisplayMode, DisplayFormatter, NumberFormatter};
 pub use error::{CalculatorError, CalculatorResult};
-pub use stack::Stack;
+pub use stack::{Stack, StackDepth};
+pub use flags::Flags;
 pub use math::*;
 pub use input::InputState;
 
 // NEW: Logger exports
-pub use logger::Logger;
\ No newline at end of file
+pub use logger::Logger;
+pub use storage::{Storage, SharedStorage, MemoryStorage};
+#[cfg(feature = "files")]
+pub use storage::FileStorage;
+pub use clock::{Clock, SharedClock, SystemClock, MockClock};
+pub use keyboard::{InputSource, Key};
+pub use lcd::{DisplaySink, LcdFrame, Annunciators};
+pub use app::{App, MemoryScreen, Screen};
+pub use audio::{AudioSink, AudioEvent, KeyFeedback, FeedbackStyle};
+pub use clipboard::{ClipboardSink, CopyTarget, MemoryClipboard};
+pub use printer::{PaperTape, Printer};
+#[cfg(feature = "clipboard")]
+pub use clipboard::SystemClipboard;
+pub use i18n::{Locale, MessageCatalog};
+pub use model::{Model, Module};
+pub use state::{MachineState, ExecutionState, StateFormat};
+pub use container::ContainerOptions;
+pub use guard::RegisterGuard;
+pub use config::Config;
+pub use confirm::{ConfirmCategory, Confirmations};
+pub use catalog::{Alarm, Catalog, CatalogRun, KeyAssignment, KeyAssignments, CATALOG_STEP};
+pub use metadata::ProgramInfo;
+pub use xmem::{ExtendedMemory, XFile};
+pub use menu::{MenuAction, MenuItem, SoftMenu};
+pub use analysis::{CrossReference, LintIssue, LintKind};
+pub use usage::{CommandUsage, UsageStats};
+pub use testgen::{CaseFormat, SessionCase};
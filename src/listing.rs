@@ -0,0 +1,247 @@
+//! Program listings as text files
+//!
+//! A listing has one program step per line, as printed or typed in an
+//! editor:
+//!
+//! ```text
+//! # Span of a beam
+//! 01 LBL "SPAN"
+//! 02 RCL 01
+//! 03 *
+//! 04 RTN
+//! ```
+//!
+//! Step numbers are optional and ignored; steps are numbered in file order.
+//! Blank lines and lines starting with `#` are skipped, and a quoted
+//! argument may contain spaces. END separates programs; a closing `.END.`,
+//! as printed listings mark the end of memory, is skipped.
+//!
+//! A line that is only quoted text is a text line, which sets ALPHA when it
+//! runs; `⊢"TEXT"` appends to ALPHA instead (also written `>"TEXT"` or
+//! `"|-TEXT"`). Text lines keep their case, and so do the local labels
+//! `a`-`e`, which differ from `A`-`E`. A quoted label is global even when
+//! its name looks local: `LBL "A"` is not `LBL A`.
+//!
+//! Comments of the form `# Title: ...` (also `Description` and `Author`)
+//! describe the program whose global label comes next; `parse_listing_info`
+//! collects them. `ListingWatcher` notices when a listing file changes so
+//! a front end can load it again while the emulator runs.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use crate::analysis::{is_global_label, is_letter_label, quoted_label};
+use crate::metadata::ProgramInfo;
+use crate::programming::{ProgramInstruction, TEXT_LINE_LENGTH};
+use crate::storage::Storage;
+
+/// Parse a listing into program steps
+///
+/// Only the layout is checked here; the calculator checks command names
+/// when it loads the steps.
+pub fn parse_listing(text: &str) -> Result<Vec<ProgramInstruction>, String> {
+    let mut program = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let located = |e: String| format!("Line {}: {}", index + 1, e);
+        if let Some((text, append)) = parse_text_line(line).map_err(located)? {
+            program.push(ProgramInstruction::text_line(program.len() as i32 + 1, &text, append));
+            continue;
+        }
+        let mut tokens = tokenize(line).map_err(located)?;
+        // A leading step number, unless the whole line is a number entry
+        if tokens.len() > 1 && tokens[0].chars().all(|c| c.is_ascii_digit()) {
+            tokens.remove(0);
+        }
+        let command = tokens.remove(0).to_uppercase();
+        if command == ".END." {
+            continue;
+        }
+        let takes_label = matches!(command.as_str(), "LBL" | "GTO" | "XEQ");
+        let arguments = tokens.iter().map(|token| argument(token, takes_label)).collect();
+        program.push(ProgramInstruction::new(program.len() as i32 + 1, command, arguments));
+    }
+    Ok(program)
+}
+
+/// Program info from a listing's comments, keyed by global label
+///
+/// Info comments apply to the next global LBL; comments that aren't
+/// `Name: value` pairs for a known field are ordinary comments.
+pub fn parse_listing_info(text: &str) -> BTreeMap<String, ProgramInfo> {
+    let mut programs = BTreeMap::new();
+    let mut pending = ProgramInfo::default();
+    for line in text.lines().map(str::trim) {
+        if let Some(comment) = line.strip_prefix('#') {
+            if let Some((name, value)) = comment.split_once(':') {
+                pending.set_field(name, value);
+            }
+            continue;
+        }
+        let Ok(tokens) = tokenize(line) else { continue };
+        let step = match tokens.first() {
+            Some(first) if tokens.len() > 1 && first.chars().all(|c| c.is_ascii_digit()) => &tokens[1..],
+            _ => &tokens[..],
+        };
+        if let [command, label, ..] = step {
+            let label = argument(label, true);
+            if command.eq_ignore_ascii_case("LBL") && is_global_label(&label) && !pending.is_empty() {
+                programs.insert(label, std::mem::take(&mut pending));
+            }
+        }
+    }
+    programs
+}
+
+/// The text and append mark of a text line, after any step number;
+/// `None` for other steps
+fn parse_text_line(line: &str) -> Result<Option<(String, bool)>, String> {
+    let step = match line.split_once(char::is_whitespace) {
+        Some((number, rest)) if number.chars().all(|c| c.is_ascii_digit()) => rest.trim_start(),
+        _ => line,
+    };
+    let (mut append, step) = match step.strip_prefix('⊢').or_else(|| step.strip_prefix('>')) {
+        Some(rest) => (true, rest),
+        None => (false, step),
+    };
+    let Some(quoted) = step.strip_prefix('"') else { return Ok(None) };
+    // The last quote closes the text, so the text may contain quotes
+    let end = quoted.rfind('"').ok_or("unterminated quote")?;
+    if !quoted[end + 1..].trim().is_empty() {
+        return Err("text after a text line".to_string());
+    }
+    let mut text = &quoted[..end];
+    if let Some(rest) = text.strip_prefix("|-").or_else(|| text.strip_prefix('⊢')) {
+        append = true;
+        text = rest;
+    }
+    if text.chars().count() + usize::from(append) > TEXT_LINE_LENGTH {
+        return Err(format!("text line longer than {} characters", TEXT_LINE_LENGTH));
+    }
+    Ok(Some((text.to_string(), append)))
+}
+
+/// A step argument from its token, upper-cased
+///
+/// A quoted label stays global even when its name looks local
+/// (`quoted_label`), and the letter labels a-e are labels of their own.
+fn argument(token: &str, takes_label: bool) -> String {
+    match token.strip_prefix('"').and_then(|token| token.strip_suffix('"')) {
+        Some(name) if takes_label => quoted_label(&name.to_uppercase()),
+        Some(text) => text.to_uppercase(),
+        None if takes_label && is_letter_label(token) => token.to_string(),
+        None => token.to_uppercase(),
+    }
+}
+
+/// Split a line at whitespace, keeping quoted text together with its quotes
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = line.trim_start();
+    while !rest.is_empty() {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').ok_or("unterminated quote")?;
+            tokens.push(rest[..end + 2].to_string());
+            rest = quoted[end + 1..].trim_start();
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            tokens.push(rest[..end].to_string());
+            rest = rest[end..].trim_start();
+        }
+    }
+    Ok(tokens)
+}
+
+/// Watches a listing file for changes
+///
+/// Files are compared by content, which works through any `Storage`
+/// provider and ignores saves that didn't change anything.
+#[derive(Debug, Clone)]
+pub struct ListingWatcher {
+    path: PathBuf,
+    last: Option<Vec<u8>>,
+}
+
+impl ListingWatcher {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        ListingWatcher { path: path.as_ref().to_path_buf(), last: None }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The listing text if the file changed since the last call (or this is
+    /// the first call)
+    ///
+    /// A missing file is not an error: editors often remove and recreate
+    /// a file while saving.
+    pub fn poll(&mut self, storage: &dyn Storage) -> Result<Option<String>, String> {
+        if !storage.exists(&self.path) {
+            return Ok(None);
+        }
+        let data = storage.read(&self.path)
+            .map_err(|e| format!("Failed to read {}: {}", self.path.display(), e))?;
+        if self.last.as_ref() == Some(&data) {
+            return Ok(None);
+        }
+        self.last = Some(data.clone());
+        String::from_utf8(data)
+            .map(Some)
+            .map_err(|_| format!("{} is not UTF-8 text", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_parse_listing() {
+        let program = parse_listing("# comment\n01 LBL \"MY SPAN\"\n\n02 rcl 01\n2.5\n*\n").unwrap();
+        let lines: Vec<String> = program.iter().map(|i| format!("{:02} {}", i.line_number, i)).collect();
+        assert_eq!(program[0].arguments, ["MY SPAN"]);
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[1], "02 RCL 01");
+        assert_eq!(program[2].command, "2.5");
+        assert!(parse_listing("LBL \"OPEN").is_err());
+    }
+
+    #[test]
+    fn test_text_lines() {
+        let program = parse_listing("01 \"Hello\"\n02 ⊢\"A \"B\"\n>\" X\"\n\"|-Y\"\n\"\"").unwrap();
+        let lines: Vec<String> = program.iter().map(ToString::to_string).collect();
+        assert_eq!(lines, ["\"Hello\"", "⊢\"A \"B\"", "⊢\" X\"", "⊢\"Y\"", "\"\""]);
+        assert!(program.iter().all(|step| step.command.is_empty()));
+        assert_eq!(program[0].bytes(), 6);
+        assert_eq!(program[1].bytes(), 6);
+        assert!(parse_listing("\"ABCDEFGHIJKLMNO\"").is_ok());
+        assert!(parse_listing("⊢\"ABCDEFGHIJKLMNO\"").is_err());
+        assert!(parse_listing("\"AB\" C").is_err());
+    }
+
+    #[test]
+    fn test_parse_listing_info() {
+        let text = "# Title: Span\n# Author: JB\n# a plain comment\n01 LBL \"SPAN\"\nLBL 01\nRTN\nLBL \"OTHER\"\n";
+        let info = parse_listing_info(text);
+        assert_eq!(info.len(), 1);
+        assert_eq!(info["SPAN"].title, "Span");
+        assert_eq!(info["SPAN"].author, "JB");
+    }
+
+    #[test]
+    fn test_watcher_reports_changes_once() {
+        let storage = MemoryStorage::new();
+        let mut watcher = ListingWatcher::new("prog.txt");
+        assert_eq!(watcher.poll(&storage), Ok(None));
+
+        storage.write(Path::new("prog.txt"), b"LBL A").unwrap();
+        assert_eq!(watcher.poll(&storage), Ok(Some("LBL A".to_string())));
+        assert_eq!(watcher.poll(&storage), Ok(None));
+        storage.write(Path::new("prog.txt"), b"LBL B").unwrap();
+        assert_eq!(watcher.poll(&storage), Ok(Some("LBL B".to_string())));
+    }
+}
@@ -0,0 +1,411 @@
+//! Lockstep sessions for teaching
+//!
+//! A leader instance shares its keystrokes with followers over TCP. A
+//! follower starts from a copy of the leader's state and then applies the
+//! same keys, so every screen in the class shows the same machine. Since
+//! the calculator is deterministic, only keys travel after the first state.
+//!
+//! In `LockstepMode::ReadOnly` followers only watch. In
+//! `LockstepMode::TakeTurns` the leader passes the turn around (Ctrl+T in
+//! the terminal front end); keys from the follower holding the turn are
+//! applied by the leader and shared like its own, while the leader's
+//! calculator keys wait until the turn comes back.
+//!
+//! The protocol is line-based text:
+//!
+//! | Direction | Line | Meaning |
+//! |---|---|---|
+//! | leader → follower | `STATE <json>` | Machine state to start from (first line) |
+//! | leader → follower | `KEY <token>` | Apply a key, as a `keyboard` script token |
+//! | leader → follower | `TURN YOU` / `TURN OTHER` | Who may type now |
+//! | follower → leader | `KEY <token>` | A key typed by a follower |
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+use crate::keyboard::{InputSource, Key};
+use crate::state::MachineState;
+
+/// How often `LeaderSource` checks for follower keys while waiting
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long a write may wait on a follower that stopped reading before the
+/// follower is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Whether followers may type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockstepMode {
+    ReadOnly,
+    TakeTurns,
+}
+
+#[derive(Debug)]
+struct Follower {
+    id: usize,
+    stream: TcpStream,
+}
+
+#[derive(Debug, Default)]
+struct LeaderState {
+    /// Accepted connections that haven't been sent the state yet
+    waiting: Vec<TcpStream>,
+    followers: Vec<Follower>,
+    next_id: usize,
+    /// Follower holding the turn; `None` for the leader
+    turn: Option<usize>,
+    /// Keys from followers, with the sender's id
+    keys: VecDeque<(usize, Key)>,
+}
+
+/// The sharing end of a lockstep session
+#[derive(Debug)]
+pub struct LockstepLeader {
+    mode: LockstepMode,
+    address: SocketAddr,
+    state: Arc<Mutex<LeaderState>>,
+}
+
+impl LockstepLeader {
+    /// Listen for followers on an address such as `0.0.0.0:4142`
+    pub fn bind<A: ToSocketAddrs>(address: A, mode: LockstepMode) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(LeaderState::default()));
+        let accepted = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                lock(&accepted).waiting.push(stream);
+            }
+        });
+        Ok(LockstepLeader { mode, address, state })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn mode(&self) -> LockstepMode {
+        self.mode
+    }
+
+    /// Number of followers that have joined
+    pub fn followers(&self) -> usize {
+        lock(&self.state).followers.len()
+    }
+
+    /// Send the current state to followers that connected since the last call
+    ///
+    /// Call between keys, when every key shared so far has been applied.
+    pub fn admit(&self, snapshot: &MachineState) -> io::Result<()> {
+        let mut state = lock(&self.state);
+        if state.waiting.is_empty() {
+            return Ok(());
+        }
+        let json = serde_json::to_string(snapshot).map_err(io::Error::other)?;
+        for mut stream in std::mem::take(&mut state.waiting) {
+            let id = state.next_id;
+            state.next_id += 1;
+            if stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_err() || writeln!(stream, "STATE {}", json).is_err() {
+                continue;
+            }
+            if self.mode == LockstepMode::TakeTurns {
+                let reader = stream.try_clone()?;
+                let shared = Arc::clone(&self.state);
+                thread::spawn(move || read_follower_keys(id, reader, &shared));
+            }
+            let _ = writeln!(stream, "TURN OTHER");
+            state.followers.push(Follower { id, stream });
+        }
+        Ok(())
+    }
+
+    /// Share a key with every follower, dropping those that left or stopped reading
+    pub fn broadcast(&self, key: Key) {
+        let line = format!("KEY {}\n", key.to_token());
+        lock(&self.state).followers.retain_mut(|follower| follower.stream.write_all(line.as_bytes()).is_ok());
+    }
+
+    /// Whether the leader's own calculator keys are applied now
+    pub fn leader_has_turn(&self) -> bool {
+        lock(&self.state).turn.is_none()
+    }
+
+    /// Give the turn to the next follower, or back to the leader after the last
+    ///
+    /// In a read-only session the leader keeps the turn.
+    pub fn pass_turn(&self) -> String {
+        let mut state = lock(&self.state);
+        if self.mode != LockstepMode::TakeTurns {
+            return "Turn: leader".to_string();
+        }
+        let next = match state.turn {
+            None => state.followers.first().map(|f| f.id),
+            Some(current) => state.followers.iter().map(|f| f.id).find(|&id| id > current),
+        };
+        state.turn = next;
+        state.keys.clear();
+        for follower in &mut state.followers {
+            let turn = if Some(follower.id) == next { "YOU" } else { "OTHER" };
+            let _ = writeln!(follower.stream, "TURN {}", turn);
+        }
+        match next {
+            Some(id) => format!("Turn: follower {}", id + 1),
+            None => "Turn: leader".to_string(),
+        }
+    }
+
+    fn has_follower_key(&self) -> bool {
+        let state = lock(&self.state);
+        state.keys.iter().any(|&(id, _)| state.turn == Some(id))
+    }
+
+    /// The next key typed by the follower holding the turn
+    pub fn follower_key(&self) -> Option<Key> {
+        let mut state = lock(&self.state);
+        while let Some((id, key)) = state.keys.pop_front() {
+            if state.turn == Some(id) {
+                return Some(key);
+            }
+        }
+        None
+    }
+}
+
+fn lock(state: &Mutex<LeaderState>) -> MutexGuard<'_, LeaderState> {
+    // Every update is a single push or pop, so a poisoned state is still consistent
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn read_follower_keys(id: usize, stream: TcpStream, state: &Mutex<LeaderState>) {
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else { break };
+        if let Some(token) = line.strip_prefix("KEY ") {
+            let keys = Key::parse_token(token);
+            lock(state).keys.extend(keys.into_iter().map(|key| (id, key)));
+        }
+    }
+}
+
+/// Keys for the leader's run loop: its own (when it holds the turn) and
+/// those of the follower holding the turn, each shared with all followers
+pub struct LeaderSource<S: InputSource> {
+    inner: S,
+    leader: Arc<LockstepLeader>,
+}
+
+impl<S: InputSource> LeaderSource<S> {
+    pub fn new(inner: S, leader: Arc<LockstepLeader>) -> Self {
+        LeaderSource { inner, leader }
+    }
+
+    /// Pass on a key to the run loop, or keep it from the loop
+    fn accept(&mut self, key: Key) -> Option<Key> {
+        match key {
+            Key::Ctrl('t') => {
+                self.leader.pass_turn();
+                None
+            }
+            // Front-end keys stay local; calculator keys wait for the leader's turn
+            key if key.to_input().is_none() => Some(key),
+            key if self.leader.leader_has_turn() => {
+                self.leader.broadcast(key);
+                Some(key)
+            }
+            _ => None,
+        }
+    }
+
+    fn follower_key(&mut self) -> Option<Key> {
+        loop {
+            let key = self.leader.follower_key()?;
+            // Followers can't quit or reconfigure the leader's front end
+            if key.to_input().is_some() && key != Key::Char('q') {
+                self.leader.broadcast(key);
+                return Some(key);
+            }
+        }
+    }
+}
+
+impl<S: InputSource> InputSource for LeaderSource<S> {
+    fn next_key(&mut self) -> io::Result<Option<Key>> {
+        loop {
+            if let Some(key) = self.follower_key() {
+                return Ok(Some(key));
+            }
+            if self.inner.poll(POLL_INTERVAL)? {
+                let Some(key) = self.inner.next_key()? else { return Ok(None) };
+                if let Some(key) = self.accept(key) {
+                    return Ok(Some(key));
+                }
+            }
+        }
+    }
+
+    fn poll(&mut self, timeout: Duration) -> io::Result<bool> {
+        if self.leader.has_follower_key() {
+            return Ok(true);
+        }
+        self.inner.poll(timeout)
+    }
+
+    fn has_queued_keys(&mut self) -> bool {
+        self.inner.has_queued_keys()
+    }
+}
+
+/// The watching end of a lockstep session: the leader's keys as an `InputSource`
+#[derive(Debug)]
+pub struct FollowerSource {
+    reader: BufReader<TcpStream>,
+    has_turn: bool,
+}
+
+impl FollowerSource {
+    /// Join a leader; returns the state to start from
+    pub fn connect<A: ToSocketAddrs>(address: A) -> io::Result<(MachineState, Self)> {
+        let mut reader = BufReader::new(TcpStream::connect(address)?);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let json = line.trim_end().strip_prefix("STATE ")
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Expected STATE from leader"))?;
+        let state = MachineState::from_json(json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok((state, FollowerSource { reader, has_turn: false }))
+    }
+
+    /// Whether the leader has given this follower the turn
+    pub fn has_turn(&self) -> bool {
+        self.has_turn
+    }
+
+    /// A handle for sending this follower's own keys to the leader
+    pub fn sender(&self) -> io::Result<KeySender> {
+        Ok(KeySender { stream: self.reader.get_ref().try_clone()? })
+    }
+}
+
+impl InputSource for FollowerSource {
+    fn next_key(&mut self) -> io::Result<Option<Key>> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            match line.trim_end().split_once(' ') {
+                Some(("KEY", token)) => {
+                    if let Some(&key) = Key::parse_token(token).first() {
+                        return Ok(Some(key));
+                    }
+                }
+                Some(("TURN", who)) => self.has_turn = who == "YOU",
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Sends a follower's keys to the leader
+#[derive(Debug)]
+pub struct KeySender {
+    stream: TcpStream,
+}
+
+impl KeySender {
+    pub fn send(&mut self, key: Key) -> io::Result<()> {
+        writeln!(self.stream, "KEY {}", key.to_token())
+    }
+
+    /// Leave the session; the follower's run loop then ends
+    pub fn disconnect(&self) -> io::Result<()> {
+        self.stream.shutdown(Shutdown::Both)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard::ReplaySource;
+
+    fn join(leader: &LockstepLeader, snapshot: &MachineState) -> FollowerSource {
+        let address = leader.address();
+        let joining = thread::spawn(move || FollowerSource::connect(address).unwrap());
+        while leader.followers() == 0 {
+            leader.admit(snapshot).unwrap();
+            thread::sleep(Duration::from_millis(10));
+        }
+        let (state, follower) = joining.join().unwrap();
+        assert_eq!(&state, snapshot);
+        follower
+    }
+
+    #[test]
+    fn test_followers_apply_leader_keys() {
+        let leader = Arc::new(LockstepLeader::bind("127.0.0.1:0", LockstepMode::ReadOnly).unwrap());
+        let mut calc = crate::HP41CCalculator::new();
+        calc.run_command_line("4").unwrap();
+        let mut follower = join(&leader, &calc.snapshot());
+
+        // Ctrl+T doesn't pass the turn when followers only watch
+        let mut keys = LeaderSource::new(ReplaySource::from_script("^t 2 + ^l"), Arc::clone(&leader));
+        while let Some(key) = keys.next_key().unwrap() {
+            if let Some(input) = key.to_input() {
+                calc.process_input(&input).unwrap();
+            }
+        }
+        assert!(leader.leader_has_turn());
+        assert_eq!(calc.test_get_stack()[0], 6.0);
+        // Ctrl+L is a front-end key and stays with the leader
+        assert_eq!(follower.next_key().unwrap(), Some(Key::Char('2')));
+        assert_eq!(follower.next_key().unwrap(), Some(Key::Char('+')));
+        assert!(!follower.has_turn());
+    }
+
+    #[test]
+    fn test_stalled_follower_is_dropped() {
+        let leader = LockstepLeader::bind("127.0.0.1:0", LockstepMode::ReadOnly).unwrap();
+        // Connected, but never reads
+        let _stalled = TcpStream::connect(leader.address()).unwrap();
+        let snapshot = crate::HP41CCalculator::new().snapshot();
+        while leader.followers() == 0 {
+            leader.admit(&snapshot).unwrap();
+            thread::sleep(Duration::from_millis(10));
+        }
+        for _ in 0..1_000_000 {
+            leader.broadcast(Key::Char('7'));
+            if leader.followers() == 0 {
+                break;
+            }
+        }
+        assert_eq!(leader.followers(), 0);
+    }
+
+    #[test]
+    fn test_take_turns() {
+        let leader = LockstepLeader::bind("127.0.0.1:0", LockstepMode::TakeTurns).unwrap();
+        let mut follower = join(&leader, &crate::HP41CCalculator::new().snapshot());
+        let mut sender = follower.sender().unwrap();
+
+        assert_eq!(leader.pass_turn(), "Turn: follower 1");
+        assert!(!leader.leader_has_turn());
+        sender.send(Key::Char('7')).unwrap();
+        let mut key = None;
+        for _ in 0..100 {
+            key = leader.follower_key();
+            if key.is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(key, Some(Key::Char('7')));
+
+        leader.broadcast(Key::Char('7'));
+        assert_eq!(follower.next_key().unwrap(), Some(Key::Char('7')));
+        assert!(follower.has_turn());
+        assert_eq!(leader.pass_turn(), "Turn: leader");
+    }
+}
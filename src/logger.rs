@@ -1,15 +1,15 @@
-/// Comprehensive logging system for HP-41C calculator debugging
-/// 
-/// Provides granular control over different types of logging to help debug
-/// calculator behavior. Now supports both console and file output.
+//! Comprehensive logging system for HP-41C calculator debugging
+//! 
+//! Provides granular control over different types of logging to help debug
+//! calculator behavior. Now supports both console and file output.
+//! File output goes through the calculator's `Storage` provider.
 
 use std::fmt::Write;
-use std::fs::{File, OpenOptions};
-use std::io::{Write as IoWrite, BufWriter};
 use std::path::{Path, PathBuf};
+use crate::storage::{default_storage, SharedStorage};
 
 /// Logger configuration and state with file output support
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Logger {
     /// Log flag changes (show_flags, stack lift, input modes, etc.)
     pub log_flags: bool,
@@ -32,10 +32,10 @@ pub struct Logger {
     /// Enable/disable all logging at once
     pub enabled: bool,
     
-    /// Optional file writer for logging to file
-    file_writer: Option<BufWriter<File>>,
+    /// Storage provider that receives file output
+    storage: SharedStorage,
     
-    /// Path to log file (for display purposes)
+    /// Path to log file within storage (None when file logging is off)
     log_file_path: Option<PathBuf>,
 }
 
@@ -50,7 +50,7 @@ impl Logger {
             log_programming: false,
             log_storage: false,
             enabled: true,
-            file_writer: None,
+            storage: default_storage(),
             log_file_path: None,
         }
     }
@@ -65,7 +65,7 @@ impl Logger {
             log_programming: true,
             log_storage: true,
             enabled: true,
-            file_writer: None,
+            storage: default_storage(),
             log_file_path: None,
         }
     }
@@ -80,40 +80,38 @@ impl Logger {
             log_programming: false,
             log_storage: false,
             enabled: true,
-            file_writer: None,
+            storage: default_storage(),
             log_file_path: None,
         }
     }
     
+    /// Use a different storage provider for file output
+    pub fn set_storage(&mut self, storage: SharedStorage) {
+        self.storage = storage;
+    }
+    
+    /// Get the storage provider used for file output
+    pub fn storage(&self) -> &SharedStorage {
+        &self.storage
+    }
+    
     /// Enable file logging to specified path
     pub fn enable_file_logging<P: AsRef<Path>>(&mut self, path: P) -> Result<(), std::io::Error> {
-        let path = path.as_ref();
+        self.log_file_path = Some(path.as_ref().to_path_buf());
         
-        // Create parent directories if they don't exist
-        if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent)?;
+        // Write header to log file (creates it if necessary)
+        if let Err(e) = self.write_to_file("\n=== HP-41C Calculator Log Session Started ===\n") {
+            self.log_file_path = None;
+            return Err(e);
         }
         
-        // Open file for append
-        let file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(path)?;
-            
-        self.file_writer = Some(BufWriter::new(file));
-        self.log_file_path = Some(path.to_path_buf());
-        
-        // Write header to log file
-        self.write_to_file(&format!("\n=== HP-41C Calculator Log Session Started ===\n"))?;
-        
         Ok(())
     }
     
     /// Disable file logging
     pub fn disable_file_logging(&mut self) -> Result<(), std::io::Error> {
-        if let Some(mut writer) = self.file_writer.take() {
-            Logger::write_to_file_direct(&mut writer, &format!("=== HP-41C Calculator Log Session Ended ===\n"))?;
-            writer.flush()?;
+        if self.log_file_path.is_some() {
+            self.write_to_file("=== HP-41C Calculator Log Session Ended ===\n")?;
         }
         self.log_file_path = None;
         Ok(())
@@ -126,19 +124,12 @@ impl Logger {
     
     /// Write a message to the log file (if enabled)
     fn write_to_file(&mut self, message: &str) -> Result<(), std::io::Error> {
-        if let Some(writer) = &mut self.file_writer {
-            Logger::write_to_file_direct(writer, message)?;
+        if let Some(path) = &self.log_file_path {
+            self.storage.append(path, format!("{}\n", message).as_bytes())?;
         }
         Ok(())
     }
     
-    /// Helper to write to file writer
-    fn write_to_file_direct(writer: &mut BufWriter<File>, message: &str) -> Result<(), std::io::Error> {
-        writeln!(writer, "{}", message)?;
-        writer.flush()?;
-        Ok(())
-    }
-    
     /// Log a message to both console and file
     fn log_message(&mut self, message: &str) {
         if self.enabled {
@@ -272,13 +263,13 @@ impl Logger {
     
     /// Reset to default configuration
     pub fn reset(&mut self) {
-        let file_writer = self.file_writer.take();
+        let storage = self.storage.clone();
         let log_file_path = self.log_file_path.take();
         
         *self = Logger::new();
         
         // Preserve file logging if it was enabled
-        self.file_writer = file_writer;
+        self.storage = storage;
         self.log_file_path = log_file_path;
     }
 }
@@ -289,24 +280,6 @@ impl Default for Logger {
     }
 }
 
-// Note: The Clone trait is harder to implement now due to BufWriter<File>
-// If Clone is needed, we'd need to reopen the file
-impl Clone for Logger {
-    fn clone(&self) -> Self {
-        Logger {
-            log_flags: self.log_flags,
-            log_stack: self.log_stack,
-            log_input: self.log_input,
-            log_commands: self.log_commands,
-            log_programming: self.log_programming,
-            log_storage: self.log_storage,
-            enabled: self.enabled,
-            file_writer: None, // Can't clone file writers
-            log_file_path: self.log_file_path.clone(),
-        }
-    }
-}
-
 /// Convenience macro for conditional logging
 #[macro_export]
 macro_rules! debug_log {
@@ -320,7 +293,6 @@ macro_rules! debug_log {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
     use std::path::PathBuf;
 
     #[test]
@@ -351,6 +323,7 @@ mod tests {
     }
     
     #[test]
+    #[cfg(feature = "files")]
     fn test_file_logging() -> Result<(), Box<dyn std::error::Error>> {
         let mut logger = Logger::new();
         let temp_path = PathBuf::from("test_hp41c.log");
@@ -367,14 +340,33 @@ mod tests {
         logger.disable_file_logging()?;
         
         // Check that file was created and contains content
-        let content = fs::read_to_string(&temp_path)?;
+        let content = std::fs::read_to_string(&temp_path)?;
         assert!(content.contains("HP-41C Calculator Log Session Started"));
         assert!(content.contains("test_flag"));
         assert!(content.contains("HP-41C Calculator Log Session Ended"));
         
         // Clean up
-        fs::remove_file(&temp_path).ok();
+        std::fs::remove_file(&temp_path).ok();
         
         Ok(())
     }
+    
+    #[test]
+    fn test_file_logging_to_memory_storage() {
+        use crate::storage::{MemoryStorage, Storage};
+        
+        let storage = MemoryStorage::new();
+        let mut logger = Logger::new();
+        logger.set_storage(std::sync::Arc::new(storage.clone()));
+        
+        logger.enable_file_logging("logs/session.log").unwrap();
+        logger.log_storage = true;
+        logger.log_storage_operation("STO", 5, 42.0);
+        logger.disable_file_logging().unwrap();
+        
+        let content = storage.read_to_string(Path::new("logs/session.log")).unwrap();
+        assert!(content.contains("[STORAGE] STO register 05: 42"));
+        assert!(content.contains("Log Session Ended"));
+        assert!(!PathBuf::from("logs/session.log").exists());
+    }
 }
\ No newline at end of file
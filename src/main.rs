@@ -1,213 +1,468 @@
+use std::collections::VecDeque;
 use std::io;
+use std::sync::Arc;
+use std::time::Duration;
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    event::{self, DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
     ExecutableCommand,
 };
 
-use hp41c::HP41CCalculator;
+use hp41c::{Config, ContainerOptions, HP41CCalculator, MachineState};
+use hp41c::{container, statediff, statedir};
+use hp41c::app::{App, Screen};
+use hp41c::audio::BellSink;
+use hp41c::mirror::MirrorServer;
+use hp41c::lockstep::{FollowerSource, LeaderSource, LockstepLeader, LockstepMode};
+use hp41c::i18n::Locale;
+use hp41c::keyboard::{format_script, paste_keys, InputSource, Key, RecordingSource};
+use hp41c::testgen::{self, append_case, CaseFormat, SessionCase};
+use hp41c::storage::default_storage;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// Keystroke source backed by the crossterm terminal
+///
+/// A bracketed paste arrives as one event and is queued as the keys that
+/// type it.
+#[derive(Default)]
+struct TerminalSource {
+    pasted: VecDeque<Key>,
+}
+
+impl InputSource for TerminalSource {
+    fn next_key(&mut self) -> io::Result<Option<Key>> {
+        loop {
+            if let Some(key) = self.pasted.pop_front() {
+                return Ok(Some(key));
+            }
+            let event = event::read()?;
+            if let Event::Paste(text) = &event {
+                self.pasted.extend(paste_keys(text));
+                continue;
+            }
+            if let Event::Key(KeyEvent { code, modifiers, kind, .. }) = event {
+                // Only process key press events, ignore key release events
+                if kind != KeyEventKind::Press {
+                    continue;
+                }
+
+                let key = match code {
+                    KeyCode::Char(c) if modifiers.contains(KeyModifiers::CONTROL) => Key::Ctrl(c),
+                    KeyCode::Char(c) => Key::Char(c),
+                    KeyCode::Enter => Key::Enter,
+                    KeyCode::Tab => Key::Tab,
+                    KeyCode::F(n @ 1..=5) if modifiers.contains(KeyModifiers::SHIFT) => Key::ShiftMenu(n),
+                    KeyCode::F(n @ 1..=10) => Key::Menu(n),
+                    KeyCode::Up => Key::Up,
+                    KeyCode::Down => Key::Down,
+                    KeyCode::Backspace => Key::Backspace,
+                    KeyCode::Delete => Key::Delete,
+                    KeyCode::Esc => Key::Escape,
+                    _ => continue, // Ignore other keys
+                };
+                return Ok(Some(key));
+            }
+        }
+    }
+
+    fn poll(&mut self, timeout: Duration) -> io::Result<bool> {
+        if !self.pasted.is_empty() {
+            return Ok(true);
+        }
+        event::poll(timeout)
+    }
+
+    fn has_queued_keys(&mut self) -> bool {
+        !self.pasted.is_empty() || event::poll(Duration::ZERO).unwrap_or(false)
+    }
+}
+
+/// Continuous memory: loaded at startup, saved on exit
+const STATE_FILE: &str = "hp41c_state.json";
+
+/// Keystroke statistics, kept across sessions the same way
+const STATS_FILE: &str = "hp41c_stats.json";
+
+/// Passphrase for encrypted state files, if set
+fn passphrase() -> Option<String> {
+    std::env::var("HP41C_PASSPHRASE").ok().filter(|p| !p.is_empty())
+}
+
+/// `hp41c dump-state [--json] [FILE]`: print a saved state and exit
+fn dump_state(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let json = args.iter().any(|a| a == "--json");
+    let path = args.iter().find(|a| !a.starts_with("--")).map_or(STATE_FILE, |s| s.as_str());
+
+    let state = read_state(path)?;
+    if json {
+        println!("{}", state.to_json()?);
+    } else {
+        println!("{}", state);
+    }
+    Ok(())
+}
+
+/// Read a state file, plain or in a container
+fn read_state(path: &str) -> Result<MachineState, Box<dyn std::error::Error>> {
+    let data = container::open(&std::fs::read(path)?, passphrase().as_deref())?;
+    Ok(MachineState::decode(&data)?)
+}
+
+/// `hp41c state-diff OLD NEW`: print what changed between two saved states
+fn state_diff(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let [old, new] = args else {
+        return Err("Usage: hp41c state-diff OLD NEW".into());
+    };
+    let changes = statediff::diff(&read_state(old)?, &read_state(new)?);
+    if changes.is_empty() {
+        println!("No differences");
+    }
+    for line in changes {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// `hp41c gen-test SESSION [--name NAME] [--dir DIR] [--golden]`: turn a
+/// recorded session into a test case in DIR (default `tests`)
+fn gen_test(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut session = None;
+    let mut name = None;
+    let mut dir = "tests".to_string();
+    let mut format = CaseFormat::Rust;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--name" => name = args.next().cloned(),
+            "--dir" => dir = args.next().cloned().ok_or("--dir needs a directory")?,
+            "--golden" => format = CaseFormat::Golden,
+            other => session = Some(other.to_string()),
+        }
+    }
+    let session = session.ok_or("Usage: hp41c gen-test SESSION [--name NAME] [--dir DIR] [--golden]")?;
+    let name = name.unwrap_or_else(|| {
+        std::path::Path::new(&session).file_stem().map_or("session".into(), |s| s.to_string_lossy().into_owned())
+    });
+
+    let storage = default_storage();
+    let script = storage.read_to_string(std::path::Path::new(&session))?;
+    let case = SessionCase::record(&name, &script);
+    let path = append_case(storage.as_ref(), std::path::Path::new(&dir), &case, format)?;
+    println!("Added session_{} ({}) to {}", case.name, case.fingerprint, path.display());
+    Ok(())
+}
+
+/// `hp41c check-golden FILE`: replay every case in a golden file
+fn check_golden(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let path = args.first().ok_or("Usage: hp41c check-golden FILE")?;
+    match testgen::verify_golden(&std::fs::read_to_string(path)?) {
+        Ok(count) => {
+            println!("{} cases passed", count);
+            Ok(())
+        }
+        Err(failures) => Err(failures.join("\n").into()),
+    }
+}
+
+/// `hp41c exec [--json] LINE...`: run command lines on a fresh calculator
+/// (read from standard input when none are given) and exit with 0 or the
+/// error code of the line that failed
+fn exec(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let json = args.iter().any(|a| a == "--json");
+    let mut lines: Vec<String> = args.iter().filter(|a| *a != "--json").cloned().collect();
+    if lines.is_empty() {
+        lines = io::stdin().lines().collect::<io::Result<_>>()?;
+    }
+    let mut calc = HP41CCalculator::new();
+    let report = hp41c::exec::run_lines(&mut calc, &lines);
+    if json {
+        println!("{}", report.to_json());
+    } else {
+        for message in &report.messages {
+            println!("{}", message);
+        }
+        match &report.error {
+            Some(error) => eprintln!("Line {}: {} (code {})", error.line, error.message, error.code),
+            None => println!("{}", report.display),
+        }
+    }
+    std::process::exit(i32::from(report.exit_code()))
+}
+
+/// `hp41c barcode LISTING [--svg]`: wand bar code for a program listing,
+/// as row bytes or an SVG image
+fn barcode(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let svg = args.iter().any(|a| a == "--svg");
+    let path = args.iter().find(|a| *a != "--svg").ok_or("Usage: hp41c barcode LISTING [--svg]")?;
     let mut calc = HP41CCalculator::new();
+    calc.load_listing(&std::fs::read_to_string(path)?)?;
+    let rows = calc.program_barcode()?;
+    if svg {
+        print!("{}", hp41c::barcode::to_svg(&rows));
+    } else {
+        print!("{}", hp41c::barcode::to_text(&rows));
+    }
+    Ok(())
+}
+
+/// `hp41c lsp`: a language server for program listings on standard input
+/// and output
+fn lsp() -> Result<(), Box<dyn std::error::Error>> {
+    let mut calc = HP41CCalculator::new();
+    calc.set_locale(Locale::from_env());
+    hp41c::editor::serve(&calc, io::stdin().lock(), io::stdout().lock())?;
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut record_to = None;
+    let mut watch = None;
+    let mut lead = None;
+    let mut cold = false;
+    let mut imports: &[String] = &[];
+    match args.first().map(String::as_str) {
+        Some("dump-state") => return dump_state(&args[1..]),
+        Some("state-diff") => return state_diff(&args[1..]),
+        Some("gen-test") => return gen_test(&args[1..]),
+        Some("check-golden") => return check_golden(&args[1..]),
+        Some("exec") => return exec(&args[1..]),
+        Some("lsp") => return lsp(),
+        Some("barcode") => return barcode(&args[1..]),
+        // `hp41c record FILE`: run normally and save the keystrokes as a session script
+        Some("record") => record_to = Some(args.get(1).ok_or("Usage: hp41c record FILE")?.clone()),
+        // `hp41c watch LISTING`: run normally, reloading the listing into program memory when it changes
+        Some("watch") => watch = Some(args.get(1).ok_or("Usage: hp41c watch LISTING")?.clone()),
+        // `hp41c lead ADDRESS [--turns]`: share keystrokes with followers
+        Some("lead") => {
+            let address = args.get(1).ok_or("Usage: hp41c lead ADDRESS [--turns]")?;
+            let mode = if args.iter().any(|a| a == "--turns") { LockstepMode::TakeTurns } else { LockstepMode::ReadOnly };
+            let leader = LockstepLeader::bind(address.as_str(), mode)?;
+            eprintln!("Leading on {}", leader.address());
+            lead = Some(Arc::new(leader));
+        }
+        // `hp41c import FILE...`: run normally after adding programs (.raw, FOCAL listings) or registers (PRREG printouts)
+        Some("import") if args.len() > 1 => imports = &args[1..],
+        Some("import") => return Err("Usage: hp41c import FILE...".into()),
+        // `hp41c memory-lost`: start cold, with continuous memory cleared
+        Some("memory-lost") => cold = true,
+        // `hp41c follow ADDRESS`: mirror a leader's calculator, keeping nothing locally
+        Some("follow") => return follow(args.get(1).ok_or("Usage: hp41c follow ADDRESS")?),
+        Some(other) => return Err(format!("Unknown command: {}", other).into()),
+        None => {}
+    }
+
+    let mut calc = HP41CCalculator::new().with_audio(Box::new(BellSink));
+    #[cfg(feature = "clipboard")]
+    match hp41c::SystemClipboard::new() {
+        Ok(clipboard) => calc = calc.with_clipboard(Box::new(clipboard)),
+        Err(e) => eprintln!("{}", e),
+    }
+    calc.set_locale(Locale::from_env());
+    // An encrypted state needs the passphrase before it can be loaded
+    calc.set_container_options(ContainerOptions { compress: false, passphrase: passphrase() });
+    let config = Config::load(calc.storage().as_ref(), &Config::default_path()).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        None
+    });
+    let state_dir = config.as_ref().and_then(|config| config.state_dir.clone());
+    let loaded = match &state_dir {
+        _ if cold => {
+            calc.memory_lost();
+            Ok(())
+        }
+        Some(dir) if calc.storage().exists(&std::path::Path::new(dir).join(statedir::MACHINE_FILE)) => {
+            calc.load_state_dir(dir).map(|_| ())
+        }
+        Some(_) => Ok(()),
+        None if calc.storage().exists(std::path::Path::new(STATE_FILE)) => calc.load_state(STATE_FILE).map(|_| ()),
+        None => Ok(()),
+    };
+    if let Err(e) = loaded {
+        eprintln!("{}", e);
+    }
+    calc.power_on();
+    for path in imports {
+        if let Err(e) = import_file(&mut calc, path) {
+            eprintln!("{}: {}", path, e);
+        }
+    }
+    if calc.storage().exists(std::path::Path::new(STATS_FILE)) {
+        if let Err(e) = calc.load_usage_stats(STATS_FILE) {
+            eprintln!("{}", e);
+        }
+    }
+    if let Some(config) = &config {
+        for e in calc.apply_config(config) {
+            eprintln!("{}", e);
+        }
+    }
+    let mirror = config.as_ref().and_then(|config| config.mirror.as_ref()).and_then(|address| {
+        MirrorServer::bind(address.as_str())
+            .inspect(|server| eprintln!("Mirroring display on http://{}/", server.address()))
+            .inspect_err(|e| eprintln!("Display mirror {}: {}", address, e))
+            .ok()
+    });
+    if let Some(path) = &watch {
+        calc.watch_listing(path);
+        if let Some(Err(e)) = calc.check_listing_watch() {
+            eprintln!("{}", e);
+        }
+    }
 
     // Enable raw mode
     terminal::enable_raw_mode()?;
-    io::stdout().execute(EnterAlternateScreen)?;
+    io::stdout().execute(EnterAlternateScreen)?.execute(EnableBracketedPaste)?;
 
     // Ensure we clean up on exit
-    let result = run_calculator(&mut calc);
+    let source: Box<dyn InputSource> = match &lead {
+        Some(leader) => Box::new(LeaderSource::new(TerminalSource::default(), Arc::clone(leader))),
+        None => Box::new(TerminalSource::default()),
+    };
+    let mut keys = RecordingSource::new(source);
+    let result = run_calculator(&mut calc, &mut keys, mirror, lead.as_deref());
 
     // Cleanup
     terminal::disable_raw_mode()?;
-    io::stdout().execute(LeaveAlternateScreen)?;
+    io::stdout().execute(DisableBracketedPaste)?.execute(LeaveAlternateScreen)?;
+
+    let saved = match &state_dir {
+        Some(dir) => calc.save_state_dir(dir),
+        None => calc.save_state(STATE_FILE),
+    };
+    if let Err(e) = saved {
+        eprintln!("{}", e);
+    }
+    if let Err(e) = calc.save_usage_stats(STATS_FILE) {
+        eprintln!("{}", e);
+    }
+    if let Some(path) = record_to {
+        // The last key is the one that quit
+        let recorded = keys.keys();
+        let script = format_script(&recorded[..recorded.len().saturating_sub(1)]);
+        if let Err(e) = calc.storage().write(std::path::Path::new(&path), format!("{}\n", script).as_bytes()) {
+            eprintln!("Failed to save session: {}", e);
+        }
+    }
 
     result
 }
 
-fn run_calculator(calc: &mut HP41CCalculator) -> Result<(), Box<dyn std::error::Error>> {
+/// Import one file from another emulator: a `.raw` program, a PRREG
+/// printout, or else a FOCAL listing
+fn import_file(calc: &mut HP41CCalculator, path: &str) -> Result<Option<String>, String> {
+    let path_ref = std::path::Path::new(path);
+    let data = calc.storage().read(path_ref).map_err(|e| e.to_string())?;
+    if path_ref.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("raw")) {
+        // Not an HP-41 program: perhaps an HP-42S one from Free42
+        return calc.import_raw(&data).or_else(|_| calc.import_free42(&data, true));
+    }
+    let text = String::from_utf8_lossy(&data);
+    if hp41c::import::parse_register_dump(&text).is_ok_and(|registers| !registers.is_empty()) {
+        calc.import_registers(&text)
+    } else {
+        calc.import_focal(&text)
+    }
+}
+
+/// Follow a lockstep leader: start from its state and apply its keys
+///
+/// Local keys go to the leader, which applies them while this follower
+/// holds the turn. Escape leaves the session.
+fn follow(address: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let (state, follower) = FollowerSource::connect(address)?;
+    let mut calc = HP41CCalculator::new();
+    calc.set_locale(Locale::from_env());
+    calc.restore(&state);
+
+    let mut sender = follower.sender()?;
+    std::thread::spawn(move || {
+        let mut local = TerminalSource::default();
+        while let Ok(Some(key)) = local.next_key() {
+            if matches!(key, Key::Escape | Key::Ctrl('c')) {
+                let _ = sender.disconnect();
+                break;
+            }
+            if sender.send(key).is_err() {
+                break;
+            }
+        }
+    });
+
+    terminal::enable_raw_mode()?;
+    io::stdout().execute(EnterAlternateScreen)?.execute(EnableBracketedPaste)?;
+    let mut keys = follower;
+    let result = run_calculator(&mut calc, &mut keys, None, None);
+    terminal::disable_raw_mode()?;
+    io::stdout().execute(DisableBracketedPaste)?.execute(LeaveAlternateScreen)?;
+    result
+}
+
+fn print_header() {
     println!("HP-41C Calculator Emulator v0.5.0 (Rust) - With Debug Logging\r");
     println!("================================================================\r");
-    println!("Enter ':' to toggle programming mode\r");
+    println!("Enter ':' to toggle programming mode, Tab to complete a GTO/XEQ label\r");
+    println!("  LBL, GTO and XEQ take a global label in quotes: xeq \"PRIME\" Enter\r");
     println!("Enter 'q' to quit, 'F' to toggle flags, 'L' for logging\r");
     println!("Logging shortcuts:\r");
     println!("  Ctrl+L (toggle), Ctrl+A (all), Ctrl+M (minimal), Ctrl+O (off)\r");
     println!("  Ctrl+F (enable file logging), Ctrl+D (disable file logging)\r");
-    println!("\r");
+    println!("  Ctrl+B (cycle key feedback: off/click/tone/haptic)\r");
+    println!("ALPHA mode: Ctrl+N, then type; Tab appends, Enter ends the text\r");
+    println!("Soft menus: F1-F6 (top key row), Up/Down (page)\r");
+    println!("Top rows: F1-F10 and Shift+F1-F5; after 'user' Enter they run LBL A-J and a-e\r");
+    println!("Catalogs: Ctrl+G (CAT 1 programs), Ctrl+K (CAT 6 key assignments), Ctrl+E (CAT 5 alarms)\r");
+    println!("  CAT n scrolls a catalog: space (R/S) stops and restarts, Enter/Backspace step\r");
+    println!("  A running program listens for space (R/S) only, which halts it\r");
+    println!("Clipboard: Ctrl+Y (copy X), Ctrl+W (copy whole stack)\r");
+    println!("Messages: Ctrl+R pages back through past messages (Up/Down)\r");
+    println!("Printer: Ctrl+P pages through the paper tape PRX, PRSTK, PRREG and PRP print on\r");
+    println!("Lockstep: Ctrl+T passes the turn when leading with --turns\r");
+}
+
+/// Draws on the terminal: header, the display, and messages underneath
+struct TerminalScreen;
+
+impl Screen for TerminalScreen {
+    fn redraw(&mut self, calc: &HP41CCalculator) -> io::Result<()> {
+        // Clear screen and move cursor to top-left
+        print!("\x1B[2J\x1B[H");
+        print_header();
 
-    loop {
-        // Clear screen and show display
-        print!("\x1B[2J\x1B[H"); // Clear screen and move cursor to top-left
-        println!("HP-41C Calculator Emulator v0.5.0 (Rust) - With Debug Logging\r");
-        println!("================================================================\r");
-        println!("Enter ':' to toggle programming mode\r");
-        println!("Enter 'q' to quit, 'F' to toggle flags, 'L' for logging\r");
-        println!("Logging shortcuts:\r");
-        println!("  Ctrl+L (toggle), Ctrl+A (all), Ctrl+M (minimal), Ctrl+O (off)\r");
-        println!("  Ctrl+F (enable file logging), Ctrl+D (disable file logging)\r");
-        
         // Show current log file if active
         if let Some(path) = calc.get_log_file_path() {
             println!("  📄 Logging to: {}\r", path.display());
         }
         println!("\r");
-        
-        // Display calculator state
-        let display = calc.get_display();
-        for line in display.lines() {
+
+        for line in calc.get_display().lines() {
             println!("{}\r", line);
         }
         println!("\r");
+        Ok(())
+    }
 
-        // Read a single key
-        if let Event::Key(KeyEvent { code, modifiers, kind, .. }) = event::read()? {
-            // Only process key press events, ignore key release events
-            if kind != KeyEventKind::Press {
-                continue;
-            }
-            
-            match code {
-                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => break,
-                KeyCode::Char('q') => break,
-                KeyCode::Esc => break,
-                
-                // Logging control shortcuts
-                KeyCode::Char('l') if modifiers.contains(KeyModifiers::CONTROL) => {
-                    if let Some(msg) = calc.toggle_logging() {
-                        println!("\r>>> {}\r", msg);
-                        std::thread::sleep(std::time::Duration::from_millis(1000));
-                    }
-                }
-                KeyCode::Char('a') if modifiers.contains(KeyModifiers::CONTROL) => {
-                    if let Some(msg) = calc.configure_logger("all") {
-                        println!("\r>>> {}\r", msg);
-                        std::thread::sleep(std::time::Duration::from_millis(1000));
-                    }
-                }
-                KeyCode::Char('m') if modifiers.contains(KeyModifiers::CONTROL) => {
-                    if let Some(msg) = calc.configure_logger("minimal") {
-                        println!("\r>>> {}\r", msg);
-                        std::thread::sleep(std::time::Duration::from_millis(1000));
-                    }
-                }
-                KeyCode::Char('o') if modifiers.contains(KeyModifiers::CONTROL) => {
-                    if let Some(msg) = calc.configure_logger("off") {
-                        println!("\r>>> {}\r", msg);
-                        std::thread::sleep(std::time::Duration::from_millis(1000));
-                    }
-                }
-                
-                // NEW: File logging controls
-                KeyCode::Char('f') if modifiers.contains(KeyModifiers::CONTROL) => {
-                    let default_path = "hp41c_debug.log";
-                    match calc.enable_file_logging(default_path) {
-                        Ok(Some(msg)) => {
-                            println!("\r>>> {}\r", msg);
-                            println!("\r>>> You can now run: tail -f {} (in another terminal)\r", default_path);
-                            std::thread::sleep(std::time::Duration::from_millis(2000));
-                        }
-                        Ok(None) => {
-                            println!("\r>>> File logging enabled\r");
-                            std::thread::sleep(std::time::Duration::from_millis(1000));
-                        }
-                        Err(e) => {
-                            println!("\r>>> ERROR: {}\r", e);
-                            std::thread::sleep(std::time::Duration::from_millis(1000));
-                        }
-                    }
-                }
-                KeyCode::Char('d') if modifiers.contains(KeyModifiers::CONTROL) => {
-                    match calc.disable_file_logging() {
-                        Ok(Some(msg)) => {
-                            println!("\r>>> {}\r", msg);
-                            std::thread::sleep(std::time::Duration::from_millis(1000));
-                        }
-                        Ok(None) => {
-                            println!("\r>>> File logging disabled\r");
-                            std::thread::sleep(std::time::Duration::from_millis(1000));
-                        }
-                        Err(e) => {
-                            println!("\r>>> ERROR: {}\r", e);
-                            std::thread::sleep(std::time::Duration::from_millis(1000));
-                        }
-                    }
-                }
-                
-                KeyCode::Enter => {
-                    match calc.process_input("enter") {
-                        Ok(Some(msg)) => {
-                            println!("\r>>> {}\r", msg);
-                            std::thread::sleep(std::time::Duration::from_millis(500));
-                        }
-                        Err(msg) => {
-                            println!("\r>>> ERROR: {}\r", msg);
-                            std::thread::sleep(std::time::Duration::from_millis(500));
-                        }
-                        Ok(None) => {}
-                    }
-                }
-                KeyCode::Char(' ') => {
-                    match calc.process_input(" ") {
-                        Ok(Some(msg)) => {
-                            println!("\r>>> {}\r", msg);
-                            std::thread::sleep(std::time::Duration::from_millis(500));
-                        }
-                        Err(msg) => {
-                            println!("\r>>> ERROR: {}\r", msg);
-                            std::thread::sleep(std::time::Duration::from_millis(500));
-                        }
-                        Ok(None) => {}
-                    }
-                }
-                KeyCode::Char('L') => {
-                    // 'L' key for logging toggle (non-Ctrl)
-                    if let Some(msg) = calc.toggle_logging() {
-                        println!("\r>>> {}\r", msg);
-                        std::thread::sleep(std::time::Duration::from_millis(1000));
-                    }
-                }
-                KeyCode::Char(c) => {
-                    match calc.process_input(&c.to_string()) {
-                        Ok(Some(msg)) => {
-                            println!("\r>>> {}\r", msg);
-                            std::thread::sleep(std::time::Duration::from_millis(500));
-                        }
-                        Err(msg) => {
-                            println!("\r>>> ERROR: {}\r", msg);
-                            std::thread::sleep(std::time::Duration::from_millis(500));
-                        }
-                        Ok(None) => {}
-                    }
-                }
-                KeyCode::Backspace => {
-                    match calc.process_input("\u{8}") {
-                        Ok(Some(msg)) => {
-                            println!("\r>>> {}\r", msg);
-                            std::thread::sleep(std::time::Duration::from_millis(500));
-                        }
-                        Err(msg) => {
-                            println!("\r>>> ERROR: {}\r", msg);
-                            std::thread::sleep(std::time::Duration::from_millis(500));
-                        }
-                        Ok(None) => {}
-                    }
-                }
-                KeyCode::Delete => {
-                    match calc.process_input("\u{7f}") {
-                        Ok(Some(msg)) => {
-                            println!("\r>>> {}\r", msg);
-                            std::thread::sleep(std::time::Duration::from_millis(500));
-                        }
-                        Err(msg) => {
-                            println!("\r>>> ERROR: {}\r", msg);
-                            std::thread::sleep(std::time::Duration::from_millis(500));
-                        }
-                        Ok(None) => {}
-                    }
-                }
-                _ => continue, // Ignore other keys
-            }
-        }
+    fn message(&mut self, text: &str, hold: Duration) -> io::Result<()> {
+        println!("\r>>> {}\r", text.replace('\n', "\r\n    "));
+        std::thread::sleep(hold);
+        Ok(())
     }
+}
 
+fn run_calculator(
+    calc: &mut HP41CCalculator,
+    keys: &mut dyn InputSource,
+    mirror: Option<MirrorServer>,
+    leader: Option<&LockstepLeader>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    print_header();
+    println!("\r");
+    let mut app = App::new();
+    if let Some(server) = mirror {
+        app = app.with_mirror(Box::new(server));
+    }
+    if let Some(leader) = leader {
+        app = app.with_leader(leader);
+    }
+    app.run(calc, keys, &mut TerminalScreen)?;
     Ok(())
-}
\ No newline at end of file
+}
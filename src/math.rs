@@ -1,7 +1,7 @@
-/// Mathematical operations for HP-41C
-/// 
-/// Provides all mathematical functions including trigonometric, logarithmic,
-/// and other scientific functions with proper error handling.
+//! Mathematical operations for HP-41C
+//! 
+//! Provides all mathematical functions including trigonometric, logarithmic,
+//! and other scientific functions with proper error handling.
 
 use crate::error::StackError;
 
@@ -27,8 +27,15 @@ pub fn execute_math_function(function: &str, x: f64) -> Result<f64, StackError>
         "log" => validate_positive(x, "log")?.log10(),
         "ln" => validate_positive(x, "ln")?.ln(),
         "exp" => x.exp(),
+        "10x" => 10f64.powf(x),
+        "x2" => x * x,
         "sqrt" => validate_non_negative(x, "sqrt")?.sqrt(),
         "inv" => invert(x)?,
+        "abs" => x.abs(),
+        "int" => x.trunc(),
+        "frc" => x.fract(),
+        // SIGN of zero is 1 on the HP-41
+        "sign" => if x < 0.0 { -1.0 } else { 1.0 },
         _ => return Err(StackError::MathError(format!("Unknown function '{}'", function))),
     };
 
@@ -37,7 +44,7 @@ pub fn execute_math_function(function: &str, x: f64) -> Result<f64, StackError>
 
 /// Validate input for asin/acos (must be in [-1, 1])
 fn validate_asin_acos_input(x: f64) -> Result<f64, StackError> {
-    if x < -1.0 || x > 1.0 {
+    if !(-1.0..=1.0).contains(&x) {
         Err(StackError::MathError("Input must be in range [-1, 1]".to_string()))
     } else {
         Ok(x)
@@ -115,6 +122,34 @@ fn gamma(x: f64) -> f64 {
     }
 }
 
+/// Y MOD X with the sign of X, as on the HP-41; Y MOD 0 is Y
+pub fn modulo(y: f64, x: f64) -> f64 {
+    if x == 0.0 {
+        y
+    } else {
+        y - x * (y / x).floor()
+    }
+}
+
+/// H.MMSS (hours or degrees, minutes, seconds) to decimal hours (HR)
+pub fn hms_to_hours(hms: f64) -> f64 {
+    // Work in whole ten-thousandths so 1.30 doesn't become 1 h 29.99 min
+    let scaled = (hms.abs() * 1e4 * 1e6).round() / 1e6;
+    let hours = (scaled / 1e4).floor();
+    let minutes = ((scaled - hours * 1e4) / 100.0).floor();
+    let seconds = scaled - hours * 1e4 - minutes * 100.0;
+    (hours + minutes / 60.0 + seconds / 3600.0).copysign(hms)
+}
+
+/// Decimal hours to H.MMSS (HMS)
+pub fn hours_to_hms(hours: f64) -> f64 {
+    let total = (hours.abs() * 3600.0 * 1e6).round() / 1e6;
+    let whole_hours = (total / 3600.0).floor();
+    let minutes = ((total - whole_hours * 3600.0) / 60.0).floor();
+    let seconds = total - whole_hours * 3600.0 - minutes * 60.0;
+    (whole_hours + minutes / 100.0 + seconds / 1e4).copysign(hours)
+}
+
 /// Convert degrees to radians
 pub fn deg_to_rad(degrees: f64) -> f64 {
     degrees * std::f64::consts::PI / 180.0
@@ -153,6 +188,14 @@ mod tests {
         assert!(execute_math_function("ln", 0.0).is_err());
     }
 
+    #[test]
+    fn test_square_and_power_of_ten() {
+        assert_eq!(execute_math_function("x2", -3.0).unwrap(), 9.0);
+        assert_eq!(execute_math_function("10x", 3.0).unwrap(), 1000.0);
+        assert!(execute_math_function("x2", 1e200).is_err());
+        assert!(execute_math_function("10x", 400.0).is_err());
+    }
+
     #[test]
     fn test_sqrt() {
         assert_eq!(execute_math_function("sqrt", 4.0).unwrap(), 2.0);
@@ -170,6 +213,28 @@ mod tests {
         assert!(factorial(5.5).is_err()); // Non-integer
     }
 
+    #[test]
+    fn test_hms_conversions() {
+        assert_eq!(hms_to_hours(1.30), 1.5);
+        assert!((hms_to_hours(-40.3836) - -40.643333333).abs() < 1e-9);
+        assert!((hours_to_hms(1.5) - 1.30).abs() < 1e-12);
+        assert!((hours_to_hms(hms_to_hours(118.2412)) - 118.2412).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_number_parts() {
+        assert_eq!(execute_math_function("abs", -2.5).unwrap(), 2.5);
+        assert_eq!(execute_math_function("int", -2.75).unwrap(), -2.0);
+        assert_eq!(execute_math_function("frc", -2.75).unwrap(), -0.75);
+        assert_eq!(execute_math_function("sign", -0.1).unwrap(), -1.0);
+        assert_eq!(execute_math_function("sign", 0.0).unwrap(), 1.0);
+
+        assert_eq!(modulo(7.0, 3.0), 1.0);
+        assert_eq!(modulo(-7.0, 3.0), 2.0);
+        assert_eq!(modulo(7.0, -3.0), -2.0);
+        assert_eq!(modulo(7.0, 0.0), 7.0);
+    }
+
     #[test]
     fn test_invert() {
         assert_eq!(execute_math_function("inv", 2.0).unwrap(), 0.5);
@@ -0,0 +1,153 @@
+//! Soft menus on the top key row
+//!
+//! A soft menu shows six captions on a row under the display, one for each
+//! key of the top row (F1-F6 on a terminal); pressing the key runs what
+//! the slot holds. Menus longer than six items are paged with Up/Down,
+//! wrapping around at either end.
+//!
+//! Modules bring a menu of their functions (`SoftMenu::for_module`), shown
+//! with `MENU` while ALPHA holds the module's name. Programs build their
+//! own user menu for menu-driven interfaces: `KEY n` puts XEQ of the label
+//! named in ALPHA in slot n, and `MENU` with any other ALPHA shows it.
+//! `EXITM` hides the menu and `CLMENU` empties the user menu.
+
+use crate::model::Module;
+
+/// Slots on one page, one per key of the top row
+pub const SLOTS: usize = 6;
+
+/// Characters of a caption shown in its slot
+const CAPTION_WIDTH: usize = 6;
+
+/// What a menu slot does when its key is pressed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuAction {
+    /// Run a function, such as a module's `RNDM`
+    Command(String),
+    /// XEQ a program label
+    Xeq(String),
+}
+
+/// One labelled slot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MenuItem {
+    pub caption: String,
+    pub action: MenuAction,
+}
+
+impl MenuItem {
+    /// A slot that runs a function, captioned with its name
+    pub fn command(name: &str) -> Self {
+        MenuItem { caption: name.to_uppercase(), action: MenuAction::Command(name.to_lowercase()) }
+    }
+
+    /// A slot that runs a program, captioned with its label
+    pub fn xeq(label: &str) -> Self {
+        MenuItem { caption: label.to_uppercase(), action: MenuAction::Xeq(label.to_uppercase()) }
+    }
+}
+
+/// A menu of items shown six at a time
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SoftMenu {
+    title: String,
+    /// Slots in order; `None` leaves a slot blank
+    items: Vec<Option<MenuItem>>,
+    page: usize,
+}
+
+impl SoftMenu {
+    pub fn new(title: &str) -> Self {
+        SoftMenu { title: title.to_string(), ..Default::default() }
+    }
+
+    /// The functions of a module, in the order the module lists them
+    pub fn for_module(module: Module) -> Self {
+        let mut menu = SoftMenu::new(&module.to_string());
+        menu.items = module.commands().iter().map(|name| Some(MenuItem::command(name))).collect();
+        menu
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Put an item in a slot, counted from 1 across pages
+    pub fn set(&mut self, slot: usize, item: Option<MenuItem>) {
+        if self.items.len() < slot {
+            self.items.resize(slot, None);
+        }
+        self.items[slot - 1] = item;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.iter().all(Option::is_none)
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.items.len().div_ceil(SLOTS).max(1)
+    }
+
+    /// The page shown, from 0
+    pub fn page(&self) -> usize {
+        self.page
+    }
+
+    pub fn next_page(&mut self) {
+        self.page = (self.page + 1) % self.page_count();
+    }
+
+    pub fn prev_page(&mut self) {
+        self.page = (self.page + self.page_count() - 1) % self.page_count();
+    }
+
+    /// The item under a top-row key (1-6) on the page shown
+    pub fn slot(&self, key: usize) -> Option<&MenuItem> {
+        if !(1..=SLOTS).contains(&key) {
+            return None;
+        }
+        self.items.get(self.page * SLOTS + key - 1)?.as_ref()
+    }
+
+    /// The page as a row of captions, with the page number when there are more
+    pub fn row(&self) -> String {
+        let captions: Vec<String> = (1..=SLOTS)
+            .map(|key| {
+                let caption = self.slot(key).map_or("", |item| item.caption.as_str());
+                format!("{:<width$}", caption.chars().take(CAPTION_WIDTH).collect::<String>(), width = CAPTION_WIDTH)
+            })
+            .collect();
+        let row = captions.join("|");
+        if self.page_count() > 1 {
+            format!("{} {}/{}", row, self.page + 1, self.page_count())
+        } else {
+            row
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pages() {
+        let mut menu = SoftMenu::for_module(Module::Time);
+        assert_eq!(menu.page_count(), 3);
+        assert_eq!(menu.slot(1), Some(&MenuItem::command("date")));
+        assert_eq!(menu.row(), "DATE  |TIME  |CLOCK |SETDAT|SETTIM|SETSW  1/3");
+        menu.prev_page();
+        assert_eq!(menu.page(), 2);
+        assert_eq!(menu.slot(4), Some(&MenuItem::command("mdy")));
+        assert_eq!(menu.slot(5), None);
+        menu.next_page();
+        assert_eq!(menu.page(), 0);
+
+        let mut user = SoftMenu::new("USER");
+        assert!(user.is_empty());
+        user.set(3, Some(MenuItem::xeq("span")));
+        assert_eq!(user.row(), "      |      |SPAN  |      |      |      ");
+        assert_eq!(user.slot(3).unwrap().action, MenuAction::Xeq("SPAN".to_string()));
+        assert_eq!(user.slot(7), None);
+    }
+}
@@ -0,0 +1,71 @@
+//! Titles, descriptions and authors of programs
+//!
+//! A program is known by its global label, and that is all program memory
+//! has room for. `ProgramInfo` keeps a title, a longer description and an
+//! author beside it, keyed by the label. The info is not part of program
+//! memory: it takes no bytes, never changes `SIZE` arithmetic or the state
+//! fingerprint, and travels with the saved state (`program_info`).
+//!
+//! Listings carry it in comment lines before the program's label:
+//!
+//! ```text
+//! # Title: Span of a beam
+//! # Author: J. Barrera
+//! # Description: Span from the load in X and R01
+//! 01 LBL "SPAN"
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+/// Descriptive fields for one program
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProgramInfo {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub title: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub author: String,
+}
+
+impl ProgramInfo {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_empty() && self.description.is_empty() && self.author.is_empty()
+    }
+
+    /// Set a field by its listing name ("title", "description" or "author",
+    /// any case); false for other names
+    pub fn set_field(&mut self, name: &str, value: &str) -> bool {
+        let field = match name.trim().to_lowercase().as_str() {
+            "title" => &mut self.title,
+            "description" => &mut self.description,
+            "author" => &mut self.author,
+            _ => return false,
+        };
+        *field = value.trim().to_string();
+        true
+    }
+
+    /// One line for catalogs and headers: the title, else the description
+    pub fn summary(&self) -> &str {
+        if self.title.is_empty() { &self.description } else { &self.title }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fields() {
+        let mut info = ProgramInfo::default();
+        assert!(info.is_empty());
+        assert!(info.set_field("Description", " Beam span "));
+        assert_eq!(info.summary(), "Beam span");
+        assert!(info.set_field("TITLE", "Span"));
+        assert_eq!(info.summary(), "Span");
+        assert!(!info.set_field("version", "2"));
+        assert_eq!(serde_json::to_string(&info).unwrap(), r#"{"title":"Span","description":"Beam span"}"#);
+    }
+}
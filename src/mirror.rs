@@ -0,0 +1,211 @@
+//! Display mirroring over the network
+//!
+//! `MirrorServer` is a `DisplaySink` that sends every new frame to the
+//! clients connected to a TCP port, so a projector or a second screen can
+//! follow the calculator in large type:
+//!
+//! - A browser opening `http://HOST:PORT/` gets a page showing the display,
+//!   updated through server-sent events from `/events`.
+//! - A terminal client (`nc HOST PORT`) gets the display redrawn in
+//!   double-size characters (DEC `ESC # 3`/`ESC # 4`, understood by xterm
+//!   and most of its descendants).
+//!
+//! Connections are accepted on a background thread; sending happens in
+//! `refresh`, and clients that stop reading are dropped.
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use crate::lcd::{DisplaySink, LcdFrame};
+
+/// How long a new connection may take to show it is a browser
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// How long a frame may wait on a client that stopped reading before the
+/// client is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_millis(500);
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><title>HP-41C</title>
+<style>
+body { background: #222; margin: 0; height: 100vh; display: flex; align-items: center; justify-content: center; }
+#lcd { background: #9a9; color: #111; font: 9vw monospace; padding: 0.2em 0.4em; white-space: pre; }
+#ann { color: #9a9; font: 3vw monospace; text-align: center; }
+</style></head>
+<body><div><div id="lcd"></div><div id="ann"></div></div>
+<script>
+new EventSource("/events").onmessage = e => {
+  const [text, ann] = JSON.parse(e.data);
+  document.getElementById("lcd").textContent = text;
+  document.getElementById("ann").textContent = ann;
+};
+</script></body></html>
+"#;
+
+#[derive(Debug)]
+enum Client {
+    /// Browser listening to `/events`
+    Events(TcpStream),
+    /// Raw terminal connection
+    Terminal(TcpStream),
+}
+
+impl Client {
+    fn send(&mut self, frame: &LcdFrame) -> io::Result<()> {
+        match self {
+            Client::Events(stream) => {
+                let data = serde_json::json!([frame.text(), frame.annunciators.to_string()]);
+                write!(stream, "data: {}\n\n", data)?;
+                stream.flush()
+            }
+            Client::Terminal(stream) => {
+                let text = frame.text();
+                write!(stream, "\x1b[2J\x1b[H\x1b#3{}\r\n\x1b#4{}\r\n{}\r\n", text, text, frame.annunciators)?;
+                stream.flush()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    clients: Vec<Client>,
+    last: Option<LcdFrame>,
+}
+
+/// Broadcasts display frames to browsers and terminals
+#[derive(Debug)]
+pub struct MirrorServer {
+    address: SocketAddr,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl MirrorServer {
+    /// Listen on an address such as `0.0.0.0:4141`
+    pub fn bind<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let accepted = Arc::clone(&shared);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // A misbehaving client only loses its own connection
+                let _ = accept(stream, &accepted);
+            }
+        });
+        Ok(MirrorServer { address, shared })
+    }
+
+    /// The bound address (useful after binding port 0)
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Number of connected mirrors
+    pub fn clients(&self) -> usize {
+        lock(&self.shared).clients.len()
+    }
+}
+
+impl DisplaySink for MirrorServer {
+    fn refresh(&mut self, frame: &LcdFrame) -> io::Result<()> {
+        let mut shared = lock(&self.shared);
+        if shared.last.as_ref() == Some(frame) {
+            return Ok(());
+        }
+        shared.clients.retain_mut(|client| client.send(frame).is_ok());
+        shared.last = Some(frame.clone());
+        Ok(())
+    }
+}
+
+fn lock(shared: &Mutex<Shared>) -> std::sync::MutexGuard<'_, Shared> {
+    // Clients are only ever appended or dropped, so a poisoned list is still usable
+    shared.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Sort a new connection into browser page, event stream or terminal
+fn accept(mut stream: TcpStream, shared: &Mutex<Shared>) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request = [0u8; 512];
+    let len = match stream.read(&mut request) {
+        Ok(len) => len,
+        Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => 0,
+        Err(e) => return Err(e),
+    };
+    stream.set_read_timeout(None)?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let request = String::from_utf8_lossy(&request[..len]);
+
+    let mut client = if request.starts_with("GET /events") {
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n")?;
+        Client::Events(stream)
+    } else if request.starts_with("GET ") {
+        write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+               PAGE.len(), PAGE)?;
+        return Ok(());
+    } else {
+        Client::Terminal(stream)
+    };
+
+    let mut shared = lock(shared);
+    if let Some(frame) = &shared.last {
+        client.send(frame)?;
+    }
+    shared.clients.push(client);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use crate::lcd::Annunciators;
+
+    fn wait_for_clients(server: &MirrorServer, count: usize) {
+        for _ in 0..100 {
+            if server.clients() == count {
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        panic!("client did not connect");
+    }
+
+    #[test]
+    fn test_event_stream_receives_frames() {
+        let mut server = MirrorServer::bind("127.0.0.1:0").unwrap();
+        server.refresh(&LcdFrame::new("1.5000", Annunciators::default())).unwrap();
+
+        let mut stream = TcpStream::connect(server.address()).unwrap();
+        stream.write_all(b"GET /events HTTP/1.1\r\n\r\n").unwrap();
+        wait_for_clients(&server, 1);
+        server.refresh(&LcdFrame::new("2.0000", Annunciators::default())).unwrap();
+
+        let lines: Vec<String> = io::BufReader::new(stream).lines()
+            .map(Result::unwrap)
+            .filter(|line| line.starts_with("data: "))
+            .take(2)
+            .collect();
+        assert_eq!(lines[0], "data: [\"1.5000      \",\"\"]");
+        assert_eq!(lines[1], "data: [\"2.0000      \",\"\"]");
+    }
+
+    #[test]
+    fn test_stalled_client_is_dropped() {
+        let mut server = MirrorServer::bind("127.0.0.1:0").unwrap();
+        // Connected, but never reads
+        let _stalled = TcpStream::connect(server.address()).unwrap();
+        wait_for_clients(&server, 1);
+        let frames = [LcdFrame::new("1.0000", Annunciators::default()), LcdFrame::new("2.0000", Annunciators::default())];
+        for frame in frames.iter().cycle().take(1_000_000) {
+            server.refresh(frame).unwrap();
+            if server.clients() == 0 {
+                break;
+            }
+        }
+        assert_eq!(server.clients(), 0);
+    }
+}
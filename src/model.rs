@@ -0,0 +1,184 @@
+//! Calculator models and plug-in modules
+//!
+//! The three members of the family differ in memory and built-in
+//! functions: the HP-41C has 63 registers of main memory, the 41CV fills
+//! all 319, and the 41CX adds the Time and Extended Functions modules to
+//! the CV's memory. After MEMORY LOST each model starts with its own
+//! default SIZE.
+//!
+//! Functions that belong to a module are rejected with NONEXISTENT unless
+//! the module is present, either built into the model or plugged in.
+
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+/// Calculator model
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Model {
+    #[serde(rename = "41C")]
+    HP41C,
+    #[serde(rename = "41CV")]
+    HP41CV,
+    #[default]
+    #[serde(rename = "41CX")]
+    HP41CX,
+}
+
+impl Model {
+    pub const ALL: [Model; 3] = [Model::HP41C, Model::HP41CV, Model::HP41CX];
+
+    /// Parse a model name such as "41cx", "HP-41CV" or "c"
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim().to_lowercase().replace('-', "");
+        let name = name.strip_prefix("hp").unwrap_or(&name);
+        let name = name.strip_prefix("41").unwrap_or(name);
+        match name {
+            "c" => Some(Model::HP41C),
+            "cv" => Some(Model::HP41CV),
+            "cx" => Some(Model::HP41CX),
+            _ => None,
+        }
+    }
+
+    /// Registers of main memory, shared by data registers and programs
+    pub fn total_registers(self) -> usize {
+        match self {
+            Model::HP41C => 63,
+            Model::HP41CV | Model::HP41CX => 319,
+        }
+    }
+
+    /// Number of data registers after MEMORY LOST
+    pub fn default_size(self) -> usize {
+        match self {
+            Model::HP41C => 17,
+            Model::HP41CV | Model::HP41CX => 100,
+        }
+    }
+
+    /// Modules built into the model
+    pub fn builtin_modules(self) -> &'static [Module] {
+        match self {
+            Model::HP41C | Model::HP41CV => &[],
+            Model::HP41CX => &[Module::Time, Module::XFunctions],
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Model::HP41C => "HP-41C",
+            Model::HP41CV => "HP-41CV",
+            Model::HP41CX => "HP-41CX",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Plug-in module that adds a set of functions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Module {
+    /// HP 82182A Time Module
+    Time,
+    /// HP 82180A Extended Functions/Memory Module
+    XFunctions,
+    /// HP 00041-15022 Games Pac
+    Games,
+    /// Navigation Pac
+    Navigation,
+    /// Surveying Pac
+    Surveying,
+}
+
+/// Time module functions
+const TIME_COMMANDS: &[&str] = &[
+    "date", "time", "clock", "setdate", "settime", "setsw", "sw", "rclsw",
+    "xyzalm", "almcat", "rclalm", "almnow", "clk12", "clk24", "dmy", "mdy",
+];
+
+/// Extended Functions functions
+const XFUNCTIONS_COMMANDS: &[&str] = &[
+    "aleng", "anum", "aclx", "atox", "xtoa", "clkeys", "pasn", "psize",
+    "regmove", "regswap", "sizeq", "emdir", "crflas", "crfld", "purfl",
+    "savep", "getp", "saver", "getr", "savex", "getx",
+];
+
+/// Games Pac functions
+const GAMES_COMMANDS: &[&str] = &["rndm", "seed", "die", "shuffle", "delay"];
+
+/// Navigation Pac functions
+const NAVIGATION_COMMANDS: &[&str] = &["gc", "rhumb", "dr"];
+
+/// Surveying Pac functions
+const SURVEYING_COMMANDS: &[&str] = &["azb", "baz", "stpt", "trav", "close", "area"];
+
+impl Module {
+    pub const ALL: [Module; 5] = [Module::Time, Module::XFunctions, Module::Games, Module::Navigation, Module::Surveying];
+
+    /// Parse a module name ("time", "xfunctions", "xf", "x-fcn", "games", "nav", "surveying")
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().replace(['-', ' ', '_'], "").as_str() {
+            "time" => Some(Module::Time),
+            "xfunctions" | "xfunction" | "xfns" | "xfcn" | "xf" => Some(Module::XFunctions),
+            "games" | "gamespac" => Some(Module::Games),
+            "navigation" | "nav" | "navpac" => Some(Module::Navigation),
+            "surveying" | "survey" | "surveypac" => Some(Module::Surveying),
+            _ => None,
+        }
+    }
+
+    /// Names of the functions the module provides
+    pub fn commands(self) -> &'static [&'static str] {
+        match self {
+            Module::Time => TIME_COMMANDS,
+            Module::XFunctions => XFUNCTIONS_COMMANDS,
+            Module::Games => GAMES_COMMANDS,
+            Module::Navigation => NAVIGATION_COMMANDS,
+            Module::Surveying => SURVEYING_COMMANDS,
+        }
+    }
+
+    /// The module a function belongs to, if it isn't a built-in function
+    pub fn for_command(command: &str) -> Option<Module> {
+        Module::ALL.into_iter()
+            .find(|module| module.commands().iter().any(|name| name.eq_ignore_ascii_case(command)))
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Module::Time => "TIME",
+            Module::XFunctions => "X FUNCTIONS",
+            Module::Games => "GAMES",
+            Module::Navigation => "NAVIGATION",
+            Module::Surveying => "SURVEYING",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_names() {
+        assert_eq!(Model::from_name("41cx"), Some(Model::HP41CX));
+        assert_eq!(Model::from_name("HP-41CV"), Some(Model::HP41CV));
+        assert_eq!(Model::from_name("c"), Some(Model::HP41C));
+        assert_eq!(Model::from_name("42s"), None);
+        assert_eq!(Model::HP41C.to_string(), "HP-41C");
+    }
+
+    #[test]
+    fn test_module_functions() {
+        assert_eq!(Module::for_command("DATE"), Some(Module::Time));
+        assert_eq!(Module::for_command("pasn"), Some(Module::XFunctions));
+        assert_eq!(Module::for_command("sin"), None);
+        assert_eq!(Module::from_name("X-Functions"), Some(Module::XFunctions));
+        assert!(Model::HP41CV.builtin_modules().is_empty());
+        assert_eq!(Model::HP41CX.builtin_modules(), [Module::Time, Module::XFunctions]);
+    }
+}
@@ -0,0 +1,164 @@
+//! Navigation module: great-circle and rhumb-line sailings
+//!
+//! Positions are entered as latitude and longitude in H.MMSS form
+//! (degrees, minutes, seconds), north and east positive. Courses are in
+//! decimal degrees from true north and distances in nautical miles, one
+//! nautical mile being one minute of arc.
+//!
+//! | Command | Stack in | Stack out |
+//! |---|---|---|
+//! | `GC` | T: lat 1, Z: lon 1, Y: lat 2, X: lon 2 | X: distance, Y: initial course, Z: lat 2, T: lon 2 |
+//! | `RHUMB` | as `GC` | X: distance, Y: constant course, Z: lat 2, T: lon 2 |
+//! | `DR` | T: lat, Z: lon, Y: course, X: distance | X: lon, Y: lat of the new position, Z: lat, T: lon of the old |
+//!
+//! `DR` (dead reckoning) sails the rhumb line, as a ship holding a
+//! compass course does. X goes to LASTX as with any function.
+
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+use crate::error::{CalculatorError, StackError};
+use crate::input::InputState;
+use crate::math::{deg_to_rad, hms_to_hours, hours_to_hms, rad_to_deg};
+use crate::stack::Stack;
+
+/// Nautical miles per radian of arc
+const MILES_PER_RADIAN: f64 = 60.0 * 180.0 / PI;
+
+/// A position in radians
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Position {
+    /// From H.MMSS latitude and longitude
+    pub fn from_hms(latitude: f64, longitude: f64) -> Result<Self, StackError> {
+        let latitude = hms_to_hours(latitude);
+        if latitude.abs() > 90.0 {
+            return Err(StackError::MathError("Latitude beyond 90°".to_string()));
+        }
+        Ok(Position { latitude: deg_to_rad(latitude), longitude: deg_to_rad(hms_to_hours(longitude)) })
+    }
+
+    /// Latitude and longitude in H.MMSS, longitude within ±180°
+    pub fn to_hms(self) -> (f64, f64) {
+        (hours_to_hms(rad_to_deg(self.latitude)), hours_to_hms(rad_to_deg(wrap_longitude(self.longitude))))
+    }
+}
+
+/// Longitude difference or longitude brought into -π..π
+fn wrap_longitude(angle: f64) -> f64 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
+
+/// Course in degrees 0..360 from a direction in radians
+fn course_degrees(angle: f64) -> f64 {
+    rad_to_deg(angle).rem_euclid(360.0)
+}
+
+/// Stretched latitude difference of the Mercator projection
+fn mercator_difference(from: f64, to: f64) -> f64 {
+    ((FRAC_PI_4 + to / 2.0).tan() / (FRAC_PI_4 + from / 2.0).tan()).ln()
+}
+
+/// Ratio of latitude to meridional difference; cos(lat) on an east-west line
+fn rhumb_ratio(from: f64, to: f64) -> f64 {
+    let stretched = mercator_difference(from, to);
+    if stretched.abs() > 1e-12 { (to - from) / stretched } else { from.cos() }
+}
+
+/// Great-circle distance in nautical miles and initial course in degrees
+pub fn great_circle(from: Position, to: Position) -> (f64, f64) {
+    let d_lat = to.latitude - from.latitude;
+    let d_lon = wrap_longitude(to.longitude - from.longitude);
+    let haversine = (d_lat / 2.0).sin().powi(2)
+        + from.latitude.cos() * to.latitude.cos() * (d_lon / 2.0).sin().powi(2);
+    let distance = 2.0 * haversine.sqrt().min(1.0).asin();
+    let course = (d_lon.sin() * to.latitude.cos())
+        .atan2(from.latitude.cos() * to.latitude.sin() - from.latitude.sin() * to.latitude.cos() * d_lon.cos());
+    (distance * MILES_PER_RADIAN, course_degrees(course))
+}
+
+/// Rhumb-line distance in nautical miles and constant course in degrees
+pub fn rhumb_line(from: Position, to: Position) -> (f64, f64) {
+    let d_lat = to.latitude - from.latitude;
+    let d_lon = wrap_longitude(to.longitude - from.longitude);
+    let q = rhumb_ratio(from.latitude, to.latitude);
+    let distance = (d_lat * d_lat + q * q * d_lon * d_lon).sqrt();
+    let course = d_lon.atan2(mercator_difference(from.latitude, to.latitude));
+    (distance * MILES_PER_RADIAN, course_degrees(course))
+}
+
+/// Position after sailing `distance` miles on a constant `course` in degrees
+pub fn dead_reckoning(from: Position, course: f64, distance: f64) -> Result<Position, StackError> {
+    let arc = distance / MILES_PER_RADIAN;
+    let course = deg_to_rad(course);
+    let latitude = from.latitude + arc * course.cos();
+    if latitude.abs() >= FRAC_PI_2 {
+        return Err(StackError::MathError("Course passes a pole".to_string()));
+    }
+    let d_lon = arc * course.sin() / rhumb_ratio(from.latitude, latitude);
+    Ok(Position { latitude, longitude: wrap_longitude(from.longitude + d_lon) })
+}
+
+/// Run GC, RHUMB or DR on the stack
+pub fn execute_navigation_command(
+    command: &str,
+    stack: &mut Stack,
+    input: &mut InputState,
+) -> Result<Option<String>, CalculatorError> {
+    let [x, y, z, t] = stack.get_registers();
+    let registers = match command {
+        "gc" | "rhumb" => {
+            let from = Position::from_hms(t, z)?;
+            let to = Position::from_hms(y, x)?;
+            let (distance, course) = if command == "gc" { great_circle(from, to) } else { rhumb_line(from, to) };
+            [distance, course, y, x]
+        }
+        "dr" => {
+            let (latitude, longitude) = dead_reckoning(Position::from_hms(t, z)?, y, x)?.to_hms();
+            [longitude, latitude, t, z]
+        }
+        _ => unreachable!(),
+    };
+    stack.set_last_x(x);
+    stack.set_registers(registers);
+    stack.set_lift_flag(true);
+    input.clear();
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64, tolerance: f64) -> bool {
+        (a - b).abs() < tolerance
+    }
+
+    #[test]
+    fn test_sailings() {
+        // Los Angeles to New York
+        let lax = Position::from_hms(33.5700, -118.2400).unwrap();
+        let jfk = Position::from_hms(40.3800, -73.4700).unwrap();
+        let (distance, course) = great_circle(lax, jfk);
+        assert!(close(distance, 2144.0, 1.0), "{}", distance);
+        assert!(close(course, 65.9, 0.1), "{}", course);
+        let (rhumb, rhumb_course) = rhumb_line(lax, jfk);
+        assert!(rhumb > distance);
+        assert!(close(rhumb_course, 79.3, 0.1), "{}", rhumb_course);
+
+        // Due east along the equator: a degree is 60 miles
+        let origin = Position::from_hms(0.0, 0.0).unwrap();
+        let (distance, course) = great_circle(origin, Position::from_hms(0.0, 1.0).unwrap());
+        assert!(close(distance, 60.0, 1e-9) && close(course, 90.0, 1e-9));
+
+        // Sailing the rhumb line arrives where it was aimed
+        let arrived = dead_reckoning(lax, rhumb_course, rhumb).unwrap();
+        assert!(close(arrived.latitude, jfk.latitude, 1e-9));
+        assert!(close(arrived.longitude, jfk.longitude, 1e-9));
+
+        assert!(Position::from_hms(91.0, 0.0).is_err());
+        assert!(dead_reckoning(origin, 0.0, 5400.0).is_err());
+    }
+}
@@ -0,0 +1,191 @@
+//! Integer functions: GCD, LCM, prime test and factorization
+//!
+//! The functions work on the integer part of X (and Y), ignoring signs.
+//! Operands and results must stay below 2^53, the largest range in which
+//! every integer is exact; beyond it they are an error rather than a
+//! silently rounded answer.
+//!
+//! | Command | Stack in | Effect |
+//! |---|---|---|
+//! | `GCD` | Y, X | X: greatest common divisor |
+//! | `LCM` | Y, X | X: least common multiple |
+//! | `PRIME?` | X | Do if true: YES/NO from the keyboard, skips the next step when false in a program |
+//! | `FACTOR` | X: n | X: smallest prime factor p, Y: n/p; the full factorization is shown |
+
+use crate::error::{CalculatorError, StackError};
+use crate::execution::do_if_true;
+use crate::input::InputState;
+use crate::programming::ProgrammingMode;
+use crate::stack::Stack;
+
+/// Integers from here on are not all exact as f64
+const MAX_INTEGER: u64 = 1 << 53;
+
+/// Witnesses that make Miller-Rabin exact for every 64-bit number
+const WITNESSES: [u64; 7] = [2, 325, 9375, 28178, 450775, 9780504, 1795265022];
+
+/// The integer part of a value, without sign
+fn integer(value: f64) -> Result<u64, StackError> {
+    let value = value.trunc().abs();
+    if value.is_nan() || value >= MAX_INTEGER as f64 {
+        return Err(StackError::MathError("Integer too large".to_string()));
+    }
+    Ok(value as u64)
+}
+
+pub fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Least common multiple, `None` when it reaches 2^53
+pub fn lcm(a: u64, b: u64) -> Option<u64> {
+    if a == 0 || b == 0 {
+        return Some(0);
+    }
+    (a / gcd(a, b)).checked_mul(b).filter(|&m| m < MAX_INTEGER)
+}
+
+fn mul_mod(a: u64, b: u64, m: u64) -> u64 {
+    (u128::from(a) * u128::from(b) % u128::from(m)) as u64
+}
+
+fn pow_mod(mut base: u64, mut exponent: u64, m: u64) -> u64 {
+    let mut result = 1;
+    base %= m;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul_mod(result, base, m);
+        }
+        base = mul_mod(base, base, m);
+        exponent >>= 1;
+    }
+    result
+}
+
+/// Deterministic Miller-Rabin test
+pub fn is_prime(n: u64) -> bool {
+    if n < 2 {
+        return false;
+    }
+    for p in [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37] {
+        if n.is_multiple_of(p) {
+            return n == p;
+        }
+    }
+    let (mut d, mut s) = (n - 1, 0);
+    while d % 2 == 0 {
+        d /= 2;
+        s += 1;
+    }
+    WITNESSES.iter().map(|&a| a % n).filter(|&a| a != 0).all(|a| {
+        let mut x = pow_mod(a, d, n);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        (1..s).any(|_| {
+            x = mul_mod(x, x, n);
+            x == n - 1
+        })
+    })
+}
+
+/// Prime factors with their exponents, smallest first
+pub fn factorize(mut n: u64) -> Vec<(u64, u32)> {
+    let mut factors = Vec::new();
+    let mut divisor = 2;
+    while n > 1 {
+        if is_prime(n) {
+            divisor = n;
+        }
+        let mut exponent = 0;
+        while n.is_multiple_of(divisor) {
+            n /= divisor;
+            exponent += 1;
+        }
+        if exponent > 0 {
+            factors.push((divisor, exponent));
+        }
+        divisor += if divisor == 2 { 1 } else { 2 };
+    }
+    factors
+}
+
+/// Factors as `2^3*3*5`
+fn describe_factors(factors: &[(u64, u32)]) -> String {
+    factors.iter()
+        .map(|&(p, e)| if e == 1 { p.to_string() } else { format!("{}^{}", p, e) })
+        .collect::<Vec<_>>()
+        .join("*")
+}
+
+/// Run one of the integer functions
+pub fn execute_number_theory_command(
+    command: &str,
+    stack: &mut Stack,
+    input: &mut InputState,
+    programming: &mut ProgrammingMode,
+) -> Result<Option<String>, CalculatorError> {
+    let [x, y, z, t] = stack.get_registers();
+    let mut message = None;
+    match command {
+        "gcd" => {
+            let result = gcd(integer(y)?, integer(x)?);
+            stack.set_registers([result as f64, z, t, t]);
+        }
+        "lcm" => {
+            let result = lcm(integer(y)?, integer(x)?)
+                .ok_or_else(|| StackError::MathError("Integer overflow".to_string()))?;
+            stack.set_registers([result as f64, z, t, t]);
+        }
+        "prime?" => {
+            let answer = is_prime(integer(x)?);
+            input.clear();
+            return Ok(do_if_true(answer, programming));
+        }
+        "factor" => {
+            let n = integer(x)?;
+            if n < 2 {
+                return Err(StackError::MathError("FACTOR needs an integer of 2 or more".to_string()).into());
+            }
+            let factors = factorize(n);
+            let p = factors[0].0;
+            stack.set_registers([p as f64, (n / p) as f64, y, z]);
+            message = Some(describe_factors(&factors));
+        }
+        _ => unreachable!(),
+    }
+    stack.set_last_x(x);
+    stack.set_lift_flag(true);
+    input.clear();
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integer_functions() {
+        assert_eq!(gcd(84, 36), 12);
+        assert_eq!(gcd(0, 5), 5);
+        assert_eq!(lcm(4, 6), Some(12));
+        assert_eq!(lcm(0, 6), Some(0));
+        assert_eq!(lcm(1 << 30, 3 << 25), Some(3 << 30));
+        assert_eq!(lcm(1 << 40, 3 << 20), Some(3 << 40));
+        assert_eq!(lcm((1 << 40) + 1, 1 << 20), None);
+
+        let primes: Vec<u64> = (0..30).filter(|&n| is_prime(n)).collect();
+        assert_eq!(primes, [2, 3, 5, 7, 11, 13, 17, 19, 23, 29]);
+        assert!(is_prime(9_007_199_254_740_881));
+        assert!(!is_prime(3_215_031_751));
+
+        assert_eq!(factorize(360), [(2, 3), (3, 2), (5, 1)]);
+        assert_eq!(describe_factors(&factorize(360)), "2^3*3^2*5");
+        assert_eq!(factorize(600_851_475_143), [(71, 1), (839, 1), (1471, 1), (6857, 1)]);
+        assert!(integer(1e16).is_err());
+        assert_eq!(integer(-7.9), Ok(7));
+    }
+}
@@ -1,9 +1,13 @@
-/// Command Parser for HP-41C Keystroke Processing
-/// 
-/// Handles keystroke-by-keystroke command parsing using the command registry.
-/// This is designed for real-time keystroke processing, not command-line input.
+//! Command Parser for HP-41C Keystroke Processing
+//! 
+//! Handles keystroke-by-keystroke command parsing using the command registry.
+//! This is designed for real-time keystroke processing, not command-line input.
+
+use crate::analysis::quoted_label;
+use crate::registry::{command_key, CommandRegistry, ArgumentPattern, AutoExecuteRule};
 
-use crate::registry::{CommandRegistry, ArgumentPattern, AutoExecuteRule};
+/// Characters of a global alpha label
+pub const ALPHA_LABEL_LENGTH: usize = 7;
 
 /// Result of parsing a command input
 #[derive(Debug, Clone)]
@@ -36,11 +40,27 @@ pub enum ParseResult {
 /// ```
 /// 
 /// The parser maintains state across keystrokes until a command is complete.
+/// 
+/// ## Label Completion
+/// 
+/// Given the labels in scope (`set_labels`), GTO and XEQ take their label a
+/// key at a time: keys that lead to no label are refused, and the command
+/// completes as soon as the keys name exactly one label. `completions`
+/// lists the candidates and `accept_completion` takes the first.
+/// 
+/// ## Alpha Labels
+/// 
+/// A `"` after LBL, GTO or XEQ starts a global label of any characters,
+/// up to seven: `xeq "PRIME"`. A second `"`, space or Enter ends it, and
+/// the seventh character completes it. Letters are taken as capitals.
 #[derive(Debug)]
 pub struct CommandParser {
     registry: CommandRegistry,
     current_command: String,
     current_args: Vec<String>,
+    labels: Vec<String>,
+    // Collecting a quoted alpha label
+    quoting: bool,
 }
 
 impl CommandParser {
@@ -50,13 +70,100 @@ impl CommandParser {
             registry: CommandRegistry::new(),
             current_command: String::new(),
             current_args: Vec::new(),
+            labels: Vec::new(),
+            quoting: false,
         }
     }
     
+    /// Labels GTO and XEQ can complete to; with none, a single key is the label
+    pub fn set_labels(&mut self, labels: Vec<String>) {
+        self.labels = labels;
+    }
+    
+    /// Whether the pending command takes its label with completion
+    fn completes_labels(&self) -> bool {
+        matches!(self.current_command.as_str(), "gto" | "xeq") && !self.labels.is_empty()
+    }
+    
+    /// Labels that start with what has been typed of a GTO/XEQ label
+    pub fn completions(&self) -> Vec<&str> {
+        if !self.completes_labels() {
+            return Vec::new();
+        }
+        let typed = self.current_args.first().map(String::as_str).unwrap_or("");
+        self.labels.iter().map(String::as_str).filter(|label| label.starts_with(typed)).collect()
+    }
+    
+    /// Complete a pending GTO/XEQ with its first candidate label
+    pub fn accept_completion(&mut self) -> ParseResult {
+        match self.completions().first() {
+            Some(&label) => {
+                let command = self.current_command.clone();
+                let args = Some(vec![label.to_string()]);
+                self.clear();
+                ParseResult::Complete { command, args }
+            }
+            None => ParseResult::Incomplete,
+        }
+    }
+    
+    /// Add a key to GTO .nnn (three digits) or GTO ..
+    fn add_line_key(&mut self, key: &str) -> ParseResult {
+        let typed = format!("{}{}", self.current_args.first().map(String::as_str).unwrap_or(""), key);
+        let valid = typed == ".." || typed[1..].chars().all(|c| c.is_ascii_digit());
+        if !valid {
+            return ParseResult::Invalid(format!("GTO . needs three digits or ., got '{}'", key));
+        }
+        if typed == ".." || typed.len() == 4 {
+            let command = self.current_command.clone();
+            self.clear();
+            return ParseResult::Complete { command, args: Some(vec![typed]) };
+        }
+        self.current_args = vec![typed];
+        ParseResult::Incomplete
+    }
+    
+    /// Add a key to a GTO/XEQ label, completing once it names exactly one label
+    fn add_label_key(&mut self, key: &str) -> ParseResult {
+        let typed = format!("{}{}", self.current_args.first().map(String::as_str).unwrap_or(""), key.to_uppercase());
+        let candidates: Vec<&String> = self.labels.iter().filter(|label| label.starts_with(&typed)).collect();
+        match candidates.as_slice() {
+            [] => ParseResult::Invalid(format!("No label {} for {}", typed, self.current_command.to_uppercase())),
+            [only] if **only == typed => {
+                let command = self.current_command.clone();
+                self.clear();
+                ParseResult::Complete { command, args: Some(vec![typed]) }
+            }
+            _ => {
+                self.current_args = vec![typed];
+                ParseResult::Incomplete
+            }
+        }
+    }
+    
+    /// Add a key to a quoted alpha label
+    fn add_quoted_key(&mut self, key: &str) -> ParseResult {
+        if key == "\"" || key == "enter" {
+            return self.force_complete();
+        }
+        let label = &mut self.current_args[0];
+        label.push_str(&key.to_uppercase());
+        if label.chars().count() < ALPHA_LABEL_LENGTH {
+            return ParseResult::Incomplete;
+        }
+        self.force_complete()
+    }
+    
+    /// Whether a quoted alpha label is being keyed, so every key belongs to it
+    pub fn is_quoting(&self) -> bool {
+        self.quoting
+    }
+    
     /// Clear current parsing state
     pub fn clear(&mut self) {
         self.current_command.clear();
         self.current_args.clear();
+        self.quoting = false;
     }
     
     /// Add input to the current command being built
@@ -65,21 +172,20 @@ impl CommandParser {
             return self.start_command(input);
         }
         
-        if self.registry.get_spec(&self.current_command).is_some() {
-            self.add_argument(input)
-        } else {
-            self.continue_building_command(input)
+        match self.registry.get_spec(&self.current_command) {
+            Some(spec) if !matches!(spec.arg_pattern, ArgumentPattern::None) => self.add_argument(input),
+            _ => self.continue_building_command(input),
         }
     }
     
     /// Start parsing a new command
     fn start_command(&mut self, input: &str) -> ParseResult {
-        let input_lower = input.to_lowercase();
+        let input_lower = command_key(input);
         
         if let Some(spec) = self.registry.get_spec(&input_lower) {
             self.current_command = input_lower;
             
-            if matches!(spec.arg_pattern, ArgumentPattern::None) {
+            if matches!(spec.arg_pattern, ArgumentPattern::None) && !self.is_prefix_of_longer(&self.current_command) {
                 // Command executes immediately - clear state and return complete
                 let command = self.current_command.clone();
                 self.clear();
@@ -103,13 +209,13 @@ impl CommandParser {
     
     /// Continue building a command name
     fn continue_building_command(&mut self, input: &str) -> ParseResult {
-        let input_lower = input.to_lowercase();
+        let input_lower = command_key(input);
         let new_command = format!("{}{}", self.current_command, input_lower);
         
         if let Some(spec) = self.registry.get_spec(&new_command) {
             self.current_command = new_command;
             
-            if matches!(spec.arg_pattern, ArgumentPattern::None) {
+            if matches!(spec.arg_pattern, ArgumentPattern::None) && !self.is_prefix_of_longer(&self.current_command) {
                 // Command executes immediately - clear state and return complete
                 let command = self.current_command.clone();
                 self.clear();
@@ -135,8 +241,30 @@ impl CommandParser {
         self.registry.get_command_names().iter().any(|cmd| cmd.starts_with(prefix))
     }
     
+    /// Check if a complete command name also starts a longer one (CLR and CLRG)
+    /// 
+    /// Such a command waits for the next key; space or Enter runs it.
+    fn is_prefix_of_longer(&self, command: &str) -> bool {
+        self.registry.get_command_names().iter().any(|cmd| cmd.len() > command.len() && cmd.starts_with(command))
+    }
+    
     /// Add an argument to the current command
     fn add_argument(&mut self, arg: &str) -> ParseResult {
+        if self.quoting {
+            return self.add_quoted_key(arg);
+        }
+        if arg == "\"" && self.current_args.is_empty() && matches!(self.current_command.as_str(), "lbl" | "gto" | "xeq") {
+            self.quoting = true;
+            self.current_args.push(String::new());
+            return ParseResult::Incomplete;
+        }
+        let addressing_line = self.current_args.first().map_or(arg == ".", |typed| typed.starts_with('.'));
+        if self.current_command == "gto" && addressing_line {
+            return self.add_line_key(arg);
+        }
+        if self.completes_labels() {
+            return self.add_label_key(arg);
+        }
         let spec = self.registry.get_spec(&self.current_command)
             .expect("Command should exist if we got here");
         
@@ -144,6 +272,20 @@ impl CommandParser {
             ArgumentPattern::Register => {
                 // Build up the register number digit by digit
                 if self.current_args.is_empty() {
+                    // A key before the number can select a longer command: STO+ after STO, FS?C after FS?
+                    let variant = format!("{}{}", self.current_command, arg.to_lowercase());
+                    if let Some(spec) = self.registry.get_spec(&variant) {
+                        // ...or one without arguments, such as STOP after STO
+                        let immediate = matches!(spec.arg_pattern, ArgumentPattern::None) && !self.is_prefix_of_longer(&variant);
+                        self.current_command = variant;
+                        if immediate {
+                            let command = self.current_command.clone();
+                            self.clear();
+                            return ParseResult::Complete { command, args: None };
+                        }
+                        return ParseResult::Incomplete;
+                    }
+                    
                     // First digit of register number
                     if arg.len() == 1 && arg.chars().next().unwrap().is_ascii_digit() {
                         self.current_args.push(arg.to_string());
@@ -176,6 +318,56 @@ impl CommandParser {
                 }
             }
             
+            ArgumentPattern::ThreeDigit => {
+                // A letter before the number can still spell a longer command: DELAY after DEL
+                let variant = format!("{}{}", self.current_command, arg.to_lowercase());
+                if self.current_args.is_empty() && self.could_be_command_prefix(&variant) {
+                    self.current_command = variant;
+                    return match self.registry.get_spec(&self.current_command) {
+                        Some(spec) if matches!(spec.arg_pattern, ArgumentPattern::None) && !self.is_prefix_of_longer(&self.current_command) => {
+                            let command = self.current_command.clone();
+                            self.clear();
+                            ParseResult::Complete { command, args: None }
+                        }
+                        _ => ParseResult::Incomplete,
+                    };
+                }
+                if !(arg.len() == 1 && arg.chars().next().unwrap().is_ascii_digit()) {
+                    return ParseResult::Invalid(format!("{} needs three digits, got '{}'", self.current_command.to_uppercase(), arg));
+                }
+                match self.current_args.first_mut() {
+                    Some(number) => number.push_str(arg),
+                    None => self.current_args.push(arg.to_string()),
+                }
+                if self.current_args[0].len() == 3 {
+                    let command = self.current_command.clone();
+                    let args = Some(self.current_args.clone());
+                    self.clear();
+                    ParseResult::Complete { command, args }
+                } else {
+                    ParseResult::Incomplete
+                }
+            }
+            
+            ArgumentPattern::RegisterRange => {
+                // First and last register, two digits each
+                if !(arg.len() == 1 && arg.chars().next().unwrap().is_ascii_digit()) {
+                    return ParseResult::Invalid(format!("Register number must be digits, got '{}'", arg));
+                }
+                match self.current_args.last_mut() {
+                    Some(last) if last.len() == 1 => last.push_str(arg),
+                    _ => self.current_args.push(arg.to_string()),
+                }
+                if self.current_args.len() == 2 && self.current_args[1].len() == 2 {
+                    let command = self.current_command.clone();
+                    let args = Some(self.current_args.clone());
+                    self.clear();
+                    ParseResult::Complete { command, args }
+                } else {
+                    ParseResult::Incomplete
+                }
+            }
+            
             _ => {
                 // For other argument patterns, validate and complete immediately
                 if !self.is_valid_argument(arg, &spec.arg_pattern) {
@@ -217,7 +409,7 @@ impl CommandParser {
                 arg.len() == 1 && arg.chars().next().unwrap().is_ascii_digit()
             }
             
-            ArgumentPattern::Register => {
+            ArgumentPattern::Register | ArgumentPattern::RegisterRange | ArgumentPattern::ThreeDigit => {
                 // Register validation is now handled in add_argument method
                 true
             }
@@ -242,7 +434,7 @@ impl CommandParser {
     fn is_complete(&self, pattern: &ArgumentPattern) -> bool {
         match pattern {
             ArgumentPattern::None => true,
-            ArgumentPattern::Register => {
+            ArgumentPattern::Register | ArgumentPattern::RegisterRange | ArgumentPattern::ThreeDigit => {
                 // Register completion is handled in add_argument method
                 false // Never complete here - always handle in add_argument
             }
@@ -255,7 +447,16 @@ impl CommandParser {
         if self.current_command.is_empty() {
             return ParseResult::Invalid("No command to complete".to_string());
         }
+        if self.quoting && self.current_args[0].is_empty() {
+            let command = self.current_command.to_uppercase();
+            self.clear();
+            return ParseResult::Invalid(format!("{} needs a label", command));
+        }
         
+        // A quoted label is global even when its name looks local
+        if self.quoting {
+            self.current_args[0] = quoted_label(&self.current_args[0]);
+        }
         let command = self.current_command.clone();
         let args = if self.current_args.is_empty() { 
             None 
@@ -275,14 +476,53 @@ impl CommandParser {
             format!("CMD: [{}]", self.current_command)
         } else {
             // Special display for register numbers being built
-            if matches!(self.current_command.as_str(), "sto" | "rcl") && self.current_args.len() == 1 && self.current_args[0].len() == 1 {
-                format!("CMD: [{} {}_]", self.current_command, self.current_args[0])
+            let digits = self.registry.get_spec(&self.current_command)
+                .and_then(|spec| match spec.arg_pattern {
+                    ArgumentPattern::Register | ArgumentPattern::RegisterRange => Some(2),
+                    ArgumentPattern::ThreeDigit => Some(3),
+                    _ => None,
+                });
+            if digits.is_some_and(|digits| self.current_args.last().is_some_and(|arg| arg.len() < digits)) {
+                format!("CMD: [{} {}_]", self.current_command, self.current_args.join(" "))
             } else {
                 format!("CMD: [{} {}]", self.current_command, self.current_args.join(" "))
             }
         }
     }
     
+    /// The command being built and the arguments typed so far
+    pub fn pending(&self) -> Option<(&str, &[String])> {
+        (!self.current_command.is_empty())
+            .then_some((self.current_command.as_str(), self.current_args.as_slice()))
+    }
+    
+    /// The pending command with blanks for what is still to be keyed, as
+    /// PRGM mode shows it: `STO 1_`, `SIZE ___`, `GTO .02_`, `XEQ "AR_"`
+    /// 
+    /// `None` while the command name itself is incomplete.
+    pub fn prompt(&self) -> Option<String> {
+        let spec = self.registry.get_spec(&self.current_command)?;
+        let name = self.current_command.to_uppercase();
+        let typed = self.current_args.concat();
+        let blanks = |width: usize, typed: &str| format!("{}{}", typed, "_".repeat(width.saturating_sub(typed.len())));
+        let argument = match spec.arg_pattern {
+            ArgumentPattern::None => return Some(name),
+            ArgumentPattern::Register => blanks(2, &typed),
+            ArgumentPattern::RegisterRange => match self.current_args.as_slice() {
+                [first, last] => format!("{} {}", first, blanks(2, last)),
+                [first] if first.len() == 2 => format!("{} __", first),
+                _ => format!("{} __", blanks(2, &typed)),
+            },
+            ArgumentPattern::ThreeDigit => blanks(3, &typed),
+            ArgumentPattern::SingleDigit => blanks(1, &typed),
+            _ if self.quoting => format!("\"{}_\"", typed),
+            _ if typed.starts_with('.') => blanks(4, &typed),
+            _ if !typed.is_empty() && !typed.chars().all(|c| c.is_ascii_digit()) => format!("\"{}_\"", typed),
+            _ => format!("{}_", typed),
+        };
+        Some(format!("{} {}", name, argument))
+    }
+    
     /// Check if we're currently building a command
     pub fn is_building(&self) -> bool {
         !self.current_command.is_empty()
@@ -359,12 +599,109 @@ mod tests {
         }
     }
     
+    #[test]
+    fn test_register_arithmetic_building() {
+        let mut parser = CommandParser::new();
+        
+        assert!(matches!(parser.add_input("sto"), ParseResult::Incomplete));
+        assert!(matches!(parser.add_input("*"), ParseResult::Incomplete));
+        assert_eq!(parser.get_current_state(), "CMD: [sto*]");
+        assert!(matches!(parser.add_input("0"), ParseResult::Incomplete));
+        match parser.add_input("5") {
+            ParseResult::Complete { command, args } => {
+                assert_eq!(command, "sto*");
+                assert_eq!(args, Some(vec!["05".to_string()]));
+            }
+            _ => panic!("STO* 05 should complete"),
+        }
+        
+        // Only one operator, and only before the register number
+        assert!(matches!(parser.add_input("sto"), ParseResult::Incomplete));
+        assert!(matches!(parser.add_input("+"), ParseResult::Incomplete));
+        assert!(matches!(parser.add_input("-"), ParseResult::Invalid(_)));
+    }
+    
+    #[test]
+    fn test_flag_test_building() {
+        let mut parser = CommandParser::new();
+        
+        // FS? is complete but C before the number selects FS?C
+        for key in ["f", "s", "?", "c", "1"] {
+            assert!(matches!(parser.add_input(key), ParseResult::Incomplete));
+        }
+        match parser.add_input("2") {
+            ParseResult::Complete { command, args } => {
+                assert_eq!(command, "fs?c");
+                assert_eq!(args, Some(vec!["12".to_string()]));
+            }
+            _ => panic!("FS?C 12 should complete"),
+        }
+    }
+    
+    #[test]
+    fn test_command_that_prefixes_another() {
+        let mut parser = CommandParser::new();
+        
+        // CLR waits because CLRG might follow
+        for key in ["c", "l", "r"] {
+            assert!(matches!(parser.add_input(key), ParseResult::Incomplete));
+        }
+        match parser.add_input("g") {
+            ParseResult::Complete { command, .. } => assert_eq!(command, "clrg"),
+            _ => panic!("CLRG should complete"),
+        }
+        
+        for key in ["c", "l", "r"] {
+            assert!(matches!(parser.add_input(key), ParseResult::Incomplete));
+        }
+        match parser.force_complete() {
+            ParseResult::Complete { command, args } => {
+                assert_eq!(command, "clr");
+                assert_eq!(args, None);
+            }
+            _ => panic!("CLR should complete on space"),
+        }
+    }
+    
+    #[test]
+    fn test_register_range_building() {
+        let mut parser = CommandParser::new();
+        
+        for key in ["protect", "1", "0", "1"] {
+            assert!(matches!(parser.add_input(key), ParseResult::Incomplete));
+        }
+        assert_eq!(parser.get_current_state(), "CMD: [protect 10 1_]");
+        match parser.add_input("9") {
+            ParseResult::Complete { command, args } => {
+                assert_eq!(command, "protect");
+                assert_eq!(args, Some(vec!["10".to_string(), "19".to_string()]));
+            }
+            _ => panic!("PROTECT 10 19 should complete"),
+        }
+    }
+    
+    #[test]
+    fn test_three_digit_building() {
+        let mut parser = CommandParser::new();
+        for key in ["size", "0", "2"] {
+            assert!(matches!(parser.add_input(key), ParseResult::Incomplete));
+        }
+        assert_eq!(parser.get_current_state(), "CMD: [size 02_]");
+        match parser.add_input("5") {
+            ParseResult::Complete { command, args } => {
+                assert_eq!(command, "size");
+                assert_eq!(args, Some(vec!["025".to_string()]));
+            }
+            _ => panic!("SIZE 025 should complete"),
+        }
+    }
+    
     #[test]
     fn test_invalid_commands() {
         let mut parser = CommandParser::new();
         
         // Invalid command should be rejected
-        match parser.add_input("xyz") {
+        match parser.add_input("xyq") {
             ParseResult::Invalid(_) => {}, // Expected
             _ => panic!("Invalid command should be rejected"),
         }
@@ -405,4 +742,95 @@ mod tests {
             _ => panic!("Force complete should work"),
         }
     }
+    
+    #[test]
+    fn test_label_completion() {
+        let mut parser = CommandParser::new();
+        parser.set_labels(vec!["AREA".to_string(), "ARC".to_string(), "A".to_string(), "01".to_string()]);
+        for key in ["x", "e", "q"] {
+            parser.add_input(key);
+        }
+        assert_eq!(parser.completions(), ["AREA", "ARC", "A", "01"]);
+        
+        // A key that leads to no label is refused and the command stays pending
+        assert!(matches!(parser.add_input("z"), ParseResult::Invalid(_)));
+        assert!(matches!(parser.add_input("a"), ParseResult::Incomplete));
+        assert!(matches!(parser.add_input("r"), ParseResult::Incomplete));
+        assert_eq!(parser.completions(), ["AREA", "ARC"]);
+        match parser.add_input("c") {
+            ParseResult::Complete { command, args } => {
+                assert_eq!(command, "xeq");
+                assert_eq!(args, Some(vec!["ARC".to_string()]));
+            }
+            other => panic!("XEQ ARC should complete, got {:?}", other),
+        }
+        
+        parser.add_input("gto");
+        parser.add_input("0");
+        assert!(matches!(parser.accept_completion(), ParseResult::Complete { args: Some(args), .. } if args == ["01"]));
+        
+        // LBL names new labels, so it never completes
+        parser.add_input("lbl");
+        assert!(parser.completions().is_empty());
+        assert!(matches!(parser.add_input("z"), ParseResult::Complete { .. }));
+    }
+    
+    #[test]
+    fn test_alpha_labels() {
+        let mut parser = CommandParser::new();
+        parser.set_labels(vec!["A".to_string()]);
+        for key in ["x", "e", "q", "\"", "p", "r", "1"] {
+            assert!(matches!(parser.add_input(key), ParseResult::Incomplete));
+        }
+        assert!(parser.is_quoting());
+        assert_eq!(parser.prompt().as_deref(), Some("XEQ \"PR1_\""));
+        assert!(matches!(parser.add_input("enter"), ParseResult::Complete { args: Some(args), .. } if args == ["PR1"]));
+        assert!(!parser.is_quoting());
+        
+        // Seven characters complete the label; an empty one is refused
+        for key in ["l", "b", "l", "\"", "a", "b", "c", "d", "e", "f"] {
+            parser.add_input(key);
+        }
+        assert!(matches!(parser.add_input("g"), ParseResult::Complete { args: Some(args), .. } if args == ["ABCDEFG"]));
+        
+        // A quoted A is the global label, kept in its quotes
+        for key in ["x", "e", "q", "\"", "a"] {
+            parser.add_input(key);
+        }
+        assert!(matches!(parser.add_input("\""), ParseResult::Complete { args: Some(args), .. } if args == ["\"A\""]));
+        for key in ["g", "t", "o", "\""] {
+            parser.add_input(key);
+        }
+        assert!(matches!(parser.add_input("\""), ParseResult::Invalid(_)));
+        assert!(!parser.is_building());
+    }
+    
+    #[test]
+    fn test_gto_line_number() {
+        let mut parser = CommandParser::new();
+        parser.set_labels(vec!["A".to_string()]);
+        for key in ["g", "t", "o", ".", "0", "2"] {
+            assert!(matches!(parser.add_input(key), ParseResult::Incomplete));
+        }
+        assert!(matches!(parser.add_input("5"), ParseResult::Complete { args: Some(args), .. } if args == [".025"]));
+        
+        for key in ["g", "t", "o", "."] {
+            parser.add_input(key);
+        }
+        assert!(matches!(parser.add_input("a"), ParseResult::Invalid(_)));
+        assert!(matches!(parser.add_input("."), ParseResult::Complete { args: Some(args), .. } if args == [".."]));
+    }
+    
+    #[test]
+    fn test_three_digit_command_prefix() {
+        let mut parser = CommandParser::new();
+        for key in ["d", "e", "l", "a"] {
+            assert!(matches!(parser.add_input(key), ParseResult::Incomplete));
+        }
+        assert!(matches!(parser.add_input("y"), ParseResult::Complete { command, .. } if command == "delay"));
+        for key in ["d", "e", "l", "0", "1"] {
+            parser.add_input(key);
+        }
+        assert!(matches!(parser.add_input("0"), ParseResult::Complete { args: Some(args), .. } if args == ["010"]));
+    }
 }
@@ -0,0 +1,165 @@
+//! The printer peripheral (HP 82143A, or 82162A on HP-IL)
+//!
+//! Printed output goes to a `Printer` so a front end can show a paper tape,
+//! write to a file or drive a real thermal printer, and tests can read
+//! back what was printed. Until another printer is attached it goes to a
+//! `PaperTape` in memory, which `HP41CCalculator::paper_tape` reads back
+//! and Ctrl+P pages through in the terminal, so programs written for a
+//! machine with a printer run and their results can still be seen.
+//!
+//! | Command | Prints |
+//! |---|---|
+//! | `PRX` | X, on the right |
+//! | `PRA` | ALPHA, on the left |
+//! | `PRSTK` | T, Z, Y and X, as `T=` and the value |
+//! | `PRREG` | Every data register, as `R00=` and the value |
+//! | `PRP` | The program with a global label, or the current one without |
+//! | `ADV` | A blank line |
+//!
+//! Alpha data prints in quotes. A PRREG printout reads back with
+//! `import::parse_register_dump`.
+//!
+//! With flag 15 set, as the printer's mode switch at TRACE sets it, a
+//! running program prints each step it executes and the X it leaves.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Characters across the paper
+pub const PRINT_WIDTH: usize = 24;
+
+/// A device that prints lines on paper
+pub trait Printer: fmt::Debug + Send {
+    /// Print one line, at most `PRINT_WIDTH` characters
+    fn print_line(&mut self, line: &str);
+
+    /// Feed the paper by one blank line (`ADV`)
+    fn advance(&mut self) {
+        self.print_line("");
+    }
+}
+
+/// A TRACE record: the step on the left and X on the right, on a line of
+/// its own when both don't fit
+pub fn trace_lines(step: &str, x: &str) -> Vec<String> {
+    let (step_width, x_width) = (step.chars().count(), x.chars().count());
+    if step_width + 1 + x_width <= PRINT_WIDTH {
+        vec![format!("{}{:>width$}", step, x, width = PRINT_WIDTH - step_width)]
+    } else {
+        vec![step.to_string(), format!("{:>width$}", x, width = PRINT_WIDTH)]
+    }
+}
+
+/// A line broken into `PRINT_WIDTH` pieces, one blank line for none
+pub fn wrap(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.is_empty() {
+        return vec![String::new()];
+    }
+    chars.chunks(PRINT_WIDTH).map(|chunk| chunk.iter().collect()).collect()
+}
+
+/// Paper tape kept in memory (tests, headless hosts)
+///
+/// Clones share the same tape, so keep one handle and give the other to
+/// the calculator.
+#[derive(Debug, Clone, Default)]
+pub struct PaperTape {
+    lines: Arc<Mutex<Vec<String>>>,
+}
+
+impl PaperTape {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every line printed so far, blank lines for paper advances
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Tear off the tape
+    pub fn clear(&self) {
+        self.lines.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+impl Printer for PaperTape {
+    fn print_line(&mut self, line: &str) {
+        let line: String = line.chars().take(PRINT_WIDTH).collect();
+        self.lines.lock().unwrap_or_else(|e| e.into_inner()).push(line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HP41CCalculator;
+
+    #[test]
+    fn test_advance() {
+        // No printer attached: ADV feeds the paper tape
+        let mut calc = HP41CCalculator::new();
+        assert_eq!(calc.run_command_line("ADV"), Ok(None));
+        assert_eq!(calc.paper_tape().unwrap().lines(), [""]);
+
+        let tape = PaperTape::new();
+        let mut calc = HP41CCalculator::new().with_printer(Box::new(tape.clone()));
+        assert!(calc.paper_tape().is_none());
+        calc.load_listing("LBL \"P\"\nADV\nADV\nRTN").unwrap();
+        calc.run_command_line("XEQ P").unwrap();
+        assert_eq!(tape.lines(), ["", ""]);
+
+        let mut long = tape.clone();
+        long.print_line(&"X".repeat(30));
+        assert_eq!(tape.lines()[2].len(), PRINT_WIDTH);
+    }
+
+    #[test]
+    fn test_trace() {
+        let tape = PaperTape::new();
+        let mut calc = HP41CCalculator::new().with_printer(Box::new(tape.clone()));
+        calc.load_listing("LBL \"T\"\n1234567\n\"ABCDEFGHIJ\"\nRTN").unwrap();
+        calc.run_command_line("XEQ T").unwrap();
+        assert!(tape.lines().is_empty());
+
+        calc.run_command_line("SF 15").unwrap();
+        calc.run_command_line("XEQ T").unwrap();
+        assert_eq!(tape.lines(), [
+            "LBL \"T\"     1234567.0000",
+            "1234567     1234567.0000",
+            "\"ABCDEFGHIJ\"",
+            "            1234567.0000",
+            "RTN         1234567.0000",
+        ]);
+    }
+
+    #[test]
+    fn test_print_functions() {
+        let mut calc = HP41CCalculator::new();
+        calc.run_command_line("SIZE 003").unwrap();
+        calc.load_listing("LBL \"PR\"\n2.5\nSTO 01\n\"ABC\"\nASTO 02\nPRX\nPRA\nPRSTK\nPRREG\nEND").unwrap();
+        calc.run_command_line("XEQ PR").unwrap();
+        let tape = calc.paper_tape().unwrap().clone();
+        assert_eq!(tape.lines(), [
+            "                  2.5000",
+            "ABC",
+            "T=                0.0000",
+            "Z=                0.0000",
+            "Y=                0.0000",
+            "X=                2.5000",
+            "R00=              0.0000",
+            "R01=              2.5000",
+            "R02=               \"ABC\"",
+        ]);
+        let registers = crate::import::parse_register_dump(&tape.lines()[6..].join("\n")).unwrap();
+        assert_eq!(registers[1], (1, 2.5));
+
+        tape.clear();
+        calc.run_command_line("PRP PR").unwrap();
+        assert_eq!(tape.lines().len(), 10);
+        assert_eq!(tape.lines()[0], "01 LBL \"PR\"");
+        assert_eq!(tape.lines()[9], "10 END");
+        assert!(calc.run_command_line("PRP NONE").is_err());
+    }
+}
@@ -1,10 +1,33 @@
 use std::collections::HashMap;
+use std::ops::{Bound, RangeBounds};
+use serde::{Deserialize, Serialize};
+use crate::analysis::{is_global_label, label_name};
+use crate::error::ProgrammingError;
+use crate::xmem::{self, ExtendedMemory};
 
-#[derive(Debug, Clone)]
+/// Characters a text line holds, the append mark included
+pub const TEXT_LINE_LENGTH: usize = 15;
+
+/// Pending returns the HP-41 keeps; a seventh XEQ pushes the oldest out
+pub const RETURN_STACK_DEPTH: usize = 6;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ProgramInstruction {
     pub line_number: i32,
     pub command: String,
     pub arguments: Vec<String>,
+    /// Set for a text line, which has no command or arguments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<TextLine>,
+}
+
+/// The string of a text line, put in ALPHA when the step runs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextLine {
+    pub text: String,
+    /// Append to ALPHA (`⊢"ABC"`) instead of replacing it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub append: bool,
 }
 
 impl ProgramInstruction {
@@ -13,13 +36,94 @@ impl ProgramInstruction {
             line_number,
             command,
             arguments,
+            text: None,
+        }
+    }
+
+    /// A text line: `"HELLO"`, or `⊢"HELLO"` when `append` is set
+    pub fn text_line(line_number: i32, text: &str, append: bool) -> Self {
+        ProgramInstruction {
+            text: Some(TextLine { text: text.to_string(), append }),
+            ..Self::new(line_number, String::new(), Vec::new())
+        }
+    }
+}
+
+impl ProgramInstruction {
+    /// Bytes the step takes in program memory
+    ///
+    /// Steps the HP-41 has take their `.raw` byte code: one byte for most
+    /// functions and for STO/RCL of R00-R15, two with a register or digit,
+    /// three for END and long-form GTO/XEQ, alpha arguments their text plus
+    /// a header (four bytes for a global LBL, two for GTO/XEQ), a byte per
+    /// digit for a number. Module and other functions the byte code lacks
+    /// are two-byte XROM instructions, with one more byte for an argument.
+    pub fn bytes(&self) -> usize {
+        if let Ok(instructions) = crate::import::encode_instructions(std::slice::from_ref(self)) {
+            return instructions.iter().map(|(_, bytes)| bytes.len()).sum();
+        }
+        match self.arguments.as_slice() {
+            [] => 2,
+            [arg] if arg.chars().all(|c| c.is_ascii_digit()) || arg.len() == 1 => 3,
+            args => 2 + args.iter().map(String::len).sum::<usize>(),
+        }
+    }
+
+    /// Whether the step enters a number
+    pub fn is_number(&self) -> bool {
+        self.text.is_none() && self.command.parse::<f64>().is_ok()
+    }
+
+    pub fn display_text(&self) -> String {
+        if self.text.is_some() {
+            return self.to_string();
+        }
+        let takes_label = matches!(self.command.to_uppercase().as_str(), "LBL" | "GTO" | "XEQ");
+        let mut parts = vec![self.command.clone()];
+        let mut args = self.arguments.iter();
+        while let Some(arg) = args.next() {
+            if arg.eq_ignore_ascii_case("ind") {
+                parts.push(format!("IND {:0>2}", args.next().map_or("__", String::as_str)));
+            } else if takes_label && is_global_label(arg) && !arg.starts_with('.') {
+                parts.push(format!("\"{}\"", label_name(arg)));
+            } else {
+                parts.push(arg.clone());
+            }
         }
+        parts.join(" ")
     }
 }
 
+/// An `IND nn` or `ST x` operand in a step's arguments, as `IND 12`
+///
+/// Listings and imports read indirect and stack register addresses, but no
+/// command runs with one yet.
+pub fn unsupported_operand(args: &[String]) -> Option<String> {
+    let at = args.windows(2).position(|pair| pair[0].eq_ignore_ascii_case("ind") || pair[0].eq_ignore_ascii_case("st"))?;
+    Some(args[at..].join(" "))
+}
+
+/// Commands that act at once in PRGM mode instead of being recorded:
+/// editing and memory management, catalogs, key assignment and the
+/// emulator's own tools
+const NOT_PROGRAMMABLE: &[&str] = &[
+    "prgm", "sst", "bst", "del", "size", "pack", "mem", "reset", "cat", "almcat", "asn", "user", "key", "menu",
+    "exitm", "clmenu", "eex", "arc", "stats", "xref", "lint", "list", "renum", "watch", "unwatch", "protect", "unprotect",
+];
+
+/// Whether a command keyed in PRGM mode becomes a program step
+///
+/// `GTO .nnn` and `GTO ..` move the edit position instead.
+pub fn is_programmable(command: &str, args: Option<&[String]>) -> bool {
+    let moves = command == "gto" && args.and_then(<[String]>::first).is_some_and(|arg| arg.starts_with('.'));
+    !moves && !NOT_PROGRAMMABLE.contains(&command)
+}
+
 impl std::fmt::Display for ProgramInstruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if !self.arguments.is_empty() {
+        if let Some(line) = &self.text {
+            write!(f, "{}\"{}\"", if line.append { "⊢" } else { "" }, line.text)
+        } else if !self.arguments.is_empty() {
             write!(f, "{} {}", self.command, self.arguments.join(" "))
         } else {
             write!(f, "{}", self.command)
@@ -27,14 +131,65 @@ impl std::fmt::Display for ProgramInstruction {
     }
 }
 
+/// Where program execution stands
+///
+/// XEQ and R/S start a run; STOP (or R/S as a step) and PROMPT halt it with
+/// the program counter kept, so R/S carries on from the next step. RTN with
+/// no return address, or running off the last step, ends it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RunState {
+    /// No program running
+    #[default]
+    Idle,
+    /// Executing steps
+    Running,
+    /// Halted by STOP, R/S or an error; R/S resumes
+    Stopped,
+    /// Halted by PROMPT showing ALPHA, waiting for input and R/S
+    Prompting,
+}
+
+/// The memory the program counter points into
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemorySpace {
+    /// Main program memory
+    #[default]
+    Main,
+    /// A program file in extended memory, by name
+    Extended(String),
+}
+
+impl MemorySpace {
+    pub fn is_main(&self) -> bool {
+        *self == MemorySpace::Main
+    }
+}
+
+/// Where a subroutine returns to
+///
+/// Saved states write a main-memory address as a bare step index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ReturnAddress {
+    Main(usize),
+    Extended { file: String, index: usize },
+}
+
 #[derive(Debug)]
 pub struct ProgrammingMode {
     pub program: Vec<ProgramInstruction>,
+    /// Program files the program counter can run in
+    pub extended: ExtendedMemory,
     
     // Execution state
-    pub program_counter: usize,        // Index into program[] for execution
-    pub is_running: bool,
-    pub subroutine_stack: Vec<usize>,
+    pub program_counter: usize,        // Index into the running steps for execution
+    pub space: MemorySpace,            // Which memory the program counter is in
+    pub run_state: RunState,
+    pub subroutine_stack: Vec<ReturnAddress>,
+    /// Fail an XEQ nested past `RETURN_STACK_DEPTH` instead of losing the
+    /// oldest return
+    pub strict_returns: bool,
     
     // Editing state  
     pub edit_position: usize,          // Index into program[] for editing
@@ -43,20 +198,87 @@ pub struct ProgrammingMode {
     // Shared state
     pub labels: HashMap<String, i32>,
     pub current_line: i32,             // For auto-numbering new instructions
+    
+    // Memory accounting
+    /// Registers the program may fill: main memory above the data registers
+    pub room: usize,
+    /// Null bytes deleted steps left behind, until PACK reclaims them
+    pub nulls: usize,
 }
 
 impl ProgrammingMode {
     pub fn new() -> Self {
         ProgrammingMode {
             program: Vec::new(),
+            extended: ExtendedMemory::new(),
             program_counter: 0,
-            is_running: false,
+            space: MemorySpace::Main,
+            run_state: RunState::Idle,
             subroutine_stack: Vec::new(),
+            strict_returns: false,
             edit_position: 0,
             is_programming: false,
             labels: HashMap::new(),
             current_line: 1,
+            room: usize::MAX,
+            nulls: 0,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.run_state == RunState::Running
+    }
+
+    /// Start or resume execution at the program counter
+    pub fn run(&mut self) {
+        self.run_state = RunState::Running;
+    }
+
+    /// Halt, keeping the program counter for R/S
+    pub fn stop(&mut self) {
+        self.run_state = RunState::Stopped;
+    }
+
+    /// Halt at a PROMPT
+    pub fn prompt(&mut self) {
+        self.run_state = RunState::Prompting;
+    }
+
+    /// The step at the program counter, advancing past it; `None` (and
+    /// the end of the run) after the last step
+    pub fn fetch_step(&mut self) -> Option<ProgramInstruction> {
+        let step = self.running_steps().get(self.program_counter).cloned();
+        match step {
+            Some(_) => self.program_counter += 1,
+            None => self.run_state = RunState::Idle,
         }
+        step
+    }
+
+    /// The steps the program counter runs through: main memory, or the
+    /// extended-memory file it is in
+    pub fn running_steps(&self) -> &[ProgramInstruction] {
+        match &self.space {
+            MemorySpace::Main => &self.program,
+            MemorySpace::Extended(file) => self.extended.program(file).unwrap_or_default(),
+        }
+    }
+
+    /// Bytes the program occupies: its steps, a null between two numbers in
+    /// a row so they don't run together, and the nulls of deleted steps
+    pub fn bytes_used(&self) -> usize {
+        let separators = self.program.windows(2).filter(|pair| pair[0].is_number() && pair[1].is_number()).count();
+        self.program.iter().map(ProgramInstruction::bytes).sum::<usize>() + separators + self.nulls
+    }
+
+    /// Registers of main memory the program occupies, seven bytes each
+    pub fn registers_used(&self) -> usize {
+        self.bytes_used().div_ceil(7)
+    }
+
+    /// PACK: close up the nulls deleted steps left, returning the bytes freed
+    pub fn pack(&mut self) -> usize {
+        std::mem::take(&mut self.nulls)
     }
 
     pub fn toggle_programming_mode(&mut self) -> bool {
@@ -70,80 +292,74 @@ impl ProgrammingMode {
         self.is_programming
     }
 
-    // SST behavior depends on current mode
-    pub fn sst_execute(&mut self, calc: &mut crate::calculator::HP41CCalculator) -> Result<Option<String>, String> {
-        // Run mode: execute one instruction and pause
-        if self.program_counter < self.program.len() {
-            let instruction = self.program[self.program_counter].clone();
-            self.program_counter += 1;
-            self.is_running = false; // Pause after single step
-            
-            // Execute the instruction
-            calc.execute_command(&instruction.command, Some(instruction.arguments.clone()))?;
-            Ok(Some(format!("SST: {}", instruction)))
-        } else {
-            Ok(Some(".END. 49".to_string()))
-        }
-    }
-    
-    pub fn sst_edit(&mut self) -> Result<Option<String>, String> {
-        // Programming mode: navigate to next step for editing
+    /// SST in PRGM mode: move to the next step for editing and show it
+    pub fn sst_edit(&mut self) -> String {
         if self.edit_position < self.program.len() {
             self.edit_position += 1;
-            if self.edit_position < self.program.len() {
-                let instruction = &self.program[self.edit_position];
-                Ok(Some(format!("{:02} {}", instruction.line_number, instruction)))
-            } else {
-                Ok(Some(format!("{:02} .END.", self.current_line)))
-            }
-        } else {
-            Ok(Some(format!("{:02} .END.", self.current_line)))
         }
-    }
-
-    // BST behavior depends on current mode  
-    pub fn bst_execute(&mut self) -> Result<Option<String>, String> {
-        // Run mode: back up one program step (don't execute)
-        if self.program_counter > 0 {
-            self.program_counter -= 1;
-            let instruction = &self.program[self.program_counter];
-            Ok(Some(format!("BST: {:02} {}", instruction.line_number, instruction)))
-        } else {
-            Ok(Some("Beginning of program".to_string()))
+        match self.program.get(self.edit_position) {
+            Some(instruction) => format!("{:02} {}", instruction.line_number, instruction.display_text()),
+            None => format!("{:02} .END.", self.current_line),
         }
     }
-    
-    pub fn bst_edit(&mut self) -> Result<Option<String>, String> {
-        // Programming mode: navigate to previous step for editing
+
+    /// BST in PRGM mode: move to the previous step for editing and show it
+    pub fn bst_edit(&mut self) -> String {
         if self.edit_position > 0 {
             self.edit_position -= 1;
             let instruction = &self.program[self.edit_position];
-            Ok(Some(format!("{:02} {}", instruction.line_number, instruction)))
+            format!("{:02} {}", instruction.line_number, instruction.display_text())
         } else {
-            Ok(Some("Beginning of program".to_string()))
+            "Beginning of program".to_string()
         }
     }
 
-    pub fn add_instruction(&mut self, command: &str, arguments: Option<Vec<String>>, _raw_input: &str) -> bool {
+    /// Record a step at the edit position; `Ok(false)` out of PRGM mode
+    pub fn add_instruction(&mut self, command: &str, arguments: Option<Vec<String>>, _raw_input: &str) -> Result<bool, ProgrammingError> {
         if !self.is_programming {
-            return false;
+            return Ok(false);
         }
 
         let args = arguments.unwrap_or_default();
-        let instruction = ProgramInstruction::new(
-            self.current_line,
-            command.to_uppercase(),
-            args.iter().map(|s| s.to_uppercase()).collect(),
-        );
+        let command = command.to_uppercase();
+        // Letter labels a-e are not A-E
+        let takes_label = matches!(command.as_str(), "LBL" | "GTO" | "XEQ");
+        let args = args.iter()
+            .map(|s| if takes_label && crate::analysis::is_letter_label(s) { s.clone() } else { s.to_uppercase() })
+            .collect();
+        let instruction = ProgramInstruction::new(self.current_line, command, args);
 
         // Insert at current edit position
-        self.insert_at_edit_position(instruction);
-        self.current_line += 1;
+        self.insert_at_edit_position(instruction)?;
         self.edit_position += 1; // Move to next position after insertion
-        true
+        self.current_line = self.edit_position as i32 + 1;
+        Ok(true)
+    }
+
+    /// Record a text line keyed in ALPHA mode
+    pub fn add_text_line(&mut self, text: &str, append: bool) -> Result<bool, ProgrammingError> {
+        if !self.is_programming {
+            return Ok(false);
+        }
+        self.insert_at_edit_position(ProgramInstruction::text_line(self.current_line, text, append))?;
+        self.edit_position += 1;
+        self.current_line = self.edit_position as i32 + 1;
+        Ok(true)
     }
 
-    pub fn insert_at_edit_position(&mut self, instruction: ProgramInstruction) {
+    /// Insert a step, into the nulls of deleted steps when they hold it,
+    /// otherwise into free registers
+    /// 
+    /// With no register left the calculator packs memory and fails with
+    /// `Packed` (PACKING, TRY AGAIN), the step not recorded; if there was
+    /// nothing to pack, with `MemoryFull` (NO ROOM).
+    pub fn insert_at_edit_position(&mut self, instruction: ProgramInstruction) -> Result<(), ProgrammingError> {
+        let bytes = instruction.bytes();
+        if self.nulls >= bytes {
+            self.nulls -= bytes;
+        } else if (self.bytes_used() + bytes).div_ceil(7) > self.room {
+            return Err(if self.pack() > 0 { ProgrammingError::Packed } else { ProgrammingError::MemoryFull });
+        }
         if self.edit_position >= self.program.len() {
             // Insert at end
             self.program.push(instruction);
@@ -154,6 +370,7 @@ impl ProgrammingMode {
         
         // Renumber all instructions after insertion
         self.renumber_program();
+        Ok(())
     }
 
     pub fn delete_current_instruction(&mut self) -> Result<Option<String>, String> {
@@ -163,6 +380,7 @@ impl ProgrammingMode {
         
         if self.edit_position < self.program.len() {
             let deleted = self.program.remove(self.edit_position);
+            self.nulls += deleted.bytes();
             self.renumber_program();
             
             // Stay at same position, but show what's now there
@@ -177,6 +395,16 @@ impl ProgrammingMode {
         }
     }
 
+    /// DEL nnn: remove up to `count` steps from the edit position on,
+    /// returning how many were removed
+    pub fn delete_steps(&mut self, count: usize) -> usize {
+        let start = self.edit_position.min(self.program.len());
+        let end = start.saturating_add(count).min(self.program.len());
+        self.nulls += self.program.drain(start..end).map(|step| step.bytes()).sum::<usize>();
+        self.renumber_program();
+        end - start
+    }
+
     fn renumber_program(&mut self) {
         for (i, instruction) in self.program.iter_mut().enumerate() {
             instruction.line_number = (i + 1) as i32;
@@ -202,57 +430,200 @@ impl ProgrammingMode {
         }
     }
 
+    /// Index the global labels; local ones are searched for in their program
     pub fn rebuild_label_table(&mut self) {
         self.labels.clear();
         for instruction in &self.program {
-            if instruction.command == "LBL" && !instruction.arguments.is_empty() {
+            if instruction.command == "LBL" && instruction.arguments.first().is_some_and(|label| is_global_label(label)) {
                 self.labels.insert(instruction.arguments[0].clone(), instruction.line_number);
             }
         }
     }
 
+    /// Compact local labels to 01, 02, ... and update the jumps to them
+    pub fn renumber_local_labels(&mut self) -> Vec<(String, String)> {
+        let changed = crate::analysis::renumber_local_labels(&mut self.program);
+        self.rebuild_label_table();
+        changed
+    }
+
+    /// Steps of the program holding a step index: from the step after the
+    /// previous END up to, not including, the next END
+    /// 
+    /// Memory holds any number of programs this way; past the last END
+    /// is the program being written, up to `.END.`.
+    pub fn program_segment(&self, index: usize) -> std::ops::Range<usize> {
+        let at = index.min(self.program.len());
+        let is_end = |step: &ProgramInstruction| step.command.eq_ignore_ascii_case("end");
+        let start = self.program[..at].iter().rposition(is_end).map_or(0, |end| end + 1);
+        let end = self.program[at..].iter().position(is_end).map_or(self.program.len(), |end| at + end);
+        start..end
+    }
+
+    /// Numbered lines for the steps in a range of step numbers (from 1), as
+    /// listing files have them: `01 LBL "AREA"`, `02 STO IND 12`, ENDs
+    /// included and `.END.` after the last step
+    /// 
+    /// The lines read back with `listing::parse_listing`. Out-of-range
+    /// numbers are left out.
+    pub fn list<R: RangeBounds<usize>>(&self, range: R) -> Vec<String> {
+        let first = match range.start_bound() {
+            Bound::Included(&n) => n.max(1),
+            Bound::Excluded(&n) => n + 1,
+            Bound::Unbounded => 1,
+        };
+        let last = match range.end_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n.saturating_sub(1),
+            Bound::Unbounded => usize::MAX,
+        };
+        let end_line = self.program.len() + 1;
+        (first..=last.min(end_line)).map(|number| match self.program.get(number - 1) {
+            Some(step) => format!("{:02} {}", number, step.display_text()),
+            None => format!("{:02} .END.", number),
+        }).collect()
+    }
+
+    /// Move to a label: the edit position in PRGM mode, otherwise the
+    /// program counter
+    /// 
+    /// Local labels (numbers, A-J and a-e) belong to the program they are
+    /// in and are searched from the current step down, wrapping to the top
+    /// of that program; `a` and `A` are different labels. Global labels reach across every program in
+    /// memory. A running extended-memory program finds its own labels
+    /// first, and a global label missing from main memory is looked for
+    /// in the program files.
     pub fn goto_label(&mut self, label: &str) -> bool {
-        if let Some(&target_line) = self.labels.get(&label.to_uppercase()) {
-            for (i, instruction) in self.program.iter().enumerate() {
-                if instruction.line_number >= target_line {
-                    if self.is_programming {
-                        self.edit_position = i;
-                    } else {
-                        self.program_counter = i;
-                    }
+        let local = !is_global_label(label);
+        if !self.is_programming {
+            if let MemorySpace::Extended(file) = &self.space {
+                let index = self.extended.program(file).and_then(|steps| xmem::find_label(steps, label));
+                if let Some(index) = index {
+                    self.program_counter = index;
                     return true;
                 }
+                if local {
+                    return false;
+                }
+            }
+        }
+        let target = if local {
+            let at = if self.is_programming { self.edit_position } else { self.program_counter };
+            let segment = self.program_segment(at);
+            let at = at.clamp(segment.start, segment.end);
+            (at..segment.end).chain(segment.start..at).find(|&index| {
+                let step = &self.program[index];
+                step.command.eq_ignore_ascii_case("lbl") && step.arguments.first().is_some_and(|arg| arg == label)
+            })
+        } else {
+            self.labels.get(&label.to_uppercase())
+                .and_then(|&target_line| self.program.iter().position(|step| step.line_number >= target_line))
+        };
+        if let Some(index) = target {
+            if self.is_programming {
+                self.edit_position = index;
+            } else {
+                self.program_counter = index;
+                self.space = MemorySpace::Main;
             }
+            return true;
+        }
+        if self.is_programming || local {
+            return false;
         }
-        false
+        let Some((file, index)) = self.extended.find_global_label(label) else { return false };
+        self.space = MemorySpace::Extended(file.to_string());
+        self.program_counter = index;
+        true
     }
 
-    pub fn execute_subroutine(&mut self, label: &str) -> bool {
-        if self.goto_label(label) {
-            self.subroutine_stack.push(self.program_counter);
-            true
+    /// GTO .nnn: move to a line number, the edit position in PRGM mode and
+    /// the program counter otherwise; line 000 is the top of memory
+    pub fn goto_line(&mut self, line: usize) -> bool {
+        if line > self.program.len() {
+            return false;
+        }
+        let index = line.saturating_sub(1);
+        if self.is_programming {
+            self.edit_position = index;
         } else {
-            false
+            self.program_counter = index;
+            self.space = MemorySpace::Main;
         }
+        true
+    }
+
+    /// GTO ..: move past the last step, where new steps are appended, and
+    /// pack memory
+    pub fn goto_end(&mut self) {
+        self.pack();
+        if self.is_programming {
+            self.edit_position = self.program.len();
+            self.current_line = self.program.len() as i32 + 1;
+        } else {
+            self.program_counter = self.program.len();
+            self.space = MemorySpace::Main;
+        }
+    }
+
+    /// Step over the next instruction (a failed conditional test)
+    pub fn skip_next_step(&mut self) {
+        if self.program_counter < self.running_steps().len() {
+            self.program_counter += 1;
+        }
+    }
+
+    /// Jump to a label, remembering where to return when called from a
+    /// running program (from the keyboard, RTN ends the run instead)
+    /// 
+    /// Only six returns are kept, as on the HP-41: nesting deeper drops the
+    /// oldest, so the outermost RTN ends the run early, unless
+    /// `strict_returns` makes it an error.
+    pub fn execute_subroutine(&mut self, label: &str) -> Result<(), ProgrammingError> {
+        let return_address = match &self.space {
+            MemorySpace::Main => ReturnAddress::Main(self.program_counter),
+            MemorySpace::Extended(file) => ReturnAddress::Extended { file: file.clone(), index: self.program_counter },
+        };
+        let full = self.is_running() && self.subroutine_stack.len() >= RETURN_STACK_DEPTH;
+        if full && self.strict_returns {
+            return Err(ProgrammingError::SubroutineStackOverflow);
+        }
+        if !self.goto_label(label) {
+            return Err(ProgrammingError::LabelNotFound(label.to_string()));
+        }
+        if self.is_running() {
+            if full {
+                self.subroutine_stack.remove(0);
+            }
+            self.subroutine_stack.push(return_address);
+        } else {
+            self.subroutine_stack.clear();
+        }
+        Ok(())
     }
 
     pub fn return_from_subroutine(&mut self) -> bool {
         if let Some(return_addr) = self.subroutine_stack.pop() {
-            self.program_counter = return_addr;
+            (self.space, self.program_counter) = match return_addr {
+                ReturnAddress::Main(index) => (MemorySpace::Main, index),
+                ReturnAddress::Extended { file, index } => (MemorySpace::Extended(file), index),
+            };
             true
         } else {
-            self.is_running = false;
+            self.run_state = RunState::Idle;
             false
         }
     }
 
     pub fn clear_program(&mut self) {
         self.program.clear();
+        self.nulls = 0;
         self.labels.clear();
         self.program_counter = 0;
+        self.space = MemorySpace::Main;
         self.edit_position = 0;
         self.current_line = 1;
-        self.is_running = false;
+        self.run_state = RunState::Idle;
         self.subroutine_stack.clear();
     }
 
@@ -266,7 +637,7 @@ impl ProgrammingMode {
             }
         } else {
             // In run mode, show instruction at program counter
-            self.program.get(self.program_counter)
+            self.running_steps().get(self.program_counter)
         }
     }
 
@@ -274,15 +645,20 @@ impl ProgrammingMode {
         if self.is_programming {
             if self.edit_position < self.program.len() {
                 let instruction = &self.program[self.edit_position];
-                format!("{:02} {}", instruction.line_number, instruction)
+                format!("{:02} {}", instruction.line_number, instruction.display_text())
             } else {
                 format!("{:02} .END.", self.current_line)
             }
-        } else if self.program_counter < self.program.len() {
-            let instruction = &self.program[self.program_counter];
-            format!("{:02} {}", instruction.line_number, instruction)
+        } else if let Some(instruction) = self.running_steps().get(self.program_counter) {
+            format!("{:02} {}", instruction.line_number, instruction.display_text())
         } else {
             ".END.".to_string()
         }
     }
 }
+
+impl Default for ProgrammingMode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,10 +1,16 @@
-/// Command Registry System for HP-41C
-/// 
-/// Provides declarative command specifications and registry management.
-/// This replaces the old hardcoded command logic with a clean, data-driven approach.
+//! Command Registry System for HP-41C
+//! 
+//! Provides declarative command specifications and registry management.
+//! This replaces the old hardcoded command logic with a clean, data-driven approach.
 
 use std::collections::HashMap;
 
+/// A command name as the registry and the dispatchers key it: lowercase,
+/// with Σ as `σ` (`to_lowercase` makes a final `ς` of the Σ in `CLΣ`)
+pub fn command_key(name: &str) -> String {
+    name.to_lowercase().replace('ς', "σ")
+}
+
 /// Specification for how a command should be parsed and executed
 #[derive(Debug, Clone)]
 pub struct CommandSpec {
@@ -14,6 +20,13 @@ pub struct CommandSpec {
     pub description: Option<String>,
 }
 
+impl CommandSpec {
+    /// Message catalog key for this command's description (see `i18n`)
+    pub fn description_key(&self) -> String {
+        format!("cmd.{}", self.name)
+    }
+}
+
 /// Defines what kind of arguments a command expects
 #[derive(Debug, Clone)]
 pub enum ArgumentPattern {
@@ -26,6 +39,12 @@ pub enum ArgumentPattern {
     /// Register number 00-99 (e.g., STO 15, RCL 07)
     Register,
     
+    /// Two register numbers typed as four digits (e.g., PROTECT 10 19)
+    RegisterRange,
+    
+    /// Three-digit number 000-999 (e.g., SIZE 025)
+    ThreeDigit,
+    
     /// Label: single letter A-Z or number 0-9 (e.g., LBL A, GTO 5)
     Label,
     
@@ -69,7 +88,8 @@ impl CommandRegistry {
     fn register_all_commands(&mut self) {
         // Math functions - no arguments, execute immediately
         for &cmd in &["sin", "cos", "tan", "asin", "acos", "atan", 
-                      "log", "ln", "exp", "sqrt", "inv", "chs"] {
+                      "log", "ln", "exp", "sqrt", "inv", "chs",
+                      "abs", "int", "frc", "sign", "x2", "10x", "rnd"] {
             self.register(CommandSpec {
                 name: cmd.to_string(),
                 arg_pattern: ArgumentPattern::None,
@@ -79,7 +99,7 @@ impl CommandRegistry {
         }
         
         // Stack operations - no arguments, execute immediately  
-        for &cmd in &["enter", "swap", "clx", "clr"] {
+        for &cmd in &["enter", "swap", "clx", "clr", "rdn", "r^"] {
             self.register(CommandSpec {
                 name: cmd.to_string(),
                 arg_pattern: ArgumentPattern::None,
@@ -89,7 +109,7 @@ impl CommandRegistry {
         }
         
         // Arithmetic operators - no arguments, execute immediately
-        for &cmd in &["+", "-", "*", "/", "^", "!"] {
+        for &cmd in &["+", "-", "*", "/", "^", "!", "mod"] {
             self.register(CommandSpec {
                 name: cmd.to_string(),
                 arg_pattern: ArgumentPattern::None,
@@ -98,6 +118,16 @@ impl CommandRegistry {
             });
         }
         
+        // Percent and percent change; % waits for a possible %CH
+        for &cmd in &["%", "%ch"] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::None,
+                auto_execute: AutoExecuteRule::Immediate,
+                description: Some("Percent".to_string()),
+            });
+        }
+        
         // Display modes - single digit argument, auto-execute on complete
         for &cmd in &["fix", "sci", "eng"] {
             self.register(CommandSpec {
@@ -108,6 +138,48 @@ impl CommandRegistry {
             });
         }
         
+        // Sound, silent while flag 26 is clear
+        self.register(CommandSpec {
+            name: "tone".to_string(),
+            arg_pattern: ArgumentPattern::SingleDigit,
+            auto_execute: AutoExecuteRule::OnComplete,
+            description: Some("Sound a tone".to_string()),
+        });
+        self.register(CommandSpec {
+            name: "beep".to_string(),
+            arg_pattern: ArgumentPattern::None,
+            auto_execute: AutoExecuteRule::Immediate,
+            description: Some("Sound a beep".to_string()),
+        });
+        
+        // Printer functions, printing on the attached printer or the paper tape
+        self.register(CommandSpec {
+            name: "adv".to_string(),
+            arg_pattern: ArgumentPattern::None,
+            auto_execute: AutoExecuteRule::Immediate,
+            description: Some("Advance paper".to_string()),
+        });
+        for (cmd, description) in [
+            ("prx", "Print X"),
+            ("pra", "Print ALPHA"),
+            ("prstk", "Print the stack"),
+            ("prreg", "Print the data registers"),
+        ] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::None,
+                auto_execute: AutoExecuteRule::Immediate,
+                description: Some(description.to_string()),
+            });
+        }
+        // PRP LABEL prints that program, PRP alone the current one
+        self.register(CommandSpec {
+            name: "prp".to_string(),
+            arg_pattern: ArgumentPattern::Alpha,
+            auto_execute: AutoExecuteRule::Manual,
+            description: Some("Print a program".to_string()),
+        });
+        
         // Storage operations - register argument, auto-execute on complete
         for &cmd in &["sto", "rcl"] {
             self.register(CommandSpec {
@@ -118,6 +190,315 @@ impl CommandRegistry {
             });
         }
         
+        // Alpha/register transfer: ASTO 05, ARCL 05
+        for &cmd in &["asto", "arcl"] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::Register,
+                auto_execute: AutoExecuteRule::OnComplete,
+                description: Some(format!("{} alpha transfer", cmd.to_uppercase())),
+            });
+        }
+        
+        // Exchange X with a register: X<> 05
+        self.register(CommandSpec {
+            name: "x<>".to_string(),
+            arg_pattern: ArgumentPattern::Register,
+            auto_execute: AutoExecuteRule::OnComplete,
+            description: Some("Exchange X with register".to_string()),
+        });
+        
+        // Statistics: Σ+ Σ- CLΣ, also spelled S+ S- CLS for keyboards without Σ
+        for &cmd in &["σ+", "σ-", "clσ", "s+", "s-", "cls", "mean", "sdev"] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::None,
+                auto_execute: AutoExecuteRule::Immediate,
+                description: Some("Statistics".to_string()),
+            });
+        }
+        
+        // Relocate the statistics block: ΣREG 20 (SREG on keyboards without Σ)
+        for &cmd in &["σreg", "sreg"] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::Register,
+                auto_execute: AutoExecuteRule::OnComplete,
+                description: Some("Statistics registers".to_string()),
+            });
+        }
+        
+        // Games module: random numbers and pauses
+        for &cmd in &["rndm", "seed", "delay"] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::None,
+                auto_execute: AutoExecuteRule::Immediate,
+                description: Some("Games function".to_string()),
+            });
+        }
+        
+        // Games module: DIE 06 rolls a six-sided die, SHUFFLE 20 deals 1..X into R20 and up
+        for &cmd in &["die", "shuffle"] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::Register,
+                auto_execute: AutoExecuteRule::OnComplete,
+                description: Some("Games function".to_string()),
+            });
+        }
+        
+        // Extended Functions module: program files in extended memory, named in ALPHA
+        for &cmd in &["savep", "purfl", "emdir"] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::None,
+                auto_execute: AutoExecuteRule::Immediate,
+                description: Some("Extended memory".to_string()),
+            });
+        }
+        
+        // Navigation module: great circle, rhumb line, dead reckoning
+        for &cmd in &["gc", "rhumb", "dr"] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::None,
+                auto_execute: AutoExecuteRule::Immediate,
+                description: Some("Navigation".to_string()),
+            });
+        }
+        
+        // Time module clock (see time_module)
+        let clock_commands = [
+            ("date", "Today's date"),
+            ("time", "Time of day"),
+            ("setdate", "Set the date from X"),
+            ("settime", "Set the time from X"),
+            ("clock", "Show the time in the status line"),
+            ("dmy", "Dates as DD.MMYYYY"),
+            ("mdy", "Dates as MM.DDYYYY"),
+            ("xyzalm", "Set an alarm from X, Y, Z and ALPHA"),
+            ("almcat", "Alarm catalog"),
+        ];
+        for (cmd, description) in clock_commands {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::None,
+                auto_execute: AutoExecuteRule::Immediate,
+                description: Some(description.to_string()),
+            });
+        }
+        
+        // Calendar arithmetic, independent of the Time module's clock
+        for &cmd in &["dow", "jdn", "ddays", "date+"] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::None,
+                auto_execute: AutoExecuteRule::Immediate,
+                description: Some("Calendar".to_string()),
+            });
+        }
+        
+        // X?Y and X?0 tests, skipping the next program step when false;
+        // X#Y?, X<=Y? etc. for keyboards without ≠ ≤ ≥
+        for &cmd in &["x=y?", "x≠y?", "x#y?", "x<y?", "x≤y?", "x<=y?", "x>y?", "x≥y?", "x>=y?"] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::None,
+                auto_execute: AutoExecuteRule::Immediate,
+                description: Some("Compare X with Y".to_string()),
+            });
+        }
+        for &cmd in &["x=0?", "x≠0?", "x#0?", "x<0?", "x≤0?", "x<=0?", "x>0?", "x≥0?", "x>=0?"] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::None,
+                auto_execute: AutoExecuteRule::Immediate,
+                description: Some("Compare X with zero".to_string()),
+            });
+        }
+        
+        // Integer functions; PRIME? is a do-if-true test
+        for &cmd in &["gcd", "lcm", "prime?", "factor"] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::None,
+                auto_execute: AutoExecuteRule::Immediate,
+                description: Some("Integer function".to_string()),
+            });
+        }
+        
+        // Show a register or ALPHA in the display without touching the stack
+        self.register(CommandSpec {
+            name: "view".to_string(),
+            arg_pattern: ArgumentPattern::Register,
+            auto_execute: AutoExecuteRule::OnComplete,
+            description: Some("View register".to_string()),
+        });
+        self.register(CommandSpec {
+            name: "aview".to_string(),
+            arg_pattern: ArgumentPattern::None,
+            auto_execute: AutoExecuteRule::Immediate,
+            description: Some("View ALPHA".to_string()),
+        });
+        
+        // Clear ALPHA, and clear a VIEW/AVIEW message from the display
+        self.register(CommandSpec {
+            name: "cla".to_string(),
+            arg_pattern: ArgumentPattern::None,
+            auto_execute: AutoExecuteRule::Immediate,
+            description: Some("Clear ALPHA".to_string()),
+        });
+        self.register(CommandSpec {
+            name: "cld".to_string(),
+            arg_pattern: ArgumentPattern::None,
+            auto_execute: AutoExecuteRule::Immediate,
+            description: Some("Clear display".to_string()),
+        });
+        
+        // Program control: run/stop, halt, halt showing ALPHA, pause
+        for &cmd in &["r/s", "stop", "prompt", "pse"] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::None,
+                auto_execute: AutoExecuteRule::Immediate,
+                description: Some("Program control".to_string()),
+            });
+        }
+        
+        // Interval timing on the calculator clock
+        for &cmd in &["tic", "toc"] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::None,
+                auto_execute: AutoExecuteRule::Immediate,
+                description: Some("Interval timer".to_string()),
+            });
+        }
+        
+        // Catalogs: CAT 1 programs, 2 modules, 3 functions, 5 alarms, 6 key assignments
+        self.register(CommandSpec {
+            name: "cat".to_string(),
+            arg_pattern: ArgumentPattern::SingleDigit,
+            auto_execute: AutoExecuteRule::OnComplete,
+            description: Some("Catalog".to_string()),
+        });
+        
+        // Soft menus: KEY 1-6 binds a slot of the user menu to the label in ALPHA
+        self.register(CommandSpec {
+            name: "key".to_string(),
+            arg_pattern: ArgumentPattern::SingleDigit,
+            auto_execute: AutoExecuteRule::OnComplete,
+            description: Some("Menu key".to_string()),
+        });
+        for &cmd in &["menu", "exitm", "clmenu"] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::None,
+                auto_execute: AutoExecuteRule::Immediate,
+                description: Some("Soft menu".to_string()),
+            });
+        }
+        
+        // USER mode: the top-row keys run assignments and letter labels (flag 27)
+        self.register(CommandSpec {
+            name: "user".to_string(),
+            arg_pattern: ArgumentPattern::None,
+            auto_execute: AutoExecuteRule::Immediate,
+            description: Some("USER mode".to_string()),
+        });
+        
+        // Memory partition: SIZE 025 leaves R00-R24 as data registers
+        self.register(CommandSpec {
+            name: "size".to_string(),
+            arg_pattern: ArgumentPattern::ThreeDigit,
+            auto_execute: AutoExecuteRule::OnComplete,
+            description: Some("Set number of data registers".to_string()),
+        });
+        
+        self.register(CommandSpec {
+            name: "pack".to_string(),
+            arg_pattern: ArgumentPattern::None,
+            auto_execute: AutoExecuteRule::Immediate,
+            description: Some("Pack program memory".to_string()),
+        });
+        
+        // Non-authentic: the free registers, as PRGM mode shows them at .END. (REG 38)
+        self.register(CommandSpec {
+            name: "mem".to_string(),
+            arg_pattern: ArgumentPattern::None,
+            auto_execute: AutoExecuteRule::Immediate,
+            description: Some("Show free registers".to_string()),
+        });
+        
+        // Surveying module: bearing conversions, then traverse blocks at a register (TRAV 10)
+        for &cmd in &["azb", "baz"] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::None,
+                auto_execute: AutoExecuteRule::Immediate,
+                description: Some("Surveying".to_string()),
+            });
+        }
+        for &cmd in &["stpt", "trav", "close", "area"] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::Register,
+                auto_execute: AutoExecuteRule::OnComplete,
+                description: Some("Surveying".to_string()),
+            });
+        }
+        
+        // Clear all data registers
+        self.register(CommandSpec {
+            name: "clrg".to_string(),
+            arg_pattern: ArgumentPattern::None,
+            auto_execute: AutoExecuteRule::Immediate,
+            description: Some("Clear registers".to_string()),
+        });
+        
+        // Non-authentic: MEMORY LOST from the keyboard, as ON with ← does on the HP-41
+        self.register(CommandSpec {
+            name: "reset".to_string(),
+            arg_pattern: ArgumentPattern::None,
+            auto_execute: AutoExecuteRule::Immediate,
+            description: Some("Clear continuous memory (MEMORY LOST)".to_string()),
+        });
+        
+        // Register arithmetic: STO+ 05, RCL* 05 etc., chosen by an operator key after STO/RCL
+        for &cmd in &["sto+", "sto-", "sto*", "sto/", "rcl+", "rcl-", "rcl*", "rcl/"] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::Register,
+                auto_execute: AutoExecuteRule::OnComplete,
+                description: Some("Register arithmetic".to_string()),
+            });
+        }
+        
+        // Read-only register ranges
+        for &cmd in &["protect", "unprotect"] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::RegisterRange,
+                auto_execute: AutoExecuteRule::OnComplete,
+                description: Some(format!("{} registers", if cmd == "protect" { "Protect" } else { "Unprotect" })),
+            });
+        }
+        
+        // Flags: two-digit flag number; the tests FS? etc. skip the next program step when false
+        for &cmd in &["sf", "cf", "fs?", "fc?", "fs?c", "fc?c"] {
+            self.register(CommandSpec {
+                name: cmd.to_string(),
+                arg_pattern: ArgumentPattern::Register,
+                auto_execute: AutoExecuteRule::OnComplete,
+                description: Some(match cmd {
+                    "sf" => "Set flag".to_string(),
+                    "cf" => "Clear flag".to_string(),
+                    _ => "Test flag".to_string(),
+                }),
+            });
+        }
+        
         // Programming commands with labels
         for &cmd in &["lbl", "gto"] {
             self.register(CommandSpec {
@@ -136,8 +517,16 @@ impl CommandRegistry {
             description: Some("Execute program".to_string()),
         });
         
+        // Delete steps in PRGM mode: DEL 003 removes three from the current one
+        self.register(CommandSpec {
+            name: "del".to_string(),
+            arg_pattern: ArgumentPattern::ThreeDigit,
+            auto_execute: AutoExecuteRule::OnComplete,
+            description: Some("Delete program steps".to_string()),
+        });
+        
         // Programming control - no args, immediate
-        for &cmd in &["rtn", "sst", "bst", "prgm"] {
+        for &cmd in &["rtn", "end", "sst", "bst", "prgm"] {
             self.register(CommandSpec {
                 name: cmd.to_string(),
                 arg_pattern: ArgumentPattern::None,
@@ -146,15 +535,97 @@ impl CommandRegistry {
             });
         }
         
+        // Key assignment: ASN function keycode
+        self.register(CommandSpec {
+            name: "asn".to_string(),
+            arg_pattern: ArgumentPattern::Alpha,
+            auto_execute: AutoExecuteRule::Manual,
+            description: Some("Assign function to key".to_string()),
+        });
+        
+        // Keystroke statistics
+        self.register(CommandSpec {
+            name: "stats".to_string(),
+            arg_pattern: ArgumentPattern::None,
+            auto_execute: AutoExecuteRule::Immediate,
+            description: Some("Command usage statistics".to_string()),
+        });
+        
+        // Program analysis
+        self.register(CommandSpec {
+            name: "xref".to_string(),
+            arg_pattern: ArgumentPattern::None,
+            auto_execute: AutoExecuteRule::Immediate,
+            description: Some("Label cross-reference".to_string()),
+        });
+        
+        self.register(CommandSpec {
+            name: "lint".to_string(),
+            arg_pattern: ArgumentPattern::None,
+            auto_execute: AutoExecuteRule::Immediate,
+            description: Some("Check program for problems".to_string()),
+        });
+        
+        self.register(CommandSpec {
+            name: "list".to_string(),
+            arg_pattern: ArgumentPattern::None,
+            auto_execute: AutoExecuteRule::Immediate,
+            description: Some("List program memory".to_string()),
+        });
+        
+        // Non-authentic: condition check, active in debug mode (ASSERT X>0)
+        self.register(CommandSpec {
+            name: "assert".to_string(),
+            arg_pattern: ArgumentPattern::Alpha,
+            auto_execute: AutoExecuteRule::Manual,
+            description: Some("Check a condition in debug mode".to_string()),
+        });
+        
+        // Non-authentic: halt a run when a value changes (WATCH R05, WATCH ST X, WATCH F15)
+        self.register(CommandSpec {
+            name: "watch".to_string(),
+            arg_pattern: ArgumentPattern::Alpha,
+            auto_execute: AutoExecuteRule::Manual,
+            description: Some("Halt when a register or flag changes".to_string()),
+        });
+        
+        self.register(CommandSpec {
+            name: "unwatch".to_string(),
+            arg_pattern: ArgumentPattern::None,
+            auto_execute: AutoExecuteRule::Immediate,
+            description: Some("Remove all watchpoints".to_string()),
+        });
+        
+        // Non-authentic: placeholder for an imported instruction (see import::decode_free42)
+        self.register(CommandSpec {
+            name: "stub".to_string(),
+            arg_pattern: ArgumentPattern::Alpha,
+            auto_execute: AutoExecuteRule::Manual,
+            description: Some("Imported instruction, skipped".to_string()),
+        });
+        
+        self.register(CommandSpec {
+            name: "renum".to_string(),
+            arg_pattern: ArgumentPattern::None,
+            auto_execute: AutoExecuteRule::Immediate,
+            description: Some("Renumber local labels".to_string()),
+        });
+        
+        // LASTX recall
+        self.register(CommandSpec {
+            name: "lastx".to_string(),
+            arg_pattern: ArgumentPattern::None,
+            auto_execute: AutoExecuteRule::Immediate,
+            description: Some("Recall last X".to_string()),
+        });
+        
         // Constants - no arguments, execute immediately
-        for &cmd in &["pi"] {
-            self.register(CommandSpec {
-                name: cmd.to_string(),
-                arg_pattern: ArgumentPattern::None,
-                auto_execute: AutoExecuteRule::Immediate,
-                description: Some("Mathematical constant".to_string()),
-            });
-        }
+        self.register(CommandSpec {
+            name: "pi".to_string(),
+            arg_pattern: ArgumentPattern::None,
+            auto_execute: AutoExecuteRule::Immediate,
+            description: Some("Mathematical constant".to_string()),
+        });
         
         // Special commands
         self.register(CommandSpec {
@@ -250,6 +721,7 @@ mod tests {
         let storage_commands = registry.get_commands_by_pattern(&ArgumentPattern::Register);
         assert!(storage_commands.iter().any(|spec| spec.name == "sto"));
         assert!(storage_commands.iter().any(|spec| spec.name == "rcl"));
+        assert!(storage_commands.iter().any(|spec| spec.name == "sto/"));
     }
 
     #[test]
@@ -1,30 +1,87 @@
-/// HP-41C Stack Operations
-/// 
-/// The HP-41C uses a 4-level RPN stack (X, Y, Z, T registers)
-/// with specific lift and drop behaviors that this module faithfully emulates.
-/// 
-/// # Example
-/// ```
-/// use hp41c::stack::Stack;
-/// 
-/// let mut stack = Stack::new();
-/// stack.set_x(5.0);
-/// stack.lift();
-/// stack.set_x(3.0);
-/// let result = stack.add().unwrap();
-/// assert_eq!(result, 8.0);
-/// ```
+//! HP-41C Stack Operations
+//! 
+//! The HP-41C uses a 4-level RPN stack (X, Y, Z, T registers)
+//! with specific lift and drop behaviors that this module faithfully emulates.
+//! 
+//! As a non-authentic option the stack can be deeper (`StackDepth::Fixed(8)`)
+//! or grow without limit (`StackDepth::Unlimited`). Levels above T behave
+//! like T does on the real machine: the top level is lost on lift and
+//! duplicated on drop. An unlimited stack never loses its top; it grows on
+//! lift and shrinks on drop, keeping at least four levels.
+//! 
+//! # Example
+//! ```
+//! use hp41c::stack::Stack;
+//! 
+//! let mut stack = Stack::new();
+//! stack.set_x(5.0);
+//! stack.lift();
+//! stack.set_x(3.0);
+//! let result = stack.add().unwrap();
+//! assert_eq!(result, 8.0);
+//! ```
 
 use std::fmt;
+use serde::{Deserialize, Serialize};
 use crate::error::StackError;
 
-/// The 4-level RPN stack used in the HP-41C
+/// Number of levels in the authentic stack
+pub const CLASSIC_DEPTH: usize = 4;
+
+/// How many levels the stack has
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StackDepth {
+    /// A fixed number of levels, at least four
+    Fixed(usize),
+    /// Grows on lift and shrinks on drop
+    Unlimited,
+}
+
+impl StackDepth {
+    /// The HP-41C's X, Y, Z, T
+    pub const CLASSIC: StackDepth = StackDepth::Fixed(CLASSIC_DEPTH);
+
+    /// Parse "4", "8", "classic" or "unlimited"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "classic" => Some(StackDepth::CLASSIC),
+            "unlimited" => Some(StackDepth::Unlimited),
+            levels => levels.parse().ok()
+                .filter(|&n| n >= CLASSIC_DEPTH)
+                .map(StackDepth::Fixed),
+        }
+    }
+
+    pub fn is_classic(&self) -> bool {
+        *self == StackDepth::CLASSIC
+    }
+}
+
+impl Default for StackDepth {
+    fn default() -> Self {
+        StackDepth::CLASSIC
+    }
+}
+
+impl fmt::Display for StackDepth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StackDepth::Fixed(levels) => write!(f, "{}", levels),
+            StackDepth::Unlimited => write!(f, "unlimited"),
+        }
+    }
+}
+
+/// The RPN stack used in the HP-41C
 #[derive(Debug, Clone)]
 pub struct Stack {
-    /// Stack registers: [X, Y, Z, T]
-    registers: [f64; 4],
+    /// Stack registers: [X, Y, Z, T, ...]
+    registers: Vec<f64>,
+    depth: StackDepth,
     /// Flag indicating if the stack should lift on next number entry
     lifted: bool,
+    /// LASTX: the X value before the most recent operation
+    last_x: f64,
 }
 
 /// Stack register indices for clarity
@@ -36,12 +93,55 @@ const T: usize = 3;
 impl Stack {
     /// Create a new stack with all registers set to 0.0
     pub fn new() -> Self {
+        Self::with_depth(StackDepth::CLASSIC)
+    }
+
+    /// Create a stack with a non-standard depth
+    pub fn with_depth(depth: StackDepth) -> Self {
+        let levels = match depth {
+            StackDepth::Fixed(levels) => levels.max(CLASSIC_DEPTH),
+            StackDepth::Unlimited => CLASSIC_DEPTH,
+        };
         Stack {
-            registers: [0.0; 4],
+            registers: vec![0.0; levels],
+            depth,
             lifted: false,
+            last_x: 0.0,
         }
     }
 
+    /// Current depth setting
+    pub fn depth(&self) -> StackDepth {
+        self.depth
+    }
+
+    /// Change the depth, keeping the lower levels
+    ///
+    /// Levels above a smaller fixed depth are discarded; new levels are 0.
+    pub fn set_depth(&mut self, depth: StackDepth) {
+        if let StackDepth::Fixed(levels) = depth {
+            self.registers.resize(levels.max(CLASSIC_DEPTH), 0.0);
+        }
+        self.depth = depth;
+    }
+
+    /// All levels, X first
+    pub fn levels(&self) -> &[f64] {
+        &self.registers
+    }
+
+    /// Replace all levels (X first), e.g. when restoring saved state
+    ///
+    /// A fixed-depth stack keeps its size: missing levels are 0 and extra
+    /// ones are dropped.
+    pub fn set_levels(&mut self, levels: &[f64]) {
+        let len = match self.depth {
+            StackDepth::Fixed(depth) => depth.max(CLASSIC_DEPTH),
+            StackDepth::Unlimited => levels.len().max(CLASSIC_DEPTH),
+        };
+        self.registers = levels.iter().copied().chain(std::iter::repeat(0.0)).take(len).collect();
+    }
+
     /// Get the value in the X register (bottom of stack)
     pub fn x(&self) -> f64 {
         self.registers[X]
@@ -62,6 +162,26 @@ impl Stack {
         self.registers[T]
     }
 
+    /// Get the LASTX register
+    pub fn last_x(&self) -> f64 {
+        self.last_x
+    }
+
+    /// Set the LASTX register directly (used by one-argument functions
+    /// and when restoring saved state)
+    pub fn set_last_x(&mut self, value: f64) {
+        self.last_x = value;
+    }
+
+    /// Recall LASTX into X, lifting the stack like any other recall
+    pub fn recall_last_x(&mut self) {
+        if self.lifted {
+            self.lift();
+        }
+        self.registers[X] = self.last_x;
+        self.lifted = true;
+    }
+
     /// Set the X register value directly (used for number entry)
     pub fn set_x(&mut self, value: f64) {
         self.registers[X] = value;
@@ -89,19 +209,27 @@ impl Stack {
     /// Lift the stack (push values up)
     /// X → Y, Y → Z, Z → T, T is lost
     pub fn lift(&mut self) {
-        self.registers[T] = self.registers[Z];
-        self.registers[Z] = self.registers[Y];
-        self.registers[Y] = self.registers[X];
+        match self.depth {
+            StackDepth::Fixed(_) => {
+                self.registers.rotate_right(1);
+                self.registers[X] = self.registers[Y];
+            }
+            StackDepth::Unlimited => self.registers.insert(X, self.registers[X]),
+        }
     }
 
     /// Drop the stack (after binary operation)
     /// HP-41C behavior: Y → X, Z → Y, T → Z, T remains unchanged
     /// This means the old T value is duplicated into Z
     fn drop(&mut self) {
-        self.registers[X] = self.registers[Y];
-        self.registers[Y] = self.registers[Z];
-        self.registers[Z] = self.registers[T];
-        // T remains unchanged (the duplication happens above)
+        if self.depth == StackDepth::Unlimited && self.registers.len() > CLASSIC_DEPTH {
+            self.registers.remove(X);
+            return;
+        }
+        let top = self.registers.len() - 1;
+        self.registers.rotate_left(1);
+        // The top level remains unchanged (the duplication happens above)
+        self.registers[top] = self.registers[top - 1];
     }
 
     /// Perform addition (Y + X)
@@ -133,6 +261,40 @@ impl Stack {
         self.binary_operation(|y, x| y.powf(x))
     }
 
+    /// Y MOD X, with the sign of X
+    pub fn modulo(&mut self) -> Result<f64, StackError> {
+        self.binary_operation(crate::math::modulo)
+    }
+
+    /// Percent (X% of Y); Y is kept so `+` or `-` can follow
+    pub fn percent(&mut self) -> Result<f64, StackError> {
+        self.y_operation(|y, x| y * x / 100.0)
+    }
+
+    /// Percent change from Y to X; Y is kept
+    pub fn percent_change(&mut self) -> Result<f64, StackError> {
+        if self.registers[Y] == 0.0 {
+            Err(StackError::DivisionByZero)
+        } else {
+            self.y_operation(|y, x| (x - y) * 100.0 / y)
+        }
+    }
+
+    /// Like `binary_operation`, but the result replaces X without a drop
+    fn y_operation<F>(&mut self, op: F) -> Result<f64, StackError>
+    where
+        F: Fn(f64, f64) -> f64,
+    {
+        let result = op(self.registers[Y], self.registers[X]);
+        if !result.is_finite() {
+            return Err(StackError::MathError("Overflow".to_string()));
+        }
+        self.last_x = self.registers[X];
+        self.registers[X] = result;
+        self.lifted = true;
+        Ok(result)
+    }
+
     /// Generic binary operation handler
     fn binary_operation<F>(&mut self, op: F) -> Result<f64, StackError>
     where
@@ -148,7 +310,8 @@ impl Stack {
             return Err(StackError::MathError("Overflow".to_string()));
         }
 
-        // Store result and drop stack
+        // Store result and drop stack; the consumed X becomes LASTX
+        self.last_x = self.registers[X];
         self.drop();
         self.registers[X] = result;
         self.lifted = true;
@@ -156,6 +319,20 @@ impl Stack {
         Ok(result)
     }
 
+    /// Roll down (RDN): Y → X, Z → Y, T → Z, X → T
+    ///
+    /// On a deeper stack X goes to the top level.
+    pub fn roll_down(&mut self) {
+        self.registers.rotate_left(1);
+    }
+
+    /// Roll up (R^): X → Y, Y → Z, Z → T, T → X
+    ///
+    /// On a deeper stack the top level comes down to X.
+    pub fn roll_up(&mut self) {
+        self.registers.rotate_right(1);
+    }
+
     /// Swap X and Y registers
     pub fn swap(&mut self) {
         self.registers.swap(X, Y);
@@ -166,9 +343,12 @@ impl Stack {
         self.registers[X] = 0.0;
     }
 
-    /// Clear entire stack
+    /// Clear entire stack (LASTX is kept, as on the HP-41C)
     pub fn clear_all(&mut self) {
-        self.registers = [0.0; 4];
+        if self.depth == StackDepth::Unlimited {
+            self.registers.truncate(CLASSIC_DEPTH);
+        }
+        self.registers.fill(0.0);
         self.lifted = false;
     }
 
@@ -177,9 +357,14 @@ impl Stack {
         self.registers[X] = -self.registers[X];
     }
 
-    /// Get a copy of all registers (for display/debugging)
+    /// Get a copy of X, Y, Z and T (for display/debugging)
     pub fn get_registers(&self) -> [f64; 4] {
-        self.registers
+        [self.registers[X], self.registers[Y], self.registers[Z], self.registers[T]]
+    }
+
+    /// Replace X, Y, Z and T, e.g. when restoring saved state
+    pub fn set_registers(&mut self, registers: [f64; 4]) {
+        self.registers[..CLASSIC_DEPTH].copy_from_slice(&registers);
     }
 }
 
@@ -204,7 +389,7 @@ mod tests {
     #[test]
     fn test_stack_lift() {
         let mut stack = Stack::new();
-        stack.registers = [1.0, 2.0, 3.0, 4.0];
+        stack.set_registers([1.0, 2.0, 3.0, 4.0]);
         
         stack.lift();
         
@@ -214,7 +399,7 @@ mod tests {
     #[test]
     fn test_stack_drop_preserves_t() {
         let mut stack = Stack::new();
-        stack.registers = [0.0, 2.0, 3.0, 4.0];
+        stack.set_registers([0.0, 2.0, 3.0, 4.0]);
         
         // Perform an operation that causes drop
         let result = stack.add().unwrap();
@@ -226,6 +411,90 @@ mod tests {
         assert_eq!(stack.t(), 4.0);  // T unchanged
     }
 
+    #[test]
+    fn test_binary_operation_saves_last_x() {
+        let mut stack = Stack::new();
+        stack.set_registers([4.0, 10.0, 0.0, 0.0]);
+
+        stack.subtract().unwrap();
+        assert_eq!(stack.last_x(), 4.0);
+
+        // A failed operation leaves LASTX alone
+        stack.set_registers([0.0, 1.0, 0.0, 0.0]);
+        assert!(stack.divide().is_err());
+        assert_eq!(stack.last_x(), 4.0);
+
+        stack.recall_last_x();
+        assert_eq!(stack.get_registers(), [4.0, 0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_percent_keeps_y() {
+        let mut stack = Stack::new();
+        stack.set_registers([15.0, 200.0, 7.0, 0.0]);
+        assert_eq!(stack.percent(), Ok(30.0));
+        assert_eq!(stack.get_registers(), [30.0, 200.0, 7.0, 0.0]);
+        assert_eq!(stack.last_x(), 15.0);
+
+        stack.set_registers([50.0, 40.0, 0.0, 0.0]);
+        assert_eq!(stack.percent_change(), Ok(25.0));
+        assert_eq!(stack.y(), 40.0);
+        stack.set_registers([50.0, 0.0, 0.0, 0.0]);
+        assert_eq!(stack.percent_change(), Err(StackError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_roll_down_and_up() {
+        let mut stack = Stack::new();
+        stack.set_registers([1.0, 2.0, 3.0, 4.0]);
+
+        stack.roll_down();
+        assert_eq!(stack.get_registers(), [2.0, 3.0, 4.0, 1.0]);
+        stack.roll_up();
+        stack.roll_up();
+        assert_eq!(stack.get_registers(), [4.0, 1.0, 2.0, 3.0]);
+
+        let mut deep = Stack::with_depth(StackDepth::Fixed(5));
+        deep.set_levels(&[1.0, 2.0, 3.0, 4.0, 5.0]);
+        deep.roll_down();
+        assert_eq!(deep.levels(), [2.0, 3.0, 4.0, 5.0, 1.0]);
+    }
+
+    #[test]
+    fn test_eight_level_stack() {
+        let mut stack = Stack::with_depth(StackDepth::Fixed(8));
+        for value in 1..=9 {
+            stack.push(value as f64);
+        }
+        // 1 fell off the top
+        assert_eq!(stack.levels(), [9.0, 8.0, 7.0, 6.0, 5.0, 4.0, 3.0, 2.0]);
+
+        stack.add().unwrap();
+        assert_eq!(stack.levels(), [17.0, 7.0, 6.0, 5.0, 4.0, 3.0, 2.0, 2.0]);
+    }
+
+    #[test]
+    fn test_unlimited_stack() {
+        let mut stack = Stack::with_depth(StackDepth::Unlimited);
+        for value in 1..=6 {
+            stack.push(value as f64);
+        }
+        assert_eq!(stack.levels(), [6.0, 5.0, 4.0, 3.0, 2.0, 1.0, 0.0, 0.0, 0.0]);
+
+        for _ in 0..4 {
+            stack.add().unwrap();
+        }
+        assert_eq!(stack.levels(), [20.0, 1.0, 0.0, 0.0, 0.0]);
+        // Never fewer than four levels
+        stack.add().unwrap();
+        stack.add().unwrap();
+        assert_eq!(stack.levels(), [21.0, 0.0, 0.0, 0.0]);
+
+        assert_eq!(StackDepth::from_name("8"), Some(StackDepth::Fixed(8)));
+        assert_eq!(StackDepth::from_name("3"), None);
+        assert_eq!(StackDepth::from_name("Unlimited"), Some(StackDepth::Unlimited));
+    }
+
     #[test]
     fn test_division_by_zero() {
         let mut stack = Stack::new();
@@ -240,7 +509,7 @@ mod tests {
     #[test]
     fn test_swap() {
         let mut stack = Stack::new();
-        stack.registers = [1.0, 2.0, 3.0, 4.0];
+        stack.set_registers([1.0, 2.0, 3.0, 4.0]);
         
         stack.swap();
         
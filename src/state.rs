@@ -0,0 +1,465 @@
+//! Saved machine state (continuous memory)
+//!
+//! A `MachineState` is a plain snapshot of everything the real HP-41C keeps
+//! in continuous memory: the stack, storage registers, display settings,
+//! program memory and the execution position. It includes the program
+//! counter and return stack so a program halted in the middle of a run can
+//! be saved, reloaded in a later session and continued where it stopped.
+//! Loop counters for ISG/DSE live in ordinary registers and travel with them.
+//!
+//! States are stored as JSON through the calculator's `Storage` provider.
+//! The same JSON is what `hp41c dump-state --json` prints for external
+//! tools; the schema is documented in `doc/state_schema.md`. Builds with
+//! the `cbor` feature can write the same structure as CBOR instead
+//! (`StateFormat`), about half the size, for small devices; reading tells
+//! the two apart by the first byte. Formats that aren't self-describing,
+//! such as bincode, can't hold a state: optional fields are left out
+//! and alpha data is written as text in place of a number.
+//! `fingerprint` condenses the machine-visible part of a state into a
+//! 64-bit hash that is stable across runs and platforms.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::display::DisplayMode;
+use crate::programming::{MemorySpace, ProgramInstruction, ReturnAddress};
+use crate::xmem::{ExtendedMemory, XFile};
+use crate::catalog::{Alarm, KeyAssignments};
+use crate::flags::Flags;
+use crate::statistics::DEFAULT_SIGMA_REG;
+use crate::model::{Model, Module};
+use crate::random::Rng;
+use crate::alpha::{self, describe};
+use crate::stack::StackDepth;
+use crate::metadata::ProgramInfo;
+
+/// Current version of the saved state format
+pub const STATE_FORMAT_VERSION: u32 = 1;
+
+/// How a state file is encoded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StateFormat {
+    /// Pretty-printed JSON, readable and diffable
+    #[default]
+    Json,
+    /// CBOR (RFC 8949), needs the `cbor` feature
+    Cbor,
+}
+
+impl StateFormat {
+    /// The format of encoded state: a CBOR map or JSON text
+    pub fn detect(data: &[u8]) -> Self {
+        match data.first() {
+            Some(0xA0..=0xBF) => StateFormat::Cbor,
+            _ => StateFormat::Json,
+        }
+    }
+}
+
+/// Where program execution stands
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionState {
+    /// Index of the next program step to execute
+    pub program_counter: usize,
+    /// The memory the program counter is in, omitted for main memory
+    #[serde(default, skip_serializing_if = "MemorySpace::is_main")]
+    pub space: MemorySpace,
+    /// Pending subroutine return addresses, innermost last
+    pub return_stack: Vec<ReturnAddress>,
+    /// The program was running or halted mid-run when saved
+    pub interrupted: bool,
+}
+
+/// Display format settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisplayState {
+    pub mode: DisplayMode,
+    pub digits: usize,
+}
+
+/// Complete snapshot of the calculator
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MachineState {
+    pub version: u32,
+    #[serde(default)]
+    pub model: Model,
+    /// Modules plugged in on top of the model's built-in ones
+    #[serde(default)]
+    pub modules: Vec<Module>,
+    /// Stack registers as [X, Y, Z, T]
+    #[serde(with = "alpha::serde_values::list")]
+    pub stack: [f64; 4],
+    pub stack_lift: bool,
+    /// LASTX register
+    #[serde(default, with = "alpha::serde_values")]
+    pub last_x: f64,
+    /// Non-authentic stack depth, omitted for the classic four levels
+    #[serde(default, skip_serializing_if = "StackDepth::is_classic")]
+    pub stack_depth: StackDepth,
+    /// Stack levels above T, bottom first
+    #[serde(default, skip_serializing_if = "Vec::is_empty", with = "alpha::serde_values::list")]
+    pub stack_upper: Vec<f64>,
+    /// Data registers; alpha data is written as a string
+    #[serde(with = "alpha::serde_values::list")]
+    pub registers: Vec<f64>,
+    /// Read-only registers (PROTECT)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub protected: Vec<usize>,
+    /// Numbers of the set flags; defaults to the MEMORY LOST flags
+    #[serde(default)]
+    pub flags: Flags,
+    /// First statistics register (ΣREG), omitted at the default R11
+    #[serde(default = "default_sigma_reg", skip_serializing_if = "is_default_sigma_reg")]
+    pub sigma_reg: usize,
+    /// Random number generator state, omitted until the sequence has moved
+    #[serde(default, skip_serializing_if = "is_default_random")]
+    pub random: Rng,
+    /// ALPHA register, omitted when empty
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub alpha: String,
+    pub display: DisplayState,
+    pub program: Vec<ProgramInstruction>,
+    /// Titles, descriptions and authors by global label, outside program memory
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub program_info: BTreeMap<String, ProgramInfo>,
+    /// Extended memory files, omitted when there are none
+    #[serde(default, skip_serializing_if = "ExtendedMemory::is_empty")]
+    pub extended_memory: ExtendedMemory,
+    pub execution: ExecutionState,
+    #[serde(default)]
+    pub key_assignments: KeyAssignments,
+    #[serde(default)]
+    pub alarms: Vec<Alarm>,
+    /// Milliseconds the Time module's clock is set ahead of the host's,
+    /// omitted when zero
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub clock_correction: i64,
+}
+
+fn default_sigma_reg() -> usize {
+    DEFAULT_SIGMA_REG
+}
+
+fn is_default_sigma_reg(base: &usize) -> bool {
+    *base == DEFAULT_SIGMA_REG
+}
+
+fn is_default_random(rng: &Rng) -> bool {
+    *rng == Rng::default()
+}
+
+fn is_zero(value: &i64) -> bool {
+    *value == 0
+}
+
+/// 64-bit FNV-1a, fixed so fingerprints never change between builds
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn number(&mut self, value: f64) {
+        self.bytes(&value.to_bits().to_le_bytes());
+    }
+
+    /// Length-prefixed so adjacent strings can't run together
+    fn text(&mut self, text: &str) {
+        self.bytes(&(text.len() as u64).to_le_bytes());
+        self.bytes(text.as_bytes());
+    }
+}
+
+impl MachineState {
+    /// Stable hash of the stack, registers, flags and program memory
+    ///
+    /// Numbers are hashed by their exact bit patterns. The execution
+    /// position is left out, so a program that has run to completion and
+    /// one that was never run compare equal if they left the same results.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hash = Fnv1a::new();
+        for value in self.stack {
+            hash.number(value);
+        }
+        // Extra levels only count when present, so classic fingerprints don't change
+        for &value in &self.stack_upper {
+            hash.number(value);
+        }
+        hash.bytes(&[u8::from(self.stack_lift)]);
+        hash.bytes(&(self.registers.len() as u64).to_le_bytes());
+        for &value in &self.registers {
+            hash.number(value);
+        }
+        // Likewise flags only count once they differ from MEMORY LOST
+        if self.flags != Flags::default() {
+            hash.bytes(&self.flags.bits().to_le_bytes());
+        }
+        hash.text(&format!("{:?} {}", self.display.mode, self.display.digits));
+        hash.bytes(&(self.program.len() as u64).to_le_bytes());
+        for instruction in &self.program {
+            hash.text(&instruction.to_string());
+        }
+        // Extended memory only counts once it holds files
+        for (name, file) in self.extended_memory.files() {
+            hash.text(name);
+            let XFile::Program(steps) = file;
+            for instruction in steps {
+                hash.text(&instruction.to_string());
+            }
+        }
+        hash.0
+    }
+
+    /// Serialize as pretty-printed JSON
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| format!("Failed to encode state: {}", e))
+    }
+
+    /// Parse JSON written by `to_json`, rejecting newer format versions
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let state: MachineState = serde_json::from_str(json)
+            .map_err(|e| format!("Invalid state file: {}", e))?;
+        state.checked()
+    }
+
+    /// Encode in a file format
+    pub fn encode(&self, format: StateFormat) -> Result<Vec<u8>, String> {
+        match format {
+            StateFormat::Json => self.to_json().map(String::into_bytes),
+            StateFormat::Cbor => cbor::encode(self),
+        }
+    }
+
+    /// Decode a state written by `encode` in either format
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        match StateFormat::detect(data) {
+            StateFormat::Json => {
+                let json = std::str::from_utf8(data).map_err(|e| format!("Invalid state file: {}", e))?;
+                Self::from_json(json)
+            }
+            StateFormat::Cbor => cbor::decode(data)?.checked(),
+        }
+    }
+
+    fn checked(self) -> Result<Self, String> {
+        if self.version > STATE_FORMAT_VERSION {
+            return Err(format!("State format version {} is newer than supported ({})",
+                               self.version, STATE_FORMAT_VERSION));
+        }
+        Ok(self)
+    }
+}
+
+#[cfg(feature = "cbor")]
+mod cbor {
+    use super::MachineState;
+
+    pub fn encode(state: &MachineState) -> Result<Vec<u8>, String> {
+        let mut data = Vec::new();
+        ciborium::into_writer(state, &mut data).map_err(|e| format!("Failed to encode state: {}", e))?;
+        Ok(data)
+    }
+
+    pub fn decode(data: &[u8]) -> Result<MachineState, String> {
+        ciborium::from_reader(data).map_err(|e| format!("Invalid state file: {}", e))
+    }
+}
+
+#[cfg(not(feature = "cbor"))]
+mod cbor {
+    use super::MachineState;
+
+    const UNAVAILABLE: &str = "CBOR state files need the cbor feature";
+
+    pub fn encode(_state: &MachineState) -> Result<Vec<u8>, String> {
+        Err(UNAVAILABLE.to_string())
+    }
+
+    pub fn decode(_data: &[u8]) -> Result<MachineState, String> {
+        Err(UNAVAILABLE.to_string())
+    }
+}
+
+/// Short human-readable summary (the non-JSON form of `dump-state`)
+impl fmt::Display for MachineState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [x, y, z, t] = self.stack;
+        writeln!(f, "Model:     {}", self.model)?;
+        write!(f, "Stack:     X={} Y={} Z={} T={}", describe(x), describe(y), describe(z), describe(t))?;
+        for (i, &value) in self.stack_upper.iter().enumerate() {
+            write!(f, " {}={}", i + 5, describe(value))?;
+        }
+        writeln!(f, " LASTX={}", describe(self.last_x))?;
+        writeln!(f, "Display:   {:?} {}", self.display.mode, self.display.digits)?;
+        writeln!(f, "Flags:     {}", self.flags)?;
+        writeln!(f, "ΣREG:      R{:02}", self.sigma_reg)?;
+        if !self.alpha.is_empty() {
+            writeln!(f, "ALPHA:     \"{}\"", self.alpha)?;
+        }
+        let used: Vec<String> = self.registers.iter().enumerate()
+            .filter(|(_, &value)| value != 0.0)
+            .map(|(i, &value)| format!("R{:02}={}", i, describe(value)))
+            .collect();
+        writeln!(f, "Registers: {} ({} in use)", self.registers.len(), used.len())?;
+        for register in used {
+            writeln!(f, "  {}", register)?;
+        }
+        write!(f, "Program:   {} steps, PC {}", self.program.len(), self.execution.program_counter)?;
+        if self.execution.interrupted {
+            write!(f, " (interrupted, {} pending returns)", self.execution.return_stack.len())?;
+        }
+        for instruction in &self.program {
+            write!(f, "\n  {:02} {}", instruction.line_number, instruction)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_round_trip() {
+        let program = vec![ProgramInstruction::new(1, "LBL".to_string(), vec!["A".to_string()])];
+        let mut extended_memory = ExtendedMemory::new();
+        extended_memory.save_program("A", &program).unwrap();
+        let state = MachineState {
+            version: STATE_FORMAT_VERSION,
+            model: Model::HP41CV,
+            modules: vec![Module::Time],
+            stack: [1.0, 2.0, 3.0, 4.0],
+            stack_lift: true,
+            last_x: 9.0,
+            stack_depth: StackDepth::Fixed(5),
+            stack_upper: vec![5.0],
+            registers: vec![0.5; 3],
+            protected: vec![1, 2],
+            flags: Flags::try_from(vec![0, 55]).unwrap(),
+            sigma_reg: 0,
+            random: Rng::new(7),
+            alpha: "HELLO".to_string(),
+            display: DisplayState { mode: DisplayMode::Sci, digits: 2 },
+            program,
+            program_info: BTreeMap::from([("A".to_string(), ProgramInfo { title: "Area".to_string(), ..Default::default() })]),
+            extended_memory,
+            execution: ExecutionState {
+                program_counter: 1,
+                space: MemorySpace::Extended("A".to_string()),
+                return_stack: vec![ReturnAddress::Main(4), ReturnAddress::Extended { file: "A".to_string(), index: 0 }],
+                interrupted: true,
+            },
+            key_assignments: KeyAssignments::default(),
+            alarms: vec![Alarm { due: 60, message: "GO".to_string(), repeat: None }],
+            clock_correction: 0,
+        };
+        let json = state.to_json().unwrap();
+        assert_eq!(MachineState::from_json(&json).unwrap(), state);
+    }
+
+    #[test]
+    fn test_state_formats() {
+        let mut calc = crate::HP41CCalculator::new();
+        calc.load_listing("LBL \"SQ\"\nX2\nRTN").unwrap();
+        calc.set_alpha("ABC");
+        calc.run_command_line("ASTO 02").unwrap();
+        let state = calc.snapshot();
+        let json = state.encode(StateFormat::Json).unwrap();
+        assert_eq!(StateFormat::detect(&json), StateFormat::Json);
+        assert_eq!(MachineState::decode(&json).unwrap().fingerprint(), state.fingerprint());
+
+        let cbor = state.encode(StateFormat::Cbor);
+        #[cfg(feature = "cbor")]
+        {
+            let cbor = cbor.unwrap();
+            assert_eq!(StateFormat::detect(&cbor), StateFormat::Cbor);
+            assert!(cbor.len() < json.len());
+            let decoded = MachineState::decode(&cbor).unwrap();
+            assert_eq!(decoded.fingerprint(), state.fingerprint());
+            assert_eq!(decoded.registers[2].to_bits(), alpha::pack("ABC").to_bits());
+        }
+        #[cfg(not(feature = "cbor"))]
+        assert!(cbor.is_err());
+    }
+
+    #[test]
+    fn test_alpha_data_is_written_as_text() {
+        let mut calc = crate::HP41CCalculator::new();
+        calc.set_alpha("ABC");
+        calc.run_command_line("ASTO 02").unwrap();
+        calc.run_command_line("RCL 02").unwrap();
+        let json = calc.dump_state_json().unwrap();
+        assert!(json.contains("\"ABC\""));
+        let state = MachineState::from_json(&json).unwrap();
+        assert_eq!(state.registers[2].to_bits(), alpha::pack("ABC").to_bits());
+        assert_eq!(state.stack[0].to_bits(), alpha::pack("ABC").to_bits());
+        assert_eq!(state.alpha, "ABC");
+    }
+
+    #[test]
+    fn test_fingerprint_is_stable() {
+        let mut state = MachineState::from_json(r#"{
+            "version": 1, "stack": [1.0, 0.0, 0.0, 0.0], "stack_lift": false,
+            "registers": [0.0, 2.5], "display": { "mode": "Fix", "digits": 4 },
+            "program": [], "execution": { "program_counter": 0, "return_stack": [], "interrupted": false }
+        }"#).unwrap();
+        // Pinned value: changing the hashing breaks saved golden references
+        assert_eq!(format!("{:016x}", state.fingerprint()), "d92ee58350304ef2");
+
+        let original = state.fingerprint();
+        state.execution.program_counter = 3;
+        assert_eq!(state.fingerprint(), original);
+        state.registers[0] = -0.0;
+        assert_ne!(state.fingerprint(), original);
+    }
+
+    #[test]
+    fn test_json_matches_documented_schema() {
+        let json = crate::HP41CCalculator::new().dump_state_json().unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let mut keys: Vec<&str> = value.as_object().unwrap().keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(keys, ["alarms", "display", "execution", "flags", "key_assignments", "last_x",
+                          "model", "modules", "program", "registers", "stack", "stack_lift", "version"]);
+        assert_eq!(value["display"]["mode"], "Fix");
+        assert_eq!(value["execution"]["return_stack"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_rejects_newer_version() {
+        let mut value: serde_json::Value = serde_json::from_str(&MachineState {
+            version: STATE_FORMAT_VERSION,
+            model: Model::default(),
+            modules: vec![],
+            stack: [0.0; 4],
+            stack_lift: false,
+            last_x: 0.0,
+            stack_depth: StackDepth::CLASSIC,
+            stack_upper: vec![],
+            registers: vec![],
+            protected: vec![],
+            flags: Flags::default(),
+            sigma_reg: DEFAULT_SIGMA_REG,
+            random: Rng::default(),
+            alpha: String::new(),
+            display: DisplayState { mode: DisplayMode::Fix, digits: 4 },
+            program: vec![],
+            program_info: BTreeMap::new(),
+            extended_memory: ExtendedMemory::new(),
+            execution: ExecutionState::default(),
+            key_assignments: KeyAssignments::default(),
+            alarms: vec![],
+            clock_correction: 0,
+        }.to_json().unwrap()).unwrap();
+        value["version"] = serde_json::json!(STATE_FORMAT_VERSION + 1);
+        assert!(MachineState::from_json(&value.to_string()).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+//! Readable differences between two saved states
+//!
+//! `hp41c state-diff OLD NEW` prints one line per change so a state kept
+//! under version control can be reviewed like code:
+//!
+//! ```text
+//! Display: Fix 4 -> Sci 2
+//! R03: 0 -> 2.5
+//! Flags set: 01
+//! - 02 SIN
+//! + 02 COS
+//! ```
+//!
+//! Program steps are compared as a sequence, so an inserted step shows up
+//! as one `+` line instead of every later step changing number.
+
+use crate::alpha::describe;
+use crate::programming::ProgramInstruction;
+use crate::state::MachineState;
+
+const STACK_NAMES: [&str; 4] = ["X", "Y", "Z", "T"];
+
+/// Report lines for everything that changed from `old` to `new`
+pub fn diff(old: &MachineState, new: &MachineState) -> Vec<String> {
+    let mut lines = Vec::new();
+    if old.model != new.model {
+        lines.push(format!("Model: {} -> {}", old.model, new.model));
+    }
+    for (i, name) in STACK_NAMES.iter().enumerate() {
+        changed_number(&mut lines, name, old.stack[i], new.stack[i]);
+    }
+    changed_number(&mut lines, "LASTX", old.last_x, new.last_x);
+    if old.alpha != new.alpha {
+        lines.push(format!("ALPHA: \"{}\" -> \"{}\"", old.alpha, new.alpha));
+    }
+    if old.display != new.display {
+        lines.push(format!("Display: {:?} {} -> {:?} {}",
+                           old.display.mode, old.display.digits, new.display.mode, new.display.digits));
+    }
+
+    if old.registers.len() != new.registers.len() {
+        lines.push(format!("SIZE: {:03} -> {:03}", old.registers.len(), new.registers.len()));
+    }
+    for i in 0..old.registers.len().max(new.registers.len()) {
+        let name = format!("R{:02}", i);
+        match (old.registers.get(i), new.registers.get(i)) {
+            (Some(&before), Some(&after)) => changed_number(&mut lines, &name, before, after),
+            (Some(&before), None) if before != 0.0 => lines.push(format!("{}: {} -> removed", name, describe(before))),
+            (None, Some(&after)) if after != 0.0 => lines.push(format!("{}: added {}", name, describe(after))),
+            _ => {}
+        }
+    }
+
+    let set: Vec<String> = new.flags.set_flags()
+        .filter(|&flag| !old.flags.is_set(flag))
+        .map(|flag| format!("{:02}", flag))
+        .collect();
+    let cleared: Vec<String> = old.flags.set_flags()
+        .filter(|&flag| !new.flags.is_set(flag))
+        .map(|flag| format!("{:02}", flag))
+        .collect();
+    if !set.is_empty() {
+        lines.push(format!("Flags set: {}", set.join(" ")));
+    }
+    if !cleared.is_empty() {
+        lines.push(format!("Flags cleared: {}", cleared.join(" ")));
+    }
+
+    lines.extend(program_diff(&old.program, &new.program));
+    lines
+}
+
+fn changed_number(lines: &mut Vec<String>, name: &str, before: f64, after: f64) {
+    // Compare bits so -0 and NaN payloads count as changes, as in fingerprints
+    if before.to_bits() != after.to_bits() {
+        lines.push(format!("{}: {} -> {}", name, describe(before), describe(after)));
+    }
+}
+
+/// `-`/`+` lines from a longest-common-subsequence alignment of the steps
+fn program_diff(old: &[ProgramInstruction], new: &[ProgramInstruction]) -> Vec<String> {
+    let old_text: Vec<String> = old.iter().map(ToString::to_string).collect();
+    let new_text: Vec<String> = new.iter().map(ToString::to_string).collect();
+
+    // common[i][j]: length of the common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old_text[i] == new_text[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old_text[i] == new_text[j] {
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(format!("- {:02} {}", i + 1, old_text[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {:02} {}", j + 1, new_text[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(command: &str) -> ProgramInstruction {
+        ProgramInstruction::new(0, command.to_string(), vec![])
+    }
+
+    #[test]
+    fn test_state_diff() {
+        let mut calc = crate::HP41CCalculator::new();
+        let old = calc.snapshot();
+        for line in ["2.5", "STO 03", "SF 01", "CF 26", "SCI 2"] {
+            calc.run_command_line(line).unwrap();
+        }
+        let mut new = calc.snapshot();
+        new.program = vec![step("SIN")];
+        assert_eq!(diff(&old, &new), [
+            "X: 0 -> 2.5",
+            "Display: Fix 4 -> Sci 2",
+            "R03: 0 -> 2.5",
+            "Flags set: 01",
+            "Flags cleared: 26",
+            "+ 01 SIN",
+        ]);
+        assert!(diff(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_program_diff_aligns_steps() {
+        let old = [step("LBL"), step("SIN"), step("RTN")];
+        let new = [step("LBL"), step("ENTER"), step("COS"), step("RTN")];
+        assert_eq!(program_diff(&old, &new), ["- 02 SIN", "+ 02 ENTER", "+ 03 COS"]);
+    }
+}
@@ -0,0 +1,228 @@
+//! Split state directory for file-syncing services
+//!
+//! A single state file changes as a whole on every save, so two machines
+//! syncing it through Dropbox or git conflict on the whole machine image.
+//! A state directory spreads the same `MachineState` over small files that
+//! change independently:
+//!
+//! | File | Content |
+//! |---|---|
+//! | `machine.json` | Everything not listed below: stack, display, execution position |
+//! | `registers.txt` | One register per line, `R07 3.25` or `R08 "TEXT"`, `protected` appended for PROTECT |
+//! | `flags.txt` | Set flag numbers on one line |
+//! | `program.jsonl` | One program step per line, without step numbers |
+//! | `assignments.json` | USER key assignments |
+//! | `alarms.json` | Pending alarms |
+//!
+//! Step numbers are left out so inserting a step changes one line rather
+//! than renumbering the rest. Every file is written through
+//! `Storage::write`, which `FileStorage` makes atomic. Only `machine.json`
+//! is required when loading; any other missing file reads as empty.
+
+use std::path::Path;
+use serde_json::{Map, Value};
+use crate::alpha::{self, describe};
+use crate::programming::{ProgramInstruction, TextLine};
+use crate::state::MachineState;
+use crate::storage::Storage;
+
+pub const MACHINE_FILE: &str = "machine.json";
+pub const REGISTERS_FILE: &str = "registers.txt";
+pub const FLAGS_FILE: &str = "flags.txt";
+pub const PROGRAM_FILE: &str = "program.jsonl";
+pub const ASSIGNMENTS_FILE: &str = "assignments.json";
+pub const ALARMS_FILE: &str = "alarms.json";
+
+/// Marker after a protected register's value
+const PROTECTED: &str = "protected";
+
+/// Program step as stored, one per line
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Step {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    command: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    arguments: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<TextLine>,
+}
+
+/// File names and contents for a state
+pub fn split(state: &MachineState) -> Result<Vec<(&'static str, String)>, String> {
+    let encode = |e: serde_json::Error| format!("Failed to encode state: {}", e);
+    let mut machine = match serde_json::to_value(state).map_err(encode)? {
+        Value::Object(fields) => fields,
+        _ => unreachable!("MachineState serializes to an object"),
+    };
+    for key in ["registers", "protected", "flags", "program", "key_assignments", "alarms"] {
+        machine.remove(key);
+    }
+
+    let registers: String = state.registers.iter().enumerate()
+        .map(|(i, &value)| if state.protected.contains(&i) {
+            format!("R{:02} {} {}\n", i, describe(value), PROTECTED)
+        } else {
+            format!("R{:02} {}\n", i, describe(value))
+        })
+        .collect();
+    let mut program = String::new();
+    for instruction in &state.program {
+        let step = Step {
+            command: instruction.command.clone(),
+            arguments: instruction.arguments.clone(),
+            text: instruction.text.clone(),
+        };
+        program.push_str(&serde_json::to_string(&step).map_err(encode)?);
+        program.push('\n');
+    }
+
+    Ok(vec![
+        (MACHINE_FILE, serde_json::to_string_pretty(&machine).map_err(encode)? + "\n"),
+        (REGISTERS_FILE, registers),
+        (FLAGS_FILE, format!("{}\n", state.flags)),
+        (PROGRAM_FILE, program),
+        (ASSIGNMENTS_FILE, serde_json::to_string_pretty(&state.key_assignments).map_err(encode)? + "\n"),
+        (ALARMS_FILE, serde_json::to_string_pretty(&state.alarms).map_err(encode)? + "\n"),
+    ])
+}
+
+/// Rebuild a state from file contents; `read` returns None for a missing file
+pub fn join<F>(read: F) -> Result<MachineState, String>
+where
+    F: Fn(&str) -> Result<Option<String>, String>,
+{
+    let invalid = |file: &str, e: &dyn std::fmt::Display| format!("Invalid {}: {}", file, e);
+    let text = read(MACHINE_FILE)?.ok_or_else(|| format!("{} is missing", MACHINE_FILE))?;
+    let mut machine: Map<String, Value> = serde_json::from_str(&text)
+        .map_err(|e| invalid(MACHINE_FILE, &e))?;
+
+    let mut registers = Vec::new();
+    let mut protected = Vec::new();
+    for (line_number, line) in read(REGISTERS_FILE)?.unwrap_or_default().lines().enumerate() {
+        let bad_line = || invalid(REGISTERS_FILE, &format!("line {}", line_number + 1));
+        let Some((name, rest)) = line.trim().split_once(char::is_whitespace) else {
+            continue;
+        };
+        let register: usize = name.strip_prefix('R').and_then(|n| n.parse().ok()).ok_or_else(bad_line)?;
+        if register != registers.len() {
+            return Err(bad_line());
+        }
+        // Alpha data is quoted and may contain spaces (or quotes)
+        let rest = rest.trim_start();
+        let (value, marker) = match rest.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.rfind('"').ok_or_else(bad_line)?;
+                (alpha::pack(&quoted[..end]), quoted[end + 1..].trim())
+            }
+            None => {
+                let (number, marker) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                (number.parse::<f64>().map_err(|_| bad_line())?, marker.trim())
+            }
+        };
+        registers.push(value);
+        match marker {
+            PROTECTED => protected.push(register),
+            "" => {}
+            _ => return Err(bad_line()),
+        }
+    }
+
+    let flags: Vec<u8> = read(FLAGS_FILE)?.unwrap_or_default().split_whitespace()
+        .map(|flag| flag.parse().map_err(|_| invalid(FLAGS_FILE, &flag)))
+        .collect::<Result<_, _>>()?;
+
+    let mut program = Vec::new();
+    for line in read(PROGRAM_FILE)?.unwrap_or_default().lines().filter(|line| !line.trim().is_empty()) {
+        let step: Step = serde_json::from_str(line).map_err(|e| invalid(PROGRAM_FILE, &e))?;
+        program.push(ProgramInstruction {
+            text: step.text,
+            ..ProgramInstruction::new(program.len() as i32 + 1, step.command, step.arguments)
+        });
+    }
+
+    // In the JSON form alpha data is a string (see `alpha::serde_values`)
+    let registers: Vec<Value> = registers.into_iter()
+        .map(|value| alpha::unpack(value).map_or_else(|| value.into(), Value::from))
+        .collect();
+    machine.insert("registers".into(), registers.into());
+    machine.insert("protected".into(), protected.into());
+    machine.insert("flags".into(), flags.into());
+    machine.insert("program".into(), serde_json::to_value(program).map_err(|e| invalid(PROGRAM_FILE, &e))?);
+    for (key, file) in [("key_assignments", ASSIGNMENTS_FILE), ("alarms", ALARMS_FILE)] {
+        if let Some(text) = read(file)? {
+            machine.insert(key.into(), serde_json::from_str(&text).map_err(|e| invalid(file, &e))?);
+        }
+    }
+    MachineState::from_json(&Value::Object(machine).to_string())
+}
+
+/// Write a state as a directory of files
+pub fn save(storage: &dyn Storage, dir: &Path, state: &MachineState) -> Result<(), String> {
+    for (name, text) in split(state)? {
+        storage.write(&dir.join(name), text.as_bytes())
+            .map_err(|e| format!("Failed to save {}: {}", name, e))?;
+    }
+    Ok(())
+}
+
+/// Read a state directory written by `save`
+pub fn load(storage: &dyn Storage, dir: &Path) -> Result<MachineState, String> {
+    join(|name| {
+        let path = dir.join(name);
+        if !storage.exists(&path) {
+            return Ok(None);
+        }
+        storage.read_to_string(&path)
+            .map(Some)
+            .map_err(|e| format!("Failed to read {}: {}", name, e))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_split_round_trip() {
+        let mut calc = crate::HP41CCalculator::new();
+        for line in ["2.5", "STO 03", "PROTECT 03", "SF 01", "FIX 2"] {
+            calc.run_command_line(line).unwrap();
+        }
+        let mut state = calc.snapshot();
+        state.program = vec![
+            ProgramInstruction::new(1, "LBL".to_string(), vec!["A".to_string()]),
+            ProgramInstruction::new(2, "SIN".to_string(), vec![]),
+            ProgramInstruction::text_line(3, "X=", true),
+        ];
+
+        let storage = MemoryStorage::new();
+        save(&storage, Path::new("sync"), &state).unwrap();
+        let registers = storage.read_to_string(Path::new("sync/registers.txt")).unwrap();
+        assert!(registers.starts_with("R00 0\nR01 0\nR02 0\nR03 2.5 protected\nR04 0\n"));
+        assert_eq!(storage.read_to_string(Path::new("sync/program.jsonl")).unwrap(),
+                   "{\"command\":\"LBL\",\"arguments\":[\"A\"]}\n{\"command\":\"SIN\"}\n{\"text\":{\"text\":\"X=\",\"append\":true}}\n");
+        assert_eq!(load(&storage, Path::new("sync")).unwrap(), state);
+
+        // Only machine.json is required
+        storage.remove(Path::new("sync/alarms.json")).unwrap();
+        storage.remove(Path::new("sync/program.jsonl")).unwrap();
+        assert!(load(&storage, Path::new("sync")).unwrap().program.is_empty());
+        storage.remove(Path::new("sync/machine.json")).unwrap();
+        assert!(load(&storage, Path::new("sync")).is_err());
+    }
+
+    #[test]
+    fn test_alpha_registers() {
+        let mut state = crate::HP41CCalculator::new().snapshot();
+        state.registers[1] = alpha::pack("A \"B\"");
+        state.protected = vec![1];
+        let files = split(&state).unwrap();
+        let registers = &files.iter().find(|(name, _)| *name == REGISTERS_FILE).unwrap().1;
+        assert!(registers.contains("R01 \"A \"B\"\" protected\n"));
+
+        let loaded = join(|name| Ok(files.iter().find(|(file, _)| *file == name).map(|(_, text)| text.clone()))).unwrap();
+        assert_eq!(loaded.registers[1].to_bits(), state.registers[1].to_bits());
+        assert_eq!(loaded.protected, [1]);
+    }
+}
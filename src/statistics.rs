@@ -0,0 +1,123 @@
+//! Two-variable statistics (Σ+, Σ-, MEAN, SDEV, CLΣ)
+//!
+//! Σ+ accumulates the X and Y values into six consecutive storage
+//! registers starting at the ΣREG base, R11 after MEMORY LOST:
+//!
+//! | Offset | Sum |
+//! |---|---|
+//! | 0 | Σx |
+//! | 1 | Σx² |
+//! | 2 | Σy |
+//! | 3 | Σy² |
+//! | 4 | Σxy |
+//! | 5 | n |
+//!
+//! The sums live in ordinary registers, so they can be recalled, edited
+//! with STO and saved along with the rest of memory. ΣREG nn moves the
+//! block to start at Rnn.
+
+use crate::error::{CalculatorError, StackError, StorageError};
+
+/// First statistics register after MEMORY LOST
+pub const DEFAULT_SIGMA_REG: usize = 11;
+
+/// Number of statistics registers
+pub const SIGMA_REGISTERS: usize = 6;
+
+pub const SUM_X: usize = 0;
+pub const SUM_X2: usize = 1;
+pub const SUM_Y: usize = 2;
+pub const SUM_Y2: usize = 3;
+pub const SUM_XY: usize = 4;
+pub const COUNT: usize = 5;
+
+/// Check that a block starting at `base` fits in `size` registers
+pub fn check_base(base: usize, size: usize) -> Result<(), StorageError> {
+    let last = base + SIGMA_REGISTERS - 1;
+    if last < size {
+        Ok(())
+    } else {
+        Err(StorageError::InvalidRegister(last))
+    }
+}
+
+/// The six statistics registers; an error if they don't fit the SIZE
+fn sigma_block(storage: &[f64], base: usize) -> Result<&[f64], StorageError> {
+    let last = base + SIGMA_REGISTERS - 1;
+    storage.get(base..=last).ok_or(StorageError::InvalidRegister(last))
+}
+
+fn sigma_block_mut(storage: &mut [f64], base: usize) -> Result<&mut [f64], StorageError> {
+    let last = base + SIGMA_REGISTERS - 1;
+    storage.get_mut(base..=last).ok_or(StorageError::InvalidRegister(last))
+}
+
+/// Add (`sign` 1.0) or remove (`sign` -1.0) an x,y pair; returns the new n
+pub fn accumulate(storage: &mut [f64], base: usize, x: f64, y: f64, sign: f64) -> Result<f64, StorageError> {
+    let sums = sigma_block_mut(storage, base)?;
+    let mut updated = [0.0; SIGMA_REGISTERS];
+    updated.copy_from_slice(sums);
+    updated[SUM_X] += sign * x;
+    updated[SUM_X2] += sign * x * x;
+    updated[SUM_Y] += sign * y;
+    updated[SUM_Y2] += sign * y * y;
+    updated[SUM_XY] += sign * x * y;
+    updated[COUNT] += sign;
+    if updated.iter().any(|value| !value.is_finite()) {
+        return Err(StorageError::ArithmeticError("Overflow".to_string()));
+    }
+    sums.copy_from_slice(&updated);
+    Ok(updated[COUNT])
+}
+
+/// Mean of x and of y
+pub fn mean(storage: &[f64], base: usize) -> Result<(f64, f64), CalculatorError> {
+    let sums = sigma_block(storage, base)?;
+    let n = sums[COUNT];
+    if n == 0.0 {
+        return Err(StackError::MathError("No statistics data".to_string()).into());
+    }
+    Ok((sums[SUM_X] / n, sums[SUM_Y] / n))
+}
+
+/// Sample standard deviation of x and of y
+pub fn standard_deviation(storage: &[f64], base: usize) -> Result<(f64, f64), CalculatorError> {
+    let sums = sigma_block(storage, base)?;
+    let n = sums[COUNT];
+    if n <= 1.0 {
+        return Err(StackError::MathError("SDEV needs at least two data points".to_string()).into());
+    }
+    // Rounding can leave a tiny negative variance for identical values
+    let deviation = |sum: f64, sum_of_squares: f64| ((sum_of_squares - sum * sum / n) / (n - 1.0)).max(0.0).sqrt();
+    Ok((deviation(sums[SUM_X], sums[SUM_X2]), deviation(sums[SUM_Y], sums[SUM_Y2])))
+}
+
+/// Zero the six statistics registers
+pub fn clear(storage: &mut [f64], base: usize) -> Result<(), StorageError> {
+    sigma_block_mut(storage, base)?.fill(0.0);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accumulate_and_summarize() {
+        let mut storage = vec![0.0; 20];
+        for (x, y) in [(1.0, 10.0), (2.0, 20.0), (3.0, 30.0), (9.0, 9.0)] {
+            accumulate(&mut storage, DEFAULT_SIGMA_REG, x, y, 1.0).unwrap();
+        }
+        assert_eq!(accumulate(&mut storage, DEFAULT_SIGMA_REG, 9.0, 9.0, -1.0), Ok(3.0));
+        assert_eq!(storage[11..17], [6.0, 14.0, 60.0, 1400.0, 140.0, 3.0]);
+
+        assert_eq!(mean(&storage, DEFAULT_SIGMA_REG).unwrap(), (2.0, 20.0));
+        assert_eq!(standard_deviation(&storage, DEFAULT_SIGMA_REG).unwrap(), (1.0, 10.0));
+
+        clear(&mut storage, DEFAULT_SIGMA_REG).unwrap();
+        assert!(mean(&storage, DEFAULT_SIGMA_REG).is_err());
+        assert_eq!(accumulate(&mut storage, 15, 1.0, 1.0, 1.0), Err(StorageError::InvalidRegister(20)));
+        assert_eq!(check_base(14, 20), Ok(()));
+        assert_eq!(check_base(15, 20), Err(StorageError::InvalidRegister(20)));
+    }
+}
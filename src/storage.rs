@@ -0,0 +1,282 @@
+//! Sandboxed file access for the HP-41C emulator
+//!
+//! Every feature that persists data (debug logs today; state files, cards,
+//! extended memory and LIF images as they arrive) goes through a `Storage`
+//! provider instead of touching `std::fs` directly. Desktop builds use
+//! `FileStorage`; WASM and sandboxed hosts can hand the calculator a
+//! `MemoryStorage` or their own implementation backed by virtual storage.
+//! `FileStorage` needs the `files` feature (part of the default `tui`);
+//! without it the default provider keeps files in memory.
+
+use std::collections::BTreeMap;
+use std::fmt;
+#[cfg(feature = "files")]
+use std::fs::{self, OpenOptions};
+use std::io;
+#[cfg(feature = "files")]
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// A provider of file-like storage keyed by relative paths
+///
+/// Methods take `&self` so a single provider can be shared between the
+/// logger and other subsystems through a `SharedStorage` handle.
+pub trait Storage: fmt::Debug + Send + Sync {
+    /// Read the entire contents of a file
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Create or replace a file with the given contents
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()>;
+
+    /// Append data to a file, creating it if necessary
+    fn append(&self, path: &Path, data: &[u8]) -> io::Result<()>;
+
+    /// Check whether a file exists
+    fn exists(&self, path: &Path) -> bool;
+
+    /// Remove a file
+    fn remove(&self, path: &Path) -> io::Result<()>;
+
+    /// Read a file as UTF-8 text
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        let bytes = self.read(path)?;
+        String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Storage handle shared between calculator subsystems
+pub type SharedStorage = Arc<dyn Storage>;
+
+/// Storage backed by the host filesystem
+///
+/// `FileStorage::new()` resolves paths exactly as given (relative to the
+/// working directory). `FileStorage::sandboxed(root)` confines every access
+/// to `root`, rejecting absolute paths and `..` components.
+#[cfg(feature = "files")]
+#[derive(Debug, Clone, Default)]
+pub struct FileStorage {
+    root: Option<PathBuf>,
+}
+
+#[cfg(feature = "files")]
+impl FileStorage {
+    /// Create unrestricted filesystem storage
+    pub fn new() -> Self {
+        FileStorage { root: None }
+    }
+
+    /// Create filesystem storage confined to a root directory
+    pub fn sandboxed<P: AsRef<Path>>(root: P) -> Self {
+        FileStorage { root: Some(root.as_ref().to_path_buf()) }
+    }
+
+    /// Get the sandbox root, if any
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    /// Map a requested path to a host path, enforcing the sandbox policy
+    fn resolve(&self, path: &Path) -> io::Result<PathBuf> {
+        match &self.root {
+            None => Ok(path.to_path_buf()),
+            Some(root) => {
+                check_relative(path)?;
+                Ok(root.join(path))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "files")]
+impl Storage for FileStorage {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(self.resolve(path)?)
+    }
+
+    /// Writes go to a temporary file that then replaces the target, so a
+    /// crash or a sync client never sees a half-written file
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let path = self.resolve(path)?;
+        create_parent_dirs(&path)?;
+        let mut temp = path.clone().into_os_string();
+        temp.push(".tmp");
+        fs::write(&temp, data)?;
+        fs::rename(&temp, &path).inspect_err(|_| {
+            let _ = fs::remove_file(&temp);
+        })
+    }
+
+    fn append(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let path = self.resolve(path)?;
+        create_parent_dirs(&path)?;
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(data)?;
+        file.flush()
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.resolve(path).map(|p| p.is_file()).unwrap_or(false)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(self.resolve(path)?)
+    }
+}
+
+/// Storage held entirely in memory
+///
+/// Clones share the same underlying files, so a host can keep one handle to
+/// inspect what the calculator wrote. Paths follow the same sandbox rules as
+/// `FileStorage::sandboxed`.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    files: Arc<Mutex<BTreeMap<PathBuf, Vec<u8>>>>,
+}
+
+impl MemoryStorage {
+    /// Create empty in-memory storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// List all stored paths in sorted order
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.lock().keys().cloned().collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<PathBuf, Vec<u8>>> {
+        // A poisoned lock only means another thread panicked mid-write;
+        // the map itself is still usable.
+        self.files.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        check_relative(path)?;
+        self.lock()
+            .get(path)
+            .cloned()
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        check_relative(path)?;
+        self.lock().insert(path.to_path_buf(), data.to_vec());
+        Ok(())
+    }
+
+    fn append(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        check_relative(path)?;
+        self.lock().entry(path.to_path_buf()).or_default().extend_from_slice(data);
+        Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.lock().contains_key(path)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        check_relative(path)?;
+        self.lock().remove(path).map(|_| ()).ok_or_else(|| not_found(path))
+    }
+}
+
+/// Create the default storage provider: the filesystem on desktop
+/// builds, memory without the `files` feature
+pub fn default_storage() -> SharedStorage {
+    #[cfg(feature = "files")]
+    return Arc::new(FileStorage::new());
+    #[cfg(not(feature = "files"))]
+    return Arc::new(MemoryStorage::new());
+}
+
+/// Reject paths that could escape a sandbox root
+fn check_relative(path: &Path) -> io::Result<()> {
+    let escapes = path.components().any(|c| {
+        matches!(c, Component::ParentDir | Component::RootDir | Component::Prefix(_))
+    });
+    if escapes || path.as_os_str().is_empty() {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("path '{}' is outside the storage sandbox", path.display()),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "files")]
+fn create_parent_dirs(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::create_dir_all(parent),
+        _ => Ok(()),
+    }
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_storage_roundtrip() {
+        let storage = MemoryStorage::new();
+        let path = Path::new("state/regs.txt");
+
+        assert!(!storage.exists(path));
+        storage.write(path, b"R00").unwrap();
+        storage.append(path, b" R01").unwrap();
+
+        assert!(storage.exists(path));
+        assert_eq!(storage.read_to_string(path).unwrap(), "R00 R01");
+        assert_eq!(storage.paths(), vec![PathBuf::from("state/regs.txt")]);
+
+        storage.remove(path).unwrap();
+        assert_eq!(storage.read(path).unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_memory_storage_clones_share_files() {
+        let storage = MemoryStorage::new();
+        let shared: SharedStorage = Arc::new(storage.clone());
+
+        shared.append(Path::new("log.txt"), b"hello").unwrap();
+        assert_eq!(storage.read(Path::new("log.txt")).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_sandbox_rejects_escaping_paths() {
+        let storage = MemoryStorage::new();
+        for path in ["../secret", "/etc/passwd", "a/../../b", ""] {
+            let err = storage.write(Path::new(path), b"x").unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::PermissionDenied, "path {:?}", path);
+        }
+
+        #[cfg(feature = "files")]
+        {
+            let files = FileStorage::sandboxed("sandbox_root");
+            let err = files.read(Path::new("../Cargo.toml")).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "files")]
+    fn test_file_storage_sandboxed_write() {
+        let root = std::env::temp_dir().join(format!("hp41c_storage_{}", std::process::id()));
+        let storage = FileStorage::sandboxed(&root);
+        let path = Path::new("cards/prog.txt");
+
+        storage.write(path, b"LBL A").unwrap();
+        storage.append(path, b"\nRTN").unwrap();
+        assert!(storage.exists(path));
+        assert_eq!(fs::read_to_string(root.join(path)).unwrap(), "LBL A\nRTN");
+
+        fs::remove_dir_all(&root).ok();
+    }
+}
@@ -0,0 +1,227 @@
+//! Surveying module: bearings, traverses and areas
+//!
+//! Angles are in H.MMSS form (degrees, minutes, seconds). An azimuth is
+//! measured clockwise from north, 0 to 360; a bearing is the angle east or
+//! west of north or south, 0 to 90, with a quadrant number: 1 NE, 2 SE,
+//! 3 SW, 4 NW.
+//!
+//! Traverses are kept in a block of registers named by the command's
+//! argument. The first register counts the points and each point takes
+//! the next two, northing then easting:
+//!
+//! ```text
+//! Rnn    number of points
+//! Rnn+1  N1     Rnn+2  E1
+//! Rnn+3  N2     Rnn+4  E2  ...
+//! ```
+//!
+//! | Command | Stack in | Effect |
+//! |---|---|---|
+//! | `AZB` | X: azimuth | X: bearing, Y: quadrant |
+//! | `BAZ` | Y: quadrant, X: bearing | X: azimuth |
+//! | `STPT nn` | Y: northing, X: easting | Appends a point to the block |
+//! | `TRAV nn` | Y: azimuth, X: distance | Appends the point that far from the last one; Y: northing, X: easting |
+//! | `CLOSE nn` | | X: misclosure back to the first point, Y: its azimuth, Z: traverse length |
+//! | `AREA nn` | | X: area enclosed by the points |
+//!
+//! The block grows by two registers per point, so a long traverse needs a
+//! correspondingly large SIZE.
+
+use crate::error::{CalculatorError, CommandError, StackError, StorageError};
+use crate::input::InputState;
+use crate::math::{deg_to_rad, hms_to_hours, hours_to_hms, rad_to_deg};
+use crate::stack::Stack;
+
+/// Bearing (0-90°) and quadrant (1-4) of an azimuth in degrees
+pub fn azimuth_to_bearing(azimuth: f64) -> (f64, u8) {
+    let azimuth = azimuth.rem_euclid(360.0);
+    match azimuth {
+        a if a <= 90.0 => (a, 1),
+        a if a <= 180.0 => (180.0 - a, 2),
+        a if a <= 270.0 => (a - 180.0, 3),
+        a => (360.0 - a, 4),
+    }
+}
+
+/// Azimuth in degrees of a bearing in a quadrant
+pub fn bearing_to_azimuth(bearing: f64, quadrant: u8) -> Result<f64, StackError> {
+    if !(0.0..=90.0).contains(&bearing) {
+        return Err(StackError::MathError("Bearing must be 0 to 90°".to_string()));
+    }
+    match quadrant {
+        1 => Ok(bearing),
+        2 => Ok(180.0 - bearing),
+        3 => Ok(180.0 + bearing),
+        4 => Ok((360.0 - bearing).rem_euclid(360.0)),
+        _ => Err(StackError::MathError("Quadrant must be 1 to 4".to_string())),
+    }
+}
+
+/// Area enclosed by a polygon of (northing, easting) points
+pub fn area(points: &[(f64, f64)]) -> f64 {
+    let twice: f64 = points.iter().zip(points.iter().cycle().skip(1))
+        .map(|(&(n1, e1), &(n2, e2))| e1 * n2 - e2 * n1)
+        .sum();
+    twice.abs() / 2.0
+}
+
+/// Distance and azimuth in degrees from one point to another
+pub fn inverse(from: (f64, f64), to: (f64, f64)) -> (f64, f64) {
+    let (dn, de) = (to.0 - from.0, to.1 - from.1);
+    (dn.hypot(de), rad_to_deg(de.atan2(dn)).rem_euclid(360.0))
+}
+
+/// A register block of traverse points
+struct PointBlock<'a> {
+    registers: &'a mut [f64],
+    base: usize,
+}
+
+impl<'a> PointBlock<'a> {
+    fn new(registers: &'a mut [f64], base: usize) -> Result<Self, CalculatorError> {
+        if base >= registers.len() {
+            return Err(StorageError::Nonexistent(base).into());
+        }
+        let block = PointBlock { registers, base };
+        block.len()?;
+        Ok(block)
+    }
+
+    fn len(&self) -> Result<usize, CalculatorError> {
+        let count = self.registers[self.base];
+        if count < 0.0 || count.fract() != 0.0 {
+            return Err(StackError::MathError(format!("R{:02} is not a point count", self.base)).into());
+        }
+        let count = count as usize;
+        let last = self.base + 2 * count;
+        if last >= self.registers.len() {
+            return Err(StorageError::Nonexistent(last).into());
+        }
+        Ok(count)
+    }
+
+    fn points(&self) -> Result<Vec<(f64, f64)>, CalculatorError> {
+        let values = &self.registers[self.base + 1..=self.base + 2 * self.len()?];
+        Ok(values.chunks(2).map(|point| (point[0], point[1])).collect())
+    }
+
+    fn push(&mut self, northing: f64, easting: f64) -> Result<(), CalculatorError> {
+        let count = self.len()?;
+        let at = self.base + 2 * count + 1;
+        if at + 1 >= self.registers.len() {
+            return Err(StorageError::Nonexistent(self.registers.len()).into());
+        }
+        self.registers[at] = northing;
+        self.registers[at + 1] = easting;
+        self.registers[self.base] = (count + 1) as f64;
+        Ok(())
+    }
+}
+
+/// Run one of the surveying commands
+pub fn execute_surveying_command(
+    command: &str,
+    args: Option<Vec<String>>,
+    stack: &mut Stack,
+    input: &mut InputState,
+    storage: &mut [f64],
+) -> Result<Option<String>, CalculatorError> {
+    let [x, y, z, t] = stack.get_registers();
+    let base = || -> Result<usize, CalculatorError> {
+        let arg = args.as_ref().and_then(|args| args.first())
+            .ok_or_else(|| CommandError::MissingArgument(command.to_uppercase()))?;
+        arg.parse().map_err(|_| CommandError::InvalidArgument {
+            command: command.to_uppercase(),
+            argument: arg.clone(),
+        }.into())
+    };
+    match command {
+        "azb" => {
+            let (bearing, quadrant) = azimuth_to_bearing(hms_to_hours(x));
+            stack.set_registers([hours_to_hms(bearing), quadrant as f64, y, z]);
+            stack.set_last_x(x);
+        }
+        "baz" => {
+            if y.fract() != 0.0 {
+                return Err(StackError::MathError("Quadrant must be 1 to 4".to_string()).into());
+            }
+            let azimuth = bearing_to_azimuth(hms_to_hours(x), y as u8)?;
+            stack.set_registers([hours_to_hms(azimuth), z, t, t]);
+            stack.set_last_x(x);
+        }
+        "stpt" => PointBlock::new(storage, base()?)?.push(y, x)?,
+        "trav" => {
+            let mut block = PointBlock::new(storage, base()?)?;
+            let &(northing, easting) = block.points()?.last()
+                .ok_or_else(|| StackError::MathError("Traverse has no starting point".to_string()))?;
+            let azimuth = deg_to_rad(hms_to_hours(y));
+            let point = (northing + x * azimuth.cos(), easting + x * azimuth.sin());
+            block.push(point.0, point.1)?;
+            stack.set_registers([point.1, point.0, z, t]);
+            stack.set_last_x(x);
+        }
+        "close" => {
+            let points = PointBlock::new(storage, base()?)?.points()?;
+            if points.len() < 2 {
+                return Err(StackError::MathError("Traverse needs two points".to_string()).into());
+            }
+            let length: f64 = points.windows(2).map(|leg| inverse(leg[0], leg[1]).0).sum();
+            let (misclosure, azimuth) = inverse(points[points.len() - 1], points[0]);
+            stack.set_registers([misclosure, hours_to_hms(azimuth), length, x]);
+        }
+        "area" => {
+            let area = area(&PointBlock::new(storage, base()?)?.points()?);
+            stack.push(area);
+        }
+        _ => unreachable!(),
+    }
+    stack.set_lift_flag(true);
+    input.clear();
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bearings() {
+        assert_eq!(azimuth_to_bearing(135.0), (45.0, 2));
+        assert_eq!(azimuth_to_bearing(350.0), (10.0, 4));
+        assert_eq!(bearing_to_azimuth(10.0, 4), Ok(350.0));
+        assert_eq!(bearing_to_azimuth(30.0, 3), Ok(210.0));
+        assert!(bearing_to_azimuth(95.0, 1).is_err());
+        assert!(bearing_to_azimuth(5.0, 5).is_err());
+    }
+
+    #[test]
+    fn test_closed_traverse() {
+        let mut stack = Stack::new();
+        let mut input = InputState::new();
+        let mut storage = vec![0.0; 20];
+        let mut run = |command: &str, y: f64, x: f64, stack: &mut Stack, storage: &mut [f64]| {
+            stack.set_registers([x, y, 0.0, 0.0]);
+            execute_surveying_command(command, Some(vec!["05".to_string()]), stack, &mut input, storage)
+        };
+
+        // A 100 x 50 rectangle, walked clockwise from the origin
+        run("stpt", 0.0, 0.0, &mut stack, &mut storage).unwrap();
+        for (azimuth, distance) in [(0.0, 100.0), (90.0, 50.0), (180.0, 100.0)] {
+            run("trav", azimuth, distance, &mut stack, &mut storage).unwrap();
+        }
+        assert_eq!(storage[5], 4.0);
+        assert!((stack.y() - 0.0).abs() < 1e-9 && (stack.x() - 50.0).abs() < 1e-9);
+
+        run("close", 0.0, 0.0, &mut stack, &mut storage).unwrap();
+        let [misclosure, azimuth, length, _] = stack.get_registers();
+        assert!((misclosure - 50.0).abs() < 1e-9 && (azimuth - 270.0).abs() < 1e-9);
+        assert!((length - 250.0).abs() < 1e-9);
+
+        run("area", 0.0, 0.0, &mut stack, &mut storage).unwrap();
+        assert!((stack.x() - 5000.0).abs() < 1e-6);
+
+        // The block can't grow past the SIZE
+        storage[5] = 7.0;
+        assert_eq!(run("stpt", 0.0, 0.0, &mut stack, &mut storage), Err(StorageError::Nonexistent(20).into()));
+    }
+}
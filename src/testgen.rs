@@ -0,0 +1,186 @@
+//! Regression tests from recorded sessions
+//!
+//! A session is a keystroke script (see `keyboard::parse_script`), for
+//! example one saved by `hp41c record`. `SessionCase::record` replays it on
+//! a fresh calculator and notes the resulting state fingerprint; the case
+//! can then be appended to a user test directory either as a Rust test
+//! (`generated_sessions.rs`) or as a golden-file entry (`sessions.toml`)
+//! checked by `verify_golden`. Once a session has been checked by hand,
+//! generating its case locks the behavior in.
+
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::calculator::HP41CCalculator;
+use crate::keyboard::{format_script, parse_script};
+use crate::storage::Storage;
+
+/// Rust test file written by `append_case`
+pub const RUST_TEST_FILE: &str = "generated_sessions.rs";
+
+/// Golden file written by `append_case`
+pub const GOLDEN_FILE: &str = "sessions.toml";
+
+/// How a case is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaseFormat {
+    /// A `#[test]` function
+    Rust,
+    /// A `[[case]]` entry in a TOML golden file
+    Golden,
+}
+
+/// A replayed session and the state it ended in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionCase {
+    pub name: String,
+    /// Keystroke script
+    pub keys: String,
+    /// `state_fingerprint()` after the replay, as 16 hex digits
+    pub fingerprint: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct GoldenFile {
+    #[serde(default)]
+    case: Vec<SessionCase>,
+}
+
+/// Feed a keystroke script to a calculator
+///
+/// Errors are part of the session (the user saw them too) and don't stop
+/// the replay. Front-end keys such as Ctrl shortcuts are skipped.
+pub fn replay(calc: &mut HP41CCalculator, script: &str) {
+    for key in parse_script(script) {
+        if let Some(input) = key.to_input() {
+            let _ = calc.process_input(&input);
+        }
+    }
+}
+
+/// Turn a name into a Rust identifier fragment: `Sin 30°` → `sin_30`
+fn sanitize(name: &str) -> String {
+    let ident: String = name.to_lowercase().chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let ident = ident.split('_').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("_");
+    if ident.is_empty() { "session".to_string() } else { ident }
+}
+
+impl SessionCase {
+    /// Replay a script on a fresh calculator and capture its fingerprint
+    pub fn record(name: &str, script: &str) -> Self {
+        let mut calc = HP41CCalculator::new();
+        replay(&mut calc, script);
+        SessionCase {
+            name: sanitize(name),
+            keys: format_script(&parse_script(script)),
+            fingerprint: format!("{:016x}", calc.state_fingerprint()),
+        }
+    }
+
+    /// Replay again and compare fingerprints
+    pub fn check(&self) -> Result<(), String> {
+        let mut calc = HP41CCalculator::new();
+        replay(&mut calc, &self.keys);
+        let actual = format!("{:016x}", calc.state_fingerprint());
+        if actual == self.fingerprint {
+            Ok(())
+        } else {
+            Err(format!("{}: expected {}, got {}", self.name, self.fingerprint, actual))
+        }
+    }
+
+    /// The case as a `#[test]` function
+    pub fn to_rust_test(&self) -> String {
+        format!(
+            "\n#[test]\nfn session_{name}() {{\n    \
+             let mut calc = hp41c::HP41CCalculator::new();\n    \
+             hp41c::testgen::replay(&mut calc, {keys:?});\n    \
+             assert_eq!(format!(\"{{:016x}}\", calc.state_fingerprint()), {fingerprint:?});\n}}\n",
+            name = self.name, keys = self.keys, fingerprint = self.fingerprint,
+        )
+    }
+
+    /// The case as a golden-file `[[case]]` entry
+    pub fn to_golden(&self) -> String {
+        let file = GoldenFile { case: vec![self.clone()] };
+        format!("\n{}", toml::to_string(&file).unwrap_or_default())
+    }
+}
+
+/// Append a case to the test file for its format inside `dir`
+///
+/// Returns the file written. A case whose name is already in the file is
+/// rejected rather than duplicated.
+pub fn append_case(storage: &dyn Storage, dir: &Path, case: &SessionCase, format: CaseFormat) -> Result<PathBuf, String> {
+    let (path, marker, text) = match format {
+        CaseFormat::Rust => (dir.join(RUST_TEST_FILE), format!("fn session_{}(", case.name), case.to_rust_test()),
+        CaseFormat::Golden => (dir.join(GOLDEN_FILE), format!("name = {:?}", case.name), case.to_golden()),
+    };
+    let existing = if storage.exists(&path) {
+        storage.read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+    } else {
+        String::new()
+    };
+    if existing.contains(&marker) {
+        return Err(format!("Case {} already exists in {}", case.name, path.display()));
+    }
+    let header = match (existing.is_empty(), format) {
+        (true, CaseFormat::Rust) => "//! Session tests generated by `hp41c gen-test`\n",
+        (true, CaseFormat::Golden) => "# Session cases generated by `hp41c gen-test --golden`\n",
+        (false, _) => "",
+    };
+    storage.append(&path, format!("{}{}", header, text).as_bytes())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+/// Check every case in a golden file, returning how many passed
+pub fn verify_golden(text: &str) -> Result<usize, Vec<String>> {
+    let file: GoldenFile = toml::from_str(text).map_err(|e| vec![format!("Invalid golden file: {}", e)])?;
+    let failures: Vec<String> = file.case.iter().filter_map(|case| case.check().err()).collect();
+    if failures.is_empty() {
+        Ok(file.case.len())
+    } else {
+        Err(failures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_generated_rust_test() {
+        let case = SessionCase::record("Add two numbers!", "2 enter 3 +");
+        assert_eq!(case.name, "add_two_numbers");
+        assert!(case.check().is_ok());
+
+        let storage = MemoryStorage::new();
+        let path = append_case(&storage, Path::new("tests"), &case, CaseFormat::Rust).unwrap();
+        assert_eq!(path, Path::new("tests/generated_sessions.rs"));
+        let text = storage.read_to_string(&path).unwrap();
+        assert!(text.starts_with("//! Session tests"));
+        assert!(text.contains("fn session_add_two_numbers() {"));
+        assert!(text.contains("replay(&mut calc, \"2 enter 3+\");"));
+        assert!(text.contains(&format!("{:?}", case.fingerprint)));
+        assert!(append_case(&storage, Path::new("tests"), &case, CaseFormat::Rust).is_err());
+    }
+
+    #[test]
+    fn test_golden_file_round_trip() {
+        let storage = MemoryStorage::new();
+        for (name, keys) in [("sum", "2 enter 3 +"), ("root", "9 sqrt")] {
+            let case = SessionCase::record(name, keys);
+            append_case(&storage, Path::new("golden"), &case, CaseFormat::Golden).unwrap();
+        }
+        let text = storage.read_to_string(Path::new("golden/sessions.toml")).unwrap();
+        assert_eq!(verify_golden(&text), Ok(2));
+
+        let tampered = text.replace("9sqrt", "16sqrt");
+        let failures = verify_golden(&tampered).unwrap_err();
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].starts_with("root: expected"));
+    }
+}
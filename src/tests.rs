@@ -1,6 +1,7 @@
 use crate::*;
 
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
     use super::*;
 
@@ -89,6 +90,221 @@ mod tests {
         assert_eq!(calc.test_get_stack()[0], 42.0);
     }
 
+    #[test]
+    fn test_register_arithmetic() {
+        let (mut calc, messages) = process_keys(&["8", "s", "t", "o", "0", "5", "3", "s", "t", "o", "+", "0", "5"]);
+        assert_eq!(messages, vec!["STO 05", "STO+ 05"]);
+        assert_eq!(calc.test_get_storage(5), Some(11.0));
+        
+        calc.run_command_line("2").unwrap();
+        calc.run_command_line("STO* 05").unwrap();
+        calc.run_command_line("STO- 05").unwrap();
+        assert_eq!(calc.test_get_storage(5), Some(20.0));
+        assert_eq!(calc.test_get_stack()[0], 2.0);
+        
+        calc.run_command_line("0").unwrap();
+        let err = calc.run_command_line("STO/ 05").unwrap_err();
+        assert!(err.contains("Division by zero"), "{}", err);
+        calc.run_command_line("1E300").unwrap();
+        calc.run_command_line("STO 06").unwrap();
+        let err = calc.run_command_line("STO* 06").unwrap_err();
+        assert!(err.contains("Overflow"), "{}", err);
+        assert_eq!(calc.test_get_storage(5), Some(20.0));
+    }
+    
+    #[test]
+    fn test_register_arithmetic_preview() {
+        let (mut calc, _) = process_keys(&["3", "s", "t", "o", "0", "5", "5", "s", "t", "o", "+"]);
+        assert!(calc.get_display().contains("CMD: [sto+] FIX"));
+        calc.process_input("0").unwrap();
+        assert!(calc.get_display().contains("CMD: [sto+ 0_] R00→5.0000"));
+        calc.process_input("5").unwrap();
+        assert_eq!(calc.test_get_storage(5), Some(8.0));
+        assert!(!calc.get_display().contains('→'));
+        
+        for key in ["r", "c", "l", "/", "1"] {
+            calc.process_input(key).unwrap();
+        }
+        assert!(calc.get_display().contains("CMD: [rcl/ 1_] X→ERR"));
+    }
+    
+    #[test]
+    fn test_recall_arithmetic() {
+        let (mut calc, messages) = process_keys(&["4", "s", "t", "o", "0", "2", "1", "0", "r", "c", "l", "-", "0", "2"]);
+        assert_eq!(messages, vec!["STO 02", "RCL- 02"]);
+        assert_eq!(calc.test_get_stack(), [6.0, 4.0, 0.0, 0.0]);
+        
+        calc.run_command_line("RCL/ 02").unwrap();
+        assert_eq!(calc.test_get_stack(), [1.5, 4.0, 0.0, 0.0]);
+        calc.execute_command("lastx", None).unwrap();
+        assert_eq!(calc.test_get_stack()[0], 6.0);
+        
+        let err = calc.run_command_line("RCL/ 03").unwrap_err();
+        assert!(err.contains("Division by zero"), "{}", err);
+        assert_eq!(calc.test_get_stack()[0], 6.0);
+    }
+    
+    #[test]
+    fn test_statistics() {
+        let mut calc = HP41CCalculator::new();
+        for (x, y) in [(2.0, 1.0), (4.0, 3.0), (6.0, 5.0)] {
+            calc.run_command_line(&y.to_string()).unwrap();
+            calc.run_command_line(&x.to_string()).unwrap();
+            calc.run_command_line("Σ+").unwrap();
+        }
+        assert_eq!(calc.test_get_stack()[0], 3.0);
+        assert_eq!(calc.test_get_storage(11), Some(12.0));
+        assert_eq!(calc.test_get_storage(16), Some(3.0));
+        
+        // Typed as S-: the last pair is keyed in again and removed
+        calc.run_command_line("5").unwrap();
+        calc.run_command_line("6").unwrap();
+        calc.process_input("s").unwrap();
+        calc.process_input("-").unwrap();
+        assert_eq!(calc.test_get_storage(16), Some(2.0));
+        assert_eq!(calc.test_get_storage(11), Some(6.0));
+        calc.execute_command("lastx", None).unwrap();
+        calc.execute_command("s+", None).unwrap();
+        
+        calc.test_set_x_register(7.0);
+        calc.execute_command("mean", None).unwrap();
+        assert_eq!(calc.test_get_stack(), [4.0, 3.0, 7.0, 5.0]);
+        calc.execute_command("sdev", None).unwrap();
+        assert_eq!(&calc.test_get_stack()[..2], [2.0, 2.0]);
+        
+        calc.execute_command("clσ", None).unwrap();
+        assert_eq!(calc.test_get_storage(11), Some(0.0));
+        assert!(calc.execute_command("mean", None).is_err());
+    }
+    
+    #[test]
+    fn test_clear_statistics_by_its_sigma_name() {
+        // Lowercasing CLΣ gives a final ς, which is still CLΣ
+        let mut calc = HP41CCalculator::new();
+        calc.run_command_line("4").unwrap();
+        calc.execute_command("Σ+", None).unwrap();
+        calc.execute_command("CLΣ", None).unwrap();
+        assert_eq!(calc.test_get_storage(11), Some(0.0));
+        
+        calc.load_listing("LBL \"ST\"\nΣ+\nCLΣ\nclσ\nRTN").unwrap();
+        assert_eq!(calc.list_program(..)[2], "03 CLΣ");
+        let raw = calc.export_raw().unwrap();
+        assert!(raw.contains(&0x70));
+        let mut other = HP41CCalculator::new();
+        assert_eq!(other.import_raw(&raw), Ok(Some("Imported 5 steps".to_string())));
+        assert_eq!(other.export_focal(), calc.export_focal());
+        other.run_command_line("XEQ ST").unwrap();
+        assert_eq!(other.test_get_storage(11), Some(0.0));
+    }
+    
+    #[test]
+    fn test_number_part_functions() {
+        let (calc, _) = process_keys(&["7", "enter", "3", "chs", "m", "o", "d"]);
+        assert_eq!(calc.test_get_stack()[0], -2.0);
+        assert_eq!(calc.snapshot().last_x, -3.0);
+        
+        let mut calc = HP41CCalculator::new();
+        calc.run_command_line("-2.75").unwrap();
+        calc.run_command_line("FRC").unwrap();
+        assert_eq!(calc.test_get_stack()[0], -0.75);
+        calc.run_command_line("SIGN").unwrap();
+        calc.run_command_line("ABS").unwrap();
+        assert_eq!(calc.test_get_stack()[0], 1.0);
+    }
+    
+    #[test]
+    fn test_square_keeps_last_x() {
+        let (calc, _) = process_keys(&["1", "2", "x", "2"]);
+        assert_eq!(calc.test_get_stack()[0], 144.0);
+        assert_eq!(calc.snapshot().last_x, 12.0);
+        
+        let mut calc = HP41CCalculator::new();
+        calc.run_command_line("2").unwrap();
+        calc.run_command_line("10X").unwrap();
+        assert_eq!(calc.test_get_stack()[0], 100.0);
+    }
+    
+    #[test]
+    fn test_rnd_follows_display_mode() {
+        let mut calc = HP41CCalculator::new();
+        calc.run_command_line("2").unwrap();
+        calc.run_command_line("3").unwrap();
+        calc.run_command_line("/").unwrap();
+        calc.run_command_line("RND").unwrap();
+        assert_eq!(calc.test_get_stack()[0], 0.6667);
+        
+        calc.run_command_line("12345.678").unwrap();
+        calc.run_command_line("SCI 2").unwrap();
+        calc.run_command_line("RND").unwrap();
+        assert_eq!(calc.test_get_stack()[0], 12300.0);
+        calc.run_command_line("FIX 0").unwrap();
+        calc.run_command_line("-2.5").unwrap();
+        calc.run_command_line("RND").unwrap();
+        assert_eq!(calc.test_get_stack()[0], -3.0);
+        
+        calc.run_command_line("FIX 2").unwrap();
+        calc.run_command_line("1.005").unwrap();
+        calc.run_command_line("RND").unwrap();
+        assert_eq!(calc.test_get_stack()[0], 1.01);
+    }
+    
+    #[test]
+    fn test_assert_only_in_debug_mode() {
+        let mut calc = HP41CCalculator::new();
+        calc.run_command_line("-1").unwrap();
+        assert_eq!(calc.run_command_line("ASSERT X>0"), Ok(None));
+        
+        calc.set_debug_mode(true);
+        assert_eq!(calc.run_command_line("ASSERT X > 0"),
+                   Err("Programming error: Assertion failed: X>0 (X=-1)".to_string()));
+        assert_eq!(calc.run_command_line("ASSERT X<Y"), Ok(None));
+        assert!(calc.run_command_line("ASSERT X").is_err());
+    }
+    
+    #[test]
+    fn test_exchange_x_with_register() {
+        let (mut calc, _) = process_keys(&["7", "s", "t", "o", "0", "5", "2", "x", "<", ">", "0", "5"]);
+        assert_eq!(calc.test_get_stack()[0], 7.0);
+        assert_eq!(calc.test_get_storage(5), Some(2.0));
+        
+        calc.run_command_line("PROTECT 05").unwrap();
+        assert!(calc.run_command_line("X<> 05").is_err());
+        assert_eq!(calc.test_get_storage(5), Some(2.0));
+    }
+    
+    #[test]
+    fn test_percent_keys() {
+        let (calc, _) = process_keys(&["2", "0", "0", "enter", "1", "5", "%", " "]);
+        assert_eq!(&calc.test_get_stack()[..2], [30.0, 200.0]);
+        
+        let (calc, _) = process_keys(&["4", "0", "enter", "5", "0", "%", "c", "h"]);
+        assert_eq!(&calc.test_get_stack()[..2], [25.0, 40.0]);
+    }
+    
+    #[test]
+    fn test_sigma_reg() {
+        let mut calc = HP41CCalculator::new();
+        for key in ["s", "r", "e", "g", "0", "2"] {
+            calc.process_input(key).unwrap();
+        }
+        assert_eq!(calc.sigma_reg(), 2);
+        calc.run_command_line("3").unwrap();
+        calc.run_command_line("Σ+").unwrap();
+        assert_eq!(calc.test_get_storage(2), Some(3.0));
+        assert_eq!(calc.test_get_storage(7), Some(1.0));
+        assert_eq!(calc.test_get_storage(16), Some(0.0));
+        
+        // The block has to fit below the SIZE
+        let size = calc.snapshot().registers.len();
+        assert!(calc.run_command_line(&format!("ΣREG {:02}", size - 5)).is_err());
+        assert_eq!(calc.sigma_reg(), 2);
+        calc.run_command_line(&format!("ΣREG {:02}", size - 6)).unwrap();
+        
+        let mut restored = HP41CCalculator::new();
+        restored.restore(&calc.snapshot());
+        assert_eq!(restored.sigma_reg(), size - 6);
+    }
+    
     #[test]
     fn test_display_modes() {
         // Test FIX mode (NEW: no space needed, auto-executes)
@@ -185,10 +401,11 @@ mod tests {
         assert_eq!(stack[0], 0.0);  // X should be 0
         assert_eq!(stack[1], 2.0);  // Y should still be 2
         
-        // Test CLR (clear all)
+        // Test CLR (clear all); it waits for space because CLRG starts the same way
         calc.process_input("c").unwrap();
         calc.process_input("l").unwrap();
         calc.process_input("r").unwrap();
+        calc.process_input(" ").unwrap();
         
         let stack = calc.test_get_stack();
         assert_eq!(stack[0], 0.0);
@@ -217,6 +434,19 @@ mod tests {
         assert_eq!(stack[1], 3.0);  // Y should now be 3
     }
 
+    #[test]
+    fn test_roll_commands() {
+        let (calc, _) = process_keys(&["1", "enter", "2", "enter", "3", "enter", "4", "r", "d", "n"]);
+        assert_eq!(calc.test_get_stack(), [3.0, 2.0, 1.0, 4.0]);
+        
+        let (calc, _) = process_keys(&["1", "enter", "2", "enter", "3", "enter", "4", "r", "^"]);
+        assert_eq!(calc.test_get_stack(), [1.0, 4.0, 3.0, 2.0]);
+        
+        // Rolling terminates number entry and enables lift
+        let (calc, _) = process_keys(&["5", "rdn", "7"]);
+        assert_eq!(calc.test_get_stack(), [7.0, 0.0, 0.0, 0.0]);
+    }
+    
     #[test]
     fn test_change_sign() {
         let mut calc = HP41CCalculator::new();
@@ -230,6 +460,60 @@ mod tests {
         assert_eq!(stack[0], -5.0);
     }
 
+    #[test]
+    fn test_lastx() {
+        let (calc, _) = process_keys(&["5", "enter", "3", "*", "lastx"]);
+        assert_eq!(calc.test_get_stack()[0], 3.0);
+        assert_eq!(calc.test_get_stack()[1], 15.0);
+        
+        // One-argument functions save X as well
+        let (calc, _) = process_keys(&["9", "sqrt", "lastx", "+"]);
+        assert_eq!(calc.test_get_stack()[0], 12.0);
+    }
+    
+    #[test]
+    fn test_deep_stack_mode() {
+        let mut calc = HP41CCalculator::new();
+        let config = crate::Config::from_toml("stack_depth = \"8\"").unwrap();
+        assert!(calc.apply_config(&config).is_empty());
+        assert_eq!(calc.stack_depth(), crate::stack::StackDepth::Fixed(8));
+        
+        for value in ["1", "2", "3", "4", "5", "6"] {
+            calc.run_command_line(value).unwrap();
+        }
+        calc.execute_command("*", None).unwrap();
+        assert_eq!(calc.test_get_stack(), [30.0, 4.0, 3.0, 2.0]);
+        
+        let display = calc.get_display();
+        assert!(display.contains("5: "));
+        assert!(display.contains("STK:8"));
+        
+        // Levels above T survive a save/restore cycle
+        let mut restored = HP41CCalculator::new();
+        restored.restore(&calc.snapshot());
+        restored.execute_command("+", None).unwrap();
+        restored.execute_command("+", None).unwrap();
+        restored.execute_command("+", None).unwrap();
+        assert_eq!(restored.test_get_stack(), [39.0, 1.0, 0.0, 0.0]);
+    }
+    
+    #[test]
+    fn test_copy_to_clipboard() {
+        let mut calc = HP41CCalculator::new();
+        assert!(calc.copy_to_clipboard(CopyTarget::X).is_err());
+        
+        let clipboard = MemoryClipboard::new();
+        let mut calc = calc.with_clipboard(Box::new(clipboard.clone()));
+        calc.run_command_line("2").unwrap();
+        calc.execute_command("pi", None).unwrap();
+        
+        assert_eq!(calc.copy_to_clipboard(CopyTarget::X).unwrap(), Some("Copied 3.141592654".to_string()));
+        assert_eq!(clipboard.text().as_deref(), Some("3.141592654"));
+        
+        calc.copy_to_clipboard(CopyTarget::Stack).unwrap();
+        assert_eq!(clipboard.text().as_deref(), Some("T: 0\nZ: 0\nY: 2\nX: 3.141592654"));
+    }
+    
     #[test]
     fn test_constants() {
         let mut calc = HP41CCalculator::new();
@@ -311,6 +595,1670 @@ mod tests {
         let stack = calc.test_get_stack();
         assert!((stack[0] - 5.0_f64.sin()).abs() < 1e-10);
     }
+
+    #[test]
+    fn test_file_logging_uses_storage_provider() {
+        let storage = MemoryStorage::new();
+        let mut calc = HP41CCalculator::new().with_storage(std::sync::Arc::new(storage.clone()));
+        
+        calc.configure_logger("all");
+        calc.enable_file_logging("memory_only.log").unwrap();
+        calc.process_input("7").unwrap();
+        calc.disable_file_logging().unwrap();
+        
+        let log = storage.read_to_string(std::path::Path::new("memory_only.log")).unwrap();
+        assert!(log.contains("[INPUT] Key: '7'"));
+        assert!(!std::path::Path::new("memory_only.log").exists());
+    }
+
+    #[test]
+    fn test_localized_help_and_errors() {
+        let mut calc = HP41CCalculator::new();
+        assert_eq!(calc.describe_command("sqrt"), Some("SQRT: SQRT function".to_string()));
+        
+        calc.set_locale(crate::i18n::Locale::German);
+        assert_eq!(calc.describe_command("sqrt"), Some("SQRT: Quadratwurzel".to_string()));
+        
+        calc.process_input("0").unwrap();
+        calc.process_input("enter").unwrap();
+        let err = calc.process_input("/").unwrap_err();
+        assert_eq!(err, "Stapelfehler: Division durch Null");
+    }
+
+    #[test]
+    fn test_xref_command() {
+        let mut calc = HP41CCalculator::new();
+        calc.process_input(":").unwrap();
+        calc.test_add_program_instruction("lbl", Some(vec!["a".to_string()]));
+        calc.test_add_program_instruction("xeq", Some(vec!["b".to_string()]));
+        calc.test_add_program_instruction("gto", Some(vec!["a".to_string()]));
+        calc.process_input(":").unwrap();
+        
+        let xref = calc.cross_reference();
+        assert_eq!(xref.undefined()[0].label, "B");
+        
+        for key in ["x", "r", "e"] {
+            calc.process_input(key).unwrap();
+        }
+        let report = calc.process_input("f").unwrap().unwrap();
+        assert_eq!(report, "LBL A @01 <- GTO 03\nLBL B @?? <- XEQ 02 (undefined)");
+    }
+    
+    #[test]
+    fn test_list_program() {
+        let mut calc = HP41CCalculator::new();
+        assert!(calc.process_command_string("list").is_err());
+
+        let listing = "LBL \"AREA\"\nX2\nSTO IND 12\n\"R=\"\nGTO a\nEND\nLBL 01\nRTN";
+        calc.load_listing(listing).unwrap();
+        assert_eq!(calc.list_program(..), [
+            "01 LBL \"AREA\"", "02 X2", "03 STO IND 12", "04 \"R=\"", "05 GTO a", "06 END",
+            "07 LBL 01", "08 RTN", "09 .END.",
+        ]);
+        assert_eq!(calc.list_program(2..4), ["02 X2", "03 STO IND 12"]);
+        assert_eq!(calc.list_program(8..=20), ["08 RTN", "09 .END."]);
+        assert!(calc.list_program(10..).is_empty());
+
+        // The listing reads back as the same program
+        let report = calc.process_command_string("list").unwrap().unwrap();
+        let steps = crate::listing::parse_listing(&report).unwrap();
+        assert_eq!(steps, crate::listing::parse_listing(listing).unwrap());
+    }
+
+    #[test]
+    fn test_lint_command() {
+        let mut calc = HP41CCalculator::new();
+        assert!(calc.process_command_string("lint").is_err());
+        
+        calc.process_input(":").unwrap();
+        calc.test_add_program_instruction("lbl", Some(vec!["a".to_string()]));
+        calc.test_add_program_instruction("rtn", None);
+        calc.test_add_program_instruction("sin", None);
+        calc.test_add_program_instruction("lbl", Some(vec!["a".to_string()]));
+        calc.process_input(":").unwrap();
+        
+        assert_eq!(calc.lint_program().len(), 2);
+        let report = calc.process_command_string("lint").unwrap().unwrap();
+        assert_eq!(report, "03-04: unreachable\n04: duplicate LBL A (first at 01)");
+    }
+    
+    #[test]
+    fn test_state_persists_interrupted_run() {
+        let storage = std::sync::Arc::new(MemoryStorage::new());
+        let mut calc = HP41CCalculator::new().with_storage(storage.clone());
+        calc.process_input(":").unwrap();
+        calc.test_add_program_instruction("lbl", Some(vec!["a".to_string()]));
+        calc.test_add_program_instruction("xeq", Some(vec!["b".to_string()]));
+        calc.test_add_program_instruction("rtn", None);
+        calc.test_add_program_instruction("lbl", Some(vec!["b".to_string()]));
+        calc.test_add_program_instruction("cos", None);
+        calc.process_input(":").unwrap();
+        calc.process_input("3").unwrap();
+        calc.execute_command("sto", Some(vec!["07".to_string()])).unwrap();
+        calc.execute_command("fix", Some(vec!["2".to_string()])).unwrap();
+        calc.execute_command("xeq", Some(vec!["b".to_string()])).unwrap();
+        
+        let saved = calc.snapshot();
+        assert!(saved.execution.interrupted);
+        calc.save_state("state.json").unwrap();
+        
+        let mut resumed = HP41CCalculator::new().with_storage(storage);
+        let msg = resumed.load_state("state.json").unwrap().unwrap();
+        assert_eq!(msg, "State loaded, program halted at 04 LBL b");
+        assert_eq!(resumed.test_get_storage(7), Some(3.0));
+        assert_eq!(resumed.test_get_display_digits(), 2);
+        assert_eq!(resumed.test_get_program_length(), 5);
+        assert_eq!(resumed.snapshot().execution.return_stack, saved.execution.return_stack);
+        assert!(resumed.load_state("missing.json").is_err());
+    }
+    
+    #[test]
+    fn test_compressed_state_file() {
+        let storage = std::sync::Arc::new(MemoryStorage::new());
+        let mut calc = HP41CCalculator::new().with_storage(storage.clone());
+        calc.process_input("4").unwrap();
+        calc.execute_command("sto", Some(vec!["12".to_string()])).unwrap();
+        calc.set_container_options(ContainerOptions { compress: true, passphrase: None });
+        calc.save_state("state.json").unwrap();
+        assert!(crate::container::is_container(&storage.read(std::path::Path::new("state.json")).unwrap()));
+        
+        // Loading doesn't need to know the file is compressed
+        let mut resumed = HP41CCalculator::new().with_storage(storage);
+        resumed.load_state("state.json").unwrap();
+        assert_eq!(resumed.test_get_storage(12), Some(4.0));
+    }
+    
+    #[test]
+    fn test_watched_listing_reloads() {
+        let storage = std::sync::Arc::new(MemoryStorage::new());
+        let mut calc = HP41CCalculator::new().with_storage(storage.clone());
+        calc.run_command_line("7").unwrap();
+        calc.run_command_line("STO 01").unwrap();
+        calc.watch_listing("span.txt");
+        assert_eq!(calc.check_listing_watch(), None);
+        
+        storage.write(std::path::Path::new("span.txt"), b"01 LBL \"SPAN\"\n02 RCL 01\n03 RTN\n").unwrap();
+        assert_eq!(calc.check_listing_watch(), Some(Ok(Some("span.txt: Loaded 3 steps".to_string()))));
+        assert_eq!(calc.check_listing_watch(), None);
+        assert_eq!(calc.test_get_program_length(), 3);
+        assert_eq!(calc.test_get_storage(1), Some(7.0));
+        
+        // A bad edit keeps the program that was loaded
+        storage.write(std::path::Path::new("span.txt"), b"LBL \"SPAN\"\nFROB\n").unwrap();
+        assert!(matches!(calc.check_listing_watch(), Some(Err(e)) if e.contains("Step 02")));
+        assert_eq!(calc.test_get_program_length(), 3);
+        
+        // One that loads but would fail is reported under the message
+        storage.write(std::path::Path::new("span.txt"), b"LBL \"SPAN\"\nGTO 02\n").unwrap();
+        let message = calc.check_listing_watch().unwrap().unwrap().unwrap();
+        assert_eq!(message.lines().collect::<Vec<_>>(), ["span.txt: Loaded 2 steps", "02: Programming error: Label 02 not found"]);
+    }
+    
+    #[test]
+    fn test_state_fingerprint() {
+        let mut a = HP41CCalculator::new();
+        let mut b = HP41CCalculator::new();
+        assert_eq!(a.state_fingerprint(), b.state_fingerprint());
+        
+        for key in ["2", "enter", "3", "*"] {
+            a.process_input(key).unwrap();
+        }
+        for key in ["3", "enter", "2", "*"] {
+            b.process_input(key).unwrap();
+        }
+        assert_ne!(a.state_fingerprint(), HP41CCalculator::new().state_fingerprint());
+        assert_eq!(a.state_fingerprint(), b.state_fingerprint());
+        
+        b.execute_command("sto", Some(vec!["01".to_string()])).unwrap();
+        assert_ne!(a.state_fingerprint(), b.state_fingerprint());
+        
+        a.restore(&b.snapshot());
+        assert_eq!(a.state_fingerprint(), b.state_fingerprint());
+    }
+    
+    #[test]
+    fn test_register_guard_undoes_protected_writes() {
+        let mut calc = HP41CCalculator::new();
+        calc.process_input(":").unwrap();
+        calc.test_add_program_instruction("lbl", Some(vec!["a".to_string()]));
+        calc.test_add_program_instruction("rtn", None);
+        calc.process_input(":").unwrap();
+        
+        calc.test_set_x_register(1.0);
+        calc.execute_command("sto", Some(vec!["05".to_string()])).unwrap();
+        calc.set_register_guard(Some(20..=29));
+        
+        // Simulated run: XEQ starts it, the final RTN ends it
+        calc.execute_command("xeq", Some(vec!["a".to_string()])).unwrap();
+        calc.test_set_x_register(9.0);
+        calc.execute_command("sto", Some(vec!["05".to_string()])).unwrap();
+        calc.execute_command("sto", Some(vec!["25".to_string()])).unwrap();
+        assert_eq!(calc.register_guard().unwrap().written().collect::<Vec<_>>(), vec![5, 25]);
+        
+        let msg = calc.execute_command("rtn", None).unwrap();
+        assert_eq!(msg, Some("Guard restored R05".to_string()));
+        assert_eq!(calc.test_get_storage(5), Some(1.0));
+        assert_eq!(calc.test_get_storage(25), Some(9.0));
+        
+        // Writes from the keyboard are never guarded
+        calc.execute_command("sto", Some(vec!["05".to_string()])).unwrap();
+        assert_eq!(calc.test_get_storage(5), Some(9.0));
+    }
+    
+    #[test]
+    fn test_register_guard_restores_after_halts() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL \"BAD\"\n9\nSTO 15\n0\n/\nRTN\nLBL \"LOOP\"\nLBL 01\n1\nSTO+ 16\nGTO 01").unwrap();
+        calc.set_register_guard(Some(0..=9));
+        
+        // A run halted by an error
+        let error = calc.run_command_line("XEQ BAD").unwrap_err();
+        assert!(error.ends_with("(Guard restored R15)"), "{}", error);
+        assert_eq!(calc.test_get_storage(15), Some(0.0));
+        
+        // A run halted by the step limit
+        calc.set_step_limit(Some(20));
+        assert!(calc.run_command_line("XEQ LOOP").is_err());
+        assert_eq!(calc.test_get_storage(16), Some(0.0));
+        assert_eq!(calc.register_guard().unwrap().written().count(), 0);
+    }
+    
+    #[test]
+    fn test_flags() {
+        let (mut calc, messages) = process_keys(&["s", "f", "0", "5", "f", "s", "?", "0", "5",
+                                                  "f", "c", "?", "c", "0", "5", "f", "s", "?", "0", "5"]);
+        assert_eq!(messages, vec!["YES", "NO", "NO"]);
+        assert!(!calc.flags().is_set(5));
+        
+        // Flags 0-4 light their indicators
+        calc.execute_command("sf", Some(vec!["03".to_string()])).unwrap();
+        let annunciators = calc.lcd_frame().annunciators;
+        assert!(annunciators.contains(Annunciators::FLAG_3));
+        assert!(!annunciators.contains(Annunciators::FLAG_0) && !annunciators.contains(Annunciators::FLAG_4));
+        calc.execute_command("cf", Some(vec!["03".to_string()])).unwrap();
+        
+        // System flags can be tested but not set
+        assert!(calc.execute_command("sf", Some(vec!["30".to_string()])).unwrap_err().contains("SF 30"));
+        assert_eq!(calc.execute_command("fs?", Some(vec!["26".to_string()])).unwrap(), Some("YES".to_string()));
+        assert!(calc.execute_command("fs?", Some(vec!["56".to_string()])).is_err());
+        
+        let mut restored = HP41CCalculator::new();
+        calc.execute_command("sf", Some(vec!["29".to_string()])).unwrap();
+        calc.execute_command("cf", Some(vec!["26".to_string()])).unwrap();
+        restored.restore(&calc.snapshot());
+        assert_eq!(restored.flags().to_string(), "28 29");
+        assert_ne!(restored.state_fingerprint(), HP41CCalculator::new().state_fingerprint());
+    }
+    
+    #[test]
+    fn test_flag_test_skips_in_program() {
+        let mut calc = HP41CCalculator::new();
+        calc.process_input(":").unwrap();
+        calc.test_add_program_instruction("lbl", Some(vec!["a".to_string()]));
+        calc.test_add_program_instruction("fs?", Some(vec!["01".to_string()]));
+        calc.test_add_program_instruction("sin", None);
+        calc.test_add_program_instruction("rtn", None);
+        calc.process_input(":").unwrap();
+        
+        // Simulated run: a true test falls through, a false one skips a step
+        calc.execute_command("xeq", Some(vec!["a".to_string()])).unwrap();
+        let pc = calc.test_get_program_counter();
+        calc.execute_command("sf", Some(vec!["01".to_string()])).unwrap();
+        assert_eq!(calc.execute_command("fs?", Some(vec!["01".to_string()])).unwrap(), None);
+        assert_eq!(calc.test_get_program_counter(), pc);
+        calc.execute_command("fs?c", Some(vec!["01".to_string()])).unwrap();
+        assert_eq!(calc.test_get_program_counter(), pc);
+        calc.execute_command("fs?", Some(vec!["01".to_string()])).unwrap();
+        assert_eq!(calc.test_get_program_counter(), pc + 1);
+    }
+    
+    #[test]
+    fn test_confirm_destructive_commands() {
+        let mut calc = HP41CCalculator::new();
+        calc.set_confirmation(ConfirmCategory::Registers, true);
+        calc.test_set_x_register(7.0);
+        calc.execute_command("sto", Some(vec!["05".to_string()])).unwrap();
+        
+        let clrg = |calc: &mut HP41CCalculator| {
+            ["c", "l", "r", "g"].iter().filter_map(|key| calc.process_input(key).unwrap()).collect::<Vec<_>>()
+        };
+        assert_eq!(clrg(&mut calc), vec!["CLRG: press again to confirm"]);
+        assert_eq!(calc.test_get_storage(5), Some(7.0));
+        
+        // A number in between cancels
+        calc.process_input("1").unwrap();
+        assert_eq!(clrg(&mut calc).len(), 1);
+        assert_eq!(clrg(&mut calc), Vec::<String>::new());
+        assert_eq!(calc.test_get_storage(5), Some(0.0));
+        
+        // Categories without confirmation run at once; programs never ask
+        calc.process_input(":").unwrap();
+        calc.test_add_program_instruction("lbl", Some(vec!["a".to_string()]));
+        calc.process_input(":").unwrap();
+        for key in ["p", "r", "g", "m"] {
+            calc.process_input(key).unwrap();
+        }
+        assert_eq!(calc.test_get_program_length(), 0);
+        assert!(calc.execute_command("clrg", None).is_ok());
+        
+        // RESET asks before MEMORY LOST
+        calc.set_confirmation(ConfirmCategory::Reset, true);
+        calc.run_command_line("SIZE 030").unwrap();
+        let reset = |calc: &mut HP41CCalculator| {
+            ["r", "e", "s", "e", "t"].iter().filter_map(|key| calc.process_input(key).unwrap()).collect::<Vec<_>>()
+        };
+        assert_eq!(reset(&mut calc), vec!["RESET: press again to confirm"]);
+        assert_eq!(calc.size(), 30);
+        reset(&mut calc);
+        assert_eq!(calc.size(), Model::default().default_size());
+        assert_eq!(calc.overlay(), Some("MEMORY LOST"));
+    }
+    
+    #[test]
+    fn test_protected_registers() {
+        let mut calc = HP41CCalculator::new();
+        calc.test_set_x_register(2.5);
+        calc.execute_command("sto", Some(vec!["12".to_string()])).unwrap();
+        assert_eq!(calc.run_command_line("PROTECT 10 19").unwrap(), Some("Protected R10-R19".to_string()));
+        
+        // Even storing the same value is refused, from keys or programs
+        let messages: Vec<String> = ["s", "t", "o", "1", "2"].iter().filter_map(|key| calc.process_input(key).err()).collect();
+        assert_eq!(messages, vec!["Storage error: Protected register: 12"]);
+        assert!(calc.execute_command("sto+", Some(vec!["15".to_string()])).is_err());
+        assert!(calc.execute_command("sto", Some(vec!["20".to_string()])).is_ok());
+        
+        // CLRG leaves every register alone when it would touch a protected one
+        assert!(calc.execute_command("clrg", None).is_err());
+        assert_eq!(calc.test_get_storage(20), Some(2.5));
+        assert_eq!(calc.test_get_storage(12), Some(2.5));
+        
+        let mut restored = HP41CCalculator::new();
+        restored.restore(&calc.snapshot());
+        assert_eq!(restored.protection().to_string(), "R10-R19");
+        
+        calc.run_command_line("UNPROTECT 12").unwrap();
+        assert!(calc.execute_command("sto", Some(vec!["12".to_string()])).is_ok());
+        assert_eq!(calc.execute_command("protect", None).unwrap(), Some("Protected R10-R11 R13-R19".to_string()));
+        calc.execute_command("unprotect", None).unwrap();
+        assert!(calc.protection().is_empty());
+        assert!(calc.run_command_line("PROTECT 19 10").is_err());
+    }
+    
+    #[test]
+    fn test_startup_commands_from_config() {
+        let config = crate::config::Config::from_toml(r#"
+            locale = "de"
+            startup = ["FIX 2", "1.5", "STO 03", "2", "*", "BOGUS 1", "FIX 12"]
+        "#).unwrap();
+        
+        let mut calc = HP41CCalculator::new();
+        let errors = calc.apply_config(&config);
+        
+        assert_eq!(calc.locale(), crate::i18n::Locale::German);
+        assert_eq!(calc.test_get_display_digits(), 2);
+        assert_eq!(calc.test_get_storage(3), Some(1.5));
+        assert_eq!(calc.test_get_stack()[0], 3.0);
+        assert_eq!(errors, vec![
+            "BOGUS 1: Befehlsfehler: Unbekannter Befehl: BOGUS",
+            "FIX 12: Befehlsfehler: Ungültiges Argument '12' für FIX",
+        ]);
+    }
+    
+    #[test]
+    fn test_assignment_and_alarm_catalogs() {
+        let mut calc = HP41CCalculator::new();
+        assert_eq!(calc.run_command_line("ASN SQRT -24").unwrap(), Some("ASN SQRT -24".to_string()));
+        calc.run_command_line("ASN SIN 11").unwrap();
+        assert!(calc.run_command_line("ASN COS 99").is_err());
+        
+        let mut cat6 = calc.catalog(6).unwrap();
+        assert_eq!(cat6.entries(), ["SQRT -24", "SIN 11"]);
+        assert!(cat6.advance());
+        assert_eq!(cat6.to_string(), "CAT 6 2/2: SIN 11");
+        
+        calc.add_alarm(crate::catalog::Alarm { due: 7200, message: "B".to_string(), repeat: None });
+        calc.add_alarm(crate::catalog::Alarm { due: 3600, message: "A".to_string(), repeat: None });
+        let messages: Vec<&str> = calc.alarms().map(|a| a.message.as_str()).collect();
+        assert_eq!(messages, vec!["A", "B"]);
+        assert_eq!(calc.catalog(5).unwrap().current(), Some("1970-01-01 01:00:00 A"));
+        assert!(calc.catalog(9).is_err());
+        
+        // Both survive a save/restore cycle
+        let mut restored = HP41CCalculator::new();
+        restored.restore(&calc.snapshot());
+        assert_eq!(restored.key_assignments().count(), 2);
+        assert_eq!(restored.alarms().count(), 2);
+        
+        calc.run_command_line("ASN 11").unwrap();
+        assert_eq!(calc.key_assignments().count(), 1);
+    }
+    
+    #[test]
+    fn test_model_selects_size_and_modules() {
+        let mut calc = HP41CCalculator::new();
+        assert_eq!(calc.model(), Model::HP41CX);
+        assert!(calc.has_module(Module::Time));
+        assert!(calc.catalog(5).is_ok());
+        
+        calc.set_model(Model::HP41C);
+        assert_eq!(calc.snapshot().registers.len(), 17);
+        assert!(calc.execute_command("sto", Some(vec!["20".to_string()])).is_err());
+        assert!(calc.catalog(5).is_err());
+        assert!(calc.execute_command("date", None).unwrap_err().contains("DATE"));
+        
+        calc.plug_module(Module::Time);
+        assert!(calc.catalog(5).is_ok());
+        assert!(calc.unplug_module(Module::Time).is_ok());
+        
+        // Selecting the model from a config file
+        let config = crate::Config::from_toml("model = \"41CV\"\nmodules = [\"xf\"]").unwrap();
+        assert!(calc.apply_config(&config).is_empty());
+        assert_eq!(calc.model(), Model::HP41CV);
+        assert_eq!(calc.modules().collect::<Vec<_>>(), vec![Module::XFunctions]);
+        assert_eq!(calc.snapshot().registers.len(), 100);
+        assert!(calc.unplug_module(Module::XFunctions).is_ok());
+        calc.set_model(Model::HP41CX);
+        assert!(calc.unplug_module(Module::Time).is_err());
+    }
+    
+    #[test]
+    fn test_games_module_replays() {
+        let clock = MockClock::new();
+        let mut calc = HP41CCalculator::new().with_clock(std::sync::Arc::new(clock.clone()));
+        assert!(calc.run_command_line("DIE 06").is_err());
+        calc.plug_module(Module::Games);
+        
+        let saved = calc.snapshot();
+        let rolls: Vec<f64> = (0..4).map(|_| {
+            calc.run_command_line("DIE 06").unwrap();
+            calc.test_get_stack()[0]
+        }).collect();
+        assert!(rolls.iter().all(|roll| (1.0..=6.0).contains(roll) && roll.fract() == 0.0));
+        
+        // Restoring the state replays the same rolls
+        calc.restore(&saved);
+        for &roll in &rolls {
+            calc.run_command_line("DIE 06").unwrap();
+            assert_eq!(calc.test_get_stack()[0], roll);
+        }
+        
+        calc.run_command_line("4").unwrap();
+        calc.run_command_line("SHUFFLE 20").unwrap();
+        let mut dealt: Vec<f64> = (20..24).map(|r| calc.test_get_storage(r).unwrap()).collect();
+        dealt.sort_by(f64::total_cmp);
+        assert_eq!(dealt, [1.0, 2.0, 3.0, 4.0]);
+        
+        calc.run_command_line("1.5").unwrap();
+        calc.run_command_line("DELAY").unwrap();
+        assert_eq!(clock.elapsed(), std::time::Duration::from_millis(1500));
+    }
+    
+    #[test]
+    fn test_navigation_module() {
+        let mut calc = HP41CCalculator::new();
+        calc.plug_module(Module::Navigation);
+        for line in ["0", "0", "0", "1", "GC"] {
+            calc.run_command_line(line).unwrap();
+        }
+        let [distance, course, latitude, longitude] = calc.test_get_stack();
+        assert!((distance - 60.0).abs() < 1e-9 && (course - 90.0).abs() < 1e-9);
+        assert_eq!((latitude, longitude), (0.0, 1.0));
+        
+        // 60 miles due north from the equator is 1°N
+        for line in ["0", "0", "0", "60", "DR"] {
+            calc.run_command_line(line).unwrap();
+        }
+        assert!((calc.test_get_stack()[1] - 1.0).abs() < 1e-9);
+        assert_eq!(calc.snapshot().last_x, 60.0);
+    }
+    
+    #[test]
+    fn test_calendar_functions() {
+        let mut calc = HP41CCalculator::new();
+        calc.run_command_line("7.041776").unwrap();
+        assert_eq!(calc.execute_command("DOW", None).unwrap().as_deref(), Some("THU"));
+        assert_eq!(calc.test_get_stack()[0], 4.0);
+        
+        // Leap day 2000 to New Year 2001, and back again
+        for line in ["2.292000", "1.012001", "DDAYS"] {
+            calc.run_command_line(line).unwrap();
+        }
+        assert_eq!(calc.test_get_stack()[0], 307.0);
+        for line in ["2.292000", "307", "DATE+"] {
+            calc.run_command_line(line).unwrap();
+        }
+        assert_eq!(calc.test_get_stack()[0], 1.012001);
+        assert_eq!(calc.snapshot().last_x, 307.0);
+        
+        // 2001 was no leap year
+        calc.run_command_line("2.292001").unwrap();
+        assert!(calc.execute_command("JDN", None).is_err());
+        
+        // Day first after DMY
+        calc.run_command_line("DMY").unwrap();
+        assert!(calc.flags().is_set(31));
+        for line in ["29.022000", "1.012001", "DDAYS"] {
+            calc.run_command_line(line).unwrap();
+        }
+        assert_eq!(calc.test_get_stack()[0], 307.0);
+        for line in ["29.022000", "307", "DATE+"] {
+            calc.run_command_line(line).unwrap();
+        }
+        assert_eq!(calc.test_get_stack()[0], 1.012001);
+        calc.run_command_line("4.071776").unwrap();
+        assert_eq!(calc.execute_command("DOW", None).unwrap().as_deref(), Some("THU"));
+        calc.run_command_line("MDY").unwrap();
+        assert!(!calc.flags().is_set(31));
+        calc.run_command_line("29.022000").unwrap();
+        assert!(calc.execute_command("DOW", None).is_err());
+    }
+    
+    #[test]
+    fn test_integer_functions() {
+        let mut calc = HP41CCalculator::new();
+        for line in ["84", "36", "GCD"] {
+            calc.run_command_line(line).unwrap();
+        }
+        assert_eq!(calc.test_get_stack()[0], 12.0);
+        assert_eq!(calc.snapshot().last_x, 36.0);
+        
+        calc.run_command_line("360").unwrap();
+        assert_eq!(calc.execute_command("FACTOR", None).unwrap().as_deref(), Some("2^3*3^2*5"));
+        assert_eq!(calc.test_get_stack()[..2], [2.0, 180.0]);
+        assert_eq!(calc.execute_command("PRIME?", None).unwrap().as_deref(), Some("YES"));
+        
+        // Coprime neighbours of 10^9 have a multiple past 2^53
+        for line in ["1000000000", "1000000001"] {
+            calc.run_command_line(line).unwrap();
+        }
+        assert!(calc.execute_command("LCM", None).is_err());
+        assert_eq!(calc.test_get_stack()[0], 1_000_000_001.0);
+    }
+    
+    #[test]
+    fn test_program_info() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("# Title: Span of a beam\n# Author: J. Barrera\n01 LBL \"SPAN\"\n02 LBL 01\n03 RTN\n\
+                           04 LBL \"AREA\"\n05 RTN").unwrap();
+        assert_eq!(calc.program_info("span").unwrap().author, "J. Barrera");
+        assert!(calc.program_info("AREA").is_none());
+        
+        let mut cat1 = calc.catalog(1).unwrap();
+        assert_eq!(cat1.current(), Some("LBL'SPAN Span of a beam"));
+        assert!(cat1.advance());
+        assert_eq!(cat1.current(), Some("LBL'AREA"));
+        
+        // The editor header names the program being edited
+        calc.set_program_info("AREA", ProgramInfo { description: "Circle area".to_string(), ..Default::default() });
+        calc.process_input(":").unwrap();
+        assert!(calc.get_display().contains("L06 \"Circle area\""));
+        calc.process_input(":").unwrap();
+        
+        // Kept in saved state, outside program memory
+        let state = calc.snapshot();
+        let fingerprint = state.fingerprint();
+        let mut restored = HP41CCalculator::new();
+        restored.restore(&state);
+        assert_eq!(restored.program_info("SPAN"), calc.program_info("SPAN"));
+        calc.set_program_info("SPAN", ProgramInfo::default());
+        assert!(calc.program_info("SPAN").is_none());
+        assert_eq!(calc.state_fingerprint(), fingerprint);
+    }
+    
+    #[test]
+    fn test_comparison_tests() {
+        let mut calc = HP41CCalculator::new();
+        calc.run_command_line("2").unwrap();
+        calc.run_command_line("ENTER").unwrap();
+        calc.run_command_line("3").unwrap();
+        for (test, answer) in [("X=Y?", "NO"), ("X≠Y?", "YES"), ("X#Y?", "YES"), ("X<Y?", "NO"),
+                               ("X<=Y?", "NO"), ("X>Y?", "YES"), ("X≥Y?", "YES")] {
+            assert_eq!(calc.run_command_line(test), Ok(Some(answer.to_string())), "{}", test);
+        }
+        assert_eq!(&calc.test_get_stack()[..2], [3.0, 2.0]);
+        
+        // Typed key by key, X<Y? waits past the X<> prefix
+        let mut messages = Vec::new();
+        for key in ["x", "<", "y", "?"] {
+            messages.extend(calc.process_input(key).unwrap());
+        }
+        assert_eq!(messages, ["NO"]);
+        
+        // In a program a false test skips the next step: MAX of X and Y
+        calc.load_listing("LBL \"MAX\"\nX<Y?\nSWAP\nRTN").unwrap();
+        calc.run_command_line("XEQ \"MAX\"").unwrap();
+        assert_eq!(calc.test_get_stack()[0], 3.0);
+        calc.run_command_line("SWAP").unwrap();
+        calc.run_command_line("XEQ \"MAX\"").unwrap();
+        assert_eq!(calc.test_get_stack()[0], 3.0);
+        
+        // Alpha data is equal to itself
+        calc.set_alpha("ABC");
+        calc.run_command_line("ASTO 01").unwrap();
+        calc.run_command_line("RCL 01").unwrap();
+        calc.run_command_line("RCL 01").unwrap();
+        assert_eq!(calc.run_command_line("X=Y?"), Ok(Some("YES".to_string())));
+        
+        // Keyed in PRGM mode the tests are recorded, with no answer shown
+        calc.process_input(":").unwrap();
+        let mut messages = Vec::new();
+        for key in ["x", "<", "y", "?", "x", "=", "y", "?"] {
+            messages.extend(calc.process_input(key).unwrap());
+        }
+        assert!(messages.is_empty());
+        assert_eq!(calc.list_program(5..=6), ["05 X<Y?", "06 X=Y?"]);
+    }
+    
+    #[test]
+    fn test_zero_comparison_tests() {
+        let mut calc = HP41CCalculator::new();
+        calc.run_command_line("0").unwrap();
+        for (test, answer) in [("X=0?", "YES"), ("X#0?", "NO"), ("X<0?", "NO"), ("X≤0?", "YES"), ("X>=0?", "YES")] {
+            assert_eq!(calc.run_command_line(test), Ok(Some(answer.to_string())), "{}", test);
+        }
+        
+        // Typed key by key, past the X<> prefix
+        let mut messages = Vec::new();
+        for key in ["x", "<", "0", "?"] {
+            messages.extend(calc.process_input(key).unwrap());
+        }
+        assert_eq!(messages, ["NO"]);
+        
+        // ABS as a program
+        calc.load_listing("LBL \"ABS\"\nX<0?\nCHS\nRTN").unwrap();
+        for (x, expected) in [("-5", 5.0), ("5", 5.0)] {
+            calc.run_command_line(x).unwrap();
+            calc.run_command_line("XEQ \"ABS\"").unwrap();
+            assert_eq!(calc.test_get_stack()[0], expected);
+        }
+    }
+    
+    #[test]
+    fn test_label_completion() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL \"AREA\"\n1\nRTN\nLBL \"ARC\"\nLBL 02\n2\nRTN").unwrap();
+        for key in ["x", "e", "q", "a", "r"] {
+            calc.process_input(key).unwrap();
+        }
+        assert!(calc.get_display().contains("→AREA|ARC"));
+        assert!(calc.process_input("x").is_err());
+        calc.process_input("tab").unwrap();
+        assert_eq!(calc.test_get_stack()[0], 1.0);
+        
+        // Typed in full, the label runs as soon as it is the only match
+        for key in ["g", "t", "o"] {
+            calc.process_input(key).unwrap();
+        }
+        assert!(calc.get_display().contains("→AREA|ARC|02 "));
+        assert!(calc.process_input("1").is_err());
+        calc.process_input("0").unwrap();
+        calc.process_input("2").unwrap();
+        assert_eq!(calc.test_get_program_counter(), 4);
+    }
+    
+    #[test]
+    fn test_soft_menus() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL \"SQ\"\nX2\nRTN").unwrap();
+        calc.set_alpha("SQ");
+        calc.run_command_line("KEY 2").unwrap();
+        assert!(calc.run_command_line("KEY 7").is_err());
+        calc.set_alpha("");
+        assert!(calc.menu().is_none());
+        calc.run_command_line("MENU").unwrap();
+        assert!(calc.get_display().contains("      |SQ    |      |"));
+        calc.run_command_line("3").unwrap();
+        calc.process_input("f2").unwrap();
+        assert_eq!(calc.test_get_stack()[0], 9.0);
+        calc.process_input("f1").unwrap();
+        assert_eq!(calc.test_get_stack()[0], 9.0);
+        
+        // A module's functions, paged; a slot needing an argument waits for it
+        calc.set_alpha("SURVEYING");
+        assert!(calc.run_command_line("MENU").is_err());
+        calc.plug_module(Module::Surveying);
+        calc.run_command_line("MENU").unwrap();
+        assert_eq!(calc.menu().unwrap().title(), "SURVEYING");
+        calc.process_input("f3").unwrap();
+        assert!(calc.get_display().contains("CMD: [stpt]"));
+        calc.process_input("\u{8}").unwrap();
+        calc.process_input("down").unwrap();
+        assert_eq!(calc.menu().unwrap().page(), 0);
+        
+        calc.run_command_line("EXITM").unwrap();
+        assert!(calc.menu().is_none());
+        assert!(calc.get_display().contains("sin cos tan"));
+    }
+    
+    #[test]
+    fn test_gto_line_number() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL A\n1\n2\n3\nRTN").unwrap();
+        for key in ["g", "t", "o", ".", "0", "0", "3"] {
+            calc.process_input(key).unwrap();
+        }
+        assert_eq!(calc.test_get_program_counter(), 2);
+        assert_eq!(calc.run_command_line("GTO .009"), Err("Programming error: Invalid line number: 9".to_string()));
+        
+        // In PRGM mode the edit position moves
+        calc.process_input(":").unwrap();
+        calc.run_command_line("GTO .004").unwrap();
+        assert!(calc.get_display().contains(">04 3"));
+        calc.run_command_line("GTO ..").unwrap();
+        assert!(calc.get_display().contains(">06 _"));
+        calc.process_input(":").unwrap();
+        calc.run_command_line("GTO ..").unwrap();
+        assert_eq!(calc.test_get_program_counter(), 5);
+    }
+    
+    #[test]
+    fn test_custom_number_formatter() {
+        /// Decimal comma, right-aligned in the field
+        #[derive(Debug)]
+        struct Comma;
+        impl NumberFormatter for Comma {
+            fn format(&self, value: f64, mode: &DisplayMode, digits: usize, width: usize) -> String {
+                let text = DisplayFormatter::new().format(value, mode, digits, width).replace('.', ",");
+                format!("{:>1$}", text, width.min(12))
+            }
+        }
+        
+        let mut calc = HP41CCalculator::new().with_number_formatter(Box::new(Comma));
+        calc.run_command_line("1.5").unwrap();
+        calc.run_command_line("ENTER").unwrap();
+        calc.run_command_line("FIX 2").unwrap();
+        assert!(calc.get_display().contains("X:         1,50"));
+        
+        // ARCL shows numbers as the display does; alpha data stays as text
+        calc.run_command_line("STO 01").unwrap();
+        calc.set_alpha("X=");
+        calc.run_command_line("ARCL 01").unwrap();
+        assert_eq!(calc.alpha(), "X=1,50");
+        calc.run_command_line("ASTO 02").unwrap();
+        calc.run_command_line("RCL 02").unwrap();
+        assert!(calc.get_display().contains("X: X=1,50"));
+    }
+    
+    #[test]
+    fn test_delete_program_steps() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL A\n1\nLBL B\n2\nRTN").unwrap();
+        assert!(calc.run_command_line("DEL 002").is_err());
+        
+        calc.process_input(":").unwrap();
+        calc.run_command_line("GTO .002").unwrap();
+        for key in ["d", "e", "l", "0", "0", "2"] {
+            calc.process_input(key).unwrap();
+        }
+        assert!(calc.get_display().contains(">02 2"));
+        calc.run_command_line("DEL 009").unwrap();
+        calc.process_input(":").unwrap();
+        let steps: Vec<String> = calc.snapshot().program.iter().map(|step| step.to_string()).collect();
+        assert_eq!(steps, ["LBL A"]);
+        assert!(calc.run_command_line("GTO B").is_err());
+    }
+    
+    #[test]
+    fn test_keyed_program_steps_are_recorded() {
+        let keys = ":|l|b|l|a|2|s|i|n|x|=|y|?|s|f|0|1|t|o|n|e|3|s|t|o|0|1|+|r|t|n|:";
+        let (calc, _) = process_keys(&keys.split('|').collect::<Vec<_>>());
+        assert_eq!(calc.list_program(..), [
+            "01 LBL a", "02 2", "03 SIN", "04 X=Y?", "05 SF 01", "06 TONE 3", "07 STO 01", "08 +", "09 RTN", "10 .END.",
+        ]);
+        // None of them ran while being keyed
+        assert_eq!(calc.test_get_storage(1), Some(0.0));
+        assert!(!calc.flags().is_set(1));
+        assert_eq!(calc.test_get_stack()[0], 0.0);
+    }
+    
+    #[test]
+    fn test_program_step_display() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL \"AB\"\nSTO IND 12\nGTO 01\nXEQ A\nRTN").unwrap();
+        let steps: Vec<String> = calc.snapshot().program.iter().map(|step| step.display_text()).collect();
+        assert_eq!(steps, ["LBL \"AB\"", "STO IND 12", "GTO 01", "XEQ A", "RTN"]);
+        
+        calc.process_input(":").unwrap();
+        calc.run_command_line("GTO .001").unwrap();
+        assert!(calc.get_display().contains(">01 LBL \"AB\""));
+        calc.run_command_line("GTO .002").unwrap();
+        assert!(calc.get_display().contains(">02 STO IND 12"));
+        
+        // A command being keyed shows its blanks
+        calc.run_command_line("GTO ..").unwrap();
+        for (key, shown) in [("s", None), ("t", None), ("o", Some("STO __")), ("1", Some("STO 1_"))] {
+            calc.process_input(key).unwrap();
+            if let Some(shown) = shown {
+                assert!(calc.get_display().contains(&format!(">06 {}", shown)), "{}", calc.get_display());
+            }
+        }
+        calc.process_input("2").unwrap();
+        assert!(calc.get_display().contains(">07 _"));
+        calc.process_input("x").unwrap();
+        calc.process_input("e").unwrap();
+        calc.process_input("q").unwrap();
+        calc.process_input("a").unwrap();
+        assert!(calc.get_display().contains(">07 XEQ \"A_\""));
+    }
+    
+    #[test]
+    fn test_text_lines() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL \"HI\"\n\"Hello\"\n⊢\", \"\nRTN").unwrap();
+        calc.set_alpha("old");
+        calc.run_command_line("XEQ HI").unwrap();
+        assert_eq!(calc.alpha(), "Hello, ");
+        
+        let state = calc.snapshot();
+        assert_eq!(state.program[2].text, Some(TextLine { text: ", ".to_string(), append: true }));
+        let mut restored = HP41CCalculator::new();
+        restored.restore(&MachineState::from_json(&state.to_json().unwrap()).unwrap());
+        assert_eq!(restored.snapshot().program, state.program);
+        
+        calc.process_input(":").unwrap();
+        calc.run_command_line("GTO .003").unwrap();
+        assert!(calc.get_display().contains(">03 ⊢\", \""));
+    }
+    
+    #[test]
+    fn test_catalog_command() {
+        let clock = MockClock::new();
+        let mut calc = HP41CCalculator::new().with_clock(std::sync::Arc::new(clock.clone()));
+        calc.load_listing("LBL \"AREA\"\nLBL 01\nRTN\nLBL \"ARC\"\nRTN").unwrap();
+        let cat3 = calc.catalog(3).unwrap();
+        assert!(cat3.entries().contains(&"SIN".to_string()));
+        assert!(!cat3.entries().contains(&"RNDM".to_string()));
+        calc.plug_module(Module::Games);
+        let cat2 = calc.catalog(2).unwrap();
+        let games = cat2.entries().iter().position(|entry| entry == "-GAMES").unwrap();
+        assert_eq!(cat2.entries()[games + 1], "RNDM");
+        
+        for key in ["c", "a", "t", "1"] {
+            calc.process_input(key).unwrap();
+        }
+        assert_eq!(calc.overlay(), Some("LBL'AREA"));
+        assert!(calc.is_catalog_running());
+        clock.advance(CATALOG_STEP);
+        assert!(calc.advance_catalog());
+        assert_eq!(calc.overlay(), Some("LBL'ARC"));
+        assert!(!calc.advance_catalog());
+        
+        // R/S stops the scroll; SST and BST step by hand
+        calc.process_input(" ").unwrap();
+        assert!(!calc.is_catalog_running());
+        calc.process_input("enter").unwrap();
+        assert_eq!(calc.overlay(), Some(".END."));
+        calc.run_command_line("R/S").unwrap();
+        clock.advance(CATALOG_STEP);
+        assert!(calc.advance_catalog());
+        assert_eq!(calc.overlay(), None);
+        assert!(!calc.is_catalog_running());
+        
+        // Any other command ends the catalog and runs
+        calc.run_command_line("CAT 1").unwrap();
+        calc.process_input("5").unwrap();
+        assert_eq!(calc.overlay(), None);
+        assert!(!calc.is_catalog_running());
+        assert!(calc.run_command_line("CAT 4").is_err());
+    }
+    
+    #[test]
+    fn test_run_from_extended_memory() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL \"DBL\"\nLBL 01\n2\n*\nSTOP\nRTN").unwrap();
+        calc.set_alpha("DBL");
+        calc.run_command_line("SAVEP").unwrap();
+        assert_eq!(calc.run_command_line("EMDIR"), Ok(Some("DBL P002".to_string())));
+        
+        // Main memory no longer has DBL; XEQ runs the file where it is
+        calc.load_listing("LBL \"MAIN\"\nXEQ \"DBL\"\n1\n+\nRTN").unwrap();
+        calc.run_command_line("3").unwrap();
+        calc.run_command_line("XEQ MAIN").unwrap();
+        let state = calc.snapshot();
+        assert_eq!(state.execution.space, MemorySpace::Extended("DBL".to_string()));
+        assert_eq!(state.execution.return_stack, [ReturnAddress::Main(2)]);
+        assert_eq!(state.program.len(), 5);
+        
+        let mut restored = HP41CCalculator::new();
+        restored.restore(&MachineState::from_json(&state.to_json().unwrap()).unwrap());
+        restored.run_command_line("R/S").unwrap();
+        assert_eq!(restored.test_get_stack()[0], 7.0);
+        assert!(restored.snapshot().execution.space.is_main());
+        
+        calc.set_alpha("DBL");
+        calc.run_command_line("PURFL").unwrap();
+        assert!(calc.run_command_line("PURFL").is_err());
+        assert!(calc.run_command_line("XEQ DBL").is_err());
+    }
+    
+    #[test]
+    fn test_multiple_programs() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL \"ONE\"\nGTO 01\n9\nLBL 01\n1\nXEQ \"TWO\"\nEND\n\
+                           LBL \"TWO\"\nGTO 01\nLBL 01\n2\nEND\n.END.").unwrap();
+        assert_eq!(calc.snapshot().program.len(), 12);
+        
+        // Each program finds its own LBL 01; END returns like RTN
+        calc.run_command_line("XEQ ONE").unwrap();
+        assert_eq!(calc.test_get_stack()[..2], [2.0, 1.0]);
+        
+        let cat1 = calc.catalog(1).unwrap();
+        assert_eq!(cat1.entries(), ["LBL'ONE", "END", "LBL'TWO", "END", ".END."]);
+        
+        // END is keyed into a program like any step
+        calc.process_input(":").unwrap();
+        for key in ["e", "n", "d"] {
+            calc.process_input(key).unwrap();
+        }
+        calc.process_input(":").unwrap();
+        assert_eq!(calc.snapshot().program[12].command, "END");
+    }
+    
+    #[test]
+    fn test_machine_time() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL \"T\"\n2\nSQRT\nSIN\nRTN").unwrap();
+        calc.run_command_line("XEQ T").unwrap();
+        let expected = timing::CYCLE * (15 + 60 + 750 + 1600 + 60);
+        assert_eq!(calc.machine_time(), expected);
+        calc.reset_machine_time();
+        assert_eq!(calc.machine_time(), std::time::Duration::ZERO);
+    }
+    
+    #[test]
+    fn test_step_budget() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL \"LOOP\"\nLBL 01\n1\nSTO+ 00\nGTO 01").unwrap();
+        calc.set_step_budget(Some(10));
+        calc.run_command_line("XEQ LOOP").unwrap();
+        assert!(calc.is_program_running());
+        // LBL LOOP, then two turns of the loop of four steps
+        assert_eq!(calc.test_get_storage(0), Some(2.0));
+        calc.advance_program().unwrap();
+        assert_eq!(calc.test_get_storage(0), Some(5.0));
+        
+        // Only R/S gets through to a running program
+        calc.process_input("7").unwrap();
+        assert!(calc.is_program_running());
+        calc.process_input(" ").unwrap();
+        assert!(!calc.is_program_running());
+        calc.advance_program().unwrap();
+        assert_eq!(calc.test_get_storage(0), Some(5.0));
+    }
+    
+    #[test]
+    fn test_step_limit() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL \"LOOP\"\nLBL 01\n1\nSTO+ 00\nGTO 01").unwrap();
+        calc.set_step_limit(Some(9));
+        assert!(calc.run_command_line("XEQ LOOP").is_err());
+        assert_eq!(calc.last_error(), Some(&error::ProgrammingError::StepLimitExceeded(9).into()));
+        assert!(!calc.is_program_running());
+        assert_eq!(calc.test_get_storage(0), Some(2.0));
+        
+        // The count runs across budgeted slices and starts over with each run
+        calc.set_step_budget(Some(4));
+        calc.run_command_line("XEQ LOOP").unwrap();
+        calc.advance_program().unwrap();
+        assert!(calc.advance_program().is_err());
+        calc.set_step_budget(None);
+        assert!(calc.run_command_line("XEQ LOOP").is_err());
+        assert_eq!(calc.test_get_storage(0), Some(6.0));
+        
+        // A time limit counts clock time, PSE included
+        let clock = MockClock::new();
+        let mut calc = HP41CCalculator::new().with_clock(std::sync::Arc::new(clock.clone()));
+        calc.load_listing("LBL \"WAIT\"\nLBL 01\nPSE\nGTO 01").unwrap();
+        calc.set_step_limit(None);
+        calc.set_time_limit(Some(std::time::Duration::from_secs(3)));
+        assert!(calc.run_command_line("XEQ WAIT").is_err());
+        assert_eq!(clock.elapsed(), std::time::Duration::from_secs(3));
+    }
+    
+    #[test]
+    fn test_six_level_return_stack() {
+        // LBL n calls LBL n+1 down to `depth`; the top level counts in R00
+        // whether it got back
+        let nested = |depth: usize| {
+            let mut listing = "LBL \"TOP\"\nXEQ 01\n1\nSTO+ 00\nRTN".to_string();
+            for level in 1..depth {
+                listing.push_str(&format!("\nLBL {:02}\nXEQ {:02}\nRTN", level, level + 1));
+            }
+            listing.push_str(&format!("\nLBL {:02}\nRTN", depth));
+            let mut calc = HP41CCalculator::new();
+            calc.load_listing(&listing).unwrap();
+            calc
+        };
+        let mut calc = nested(6);
+        calc.run_command_line("XEQ TOP").unwrap();
+        assert_eq!(calc.test_get_storage(0), Some(1.0));
+        
+        // A seventh level loses the oldest return, so the run ends early
+        let mut calc = nested(7);
+        calc.run_command_line("XEQ TOP").unwrap();
+        assert!(!calc.is_program_running());
+        assert_eq!(calc.test_get_storage(0), Some(0.0));
+        
+        let mut calc = nested(7);
+        calc.set_strict_returns(true);
+        assert!(calc.run_command_line("XEQ TOP").is_err());
+        assert_eq!(calc.last_error().map(CalculatorError::code), Some(44));
+        assert_eq!(calc.snapshot().execution.return_stack.len(), programming::RETURN_STACK_DEPTH);
+    }
+    
+    #[test]
+    fn test_warm_and_cold_start() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL \"AUTO\"\n7").unwrap();
+        for line in ["SIZE 030", "FIX 2", "SF 05", "SF 11", "SF 25", "CF 26"] {
+            calc.run_command_line(line).unwrap();
+        }
+        calc.power_on();
+        assert_eq!(calc.flags().to_string(), "05 26 28 29");
+        assert_eq!(calc.size(), 30);
+        // Flag 11 started the program
+        assert!(calc.is_program_running());
+        calc.advance_program().unwrap();
+        assert_eq!(calc.test_get_stack()[0], 7.0);
+        assert!(calc.get_display().contains("7.00"));
+        
+        calc.memory_lost();
+        assert_eq!(calc.size(), Model::default().default_size());
+        assert_eq!(calc.flags().to_string(), "26 28 29");
+        assert!(calc.snapshot().program.is_empty());
+        assert_eq!(calc.test_get_stack()[0], 0.0);
+    }
+    
+    #[test]
+    fn test_single_step() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL \"T\"\n2\nX=0?\nCHS\n3\n+").unwrap();
+        calc.run_command_line("GTO T").unwrap();
+        calc.run_command_line("SST").unwrap();
+        calc.run_command_line("SST").unwrap();
+        assert_eq!(calc.test_get_stack()[0], 2.0);
+        // The false test skips CHS
+        calc.run_command_line("SST").unwrap();
+        calc.run_command_line("SST").unwrap();
+        calc.run_command_line("SST").unwrap();
+        assert_eq!(calc.test_get_stack()[0], 5.0);
+        assert!(!calc.is_program_running());
+        // Past the end SST starts again at the top
+        calc.run_command_line("SST").unwrap();
+        assert_eq!(calc.test_get_program_counter(), 1);
+        
+        // In PRGM mode it only moves through the steps
+        calc.process_input(":").unwrap();
+        calc.run_command_line("GTO .001").unwrap();
+        assert_eq!(calc.run_command_line("SST"), Ok(Some("02 2".to_string())));
+        assert_eq!(calc.test_get_stack()[0], 5.0);
+    }
+    
+    #[test]
+    fn test_import_from_other_emulators() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL \"ONE\"\n1").unwrap();
+        // LBL "TWO", 2, RTN, END
+        let raw = [0xC0, 0x00, 0xF4, 0x00, b'T', b'W', b'O', 0x12, 0x85, 0xC0, 0x00, 0x0D];
+        assert_eq!(calc.import_raw(&raw), Ok(Some("Imported 4 steps".to_string())));
+        calc.run_command_line("XEQ TWO").unwrap();
+        assert_eq!(calc.test_get_stack()[0], 2.0);
+        assert_eq!(calc.snapshot().program.len(), 7);
+        // OCT isn't here yet
+        assert!(calc.import_raw(&[0x6F]).unwrap_err().contains("OCT"));
+        // Nor are indirect and stack register operands: 2, STO IND 12, RCL ST Y
+        let error = calc.import_raw(&[0x12, 0x91, 0x8C, 0x90, 0x72]).unwrap_err();
+        assert_eq!(error.lines().collect::<Vec<_>>(), [
+            "Step 02: Command error: Invalid argument 'IND 12' for STO",
+            "Step 03: Command error: Invalid argument 'ST Y' for RCL",
+        ]);
+        assert_eq!(calc.snapshot().program.len(), 7);
+        
+        calc.import_registers("R00= 4.5\nR02= \"XY\"").unwrap();
+        assert_eq!(calc.test_get_storage(0), Some(4.5));
+        assert!(calc.import_registers("R999= 1").is_err());
+    }
+    
+    #[test]
+    fn test_keyed_alpha_labels() {
+        let mut calc = HP41CCalculator::new();
+        calc.process_input(":").unwrap();
+        for key in ["l", "b", "l", "\"", "S", "Q", "F", "\"", "7", "r", "t", "n"] {
+            calc.process_input(key).unwrap();
+        }
+        calc.process_input(":").unwrap();
+        assert_eq!(calc.snapshot().program[0].to_string(), "LBL SQF");
+        
+        calc.run_command_line("3").unwrap();
+        for key in ["x", "e", "q", "\"", "s", "q", "f", "enter"] {
+            calc.process_input(key).unwrap();
+        }
+        assert_eq!(calc.test_get_stack()[..2], [7.0, 3.0]);
+    }
+    
+    #[test]
+    fn test_letter_labels_on_the_top_keys() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL \"ONE\"\nLBL A\n2\nRTN\nLBL a\n3\nRTN\nEND\n\
+                           LBL \"TWO\"\nLBL A\n4\nRTN\nEND").unwrap();
+        assert_eq!(calc.snapshot().program[4].to_string(), "LBL a");
+        let cat1 = calc.catalog(1).unwrap();
+        assert_eq!(cat1.entries(), ["LBL'ONE", "END", "LBL'TWO", "END", ".END."]);
+        
+        // Outside USER mode the keys do what their legends say
+        calc.run_command_line("4").unwrap();
+        calc.process_input("f2").unwrap();
+        assert_eq!(calc.test_get_stack()[0], 0.25);
+        
+        // In USER mode each program's own A, with a-e shifted
+        for key in ["u", "s", "e", "r"] {
+            calc.process_input(key).unwrap();
+        }
+        assert!(calc.flags().is_set(27));
+        assert!(calc.lcd_frame().annunciators.contains(Annunciators::USER));
+        calc.run_command_line("GTO ONE").unwrap();
+        calc.process_input("f1").unwrap();
+        assert_eq!(calc.test_get_stack()[0], 2.0);
+        calc.process_input("shift-f1").unwrap();
+        assert_eq!(calc.test_get_stack()[0], 3.0);
+        calc.run_command_line("GTO TWO").unwrap();
+        calc.process_input("f1").unwrap();
+        assert_eq!(calc.test_get_stack()[0], 4.0);
+        assert!(calc.run_command_line("GTO a").is_err());
+        
+        // A key without a label keeps its function; an assignment wins over both
+        calc.process_input("f3").unwrap();
+        assert_eq!(calc.test_get_stack()[0], 2.0);
+        calc.assign_key(11, "SQRT").unwrap();
+        calc.process_input("f1").unwrap();
+        assert!((calc.test_get_stack()[0] - 2f64.sqrt()).abs() < 1e-9);
+    }
+    
+    #[test]
+    fn test_tone_and_beep() {
+        let sink = audio::MemoryAudioSink::new();
+        let mut calc = HP41CCalculator::new().with_audio(Box::new(sink.clone()));
+        calc.run_command_line("TONE 3").unwrap();
+        assert_eq!(sink.events(), [AudioEvent::Tone { tone: 3, millis: audio::TONE_MS }]);
+        assert!(calc.run_command_line("TONE 12").is_err());
+        
+        // Both are program steps, silent while flag 26 is clear
+        sink.clear();
+        calc.process_input(":").unwrap();
+        for key in ["t", "o", "n", "e", "9", "b", "e", "e", "p"] {
+            calc.process_input(key).unwrap();
+        }
+        calc.process_input(":").unwrap();
+        let steps: Vec<String> = calc.snapshot().program.iter().map(ToString::to_string).collect();
+        assert_eq!(steps, ["TONE 9", "BEEP"]);
+        assert!(sink.events().is_empty());
+        calc.run_command_line("CF 26").unwrap();
+        calc.run_command_line("BEEP").unwrap();
+        assert!(sink.events().is_empty());
+        calc.run_command_line("SF 26").unwrap();
+        calc.run_command_line("GTO .001").unwrap();
+        calc.run_command_line("R/S").unwrap();
+        assert_eq!(sink.events().len(), 1 + audio::beep().len());
+    }
+    
+    #[test]
+    fn test_clear_alpha_and_display() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL \"M\"\n\"HELLO\"\nAVIEW\nCLD\nCLA\nRTN").unwrap();
+        calc.run_command_line("XEQ M").unwrap();
+        assert_eq!(calc.alpha(), "");
+        assert_eq!(calc.overlay(), None);
+        
+        calc.set_alpha("HI");
+        calc.run_command_line("AVIEW").unwrap();
+        assert_eq!(calc.overlay(), Some("HI"));
+        calc.run_command_line("CLD").unwrap();
+        assert!(!calc.get_display().contains("HI"));
+        assert_eq!(calc.alpha(), "HI");
+        
+        // Keyed in PRGM mode, both become steps
+        let mut calc = HP41CCalculator::new();
+        calc.process_input(":").unwrap();
+        for key in ["c", "l", "a", "c", "l", "d"] {
+            calc.process_input(key).unwrap();
+        }
+        calc.process_input(":").unwrap();
+        let steps: Vec<String> = calc.snapshot().program.iter().map(ToString::to_string).collect();
+        assert_eq!(steps, ["CLA", "CLD"]);
+    }
+    
+    #[test]
+    fn test_alpha_mode() {
+        let mut calc = HP41CCalculator::new();
+        calc.set_alpha("OLD");
+        calc.process_input("alpha").unwrap();
+        assert!(calc.lcd_frame().annunciators.contains(Annunciators::ALPHA));
+        assert_eq!(calc.lcd_frame().text().trim(), "OLD");
+        
+        // Typing replaces ALPHA, with a cursor until Enter; F and : are characters
+        for key in ["H", "I", "F", ":", "\u{8}"] {
+            calc.process_input(key).unwrap();
+        }
+        assert_eq!(calc.alpha(), "HIF");
+        assert_eq!(calc.lcd_frame().text().trim(), "HIF_");
+        let display = calc.get_display();
+        assert!(display.contains("α: HIF_"));
+        assert!(display.contains("Tab append"));
+        calc.process_input("enter").unwrap();
+        assert_eq!(calc.lcd_frame().text().trim(), "HIF");
+        
+        // Tab appends; ⌫ with nothing typed clears ALPHA
+        for key in ["tab", "!"] {
+            calc.process_input(key).unwrap();
+        }
+        assert_eq!(calc.alpha(), "HIF!");
+        calc.process_input("enter").unwrap();
+        calc.process_input("\u{8}").unwrap();
+        assert_eq!(calc.alpha(), "");
+        calc.process_input("alpha").unwrap();
+        assert!(!calc.is_alpha_mode());
+        
+        // In PRGM mode the text becomes a text line when it ends
+        calc.process_input(":").unwrap();
+        calc.process_input("alpha").unwrap();
+        for key in ["A", "B", "enter", "tab", "C"] {
+            calc.process_input(key).unwrap();
+        }
+        assert!(calc.get_display().contains(">02 ⊢\"C_"));
+        calc.process_input("alpha").unwrap();
+        calc.process_input(":").unwrap();
+        let steps: Vec<String> = calc.snapshot().program.iter().map(ToString::to_string).collect();
+        assert_eq!(steps, ["\"AB\"", "⊢\"C\""]);
+    }
+    
+    #[test]
+    fn test_watchpoints() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL \"W\"\n1\nSTO 05\n2\nSTO 05\nSF 03\nRTN").unwrap();
+        assert_eq!(calc.run_command_line("WATCH R05"), Ok(Some("Watching R05".to_string())));
+        calc.run_command_line("WATCH F03").unwrap();
+        assert_eq!(calc.watchpoints().len(), 2);
+
+        // Halts after each change, and R/S carries on
+        assert_eq!(calc.run_command_line("XEQ W"), Ok(Some("R05: 0 -> 1".to_string())));
+        assert!(!calc.is_program_running());
+        assert_eq!(calc.run_command_line("R/S"), Ok(Some("R05: 1 -> 2".to_string())));
+        assert_eq!(calc.run_command_line("R/S"), Ok(Some("F03: clear -> set".to_string())));
+        assert_eq!(calc.watch_hits()[0].new, 1.0);
+        assert_eq!(calc.run_command_line("R/S"), Ok(None));
+
+        // Changes from the keyboard don't halt anything
+        calc.run_command_line("STO 05").unwrap();
+        calc.run_command_line("UNWATCH").unwrap();
+        assert!(calc.watchpoints().is_empty());
+        assert_eq!(calc.run_command_line("XEQ W"), Ok(None));
+        assert!(calc.run_command_line("WATCH ST Q").is_err());
+    }
+
+    #[test]
+    fn test_focal_import_and_export() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL \"ONE\"\n1\nRTN").unwrap();
+        let text = "01 LBL \"HYP\"\n02 X^2\n03 X<>Y\n04 X^2\n05 +\n06 SQRT\n07 RTN\n08 .END.";
+        assert_eq!(calc.import_focal(text), Ok(Some("Imported 7 steps".to_string())));
+        calc.run_command_line("3").unwrap();
+        calc.run_command_line("4").unwrap();
+        calc.run_command_line("XEQ HYP").unwrap();
+        assert_eq!(calc.snapshot().stack[0], 5.0);
+
+        // Every bad line is reported, and nothing is added
+        let error = calc.import_focal("01 LBL \"B\"\n02 FOO\n03 BAR").unwrap_err();
+        assert_eq!(error.lines().count(), 2);
+        assert_eq!(calc.list_program(..).len(), 12);
+
+        // Through a .raw file and back
+        let raw = calc.export_raw().unwrap();
+        let mut other = HP41CCalculator::new();
+        other.import_raw(&raw).unwrap();
+        assert_eq!(other.export_focal(), calc.export_focal());
+
+        assert!(calc.export_focal().starts_with("01 LBL \"ONE\"\n02 1\n03 RTN\n04 END\n05 LBL \"HYP\"\n06 X^2\n07 X<>Y"));
+    }
+
+    #[test]
+    fn test_one_letter_global_labels() {
+        // Quoted, A and B are global labels, not the top-key labels A and B
+        let mut calc = HP41CCalculator::new();
+        calc.import_focal("LBL \"A\"\nXEQ \"B\"\nSTO 01\nEND\nLBL \"B\"\n7\nEND").unwrap();
+        calc.run_command_line("XEQ \"A\"").unwrap();
+        assert_eq!(calc.test_get_storage(1), Some(7.0));
+        assert_eq!(calc.catalog(1).unwrap().entries(), ["LBL'A", "END", "LBL'B", "END", ".END."]);
+        let focal = calc.export_focal();
+        assert!(focal.starts_with("01 LBL \"A\"\n02 XEQ \"B\"\n03 STO 01"));
+        
+        // Through a listing, FOCAL and a .raw file
+        let mut listed = HP41CCalculator::new();
+        listed.load_listing(&calc.list_program(..).join("\n")).unwrap();
+        assert_eq!(listed.export_focal(), focal);
+        let mut imported = HP41CCalculator::new();
+        imported.import_focal(&focal).unwrap();
+        assert_eq!(imported.export_focal(), focal);
+        let raw = calc.export_raw().unwrap();
+        assert_eq!(raw[..7], [0xC0, 0x00, 0xF2, 0x00, b'A', 0x1E, 0xF1]);
+        let mut other = HP41CCalculator::new();
+        other.import_raw(&raw).unwrap();
+        assert_eq!(other.export_focal(), focal);
+        
+        // Unquoted, A is still the local top-key label
+        let mut local = HP41CCalculator::new();
+        local.load_listing("LBL A\nRTN").unwrap();
+        assert_eq!(local.export_raw().unwrap()[0], 0xCF);
+        assert_eq!(local.export_focal(), "01 LBL A\n02 RTN\n03 .END.\n");
+    }
+
+    #[test]
+    fn test_free42_import() {
+        // LBL "T", 2, 42S XROM function, ISG 01, STO IND 12, RCL ST Y, SQRT, RTN
+        let raw = [0xC0, 0x00, 0xF2, 0x00, b'T', 0x12, 0xA7, 0x5E, 0x96, 0x01, 0x91, 0x8C, 0x90, 0x72, 0x52, 0x85];
+        let mut calc = HP41CCalculator::new();
+        let error = calc.import_free42(&raw, false).unwrap_err();
+        assert_eq!(error, "Not supported:\nStep 03: XROM 29,30\nStep 04: ISG 01\nStep 05: STO IND 12\nStep 06: RCL ST Y");
+        assert_eq!(calc.list_program(..), ["01 .END."]);
+
+        assert_eq!(calc.import_free42(&raw, true), Ok(Some("Imported 8 steps, 4 stubbed".to_string())));
+        assert_eq!(calc.list_program(3..=6), [
+            "03 STUB XROM 29,30", "04 STUB ISG 01", "05 STUB STO IND 12", "06 STUB RCL ST Y",
+        ]);
+        // The stubs are skipped and the rest runs
+        calc.run_command_line("XEQ T").unwrap();
+        assert_eq!(calc.snapshot().stack[0], 2.0_f64.sqrt());
+    }
+
+    #[test]
+    fn test_program_memory() {
+        let step = |line: &str| crate::listing::parse_listing(line).unwrap().remove(0).bytes();
+        assert_eq!(step("STO 05"), 1);
+        assert_eq!(step("STO 20"), 2);
+        assert_eq!(step("XEQ \"AREA\""), 6);
+        assert_eq!(step("END"), 3);
+        assert_eq!(step("AZB"), 2);
+        
+        let mut calc = HP41CCalculator::new();
+        calc.set_model(Model::HP41C);
+        // 12 bytes: LBL "AREA" takes 8
+        calc.load_listing("LBL \"AREA\"\nX2\nPI\n*\nRTN").unwrap();
+        assert_eq!(calc.free_registers(), 63 - 17 - 2);
+        assert_eq!(calc.run_command_line("MEM"), Ok(Some("REG 44".to_string())));
+        calc.run_command_line("SIZE 061").unwrap();
+        assert_eq!(calc.free_registers(), 0);
+        
+        // Deleted steps leave nulls until memory is packed
+        calc.process_input(":").unwrap();
+        calc.run_command_line("GTO .002").unwrap();
+        calc.run_command_line("DEL 003").unwrap();
+        assert_eq!(calc.test_get_program_length(), 2);
+        assert_eq!(calc.free_registers(), 0);
+        
+        // No register for LBL "Q" (5 bytes): memory is packed and the step
+        // goes in when tried again
+        assert_eq!(calc.run_command_line("LBL Q"), Err("Programming error: Packing, try again".to_string()));
+        assert_eq!(calc.last_error().map(CalculatorError::code), Some(47));
+        assert_eq!(calc.test_get_program_length(), 2);
+        calc.run_command_line("LBL Q").unwrap();
+        assert_eq!(calc.test_get_program_length(), 3);
+        assert_eq!(calc.run_command_line("LBL 01"), Err("Programming error: Program memory full".to_string()));
+        
+        // A deleted step's nulls take a shorter one
+        calc.run_command_line("DEL 001").unwrap();
+        calc.run_command_line("LBL 01").unwrap();
+        assert_eq!(calc.test_get_program_length(), 3);
+        
+        // PACK frees the registers of deleted steps
+        calc.run_command_line("GTO .001").unwrap();
+        calc.run_command_line("DEL 001").unwrap();
+        assert_eq!(calc.free_registers(), 0);
+        calc.run_command_line("PACK").unwrap();
+        assert_eq!(calc.free_registers(), 1);
+        assert_eq!(calc.memory_message(), "REG 01");
+        calc.run_command_line("SIZE 062").unwrap();
+    }
+    
+    #[test]
+    fn test_time_module_clock() {
+        // 2026-10-16 12:30:00 UTC
+        let clock = MockClock::at_unix_time(std::time::Duration::from_secs(1_792_153_800));
+        let mut calc = HP41CCalculator::new().with_clock(std::sync::Arc::new(clock.clone()));
+        assert_eq!(calc.run_command_line("DATE"), Ok(Some("10/16/26 FRI".to_string())));
+        assert_eq!(calc.snapshot().stack[0], 10.162026);
+        assert_eq!(calc.run_command_line("TIME"), Ok(Some("12:30:00".to_string())));
+        assert_eq!(calc.snapshot().stack[..2], [12.3, 10.162026]);
+        
+        // Set to local time; the setting is kept in the state
+        calc.run_command_line("14.0530").unwrap();
+        calc.run_command_line("SETTIME").unwrap();
+        calc.run_command_line("1.012027").unwrap();
+        calc.run_command_line("SETDATE").unwrap();
+        clock.advance(std::time::Duration::from_secs(90));
+        assert_eq!(calc.run_command_line("DATE"), Ok(Some("01/01/27 FRI".to_string())));
+        assert_eq!(calc.run_command_line("TIME"), Ok(Some("14:07:00".to_string())));
+        let state = calc.snapshot();
+        let mut restored = HP41CCalculator::new().with_clock(std::sync::Arc::new(clock.clone()));
+        restored.restore(&state);
+        assert_eq!(restored.run_command_line("TIME"), Ok(Some("14:07:00".to_string())));
+        
+        calc.run_command_line("25").unwrap();
+        assert!(calc.run_command_line("SETTIME").is_err());
+        
+        // CLOCK runs the time in the status line
+        assert!(!calc.get_display().contains("14:07:00"));
+        calc.run_command_line("CLOCK").unwrap();
+        assert!(calc.is_clock_shown());
+        assert!(calc.get_display().contains("14:07:00"));
+        clock.advance(std::time::Duration::from_secs(2));
+        assert!(calc.get_display().contains("14:07:02"));
+        calc.run_command_line("CLOCK").unwrap();
+        assert!(!calc.is_clock_shown());
+        
+        // The functions need the module
+        calc.set_model(Model::HP41C);
+        assert!(calc.run_command_line("DATE").is_err());
+    }
+    
+    #[test]
+    fn test_alarms() {
+        // 2026-10-16 12:30:00 UTC
+        let clock = MockClock::at_unix_time(std::time::Duration::from_secs(1_792_153_800));
+        let mut calc = HP41CCalculator::new().with_clock(std::sync::Arc::new(clock.clone()));
+        calc.load_listing("LBL \"TEA\"\n42\nRTN").unwrap();
+        
+        // At 12:45 today, every half hour, running TEA
+        calc.set_alpha("TEA");
+        for number in ["0.3", "0", "12.45"] {
+            calc.run_command_line(number).unwrap();
+        }
+        calc.run_command_line("XYZALM").unwrap();
+        // A message alarm on October 17 at 8:00
+        calc.set_alpha("WAKE UP");
+        for number in ["0", "10.172026", "8"] {
+            calc.run_command_line(number).unwrap();
+        }
+        calc.run_command_line("XYZALM").unwrap();
+        let alarms: Vec<String> = calc.alarms().map(ToString::to_string).collect();
+        assert_eq!(alarms, ["2026-10-16 12:45:00 every 1800s TEA", "2026-10-17 08:00:00 WAKE UP"]);
+        assert_eq!(calc.run_command_line("ALMCAT"), Ok(None));
+        assert_eq!(calc.overlay(), Some("2026-10-16 12:45:00 every 1800s TEA"));
+        
+        assert_eq!(calc.check_alarms(), None);
+        clock.advance(std::time::Duration::from_secs(15 * 60));
+        assert_eq!(calc.check_alarms(), Some(Ok(None)));
+        assert_eq!(calc.snapshot().stack[0], 42.0);
+        // Set again for the next half hour, skipping those missed
+        clock.advance(std::time::Duration::from_secs(20 * 3600));
+        calc.check_alarms().unwrap().unwrap();
+        assert_eq!(calc.check_alarms(), Some(Ok(Some("WAKE UP".to_string()))));
+        assert_eq!(calc.check_alarms(), None);
+        assert_eq!(calc.alarms().next().unwrap().to_string(), "2026-10-17 09:15:00 every 1800s TEA");
+        
+        calc.run_command_line("25").unwrap();
+        assert!(calc.run_command_line("XYZALM").is_err());
+    }
+    
+    #[test]
+    fn test_size_moves_the_curtain() {
+        let mut calc = HP41CCalculator::new();
+        calc.set_model(Model::HP41C);
+        calc.run_command_line("2.5").unwrap();
+        calc.run_command_line("STO 03").unwrap();
+        for key in ["s", "i", "z", "e", "0", "2", "5"] {
+            calc.process_input(key).unwrap();
+        }
+        assert_eq!(calc.size(), 25);
+        assert_eq!(calc.test_get_storage(3), Some(2.5));
+        assert_eq!(calc.free_registers(), 63 - 25);
+        calc.run_command_line("STO 24").unwrap();
+        assert_eq!(calc.run_command_line("RCL 25"), Err("Storage error: Nonexistent register: R25".to_string()));
+        
+        // The program keeps its registers
+        calc.load_listing("LBL \"AREA\"").unwrap();
+        assert_eq!(calc.free_registers(), 63 - 25 - 2);
+        assert_eq!(calc.run_command_line("SIZE 062"), Err("Storage error: No room".to_string()));
+        calc.run_command_line("SIZE 061").unwrap();
+        
+        calc.run_command_line("SIZE 003").unwrap();
+        assert_eq!(calc.snapshot().registers, [0.0, 0.0, 0.0]);
+    }
+    
+    #[test]
+    fn test_asto_and_arcl() {
+        let mut calc = HP41CCalculator::new();
+        calc.set_alpha("DISTANCE");
+        for key in ["a", "s", "t", "o", "0", "4"] {
+            calc.process_input(key).unwrap();
+        }
+        calc.run_command_line("FIX 2").unwrap();
+        calc.run_command_line("12.345").unwrap();
+        calc.run_command_line("STO 05").unwrap();
+        
+        calc.set_alpha("");
+        calc.run_command_line("ARCL 04").unwrap();
+        calc.run_command_line("ARCL 05").unwrap();
+        assert_eq!(calc.alpha(), "DISTAN12.35");
+        
+        // RCL of alpha data shows the text
+        calc.run_command_line("RCL 04").unwrap();
+        assert!(calc.get_display().contains("DISTAN"));
+        assert!(calc.run_command_line("ASTO 99").is_ok());
+        calc.run_command_line("SIZE 010").unwrap();
+        assert!(calc.run_command_line("ARCL 10").is_err());
+    }
+    
+    #[test]
+    fn test_view_and_aview() {
+        let mut calc = HP41CCalculator::new();
+        calc.run_command_line("2.5").unwrap();
+        calc.run_command_line("STO 03").unwrap();
+        calc.run_command_line("7").unwrap();
+        calc.run_command_line("VIEW 03").unwrap();
+        assert_eq!(calc.lcd_frame().text().trim_end(), "2.5000");
+        assert_eq!(calc.test_get_stack()[0], 7.0);
+        
+        // Later steps keep the message; the next key clears it
+        calc.run_command_line("1").unwrap();
+        assert_eq!(calc.overlay(), Some("2.5000"));
+        calc.process_input("+").unwrap();
+        assert_eq!(calc.overlay(), None);
+        assert_eq!(calc.lcd_frame().text().trim_end(), "8.0000");
+        
+        calc.set_alpha("HELLO");
+        calc.run_command_line("AVIEW").unwrap();
+        assert!(calc.get_display().contains("X: HELLO"));
+        assert!(calc.run_command_line(&format!("VIEW {}", calc.size())).is_err());
+    }
+    
+    #[test]
+    fn test_tic_toc() {
+        let clock = MockClock::new();
+        let mut calc = HP41CCalculator::new().with_clock(std::sync::Arc::new(clock.clone()));
+        assert!(calc.run_command_line("TOC").is_err());
+        
+        calc.run_command_line("TIC").unwrap();
+        clock.advance(std::time::Duration::from_millis(1500));
+        calc.run_command_line("TOC").unwrap();
+        clock.advance(std::time::Duration::from_secs(1));
+        calc.run_command_line("TOC").unwrap();
+        assert_eq!(calc.test_get_stack()[..2], [2.5, 1.5]);
+    }
+    
+    #[test]
+    fn test_run_stop_and_prompt() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL \"CIRC\"\nPROMPT\nX2\nPI\n*\nSTOP\n2\n/\nRTN").unwrap();
+        calc.set_alpha("RADIUS?");
+        calc.run_command_line("XEQ \"CIRC\"").unwrap();
+        assert_eq!(calc.overlay(), Some("RADIUS?"));
+        assert_eq!(calc.test_get_program_counter(), 2);
+        
+        // Key in the radius and continue to the STOP
+        for key in ["3", "r", "/", "s"] {
+            calc.process_input(key).unwrap();
+        }
+        assert!((calc.test_get_stack()[0] - 9.0 * std::f64::consts::PI).abs() < 1e-9);
+        assert_eq!(calc.test_get_program_counter(), 6);
+        for key in ["r", "/", "s"] {
+            calc.process_input(key).unwrap();
+        }
+        assert!((calc.test_get_stack()[0] - 4.5 * std::f64::consts::PI).abs() < 1e-9);
+        assert!(!calc.snapshot().execution.interrupted);
+        
+        // A failing step halts the run on that step
+        calc.load_listing("LBL A\n0\nLN\nRTN").unwrap();
+        assert!(calc.run_command_line("XEQ A").is_err());
+        assert_eq!(calc.test_get_program_counter(), 2);
+        
+        // In PRGM mode the control functions are recorded, STOP despite STO
+        calc.process_input(":").unwrap();
+        for key in ["s", "t", "o", "p", "p", "r", "o", "m", "p", "t"] {
+            calc.process_input(key).unwrap();
+        }
+        calc.process_input(":").unwrap();
+        assert_eq!(calc.test_get_program_length(), 6);
+        assert_eq!(calc.snapshot().program[4].command, "STOP");
+    }
+    
+    #[test]
+    fn test_pause_shows_display() {
+        #[derive(Clone, Default)]
+        struct Frames(std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+        impl crate::lcd::DisplaySink for Frames {
+            fn refresh(&mut self, frame: &crate::lcd::LcdFrame) -> std::io::Result<()> {
+                self.0.lock().unwrap().push(frame.text().trim_end().to_string());
+                Ok(())
+            }
+        }
+        
+        let clock = MockClock::new();
+        let frames = Frames::default();
+        let mut calc = HP41CCalculator::new()
+            .with_clock(std::sync::Arc::new(clock.clone()))
+            .with_display(Box::new(frames.clone()));
+        calc.set_pause(std::time::Duration::from_millis(500));
+        calc.load_listing("LBL A\n1\nPSE\n2\nPSE\nRTN").unwrap();
+        calc.run_command_line("XEQ A").unwrap();
+        assert_eq!(*frames.0.lock().unwrap(), ["1.0000", "2.0000"]);
+        assert_eq!(clock.elapsed(), std::time::Duration::from_secs(1));
+        
+        // From the keyboard PSE neither waits nor shows anything
+        calc.run_command_line("PSE").unwrap();
+        assert_eq!(clock.elapsed(), std::time::Duration::from_secs(1));
+    }
+    
+    #[test]
+    fn test_run_stop_confirms_pending_command() {
+        let mut calc = HP41CCalculator::new();
+        calc.set_confirmation(ConfirmCategory::Registers, true);
+        calc.run_command_line("5").unwrap();
+        calc.run_command_line("STO 01").unwrap();
+        for key in ["c", "l", "r", "g", "r", "/", "s"] {
+            calc.process_input(key).unwrap();
+        }
+        assert_eq!(calc.test_get_storage(1), Some(0.0));
+        assert!(calc.run_command_line("R/S").is_err());
+    }
+    
+    #[test]
+    fn test_usage_statistics() {
+        let clock = MockClock::new();
+        let mut calc = HP41CCalculator::new().with_clock(std::sync::Arc::new(clock.clone()));
+        
+        for _ in 0..2 {
+            calc.process_input("2").unwrap();
+            for key in ["s", "q", "r", "t"] {
+                calc.process_input(key).unwrap();
+                clock.advance(std::time::Duration::from_millis(100));
+            }
+        }
+        calc.process_input("enter").unwrap();
+        calc.process_input("+").unwrap();
+        // Commands run from scripts aren't keystrokes
+        calc.run_command_line("CHS").unwrap();
+        
+        let sqrt = calc.usage_stats().get("sqrt").unwrap();
+        assert_eq!(sqrt.count, 2);
+        assert_eq!(sqrt.average_entry(), std::time::Duration::from_millis(300));
+        assert_eq!(calc.usage_stats().total(), 4);
+        assert!(calc.usage_stats().get("chs").is_none());
+        
+        let report = calc.execute_command("stats", None).unwrap().unwrap();
+        assert!(report.starts_with("4 commands\nSQRT         2  avg 0.30s"));
+    }
+    
+    #[test]
+    fn test_renum_command() {
+        let mut calc = HP41CCalculator::new();
+        calc.process_input(":").unwrap();
+        calc.test_add_program_instruction("lbl", Some(vec!["15".to_string()]));
+        calc.test_add_program_instruction("gto", Some(vec!["15".to_string()]));
+        calc.process_input(":").unwrap();
+        
+        assert_eq!(calc.process_command_string("renum").unwrap(), Some("Renumbered 1 label".to_string()));
+        assert_eq!(calc.cross_reference().to_string(), "LBL 01 @01 <- GTO 02");
+        assert_eq!(calc.process_command_string("renum").unwrap(), Some("Labels already compact".to_string()));
+    }
 }
 
 // Updated debug tests for new system
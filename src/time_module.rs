@@ -0,0 +1,170 @@
+//! HP 82182A Time module: the clock
+//!
+//! The module keeps time of its own. Here it reads the calculator's `Clock`
+//! (the host's wall clock, or a `MockClock` in tests) plus a correction
+//! that SETDATE and SETTIME set, so a new calculator's module reads UTC
+//! until the user sets it to local time. Dates are numbers in MM.DDYYYY
+//! form, or DD.MMYYYY while flag 31 is set, as for the calendar functions;
+//! times are HH.MMSSss, to hundredths of a second.
+//!
+//! | Command | Stack in | Effect |
+//! |---|---|---|
+//! | `DATE` | | X: today's date, shown with the day of the week |
+//! | `TIME` | | X: the time of day, shown as `14:05:09` |
+//! | `SETDATE` | X: date | Set the date, keeping the time of day |
+//! | `SETTIME` | X: time | Set the time of day, keeping the date |
+//! | `CLOCK` | | Show the running time in the status line, until CLOCK again |
+//! | `DMY`, `MDY` | | Read and show dates day first, or month first (flag 31) |
+//! | `XYZALM` | X: time, Y: date (0 for today), Z: repeat interval (0 for none), ALPHA: message | Set an alarm |
+//! | `ALMCAT` | | Scroll through the pending alarms (CAT 5) |
+//!
+//! An alarm goes off while the calculator waits for a key: it beeps and
+//! shows its message, or the time it was set for when the message is
+//! empty. When the message names a global label in program memory the
+//! alarm runs that program instead, as the module's control alarms do. A
+//! repeating alarm is set again for the first repeat still to come.
+
+use crate::calendar::{Date, DAY_NAMES};
+use crate::clock::Clock;
+use crate::error::StackError;
+
+const MILLIS_PER_DAY: i64 = 86_400_000;
+
+/// Julian day number of January 1, 1970, the first day of Unix time
+const UNIX_EPOCH_DAY: i64 = 2_440_588;
+
+/// The module's clock and display state
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimeModule {
+    /// Milliseconds the module's clock is ahead of the host's
+    pub correction: i64,
+    /// Whether CLOCK has the time running in the status line
+    pub clock_shown: bool,
+}
+
+impl TimeModule {
+    /// Milliseconds since January 1, 1970 on the module's clock
+    pub fn now(&self, clock: &dyn Clock) -> i64 {
+        clock.unix_time().as_millis() as i64 + self.correction
+    }
+
+    /// Today's date on the module's clock
+    pub fn date(&self, clock: &dyn Clock) -> Result<Date, StackError> {
+        Date::from_julian_day(UNIX_EPOCH_DAY + self.now(clock).div_euclid(MILLIS_PER_DAY))
+    }
+
+    /// Milliseconds since midnight on the module's clock
+    pub fn time_of_day(&self, clock: &dyn Clock) -> i64 {
+        self.now(clock).rem_euclid(MILLIS_PER_DAY)
+    }
+
+    /// SETDATE: move the clock to a date, keeping the time of day
+    pub fn set_date(&mut self, clock: &dyn Clock, date: Date) {
+        let midnight = (date.julian_day() - UNIX_EPOCH_DAY) * MILLIS_PER_DAY;
+        self.set(clock, midnight + self.time_of_day(clock));
+    }
+
+    /// SETTIME: move the clock to a time of day, keeping the date
+    pub fn set_time(&mut self, clock: &dyn Clock, millis: i64) {
+        let midnight = self.now(clock) - self.time_of_day(clock);
+        self.set(clock, midnight + millis);
+    }
+
+    /// Seconds since January 1, 1970 on the module's clock of a time on a
+    /// date, or today with none, as alarms are due
+    pub fn alarm_due(&self, clock: &dyn Clock, date: Option<Date>, millis: i64) -> Result<u64, StackError> {
+        let day = match date {
+            Some(date) => date.julian_day() - UNIX_EPOCH_DAY,
+            None => self.now(clock).div_euclid(MILLIS_PER_DAY),
+        };
+        u64::try_from((day * MILLIS_PER_DAY + millis) / 1000)
+            .map_err(|_| StackError::MathError("Date out of range".to_string()))
+    }
+
+    fn set(&mut self, clock: &dyn Clock, millis: i64) {
+        self.correction = millis - clock.unix_time().as_millis() as i64;
+    }
+}
+
+/// Milliseconds since midnight as an HH.MMSSss number
+pub fn time_to_number(millis: i64) -> f64 {
+    let hundredths = millis / 10;
+    let (hours, minutes) = (hundredths / 360_000, hundredths / 6000 % 60);
+    let (seconds, hundredths) = (hundredths / 100 % 60, hundredths % 100);
+    (hours * 1_000_000 + minutes * 10_000 + seconds * 100 + hundredths) as f64 / 1e6
+}
+
+/// Milliseconds since midnight from an HH.MMSSss number
+pub fn time_from_number(value: f64) -> Result<i64, StackError> {
+    hms_from_number(value).ok().filter(|&millis| millis < MILLIS_PER_DAY).ok_or_else(invalid_time)
+}
+
+/// Milliseconds from an HH.MMSSss number of any number of hours, as an
+/// alarm's repeat interval is given
+pub fn hms_from_number(value: f64) -> Result<i64, StackError> {
+    let hundredths = (value * 1e6).round();
+    if !(0.0..1e12).contains(&hundredths) {
+        return Err(invalid_time());
+    }
+    let hundredths = hundredths as i64;
+    let (hours, minutes, seconds) = (hundredths / 1_000_000, hundredths / 10_000 % 100, hundredths / 100 % 100);
+    if minutes >= 60 || seconds >= 60 {
+        return Err(invalid_time());
+    }
+    Ok(((hours * 60 + minutes) * 60 + seconds) * 1000 + hundredths % 100 * 10)
+}
+
+fn invalid_time() -> StackError {
+    StackError::MathError("Invalid time".to_string())
+}
+
+/// `14:05:09`, as CLOCK and TIME show it
+pub fn format_time(millis: i64) -> String {
+    let seconds = millis / 1000;
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// `10/16/26 FRI`, or `16.10.26 FRI` with `dmy`, as DATE shows it
+pub fn format_date(date: Date, dmy: bool) -> String {
+    let day_name = DAY_NAMES[date.day_of_week()];
+    if dmy {
+        format!("{:02}.{:02}.{:02} {}", date.day, date.month, date.year % 100, day_name)
+    } else {
+        format!("{:02}/{:02}/{:02} {}", date.month, date.day, date.year % 100, day_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use std::time::Duration;
+
+    #[test]
+    fn test_clock() {
+        // 2026-10-16 12:30:00 UTC
+        let clock = MockClock::at_unix_time(Duration::from_secs(1_792_153_800));
+        let mut module = TimeModule::default();
+        assert_eq!(module.date(&clock), Date::new(2026, 10, 16));
+        assert_eq!(time_to_number(module.time_of_day(&clock)), 12.3);
+
+        module.set_time(&clock, time_from_number(8.150525).unwrap());
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(time_to_number(module.time_of_day(&clock)), 8.160525);
+        module.set_date(&clock, Date::new(2000, 2, 29).unwrap());
+        assert_eq!(module.date(&clock), Date::new(2000, 2, 29));
+        assert_eq!(format_time(module.time_of_day(&clock)), "08:16:05");
+        assert_eq!(format_date(module.date(&clock).unwrap(), false), "02/29/00 TUE");
+        assert_eq!(format_date(module.date(&clock).unwrap(), true), "29.02.00 TUE");
+
+        assert!(time_from_number(24.0).is_err());
+        assert!(time_from_number(12.6).is_err());
+        assert_eq!(time_from_number(23.595999), Ok(86_399_990));
+        assert_eq!(hms_from_number(36.3), Ok(131_400_000));
+
+        // Alarms are due on the module's clock
+        let today = module.date(&clock).unwrap();
+        assert_eq!(module.alarm_due(&clock, None, 0), module.alarm_due(&clock, Some(today), 0));
+        assert_eq!(module.alarm_due(&clock, Date::new(1970, 1, 2).ok(), 1000), Ok(86_401));
+    }
+}
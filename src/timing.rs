@@ -0,0 +1,98 @@
+//! How long a real HP-41 takes per instruction
+//!
+//! The HP-41 processor executes one microcode word per cycle of about
+//! 158.7 µs (6.3 kHz). User instructions cost a few dozen to a few thousand
+//! cycles: the table below gives typical costs, rounded from published
+//! stopwatch measurements of an HP-41CV running FOCAL loops. Real times
+//! vary a little with the arguments (trigonometry on large angles, label
+//! searches over long programs), which the table does not model.
+//!
+//! The calculator adds up the cost of each step a program runs as its
+//! machine time (`HP41CCalculator::machine_time`), an estimate of how long
+//! the same run would take on the real machine.
+
+use std::time::Duration;
+use crate::programming::ProgramInstruction;
+use crate::registry::command_key;
+
+/// One microcode cycle
+pub const CYCLE: Duration = Duration::from_nanos(158_700);
+
+/// Cost of instructions the table doesn't list
+const DEFAULT_CYCLES: u32 = 200;
+
+/// Typical cycles by command
+const CYCLES: &[(&str, u32)] = &[
+    // Stack
+    ("enter", 90), ("swap", 50), ("clx", 50), ("clr", 80), ("rdn", 60), ("r^", 60),
+    ("chs", 60), ("lastx", 70),
+    // Arithmetic
+    ("+", 150), ("-", 150), ("*", 200), ("/", 290), ("inv", 290), ("x2", 200),
+    ("sqrt", 750), ("abs", 60), ("int", 110), ("frc", 110), ("sign", 80),
+    ("mod", 400), ("%", 250), ("%ch", 350), ("!", 900),
+    // Transcendental
+    ("sin", 1600), ("cos", 1600), ("tan", 1700),
+    ("asin", 1900), ("acos", 1900), ("atan", 1800),
+    ("ln", 1300), ("log", 1350), ("exp", 1450), ("10x", 1500), ("^", 2900),
+    // Registers
+    ("sto", 85), ("rcl", 85), ("x<>", 100),
+    ("sto+", 180), ("sto-", 180), ("sto*", 220), ("sto/", 310),
+    ("asto", 120), ("arcl", 250),
+    // Program control
+    ("lbl", 15), ("gto", 150), ("xeq", 160), ("rtn", 60), ("end", 60),
+    ("isg", 250), ("dse", 250), ("stop", 30), ("prompt", 700),
+    ("view", 700), ("aview", 700),
+    ("sf", 80), ("cf", 80), ("fs?", 100), ("fc?", 100),
+];
+
+/// Number entry steps: a fixed cost plus one per character keyed
+const NUMBER_CYCLES: u32 = 40;
+const NUMBER_CYCLES_PER_CHAR: u32 = 20;
+
+/// Text lines: the same shape as number entry
+const TEXT_CYCLES: u32 = 60;
+const TEXT_CYCLES_PER_CHAR: u32 = 6;
+
+/// Comparison tests (X=Y?, X<0? and the like)
+const TEST_CYCLES: u32 = 110;
+
+/// Cycles one program step takes
+pub fn cycles(step: &ProgramInstruction) -> u32 {
+    if let Some(line) = &step.text {
+        return TEXT_CYCLES + TEXT_CYCLES_PER_CHAR * line.text.chars().count() as u32;
+    }
+    if step.command.parse::<f64>().is_ok() {
+        return NUMBER_CYCLES + NUMBER_CYCLES_PER_CHAR * step.command.len() as u32;
+    }
+    let name = command_key(&step.command);
+    match CYCLES.iter().find(|(command, _)| *command == name) {
+        Some(&(_, cycles)) => cycles,
+        None if name.starts_with('x') && name.ends_with('?') => TEST_CYCLES,
+        None => DEFAULT_CYCLES,
+    }
+}
+
+/// Time one program step takes on a real HP-41
+pub fn duration(step: &ProgramInstruction) -> Duration {
+    CYCLE * cycles(step)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(command: &str) -> ProgramInstruction {
+        ProgramInstruction::new(1, command.to_string(), vec![])
+    }
+
+    #[test]
+    fn test_step_costs() {
+        assert_eq!(cycles(&step("SIN")), 1600);
+        assert_eq!(cycles(&step("12.5")), 40 + 4 * 20);
+        assert_eq!(cycles(&step("X<=Y?")), TEST_CYCLES);
+        assert_eq!(cycles(&step("BEEP")), DEFAULT_CYCLES);
+        assert_eq!(cycles(&ProgramInstruction::text_line(1, "HI", true)), 60 + 2 * 6);
+        // About a quarter of a second for SIN, as on the real machine
+        assert_eq!(duration(&step("sin")).as_millis(), 253);
+    }
+}
@@ -0,0 +1,121 @@
+//! Keystroke statistics
+//!
+//! Counts how often each command is keyed in and how long its entry took,
+//! from the first key of the command name to the key that completed it.
+//! The numbers stay on the local machine (in memory, and in a stats file
+//! if the front end saves one); `STATS` prints the report. Frequently used
+//! multi-key functions are good candidates for ASN.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+
+/// Usage of one command
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandUsage {
+    /// Times the command was keyed in
+    pub count: u64,
+    /// Total entry time in milliseconds
+    pub entry_millis: u64,
+}
+
+impl CommandUsage {
+    /// Average time from the first key to completion
+    pub fn average_entry(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            count => Duration::from_millis(self.entry_millis / count),
+        }
+    }
+}
+
+/// Per-command usage counts
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageStats {
+    commands: BTreeMap<String, CommandUsage>,
+}
+
+impl UsageStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count one use of a command
+    pub fn record(&mut self, command: &str, entry_time: Duration) {
+        let usage = self.commands.entry(command.to_uppercase()).or_default();
+        usage.count += 1;
+        usage.entry_millis += entry_time.as_millis() as u64;
+    }
+
+    /// Usage of a single command
+    pub fn get(&self, command: &str) -> Option<&CommandUsage> {
+        self.commands.get(&command.to_uppercase())
+    }
+
+    /// Commands by descending use, ties in name order
+    pub fn ranked(&self) -> Vec<(&str, &CommandUsage)> {
+        let mut ranked: Vec<(&str, &CommandUsage)> = self.commands.iter()
+            .map(|(name, usage)| (name.as_str(), usage))
+            .collect();
+        ranked.sort_by_key(|&(_, usage)| std::cmp::Reverse(usage.count));
+        ranked
+    }
+
+    /// Total number of commands keyed in
+    pub fn total(&self) -> u64 {
+        self.commands.values().map(|usage| usage.count).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.commands.clear();
+    }
+
+    /// Serialize as JSON
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| format!("Failed to encode stats: {}", e))
+    }
+
+    /// Parse JSON written by `to_json`
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("Invalid stats file: {}", e))
+    }
+}
+
+/// One line per command, e.g. `SIN       12  avg 0.85s`
+impl fmt::Display for UsageStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "No commands used");
+        }
+        write!(f, "{} commands", self.total())?;
+        for (name, usage) in self.ranked() {
+            write!(f, "\n{:<8} {:>5}  avg {:.2}s", name, usage.count, usage.average_entry().as_secs_f64())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ranking_and_averages() {
+        let mut stats = UsageStats::new();
+        stats.record("sin", Duration::from_millis(900));
+        stats.record("+", Duration::ZERO);
+        stats.record("SIN", Duration::from_millis(700));
+
+        assert_eq!(stats.get("sin").unwrap().average_entry(), Duration::from_millis(800));
+        assert_eq!(stats.total(), 3);
+        assert_eq!(stats.to_string(), "3 commands\nSIN          2  avg 0.80s\n+            1  avg 0.00s");
+
+        let json = stats.to_json().unwrap();
+        assert_eq!(UsageStats::from_json(&json).unwrap(), stats);
+    }
+}
@@ -0,0 +1,215 @@
+//! Dry runs: checking commands without executing them
+//!
+//! `HP41CCalculator::validate_lines` takes command lines as
+//! `run_command_line` does, and `validate_steps` program steps as a
+//! listing loads them, and reports what would fail: commands this
+//! calculator doesn't have, arguments a command can't take (`IND` and `ST`
+//! operands among them, which nothing runs yet), data registers beyond
+//! SIZE and GTO/XEQ targets that don't exist. Nothing runs and the
+//! calculator is left as it was, so an editor can check a buffer on every
+//! change.
+//!
+//! A finding is the `CalculatorError` the line would fail with, so it has
+//! the same code as in `hp41c exec`. What only shows while running, such
+//! as a division by zero, isn't found.
+
+use std::fmt;
+use crate::error::{CalculatorError, CommandError, ProgrammingError, StorageError};
+use crate::execution::register_operand;
+use crate::programming::{unsupported_operand, ProgramInstruction};
+use crate::registry::{command_key, ArgumentPattern, CommandRegistry};
+use crate::xmem::{self, ExtendedMemory};
+
+/// Highest flag number
+const LAST_FLAG: usize = 55;
+
+/// A problem found in one line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// Line or step number, from 1
+    pub line: usize,
+    pub error: CalculatorError,
+}
+
+/// `03: Command error: Unknown command: FOO`
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}: {}", self.line, self.error)
+    }
+}
+
+/// What the steps are checked against
+pub(crate) struct Context<'a> {
+    pub registry: &'a CommandRegistry,
+    /// Steps whose labels GTO and XEQ can reach
+    pub program: &'a [ProgramInstruction],
+    pub extended: &'a ExtendedMemory,
+    /// Data registers (SIZE)
+    pub size: usize,
+}
+
+/// Command lines as program steps, numbered by line; blank lines are skipped
+pub(crate) fn parse_lines<S: AsRef<str>>(lines: &[S]) -> Vec<ProgramInstruction> {
+    lines.iter().enumerate().filter_map(|(index, line)| {
+        let mut tokens = line.as_ref().split_whitespace();
+        let command = tokens.next()?.to_string();
+        let args = tokens.map(|t| t.trim_matches('"').to_string()).collect();
+        Some(ProgramInstruction::new(index as i32 + 1, command, args))
+    }).collect()
+}
+
+/// Check each step, at most one finding per step
+pub(crate) fn check_steps(steps: &[ProgramInstruction], context: &Context) -> Vec<Diagnostic> {
+    steps.iter().filter_map(|step| {
+        let error = check_step(step, context).err()?;
+        Some(Diagnostic { line: step.line_number.max(0) as usize, error })
+    }).collect()
+}
+
+fn check_step(step: &ProgramInstruction, context: &Context) -> Result<(), CalculatorError> {
+    if step.text.is_some() || step.command.parse::<f64>().is_ok() {
+        return Ok(());
+    }
+    let command = command_key(&step.command);
+    let spec = context.registry.get_spec(&command)
+        .ok_or_else(|| CommandError::UnknownCommand(step.command.clone()))?;
+    let args = step.arguments.as_slice();
+    let invalid = |argument: &str| CommandError::InvalidArgument {
+        command: command.to_uppercase(),
+        argument: argument.to_string(),
+    };
+    let missing = || CommandError::MissingArgument(command.to_uppercase());
+    match &spec.arg_pattern {
+        ArgumentPattern::None => match args.first() {
+            Some(arg) => Err(invalid(arg).into()),
+            None => Ok(()),
+        },
+        ArgumentPattern::SingleDigit => match args {
+            [] => Err(missing().into()),
+            [digit] if digit.len() == 1 && digit.chars().all(|c| c.is_ascii_digit()) => Ok(()),
+            [ind, ..] if ind.eq_ignore_ascii_case("ind") => check_register(&command, args, context),
+            [arg, ..] => Err(invalid(arg).into()),
+        },
+        ArgumentPattern::Register => check_register(&command, args, context),
+        ArgumentPattern::RegisterRange | ArgumentPattern::ThreeDigit => {
+            match args.iter().find(|arg| arg.parse::<usize>().is_err()) {
+                _ if args.is_empty() => Err(missing().into()),
+                Some(arg) => Err(invalid(arg).into()),
+                None => Ok(()),
+            }
+        }
+        ArgumentPattern::Label | ArgumentPattern::Alpha => match args {
+            [] => Err(missing().into()),
+            [label] if matches!(command.as_str(), "gto" | "xeq") => check_target(&command, label, context),
+            [ind, ..] if ind.eq_ignore_ascii_case("ind") && matches!(command.as_str(), "gto" | "xeq") => {
+                check_register(&command, args, context)
+            }
+            _ => Ok(()),
+        },
+        ArgumentPattern::Custom(valid) => match args.iter().find(|arg| !valid(arg)) {
+            Some(arg) => Err(invalid(arg).into()),
+            None => Ok(()),
+        },
+    }
+}
+
+/// A register address `nn`, read as the command will read it; `IND nn`
+/// and `ST x` fail, as they do when run
+///
+/// Flag commands take a flag number instead, 00-55.
+fn check_register(command: &str, args: &[String], context: &Context) -> Result<(), CalculatorError> {
+    let invalid = |argument: &str| CommandError::InvalidArgument {
+        command: command.to_uppercase(),
+        argument: argument.to_string(),
+    };
+    if let Some(operand) = unsupported_operand(args) {
+        return Err(invalid(&operand).into());
+    }
+    match args {
+        [] => Err(CommandError::MissingArgument(command.to_uppercase()).into()),
+        [number] => {
+            let storage = matches!(
+                command,
+                "sto" | "rcl" | "sto+" | "sto-" | "sto*" | "sto/" | "rcl+" | "rcl-" | "rcl*" | "rcl/" | "x<>"
+            );
+            let register = if storage {
+                register_operand(command, args)?
+            } else {
+                number.parse::<usize>().map_err(|_| invalid(number))?
+            };
+            let flag = matches!(command, "sf" | "cf" | "fs?" | "fc?" | "fs?c" | "fc?c");
+            if flag {
+                return if register <= LAST_FLAG { Ok(()) } else { Err(invalid(number).into()) };
+            }
+            if register >= context.size {
+                return Err(StorageError::Nonexistent(register).into());
+            }
+            Ok(())
+        }
+        [_, extra, ..] => Err(invalid(extra).into()),
+    }
+}
+
+/// A GTO/XEQ target: a label in reach, or for GTO a line (`.012`, `..`)
+fn check_target(command: &str, label: &str, context: &Context) -> Result<(), CalculatorError> {
+    if command == "gto" {
+        if label == ".." {
+            return Ok(());
+        }
+        if let Some(line) = label.strip_prefix('.') {
+            let line = line.parse::<usize>().map_err(|_| CommandError::InvalidArgument {
+                command: "GTO".to_string(),
+                argument: label.to_string(),
+            })?;
+            return if line <= context.program.len() {
+                Ok(())
+            } else {
+                Err(ProgrammingError::InvalidLine(line as i32).into())
+            };
+        }
+    }
+    if xmem::find_label(context.program, label).is_some() || context.extended.find_global_label(label).is_some() {
+        Ok(())
+    } else {
+        Err(ProgrammingError::LabelNotFound(label.to_string()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::HP41CCalculator;
+
+    #[test]
+    fn test_validate_lines() {
+        let mut calc = HP41CCalculator::new();
+        calc.load_listing("LBL \"AREA\"\nX2\nPI\n*\nRTN").unwrap();
+        calc.run_command_line("SIZE 010").unwrap();
+        let before = calc.snapshot();
+        let lines = [
+            "2.5", "XEQ AREA", "STO 09", "", "STO 10", "FOO", "FIX 12", "SIN 3",
+            "XEQ NOWHERE", "GTO .006", "SF 56", "RCL IND ST X", "SF 55", "X<> ST Y",
+        ];
+        let found: Vec<(usize, u8)> = calc.validate_lines(&lines).iter()
+            .map(|d| (d.line, d.error.code()))
+            .collect();
+        assert_eq!(found, [(5, 53), (6, 30), (7, 32), (8, 32), (9, 40), (10, 43), (11, 32), (12, 32), (14, 32)]);
+        // Nothing ran
+        assert_eq!(calc.snapshot(), before);
+        assert_eq!(calc.validate_lines(&["FOO"])[0].to_string(), "01: Command error: Unknown command: FOO");
+        // A finding is the error the line fails with when run
+        let finding = calc.validate_lines(&["X<> ST Y"])[0].error.to_string();
+        assert_eq!(calc.run_command_line("X<> ST Y"), Err(finding));
+        let finding = calc.validate_lines(&["STO ind 03"])[0].error.to_string();
+        assert_eq!(finding, "Command error: Invalid argument 'ind 03' for STO");
+        assert_eq!(calc.run_command_line("STO ind 03"), Err(finding));
+    }
+
+    #[test]
+    fn test_validate_steps() {
+        let calc = HP41CCalculator::new();
+        // The steps' own labels count, as they would once loaded
+        let steps = crate::listing::parse_listing("LBL 01\nXEQ 02\nGTO 01\nLBL 02\nGTO 03\nRTN").unwrap();
+        let found: Vec<usize> = calc.validate_steps(&steps).iter().map(|d| d.line).collect();
+        assert_eq!(found, [5]);
+    }
+}
@@ -0,0 +1,123 @@
+//! WATCH, a non-authentic debugging facility
+//!
+//! `WATCH R05`, `WATCH X` (or `ST X`; Y, Z, T and L as well) or `WATCH F15`
+//! keeps an eye on a data register, stack register or flag while a
+//! program runs. When a step changes a watched value the run halts after
+//! that step, with the program counter on the next one so R/S carries on,
+//! and the old and new values go to the log. `UNWATCH` removes every
+//! watchpoint.
+
+use std::fmt;
+use crate::alpha;
+use crate::error::{CalculatorError, CommandError};
+use crate::flags::{Flags, FLAG_COUNT};
+use crate::stack::Stack;
+
+/// Something a running program is watched for changing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watchpoint {
+    Register(usize),
+    /// `X`, `Y`, `Z`, `T` or `L`
+    Stack(char),
+    Flag(u8),
+}
+
+impl Watchpoint {
+    /// Parse the arguments of a WATCH command
+    pub fn parse(args: &[String]) -> Result<Self, CalculatorError> {
+        let text: String = args.concat().split_whitespace().collect::<String>().to_uppercase();
+        let invalid = || CommandError::InvalidArgument { command: "WATCH".to_string(), argument: text.clone() };
+        if text.is_empty() {
+            return Err(CommandError::MissingArgument("WATCH".to_string()).into());
+        }
+        let name = text.strip_prefix("ST").filter(|name| name.len() == 1).unwrap_or(&text);
+        if let [register @ ('X' | 'Y' | 'Z' | 'T' | 'L')] = name.chars().collect::<Vec<_>>()[..] {
+            return Ok(Watchpoint::Stack(register));
+        }
+        if let Some(flag) = text.strip_prefix('F') {
+            return match flag.parse::<u8>() {
+                Ok(flag) if flag < FLAG_COUNT => Ok(Watchpoint::Flag(flag)),
+                _ => Err(invalid().into()),
+            };
+        }
+        let register = text.strip_prefix('R').unwrap_or(&text);
+        register.parse().map(Watchpoint::Register).map_err(|_| invalid().into())
+    }
+
+    /// The watched value now; a flag is 1 when set, a register beyond SIZE 0
+    pub fn value(&self, stack: &Stack, registers: &[f64], flags: &Flags) -> f64 {
+        match *self {
+            Watchpoint::Register(r) => registers.get(r).copied().unwrap_or_default(),
+            Watchpoint::Stack('X') => stack.x(),
+            Watchpoint::Stack('Y') => stack.y(),
+            Watchpoint::Stack('Z') => stack.z(),
+            Watchpoint::Stack('T') => stack.t(),
+            Watchpoint::Stack(_) => stack.last_x(),
+            Watchpoint::Flag(flag) => f64::from(u8::from(flags.is_set(flag))),
+        }
+    }
+}
+
+/// `R05`, `ST X`, `F15`
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Watchpoint::Register(r) => write!(f, "R{:02}", r),
+            Watchpoint::Stack(register) => write!(f, "ST {}", register),
+            Watchpoint::Flag(flag) => write!(f, "F{:02}", flag),
+        }
+    }
+}
+
+/// A watched value a step changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchHit {
+    pub watchpoint: Watchpoint,
+    pub old: f64,
+    pub new: f64,
+}
+
+impl WatchHit {
+    /// Compare by bits, so alpha data counts as a value like any other
+    pub fn changed(watchpoint: Watchpoint, old: f64, new: f64) -> Option<Self> {
+        (old.to_bits() != new.to_bits()).then_some(WatchHit { watchpoint, old, new })
+    }
+}
+
+/// `R05: 1 -> 2.5`, `F15: clear -> set`
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let describe = |value: f64| match self.watchpoint {
+            Watchpoint::Flag(_) if value != 0.0 => "set".to_string(),
+            Watchpoint::Flag(_) => "clear".to_string(),
+            _ => alpha::describe(value),
+        };
+        write!(f, "{}: {} -> {}", self.watchpoint, describe(self.old), describe(self.new))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Watchpoint, CalculatorError> {
+        Watchpoint::parse(&text.split_whitespace().map(String::from).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("R05"), Ok(Watchpoint::Register(5)));
+        assert_eq!(parse("12"), Ok(Watchpoint::Register(12)));
+        assert_eq!(parse("ST X"), Ok(Watchpoint::Stack('X')));
+        assert_eq!(parse("l"), Ok(Watchpoint::Stack('L')));
+        assert_eq!(parse("F15"), Ok(Watchpoint::Flag(15)));
+        assert!(parse("F56").is_err());
+        assert!(parse("ST Q").is_err());
+        assert!(parse("").is_err());
+        assert_eq!(parse("ST Y").unwrap().to_string(), "ST Y");
+
+        let hit = WatchHit::changed(Watchpoint::Flag(15), 0.0, 1.0).unwrap();
+        assert_eq!(hit.to_string(), "F15: clear -> set");
+        assert_eq!(WatchHit::changed(Watchpoint::Register(1), 2.0, 2.0), None);
+    }
+}
@@ -0,0 +1,134 @@
+//! Extended memory files
+//!
+//! The Extended Functions module adds a second memory of named files beside
+//! main memory. Program files hold a copy of a program, saved with `SAVEP`;
+//! unlike the real module, which only copies a file back with `GETP`, a
+//! program file here can be run in place: `XEQ` of a global label that main
+//! memory doesn't have finds it in a program file and runs the file's steps
+//! where they are. The program counter then lives in that file (see
+//! `MemorySpace`) until a `RTN` or a `GTO`/`XEQ` into main memory takes it
+//! back.
+//!
+//! | Command | ALPHA | Effect |
+//! |---|---|---|
+//! | `SAVEP` | `PROG` or `PROG,FILE` | Copy the program holding global label PROG to a program file (named PROG unless given) |
+//! | `PURFL` | `FILE` | Remove a file |
+//! | `EMDIR` | | List the files as `NAME P012`: name, type and size in registers |
+
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::analysis::is_global_label;
+use crate::programming::ProgramInstruction;
+
+/// Characters of a file name
+pub const FILE_NAME_LENGTH: usize = 7;
+
+/// One extended-memory file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum XFile {
+    /// A program, steps numbered from 1
+    Program(Vec<ProgramInstruction>),
+}
+
+impl XFile {
+    /// Registers the file takes, seven bytes each for a program
+    pub fn registers(&self) -> usize {
+        match self {
+            XFile::Program(steps) => steps.iter().map(ProgramInstruction::bytes).sum::<usize>().div_ceil(7),
+        }
+    }
+
+    /// `P` for a program file, as EMDIR shows it
+    pub fn type_letter(&self) -> char {
+        match self {
+            XFile::Program(_) => 'P',
+        }
+    }
+}
+
+/// The files of extended memory, by name
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExtendedMemory {
+    files: BTreeMap<String, XFile>,
+}
+
+impl ExtendedMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = (&str, &XFile)> {
+        self.files.iter().map(|(name, file)| (name.as_str(), file))
+    }
+
+    /// The steps of a program file
+    pub fn program(&self, name: &str) -> Option<&[ProgramInstruction]> {
+        match self.files.get(&name.to_uppercase())? {
+            XFile::Program(steps) => Some(steps),
+        }
+    }
+
+    /// Store a program under a name, renumbering its steps from 1 and
+    /// replacing any file of that name
+    pub fn save_program(&mut self, name: &str, steps: &[ProgramInstruction]) -> Result<(), String> {
+        let name = name.trim().to_uppercase();
+        if name.is_empty() || name.chars().count() > FILE_NAME_LENGTH {
+            return Err(format!("File name must be 1 to {} characters", FILE_NAME_LENGTH));
+        }
+        let steps = steps.iter().enumerate()
+            .map(|(index, step)| ProgramInstruction { line_number: index as i32 + 1, ..step.clone() })
+            .collect();
+        self.files.insert(name, XFile::Program(steps));
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<XFile> {
+        self.files.remove(&name.trim().to_uppercase())
+    }
+
+    /// The program file holding a global label, and the label's step index
+    pub fn find_global_label(&self, label: &str) -> Option<(&str, usize)> {
+        if !is_global_label(label) {
+            return None;
+        }
+        self.files.iter().find_map(|(name, file)| {
+            let XFile::Program(steps) = file;
+            find_label(steps, label).map(|index| (name.as_str(), index))
+        })
+    }
+}
+
+/// Index of `LBL label` among some steps; only global labels ignore case
+pub fn find_label(steps: &[ProgramInstruction], label: &str) -> Option<usize> {
+    steps.iter().position(|step| {
+        step.command.eq_ignore_ascii_case("lbl")
+            && step.arguments.first().is_some_and(|arg| arg == label || (is_global_label(label) && arg.eq_ignore_ascii_case(label)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(line: i32, command: &str, arg: &str) -> ProgramInstruction {
+        ProgramInstruction::new(line, command.to_string(), vec![arg.to_string()])
+    }
+
+    #[test]
+    fn test_program_files() {
+        let mut memory = ExtendedMemory::new();
+        memory.save_program("area", &[step(7, "LBL", "AREA"), step(8, "LBL", "01")]).unwrap();
+        assert!(memory.save_program("TOOLONGNAME", &[]).is_err());
+        assert_eq!(memory.program("AREA").unwrap()[1].line_number, 2);
+        assert_eq!(memory.find_global_label("area"), Some(("AREA", 0)));
+        assert_eq!(memory.find_global_label("01"), None);
+        assert_eq!(memory.files().next().unwrap().1.registers(), 2);
+        assert!(memory.remove("Area").is_some());
+        assert!(memory.is_empty());
+    }
+}